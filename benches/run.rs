@@ -0,0 +1,49 @@
+//! Throughput of running scripts end to end with the autocast binary,
+//! to catch regressions in the overhead around each instruction.
+
+use std::{fmt::Write, fs, process::Command};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tempfile::TempDir;
+
+/// A script with `commands` commands, which output a few lines each.
+fn script(commands: usize) -> String {
+    let mut script = String::from(
+        "settings:\n  width: 80\n  height: 24\n  shell: bash\n  sandbox: true\ninstructions:\n",
+    );
+    for num in 0..commands {
+        writeln!(
+            script,
+            "  - !Command\n    command: seq {num} $(({num} + 10))"
+        )
+        .expect("writing to a string can't fail");
+    }
+    script
+}
+
+fn run(c: &mut Criterion) {
+    let dir = TempDir::new().expect("temporary directory can be created");
+    let mut group = c.benchmark_group("run");
+    group.sample_size(10);
+    for commands in [1, 10, 50] {
+        let in_file = dir.path().join(format!("{commands}.yaml"));
+        fs::write(&in_file, script(commands)).expect("script can be written");
+        let out_file = dir.path().join(format!("{commands}.cast"));
+        group.bench_with_input(BenchmarkId::from_parameter(commands), &commands, |b, _| {
+            b.iter(|| {
+                let status = Command::new(env!("CARGO_BIN_EXE_autocast"))
+                    .arg("--overwrite")
+                    .arg(&in_file)
+                    .arg(&out_file)
+                    .output()
+                    .expect("autocast can be run")
+                    .status;
+                assert!(status.success(), "autocast failed");
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, run);
+criterion_main!(benches);
@@ -1,27 +1,172 @@
 use std::{
+    borrow::Cow,
     collections::HashMap,
-    io::{self, Write},
+    io::{self, BufRead, Write},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use clap::ValueEnum;
 use serde::{
-    ser::{Error, SerializeSeq, SerializeStruct},
-    Serialize, Serializer,
+    de,
+    ser::{self, SerializeSeq, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
 };
+use sha2::{Digest, Sha256};
 
 #[derive(Debug, Clone)]
 pub struct File {
+    pub version: Version,
     pub header: Header,
     pub events: Vec<Event>,
 }
 
 impl File {
+    /// Writes the header and events as an asciicast file of the file's version.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing to `writer` fails.
     pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
-        self.header.serialize(&mut serializer(&mut writer))?;
+        match self.version {
+            Version::V2 => self.header.serialize(&mut serializer(&mut writer))?,
+            Version::V3 => HeaderV3(&self.header).serialize(&mut serializer(&mut writer))?,
+        }
         writeln!(writer)?;
+        self.write_events(writer)
+    }
+
+    /// Reads an asciicast v2 or v3 file.
+    ///
+    /// Event times are converted to be since the start, as they are in v2.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails, or the header or an event is invalid.
+    pub fn read(reader: impl BufRead) -> io::Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing header"))??;
+        let ReadHeader {
+            version,
+            width,
+            height,
+            term,
+            timestamp,
+            duration,
+            idle_time_limit,
+            command,
+            title,
+            mut env,
+        } = serde_json::from_str(&header)?;
+
+        let (width, height) = match (version, term) {
+            (Version::V2, _) => (width, height),
+            (Version::V3, Some(term)) => {
+                if let Some(kind) = term.kind {
+                    env.entry(String::from("TERM")).or_insert(kind);
+                }
+                (Some(term.cols), Some(term.rows))
+            }
+            (Version::V3, None) => (None, None),
+        };
+        let (Some(width), Some(height)) = (width, height) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "header is missing the terminal size",
+            ));
+        };
+
+        let mut events = Vec::new();
+        let mut time = Duration::ZERO;
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut event: Event = serde_json::from_str(&line)?;
+            if version == Version::V3 {
+                time += event.time;
+                event.time = time;
+            }
+            events.push(event);
+        }
+
+        Ok(Self {
+            version,
+            header: Header {
+                width,
+                height,
+                timestamp: timestamp.map(|timestamp| UNIX_EPOCH + Duration::from_secs(timestamp)),
+                duration: duration.and_then(|duration| Duration::try_from_secs_f64(duration).ok()),
+                idle_time_limit,
+                command,
+                title,
+                env,
+            },
+            events,
+        })
+    }
+
+    /// Appends the events of `other` after the last event,
+    /// replacing the exit event, if there is one.
+    ///
+    /// The header is kept, except for the duration and the events hash, which is removed.
+    pub fn append(&mut self, other: Self) {
+        if self
+            .events
+            .last()
+            .is_some_and(|event| event.event_type == EventType::Exit)
+        {
+            self.events.pop();
+        }
+        let offset = self
+            .events
+            .last()
+            .map_or(Duration::ZERO, |event| event.time);
+        self.events
+            .extend(other.events.into_iter().map(|mut event| {
+                event.time += offset;
+                event
+            }));
+        self.header.duration = self
+            .events
+            .iter()
+            .rev()
+            .find(|event| event.event_type != EventType::Exit)
+            .map(|event| event.time);
+        self.header.env.remove(EVENTS_HASH_ENV);
+    }
 
+    /// Adds the SHA-256 hash of the events, as they are written after the header,
+    /// to the header's env as [`EVENTS_HASH_ENV`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an event could not be serialized.
+    pub fn add_events_hash(&mut self) -> io::Result<()> {
+        let mut hasher = Sha256::new();
+        self.write_events(&mut hasher)?;
+        self.header.env.insert(
+            String::from(EVENTS_HASH_ENV),
+            format!("{:x}", hasher.finalize()),
+        );
+        Ok(())
+    }
+
+    /// Writes each event as a line.
+    fn write_events(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut previous = Duration::ZERO;
         for event in &self.events {
-            event.serialize(&mut serializer(&mut writer))?;
+            match self.version {
+                Version::V2 => event.serialize(&mut serializer(&mut writer))?,
+                // v3 event times are the interval since the previous event.
+                Version::V3 => {
+                    let interval = event.time.saturating_sub(previous);
+                    previous = event.time;
+                    event.serialize_at(interval, &mut serializer(&mut writer))?;
+                }
+            }
             writeln!(writer)?;
         }
 
@@ -29,6 +174,34 @@ impl File {
     }
 }
 
+/// Header env key of the hash added by [`File::add_events_hash()`]
+pub const EVENTS_HASH_ENV: &str = "AUTOCAST_EVENTS_SHA256";
+
+/// Version of the asciicast file format
+#[derive(ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "u8")]
+pub enum Version {
+    #[default]
+    #[value(name = "2")]
+    V2,
+    #[value(name = "3")]
+    V3,
+}
+
+impl TryFrom<u8> for Version {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            2 => Ok(Self::V2),
+            3 => Ok(Self::V3),
+            version => Err(format!(
+                "unsupported asciicast version {version}, expected 2 or 3"
+            )),
+        }
+    }
+}
+
 fn serializer<W: Write>(writer: W) -> serde_json::Serializer<W, Formatter> {
     serde_json::Serializer::with_formatter(writer, Formatter)
 }
@@ -107,7 +280,7 @@ impl Serialize for Header {
             if let Ok(timestamp) = timestamp.duration_since(UNIX_EPOCH) {
                 header.serialize_field("timestamp", &timestamp.as_secs())?;
             } else {
-                return Err(S::Error::custom("timestamp is before unix epoch"));
+                return Err(ser::Error::custom("timestamp is before unix epoch"));
             }
         }
         if let Some(duration) = &self.duration {
@@ -134,6 +307,104 @@ impl Header {
     const VERSION: u8 = 2;
 }
 
+/// Serializes a [`Header`] in the asciicast v3 format.
+///
+/// The terminal size and type are grouped under "term" and there is no duration.
+struct HeaderV3<'a>(&'a Header);
+
+impl Serialize for HeaderV3<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Header {
+            width,
+            height,
+            timestamp,
+            duration: _,
+            idle_time_limit,
+            command,
+            title,
+            env,
+        } = self.0;
+
+        let mut header = serializer.serialize_struct(
+            "Header",
+            // version, term
+            2 + usize::from(timestamp.is_some())
+                + usize::from(idle_time_limit.is_some())
+                + usize::from(command.is_some())
+                + usize::from(title.is_some())
+                + usize::from(!env.is_empty()),
+        )?;
+
+        header.serialize_field("version", &Self::VERSION)?;
+        header.serialize_field(
+            "term",
+            &Term {
+                cols: *width,
+                rows: *height,
+                kind: env.get("TERM"),
+            },
+        )?;
+        if let Some(timestamp) = timestamp {
+            if let Ok(timestamp) = timestamp.duration_since(UNIX_EPOCH) {
+                header.serialize_field("timestamp", &timestamp.as_secs())?;
+            } else {
+                return Err(ser::Error::custom("timestamp is before unix epoch"));
+            }
+        }
+        if let Some(idle_time_limit) = idle_time_limit {
+            header.serialize_field("idle_time_limit", idle_time_limit)?;
+        }
+        if let Some(command) = command {
+            header.serialize_field("command", command)?;
+        }
+        if let Some(title) = title {
+            header.serialize_field("title", title)?;
+        }
+        if !env.is_empty() {
+            header.serialize_field("env", env)?;
+        }
+
+        header.end()
+    }
+}
+
+impl HeaderV3<'_> {
+    const VERSION: u8 = 3;
+}
+
+/// Header of an asciicast v2 or v3 file, as it is read
+#[derive(Deserialize)]
+struct ReadHeader {
+    version: Version,
+    width: Option<u16>,
+    height: Option<u16>,
+    term: Option<ReadTerm>,
+    timestamp: Option<u64>,
+    duration: Option<f64>,
+    idle_time_limit: Option<f64>,
+    command: Option<String>,
+    title: Option<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ReadTerm {
+    cols: u16,
+    rows: u16,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Term<'a> {
+    cols: u16,
+    rows: u16,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    kind: Option<&'a String>,
+}
+
+#[allow(clippy::struct_field_names)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Event {
     pub time: Duration,
@@ -141,19 +412,26 @@ pub struct Event {
     pub data: String,
 }
 
+impl<'de> Deserialize<'de> for Event {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (time, event_type, data) = <(f64, EventType, String)>::deserialize(deserializer)?;
+        let time = Duration::try_from_secs_f64(time).map_err(de::Error::custom)?;
+        Ok(Self {
+            time,
+            event_type,
+            data,
+        })
+    }
+}
+
 impl Serialize for Event {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut event = serializer.serialize_seq(Some(3))?;
-
-        event.serialize_element(&self.time.as_secs_f64())?;
-        event.serialize_element(&self.event_type)?;
-        event.serialize_element(&self.data)?;
-
-        event.end()
+        self.serialize_at(self.time, serializer)
     }
 }
 
 impl Event {
+    #[must_use]
     pub fn output(time: Duration, data: String) -> Self {
         Self {
             time,
@@ -162,6 +440,7 @@ impl Event {
         }
     }
 
+    #[must_use]
     pub fn outputln(time: Duration) -> Self {
         Self {
             time,
@@ -170,6 +449,7 @@ impl Event {
         }
     }
 
+    #[must_use]
     pub fn marker(time: Duration, data: String) -> Self {
         Self {
             time,
@@ -177,21 +457,239 @@ impl Event {
             data,
         }
     }
+
+    #[must_use]
+    pub fn resize(time: Duration, width: u16, height: u16) -> Self {
+        Self {
+            time,
+            event_type: EventType::Resize,
+            data: format!("{width}x{height}"),
+        }
+    }
+
+    #[must_use]
+    pub fn exit(time: Duration, status: i32) -> Self {
+        Self {
+            time,
+            event_type: EventType::Exit,
+            data: status.to_string(),
+        }
+    }
+
+    /// Serializes the event with `time` instead of the event's time.
+    fn serialize_at<S: Serializer>(
+        &self,
+        time: Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut event = serializer.serialize_seq(Some(3))?;
+
+        event.serialize_element(&time.as_secs_f64())?;
+        event.serialize_element(&self.event_type)?;
+        event.serialize_element(&self.data)?;
+
+        event.end()
+    }
 }
 
+/// Type of an [`Event`], serialized as the event's code.
+///
+/// Every event's data is a string, its meaning depends on the type.
+/// Codes not known by autocast are kept as [`EventType::Custom`] so they can be written back.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum EventType {
+    /// Data written to the terminal, code "o"
     Output,
+    /// Data read from the keyboard, code "i"
+    Input,
+    /// Marker with an optional label, code "m"
     Marker,
+    /// Terminal resize as "{width}x{height}", code "r"
+    Resize,
+    /// Exit status of the recorded process, code "x"
+    Exit,
+    /// Any other event code
+    Custom(String),
 }
 
-impl Serialize for EventType {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let event_type = match self {
+impl EventType {
+    #[must_use]
+    pub fn code(&self) -> &str {
+        match self {
             Self::Output => "o",
+            Self::Input => "i",
             Self::Marker => "m",
+            Self::Resize => "r",
+            Self::Exit => "x",
+            Self::Custom(code) => code,
+        }
+    }
+}
+
+impl From<&str> for EventType {
+    fn from(code: &str) -> Self {
+        match code {
+            "o" => Self::Output,
+            "i" => Self::Input,
+            "m" => Self::Marker,
+            "r" => Self::Resize,
+            "x" => Self::Exit,
+            code => Self::Custom(String::from(code)),
+        }
+    }
+}
+
+impl Serialize for EventType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for EventType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = <Cow<str>>::deserialize(deserializer)?;
+        Ok(Self::from(code.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_round_trip() {
+        let events = [
+            Event::output(Duration::from_millis(1500), String::from("hello\r\n")),
+            Event::marker(Duration::from_secs(2), String::from("chapter")),
+            Event {
+                time: Duration::from_secs(3),
+                event_type: EventType::Resize,
+                data: String::from("80x24"),
+            },
+            Event {
+                time: Duration::from_secs(4),
+                event_type: EventType::Custom(String::from("z")),
+                data: String::from("custom"),
+            },
+        ];
+
+        for event in events {
+            let mut json = Vec::new();
+            event.serialize(&mut serializer(&mut json)).unwrap();
+            let json = String::from_utf8(json).unwrap();
+            let parsed: Event = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed, event, "{json}");
+        }
+    }
+
+    #[test]
+    fn v3() {
+        let file = File {
+            version: Version::V3,
+            header: Header {
+                width: 80,
+                height: 24,
+                timestamp: None,
+                duration: Some(Duration::from_secs(2)),
+                idle_time_limit: None,
+                command: None,
+                title: Some(String::from("demo")),
+                env: HashMap::from([(String::from("TERM"), String::from("xterm"))]),
+            },
+            events: vec![
+                Event::output(Duration::from_millis(500), String::from("a")),
+                Event::output(Duration::from_secs(2), String::from("b")),
+                Event::exit(Duration::from_secs(2), 0),
+            ],
         };
+        let mut output = Vec::new();
+        file.write(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"{"version": 3, "term": {"cols": 80, "rows": 24, "type": "xterm"}, "title": "demo", "env": {"TERM": "xterm"}}
+[0.500000, "o", "a"]
+[1.500000, "o", "b"]
+[0.000000, "x", "0"]
+"#
+        );
+    }
 
-        serializer.serialize_str(event_type)
+    #[test]
+    fn event_codes() {
+        let json = r#"[1.000000, "i", "q"]"#;
+        let event: Event = serde_json::from_str(json).unwrap();
+        assert_eq!(event.event_type, EventType::Input);
+        assert_eq!(EventType::Exit.code(), "x");
+    }
+
+    #[test]
+    fn read_and_append() -> io::Result<()> {
+        let v3 = "\
+{\"version\": 3, \"term\": {\"cols\": 80, \"rows\": 24, \"type\": \"xterm\"}}
+[0.5, \"o\", \"$ \"]
+[1.0, \"o\", \"ls\\r\\n\"]
+[0.0, \"x\", \"0\"]
+";
+        let mut file = File::read(v3.as_bytes())?;
+        assert_eq!(file.version, Version::V3);
+        assert_eq!((file.header.width, file.header.height), (80, 24));
+        assert_eq!(file.header.env["TERM"], "xterm");
+        assert_eq!(
+            file.events
+                .iter()
+                .map(|event| event.time)
+                .collect::<Vec<_>>(),
+            [500, 1500, 1500].map(Duration::from_millis)
+        );
+
+        let mut other = file.clone();
+        other.events = vec![Event::output(Duration::from_secs(1), String::from("a"))];
+        file.header
+            .env
+            .insert(String::from(EVENTS_HASH_ENV), String::new());
+        file.append(other);
+        assert_eq!(
+            file.events.last(),
+            Some(&Event::output(
+                Duration::from_millis(2500),
+                String::from("a")
+            ))
+        );
+        assert_eq!(file.events.len(), 3);
+        assert_eq!(file.header.duration, Some(Duration::from_millis(2500)));
+        assert!(!file.header.env.contains_key(EVENTS_HASH_ENV));
+
+        let mut written = Vec::new();
+        file.write(&mut written)?;
+        let read = File::read(written.as_slice())?;
+        assert_eq!(read.events, file.events);
+        Ok(())
+    }
+
+    #[test]
+    fn events_hash() {
+        let mut file = File {
+            version: Version::V2,
+            header: Header {
+                width: 80,
+                height: 24,
+                timestamp: None,
+                duration: None,
+                idle_time_limit: None,
+                command: None,
+                title: None,
+                env: HashMap::new(),
+            },
+            events: vec![Event::output(Duration::from_millis(500), String::from("a"))],
+        };
+        file.add_events_hash().unwrap();
+        let mut output = Vec::new();
+        file.write(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let (_, events) = output.split_once('\n').unwrap();
+        assert_eq!(
+            file.header.env[EVENTS_HASH_ENV],
+            format!("{:x}", Sha256::digest(events))
+        );
     }
 }
@@ -0,0 +1,197 @@
+//! Creating the asciicast files for several input files with `autocast batch`,
+//! running `--jobs` of them at a time.
+
+use std::{
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use color_eyre::eyre::{self, Context};
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use itertools::Itertools;
+
+use crate::{config::SharedProgress, read_script, record, Batch, Output, Select};
+
+impl Batch {
+    /// Creates an asciicast file in `out_dir` for each of the input files.
+    pub fn run(self) -> color_eyre::Result<()> {
+        let in_files = in_files(&self.in_files)?;
+        eyre::ensure!(!in_files.is_empty(), "no input files found");
+        let out_files = in_files
+            .iter()
+            .map(|in_file| {
+                let name = Path::new(in_file.file_stem().unwrap_or_default())
+                    .with_extension(self.format.extension());
+                self.out_dir.join(name)
+            })
+            .collect_vec();
+        if let Some(duplicate) = out_files.iter().duplicates().next() {
+            eyre::bail!(
+                "several input files would be written to `{}`",
+                duplicate.display()
+            );
+        }
+        fs::create_dir_all(&self.out_dir).wrap_err_with(|| {
+            format!(
+                "could not create output directory `{}`",
+                self.out_dir.display()
+            )
+        })?;
+
+        let mut results = self.record_all(&in_files, &out_files);
+        results.sort_by_key(|(num, _)| *num);
+        let mut failed = Vec::new();
+        for (num, result) in results {
+            match result {
+                Ok(()) => {}
+                Err(_) if self.keep_going => failed.push(&in_files[num]),
+                Err(error) => return Err(error),
+            }
+        }
+
+        eyre::ensure!(
+            failed.is_empty(),
+            "{} of {} input file(s) failed:\n  {}",
+            failed.len(),
+            in_files.len(),
+            failed.iter().map(|path| path.display()).join("\n  ")
+        );
+        eprintln!(
+            "Created asciicast files for {} input file(s) in: {}",
+            in_files.len(),
+            self.out_dir.display()
+        );
+        Ok(())
+    }
+
+    /// Runs the input files, `jobs` at a time, and returns the result of each by its index.
+    ///
+    /// Unless `keep_going`, no more input files are started after one fails.
+    fn record_all(
+        &self,
+        in_files: &[PathBuf],
+        out_files: &[PathBuf],
+    ) -> Vec<(usize, color_eyre::Result<()>)> {
+        // With several jobs, the scripts share the progress bars so they don't draw over each
+        // other, and messages are printed above them.
+        let bars = (self.jobs.get() > 1)
+            .then(|| MultiProgress::with_draw_target(ProgressDrawTarget::stderr()));
+        let print = |message: String| match &bars {
+            Some(bars) => bars.suspend(|| eprintln!("{message}")),
+            None => eprintln!("{message}"),
+        };
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let results = Mutex::new(Vec::with_capacity(in_files.len()));
+        thread::scope(|scope| {
+            for _ in 0..self.jobs.get().min(in_files.len()) {
+                scope.spawn(|| {
+                    while !stop.load(Ordering::Relaxed) {
+                        let num = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(in_file) = in_files.get(num) else {
+                            break;
+                        };
+                        print(format!(
+                            "Running script {}/{}: {}",
+                            num + 1,
+                            in_files.len(),
+                            in_file.display()
+                        ));
+                        let name = in_file.file_name().unwrap_or_default().to_string_lossy();
+                        let progress = bars
+                            .as_ref()
+                            .map(|bars| SharedProgress { bars, name: &name });
+                        let result = self
+                            .record(in_file, &out_files[num], progress)
+                            .wrap_err_with(|| {
+                                format!("error creating asciicast for `{}`", in_file.display())
+                            });
+                        if let Err(error) = &result {
+                            if self.keep_going {
+                                print(format!("Error: {error:#}"));
+                            } else {
+                                stop.store(true, Ordering::Relaxed);
+                            }
+                        }
+                        results
+                            .lock()
+                            .expect("no thread panics with the lock")
+                            .push((num, result));
+                    }
+                });
+            }
+        });
+        results
+            .into_inner()
+            .expect("no thread panics with the lock")
+    }
+
+    /// Runs the script at `in_file` and writes its asciicast files to `out_file`,
+    /// with the values of its matrix added to the name if it has one.
+    fn record(
+        &self,
+        in_file: &Path,
+        out_file: &Path,
+        progress: Option<SharedProgress>,
+    ) -> color_eyre::Result<()> {
+        let mut script = read_script(in_file)?;
+        script.resolve_paths(in_file.parent().unwrap_or(Path::new("")));
+        script.merge_global_settings()?;
+        script.merge_settings(self.settings.clone());
+        let output = Output {
+            format: self.format,
+            overwrite: self.overwrite,
+            append: false,
+            compress: false,
+            checksum: false,
+            typescript: None,
+            timing: None,
+            html: None,
+            html_player: None,
+            a11y: None,
+            check: None,
+            progress,
+            takes: NonZeroUsize::MIN,
+            select: Select::default(),
+        };
+        for (script, out_file) in script.matrix(&script.matrix_out_file(out_file))? {
+            record(script, &out_file, output)?;
+        }
+        Ok(())
+    }
+}
+
+/// The input files in `paths`, with each directory replaced by its YAML, TOML, and JSON files,
+/// sorted by name.
+fn in_files(paths: &[PathBuf]) -> color_eyre::Result<Vec<PathBuf>> {
+    let mut in_files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            in_files.push(path.clone());
+            continue;
+        }
+        let entries = fs::read_dir(path)
+            .wrap_err_with(|| format!("could not read directory `{}`", path.display()))?;
+        let mut files = Vec::new();
+        for entry in entries {
+            let file = entry?.path();
+            let is_script = file.extension().is_some_and(|extension| {
+                ["yaml", "yml", "toml", "json"]
+                    .iter()
+                    .any(|script| extension.eq_ignore_ascii_case(script))
+            });
+            if is_script && file.is_file() {
+                files.push(file);
+            }
+        }
+        files.sort();
+        in_files.extend(files);
+    }
+    Ok(in_files)
+}
@@ -0,0 +1,155 @@
+//! `cargo autocast`: creates the asciicast files for the current crate's demos,
+//! with the crate's freshly built binaries first in the `PATH` of the demos' shell.
+
+#![warn(clippy::pedantic)]
+#![warn(clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
+
+use std::{
+    env,
+    ffi::OsString,
+    iter,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use clap::{Args, Parser};
+use color_eyre::eyre::{self, Context};
+use serde::Deserialize;
+
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+    let Cargo::Autocast(autocast) = Cargo::parse();
+    autocast.run()
+}
+
+/// Cargo runs `cargo-autocast` with the subcommand's name as the first argument.
+#[derive(Parser, Debug)]
+#[command(name = "cargo", bin_name = "cargo")]
+enum Cargo {
+    Autocast(Autocast),
+}
+
+/// Build the current crate and create the asciicast files for its demos,
+/// with its binaries first in the shell's `PATH`
+///
+/// Each script in the demo directory is written to an asciicast file next to it,
+/// i.e. "autocast/demo.yaml" becomes "autocast/demo.cast"
+#[derive(Args, Debug)]
+#[command(version, author)]
+struct Autocast {
+    /// Build the binaries with the release profile
+    #[arg(long)]
+    release: bool,
+
+    /// Directory of the demo scripts, relative to the crate's root
+    #[arg(long, value_name = "DIR", default_value = "autocast")]
+    dir: PathBuf,
+
+    /// Arguments passed to `autocast batch`, i.e. `cargo autocast -- --jobs 4`
+    #[arg(last = true)]
+    args: Vec<OsString>,
+}
+
+/// The part of `cargo metadata`'s output which is used.
+#[derive(Deserialize, Debug)]
+struct Metadata {
+    target_directory: PathBuf,
+}
+
+impl Autocast {
+    fn run(self) -> color_eyre::Result<()> {
+        let autocast = autocast();
+        let manifest = locate_manifest()?;
+        let crate_dir = manifest.parent().unwrap_or(Path::new(""));
+        let scripts = crate_dir.join(&self.dir);
+        eyre::ensure!(
+            scripts.is_dir(),
+            "demo directory `{}` does not exist",
+            scripts.display()
+        );
+
+        let status = cargo()
+            .args(["build", "--bins", "--manifest-path"])
+            .arg(&manifest)
+            .args(self.release.then_some("--release"))
+            .status()
+            .wrap_err("could not run `cargo build`")?;
+        eyre::ensure!(status.success(), "`cargo build` failed with {status}");
+
+        let profile = if self.release { "release" } else { "debug" };
+        let bin_dir = target_directory(&manifest)?.join(profile);
+        let path = env::var_os("PATH").unwrap_or_default();
+        let path = env::join_paths(iter::once(bin_dir).chain(env::split_paths(&path)))
+            .wrap_err("could not add the crate's binaries to `PATH`")?;
+
+        let status = Command::new(autocast)
+            .arg("batch")
+            .arg("--out-dir")
+            .arg(&scripts)
+            .arg("--overwrite")
+            .args(&self.args)
+            .arg(&scripts)
+            .env("PATH", path)
+            .status()
+            .wrap_err("could not run `autocast batch`")?;
+        eyre::ensure!(status.success(), "`autocast batch` failed with {status}");
+        Ok(())
+    }
+}
+
+/// The cargo which ran this subcommand.
+fn cargo() -> Command {
+    Command::new(env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo")))
+}
+
+/// Path of the current crate's `Cargo.toml`.
+fn locate_manifest() -> color_eyre::Result<PathBuf> {
+    let output = cargo()
+        .args(["locate-project", "--message-format", "plain"])
+        .output()
+        .wrap_err("could not run `cargo locate-project`")?;
+    eyre::ensure!(
+        output.status.success(),
+        "could not find the current crate's Cargo.toml: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    let manifest = String::from_utf8(output.stdout).wrap_err("Cargo.toml path is not UTF-8")?;
+    Ok(PathBuf::from(manifest.trim_end()))
+}
+
+/// The directory cargo builds the crate at `manifest` in.
+fn target_directory(manifest: &Path) -> color_eyre::Result<PathBuf> {
+    let output = cargo()
+        .args([
+            "metadata",
+            "--format-version",
+            "1",
+            "--no-deps",
+            "--manifest-path",
+        ])
+        .arg(manifest)
+        .output()
+        .wrap_err("could not run `cargo metadata`")?;
+    eyre::ensure!(
+        output.status.success(),
+        "`cargo metadata` failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    let metadata: Metadata =
+        serde_json::from_slice(&output.stdout).wrap_err("could not parse `cargo metadata`")?;
+    Ok(metadata.target_directory)
+}
+
+/// The autocast binary installed next to this one, otherwise `autocast` in `PATH`.
+///
+/// Found with the current `PATH`, so a crate's own `autocast` binary is not run instead.
+fn autocast() -> PathBuf {
+    let name = format!("autocast{}", env::consts::EXE_SUFFIX);
+    env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(&name)))
+        .filter(|path| path.is_file())
+        .or_else(|| which::which(&name).ok())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
@@ -0,0 +1,95 @@
+//! Packaging the generated files into a single ZIP archive.
+
+use std::{
+    fs,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{self, Context};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// Files to put in a bundle, added at the top level of the archive by their file names.
+#[derive(Debug, Default)]
+pub struct Bundle {
+    files: Vec<PathBuf>,
+}
+
+impl Bundle {
+    /// Adds a file to the bundle.
+    pub fn add(&mut self, path: impl Into<PathBuf>) {
+        self.files.push(path.into());
+    }
+
+    /// Writes the ZIP archive to `out`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if two files have the same name or a file could not be read.
+    pub fn write(&self, out: fs::File) -> color_eyre::Result<()> {
+        let mut zip = ZipWriter::new(BufWriter::new(out));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        let mut names = Vec::with_capacity(self.files.len());
+        for path in &self.files {
+            let name = file_name(path)?;
+            eyre::ensure!(
+                !names.contains(&name),
+                "bundle already has a file named `{name}`"
+            );
+            zip.start_file(name.as_str(), options)?;
+            let mut file = fs::File::open(path)
+                .wrap_err_with(|| format!("could not open `{}`", path.display()))?;
+            io::copy(&mut file, &mut zip)
+                .wrap_err_with(|| format!("could not add `{}` to bundle", path.display()))?;
+            names.push(name);
+        }
+
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+fn file_name(path: &Path) -> color_eyre::Result<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(String::from)
+        .ok_or_else(|| eyre::eyre!("`{}` does not have a valid file name", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tempfile::TempDir;
+    use zip::ZipArchive;
+
+    use super::*;
+
+    #[test]
+    fn write() -> color_eyre::Result<()> {
+        let dir = TempDir::new()?;
+        let cast = dir.path().join("demo.cast");
+        fs::write(&cast, "cast")?;
+        let script = dir.path().join("demo.yaml");
+        fs::write(&script, "script")?;
+
+        let mut bundle = Bundle::default();
+        bundle.add(&cast);
+        bundle.add(&script);
+        let out = dir.path().join("demo.zip");
+        bundle.write(fs::File::create(&out)?)?;
+
+        let mut archive = ZipArchive::new(fs::File::open(&out)?)?;
+        assert_eq!(archive.len(), 2);
+        let mut contents = String::new();
+        archive
+            .by_name("demo.yaml")?
+            .read_to_string(&mut contents)?;
+        assert_eq!(contents, "script");
+
+        bundle.add(&cast);
+        assert!(bundle.write(fs::File::create(&out)?).is_err());
+        Ok(())
+    }
+}
@@ -1,32 +1,83 @@
+mod artifacts;
+mod calibrate;
+mod ci;
+mod collapse;
+mod condition;
 mod de;
+mod doctor;
+mod driver;
+pub mod export;
+mod fetch;
+mod global;
+mod idle;
+mod include;
+pub mod lint;
+mod markdown;
+mod matrix;
+mod parse;
+mod plan;
+mod post_edit;
+mod present;
+mod probe;
+mod profile;
+mod proxy;
+mod resources;
 mod run;
+mod safe;
+mod sandbox;
 mod spawn;
+mod step;
+mod timing_report;
+pub mod transcript;
+mod tui;
+mod validate;
+mod variables;
 
 use std::{
     borrow::Cow,
+    cell::RefCell,
     collections::HashMap,
     ffi::OsStr,
     fmt::{self, Display},
     io::Read,
-    iter, process,
-    time::{Duration, SystemTime},
+    iter,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 use clap::{Args, ValueEnum};
 use color_eyre::eyre::{self, Context};
 use console::Term;
-use expectrl::ControlCode;
+use expectrl::{session::OsProcess, ControlCode};
+use indicatif::MultiProgress;
 use itertools::Itertools;
+use regex::{Regex, RegexSet};
 use serde::{Deserialize, Deserializer};
 
-use crate::asciicast;
+use crate::asciicast::{self, Event};
 
-use self::spawn::ShellSession;
+pub use self::de::duration::parse as parse_duration;
+
+use self::{
+    calibrate::Measurement,
+    driver::{replay::Replay, SessionDriver, Spawn, Terminal},
+    profile::Profile,
+    proxy::Proxy,
+    sandbox::Sandbox,
+    spawn::ShellSession,
+    transcript::Transcript,
+    tui::{Dashboard, Watched},
+};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Script {
     #[serde(default)]
     settings: Settings,
+    /// Values of variables to run the script with each combination of,
+    /// see [`Script::matrix()`]
+    #[serde(default, deserialize_with = "de::matrix::deserialize")]
+    matrix: Vec<(String, Vec<String>)>,
     instructions: Vec<Instruction>,
 }
 
@@ -35,96 +86,629 @@ impl Script {
         serde_yaml::from_reader(reader)
     }
 
+    /// Parses a TOML script.
+    ///
+    /// Instructions, and commands or keys which are tagged in YAML, are tables with
+    /// the tag as their only key, i.e. `{ Wait = "1s" }`, or a string for unit variants
+    /// like `"Clear"`.
+    pub fn try_from_toml(toml: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(toml)
+    }
+
+    /// Parses a JSON script, i.e. one generated by another tool.
+    ///
+    /// Tagged values are objects like in [`Script::try_from_toml()`].
+    pub fn try_from_json(reader: impl Read) -> serde_json::Result<Self> {
+        serde_json::from_reader(reader)
+    }
+
+    /// Resolves the relative paths in the script's settings against `base_dir`,
+    /// usually the directory of the script file.
+    ///
+    /// Call before merging settings from the command line, so their paths stay relative to
+    /// the current directory.
+    pub fn resolve_paths(&mut self, base_dir: &Path) {
+        let Settings {
+            replay_fixtures,
+            sandbox_template,
+            proxy_fixtures,
+            artifacts_dir,
+            ..
+        } = &mut self.settings;
+        for path in [
+            replay_fixtures,
+            sandbox_template,
+            proxy_fixtures,
+            artifacts_dir,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if path.is_relative() {
+                *path = base_dir.join(&*path);
+            }
+        }
+    }
+
     pub fn merge_settings(&mut self, other_settings: Settings) {
         self.settings.merge(other_settings);
     }
+
+    /// Whether to print where the time of a run is spent, see `--profile-run`.
+    pub fn profile_run(&self) -> bool {
+        self.settings.profile_run
+    }
+
+    /// Sets the terminal size, overriding the settings.
+    pub fn set_size(&mut self, width: u16, height: u16) {
+        self.settings.width = Some(Width::Columns(width));
+        self.settings.height = Some(height);
+    }
 }
 
 impl TryFrom<Script> for asciicast::File {
     type Error = color_eyre::Report;
 
     fn try_from(value: Script) -> Result<Self, Self::Error> {
-        let Settings {
+        run_script(value, None, false, None, None)
+    }
+}
+
+/// Progress bars shared by scripts which run at the same time, so they don't draw over
+/// each other.
+#[derive(Debug, Clone, Copy)]
+pub struct SharedProgress<'a> {
+    pub bars: &'a MultiProgress,
+    /// Prefix of the script's progress bar, instead of "Instructions"
+    pub name: &'a str,
+}
+
+/// Runs the script and creates the asciicast from its output.
+///
+/// If `measurements` is given, a [`Measurement`] of each instruction is pushed to it.
+/// If `transcript` is given, the typed commands and their output are added to it.
+/// If `progress` is given, the script's progress bars are drawn with it.
+/// If `present`, each instruction is shown in the terminal as it runs, see [`Script::present()`].
+// Most of the lines destructure the settings, so none are missed.
+#[allow(clippy::too_many_lines)]
+fn run_script(
+    mut value: Script,
+    measurements: Option<&RefCell<Vec<Measurement>>>,
+    present: bool,
+    transcript: Option<&RefCell<Transcript>>,
+    progress: Option<SharedProgress>,
+) -> color_eyre::Result<asciicast::File> {
+    value.expand_variables();
+    let Settings {
+        width,
+        height,
+        title,
+        variables,
+        fetch,
+        artifacts,
+        artifacts_dir,
+        shell,
+        shell_wrapper,
+        backend,
+        tmux_session,
+        replay_fixtures,
+        environment,
+        environment_capture,
+        header_shell,
+        header_env,
+        disable_history,
+        disable_default_environment,
+        force_color,
+        sandbox,
+        sandbox_template,
+        safe,
+        dangerous_commands,
+        proxy,
+        proxy_fixtures,
+        asciicast_version,
+        exit_code,
+        post_edit,
+        collapse_rewrites,
+        collapse_keep_every,
+        max_idle,
+        type_speed,
+        pre_enter_delay,
+        double_width_pacing,
+        typing_speed_factor,
+        output_speed_factor,
+        coalesce_leading_events,
+        trailing_output,
+        binary_output,
+        echoed_newline,
+        stderr_style,
+        alt_screen_markers,
+        clear_after_alt_screen,
+        prompt,
+        prompt_preset,
+        capture_prompt,
+        wrap_commands,
+        secondary_prompt,
+        timeout,
+        spawn_retries,
+        spawn_retry_delay,
+        disable_flow_control,
+        disable_echoctl,
+        step,
+        ci,
+        tui,
+        timing_report,
+        profile_run,
+    } = value.settings;
+
+    check_speed_factors(typing_speed_factor, output_speed_factor)?;
+    eyre::ensure!(
+        tmux_session.is_none() || backend == Backend::Tmux,
+        "`tmux_session` requires the tmux backend"
+    );
+    check_modes(tui, step, ci, present)?;
+    if safe {
+        safe::check(&value.instructions, &dangerous_commands)?;
+    }
+    fetch::fetch_all(&fetch)?;
+
+    let prompt = prompt_preset.map_or(prompt, PromptPreset::prompt);
+    let (width, height) = script_terminal_size(
+        width,
+        height,
+        &value.instructions,
+        &prompt,
+        &secondary_prompt,
+        &shell,
+    )?;
+
+    let line_split = shell.line_split().to_string();
+    let line_continuation = shell.line_continuation().to_string();
+    let stderr_wrapper = shell.stderr_wrapper();
+    let shell_env = header_shell.unwrap_or_else(|| shell.path());
+    let default_environment = default_environment(&shell, disable_default_environment, force_color);
+
+    let sandbox = Sandbox::start(sandbox, sandbox_template.as_deref())?;
+    let proxy = Proxy::start(proxy, proxy_fixtures.as_deref())?;
+
+    let dashboard = tui
+        .then(|| Dashboard::start(&value.instructions, width, height))
+        .transpose()?;
+    let profile = profile_run.then(Profile::new);
+    // Checked by `!If` instructions, with the variables `!Capture` instructions add.
+    let captures = RefCell::new(variables);
+
+    let record = Record {
+        instructions: &value.instructions,
+        start: ShellStart {
+            shell,
+            shell_wrapper: shell_wrapper.unwrap_or_default(),
+            tmux_session,
+            environment: &environment,
+            sandbox: sandbox.as_ref(),
+            proxy: proxy.as_ref(),
+            default_environment,
+            disable_history,
+            disable_history_expansion: !disable_default_environment,
+            capture_prompt,
+            binary_output,
+            terminal: Terminal {
+                width,
+                height,
+                disable_flow_control,
+                disable_echoctl,
+            },
+            timeout,
+            spawn_retries,
+            spawn_retry_delay,
+        },
+        options: run::Options {
+            prompt: &prompt,
+            secondary_prompt: &secondary_prompt,
+            type_speed,
+            pre_enter_delay,
+            double_width_pacing,
+            line_split: &line_split,
+            line_continuation: &line_continuation,
+            typing_speed_factor,
+            output_speed_factor,
+            coalesce_leading_events,
+            trailing_output,
+            alt_screen_markers,
+            clear_after_alt_screen,
+            strip_prompt_line: false,
+            strip_echoed_newline: echoed_newline.strips(),
+            stderr_wrapper,
+            stderr_style: &stderr_style,
+            size: (width, height),
+            wrap_width: wrap_commands.then_some(width),
+            step,
+            present,
+            ci,
+            timing_report,
+            measurements,
+            dashboard: dashboard.as_ref(),
+            profile: profile.as_ref(),
+            captures: Some(&captures),
+            transcript,
+            progress,
+        },
+    };
+    let (mut events, exit_status) = record.run_backend(backend, replay_fixtures)?;
+    proxy.map(Proxy::finish).transpose()?;
+    // Copied before the sandbox is removed.
+    artifacts::copy(
+        &artifacts,
+        artifacts_dir
+            .as_deref()
+            .unwrap_or(Path::new(artifacts::DEFAULT_DIR)),
+        sandbox.as_ref().map(Sandbox::home),
+    );
+    let post_processing = Instant::now();
+    if collapse_rewrites {
+        collapse::collapse_rewrites(&mut events, collapse_keep_every);
+    }
+    if let Some(command) = post_edit {
+        events = post_edit::post_edit(&events, &command).wrap_err("error post editing events")?;
+    }
+    if let Some(max_idle) = max_idle {
+        idle::clamp(&mut events, max_idle);
+    }
+    if let Some(profile) = profile {
+        profile.add_post_processing(post_processing.elapsed());
+        eprintln!("{profile}");
+    }
+
+    let duration = events.last().map(|event| event.time);
+    push_exit_event(&mut events, asciicast_version, exit_code.or(exit_status));
+
+    Ok(asciicast::File {
+        version: asciicast_version,
+        header: asciicast::Header {
             width,
             height,
+            timestamp: Some(SystemTime::now()),
+            duration,
+            idle_time_limit: None,
+            command: None,
             title,
-            shell,
-            environment,
-            environment_capture,
-            type_speed,
-            prompt,
-            secondary_prompt,
-            timeout,
-        } = value.settings;
+            env: header_environment(environment, environment_capture, header_env, shell_env),
+        },
+        events,
+    })
+}
 
-        let (width, height) = terminal_size(width, height).ok_or(eyre::eyre!(
-            "terminal width or height not provided and could not get terminal size"
-        ))?;
+/// Variables set in the built-in shells so commands don't wait for input, unless `disabled`,
+/// and `CLICOLOR_FORCE` if `force_color` is true.
+fn default_environment(
+    shell: &Shell,
+    disabled: bool,
+    force_color: bool,
+) -> Vec<(&'static str, &'static str)> {
+    let mut environment = Vec::new();
+    if !disabled && !matches!(shell, Shell::Custom { .. }) {
+        environment.extend([("PAGER", "cat"), ("GIT_PAGER", "cat")]);
+    }
+    if force_color {
+        environment.push(("CLICOLOR_FORCE", "1"));
+    }
+    environment
+}
 
-        let line_split = shell.line_split().to_string();
-        let program = shell.program();
-        let shell_env = which::which(program).map_or_else(
-            |_| String::from(program),
-            |program| program.to_string_lossy().into_owned(),
-        );
+/// Everything needed to start a script's shell, shared by recording and probing it.
+struct ShellStart<'a> {
+    shell: Shell,
+    shell_wrapper: ShellWrapper,
+    tmux_session: Option<String>,
+    environment: &'a [EnvVar],
+    sandbox: Option<&'a Sandbox>,
+    proxy: Option<&'a Proxy>,
+    default_environment: Vec<(&'static str, &'static str)>,
+    disable_history: bool,
+    disable_history_expansion: bool,
+    capture_prompt: bool,
+    binary_output: BinaryOutput,
+    terminal: Terminal,
+    timeout: Duration,
+    spawn_retries: u32,
+    spawn_retry_delay: Duration,
+}
 
-        let mut shell_session = shell
-            .spawn(timeout, environment.iter().map_into(), width, height)
-            .wrap_err("could not start shell")?;
+impl ShellStart<'_> {
+    /// Spawns the shell with driver `D`, retrying up to `spawn_retries` times.
+    fn spawn<D: Spawn>(&self) -> color_eyre::Result<D> {
+        // The default, sandbox's, and proxy's variables come first so they can be overridden.
+        let sandbox_environment = self.sandbox.map(Sandbox::environment).unwrap_or_default();
+        let proxy_environment = self.proxy.map(Proxy::environment).unwrap_or_default();
+        // Read by the tmux backend, and not passed on to the shell.
+        #[cfg(unix)]
+        let tmux_session = self
+            .tmux_session
+            .as_deref()
+            .map(|session| (OsStr::new(spawn::tmux::SESSION_ENV), OsStr::new(session)));
+        #[cfg(not(unix))]
+        let tmux_session = None;
+        let environment = self
+            .default_environment
+            .iter()
+            .map(|(name, value)| (OsStr::new(name), OsStr::new(value)))
+            .chain(
+                sandbox_environment
+                    .iter()
+                    .map(|(name, value)| (*name, value.as_os_str())),
+            )
+            .chain(
+                proxy_environment
+                    .iter()
+                    .map(|(name, value)| (OsStr::new(name), OsStr::new(value))),
+            )
+            .chain(
+                self.environment
+                    .iter()
+                    .map(|EnvVar { name, value }| (OsStr::new(name), OsStr::new(value))),
+            )
+            .chain(tmux_session);
 
-        let events = run::instructions(
-            &value.instructions,
-            &prompt,
-            &secondary_prompt,
-            type_speed,
-            &line_split,
-            &mut shell_session,
-        )
-        .wrap_err("error running instructions")?;
-        shell_session.quit().wrap_err("could not exit shell")?;
+        // The shell may fail to start or show its prompt in slow environments,
+        // like containers which are still starting.
+        let mut attempt = 0;
+        loop {
+            let result = self.shell.clone().spawn::<D, _, _, _>(
+                environment.clone(),
+                spawn::Options {
+                    wrapper: &self.shell_wrapper.0,
+                    timeout: self.timeout,
+                    terminal: self.terminal,
+                    disable_history: self.disable_history,
+                    disable_history_expansion: self.disable_history_expansion,
+                    keep_prompt: self.capture_prompt,
+                    binary_output: self.binary_output,
+                },
+            );
+            match result {
+                Err(error) if attempt < self.spawn_retries => {
+                    attempt += 1;
+                    eprintln!(
+                        "warning: could not start shell, retrying ({attempt}/{}): {error}",
+                        self.spawn_retries
+                    );
+                    thread::sleep(self.spawn_retry_delay);
+                }
+                result => break result.wrap_err("could not start shell"),
+            }
+        }
+    }
+}
+
+/// Everything needed to run a script's instructions in a shell session.
+struct Record<'a> {
+    instructions: &'a [Instruction],
+    start: ShellStart<'a>,
+    options: run::Options<'a>,
+}
 
-        let duration = events.last().map(|event| event.time);
+impl Record<'_> {
+    /// Runs the instructions with the driver for `backend`.
+    fn run_backend(
+        self,
+        backend: Backend,
+        replay_fixtures: Option<PathBuf>,
+    ) -> color_eyre::Result<(Vec<Event>, Option<i32>)> {
+        match backend {
+            Backend::Pty => self.run::<ShellSession<OsProcess, _>>(),
+            #[cfg(unix)]
+            Backend::Tmux => self.run::<ShellSession<spawn::tmux::Tmux, _>>(),
+            #[cfg(not(unix))]
+            Backend::Tmux => Err(eyre::eyre!("the tmux backend is only supported on unix")),
+            Backend::Replay => {
+                let replay_fixtures = replay_fixtures.ok_or(eyre::eyre!(
+                    "the replay backend requires replay fixtures to be provided"
+                ))?;
+                self.run_session(Replay::from_fixtures(&replay_fixtures)?)
+            }
+        }
+    }
 
-        let mut env: HashMap<_, _> = environment.into_iter().map_into().collect();
-        for env_var in environment_capture {
-            env.entry(env_var)
-                .or_insert_with_key(|key| std::env::var(key).unwrap_or_default());
+    /// Spawns the shell with driver `D`, runs the instructions, and quits the shell.
+    fn run<D: Spawn>(self) -> color_eyre::Result<(Vec<Event>, Option<i32>)> {
+        let spawn = Instant::now();
+        let shell_session = self.start.spawn::<D>()?;
+        if let Some(profile) = self.options.profile {
+            profile.add_spawn(spawn.elapsed());
         }
-        env.insert(String::from("SHELL"), shell_env);
 
-        Ok(Self {
-            header: asciicast::Header {
-                width,
-                height,
-                timestamp: Some(SystemTime::now()),
-                duration,
-                idle_time_limit: None,
-                command: None,
-                title,
-                env,
+        // The shell's prompt is only known once it has been detected.
+        let prompt_line = self
+            .start
+            .capture_prompt
+            .then(|| shell_session.first_prompt_line().map(String::from))
+            .flatten();
+        let Some(prompt_line) = prompt_line else {
+            return self.run_session(shell_session);
+        };
+        let prompt = format!("{prompt_line}{}", self.start.shell.displayed_prompt());
+        Record {
+            options: run::Options {
+                prompt: &prompt,
+                strip_prompt_line: !prompt_line.is_empty(),
+                ..self.options
             },
-            events,
-        })
+            ..self
+        }
+        .run_session(shell_session)
+    }
+
+    /// Runs the instructions in an already started session and quits the shell.
+    ///
+    /// Returns the events and the shell's exit status, if known.
+    fn run_session<D: SessionDriver>(
+        self,
+        mut shell_session: D,
+    ) -> color_eyre::Result<(Vec<Event>, Option<i32>)> {
+        let events = match self.options.dashboard {
+            Some(dashboard) => {
+                let events = run::instructions(
+                    self.instructions,
+                    self.options,
+                    &mut Watched::new(&mut shell_session, dashboard),
+                );
+                // Closed first so the error or warnings below are shown.
+                dashboard.close().wrap_err("could not close dashboard")?;
+                events
+            }
+            None => run::instructions(self.instructions, self.options, &mut shell_session),
+        }
+        .wrap_err("error running instructions")?;
+        let exit_status = shell_session.quit().wrap_err("could not exit shell")?;
+
+        for time in run::misplaced_prompts(&events, self.options.prompt) {
+            eprintln!(
+                "warning: prompt {:?} found in command output at {:.3}s, \
+                the shell's prompt may have been detected in the wrong place",
+                self.options.prompt,
+                time.as_secs_f64()
+            );
+        }
+
+        Ok((events, exit_status))
     }
 }
 
+/// Ends the `events` with an exit event, if the asciicast version supports it
+/// and the exit `status` is known.
+fn push_exit_event(events: &mut Vec<Event>, version: asciicast::Version, status: Option<i32>) {
+    if let (asciicast::Version::V3, Some(status)) = (version, status) {
+        let time = events.last().map(|event| event.time).unwrap_or_default();
+        events.push(Event::exit(time, status));
+    }
+}
+
+fn check_speed_factors(typing: f64, output: f64) -> color_eyre::Result<()> {
+    for (name, factor) in [("typing", typing), ("output", output)] {
+        eyre::ensure!(
+            factor.is_finite() && factor > 0.0,
+            "{name} speed factor must be a positive number, got {factor}"
+        );
+    }
+    Ok(())
+}
+
+/// Checks that the ways of showing the script's progress can be used together.
+fn check_modes(tui: bool, step: bool, ci: ci::Ci, present: bool) -> color_eyre::Result<()> {
+    eyre::ensure!(
+        !(tui && (step || ci.is_enabled())),
+        "the dashboard can't be shown while stepping through instructions or printing CI annotations"
+    );
+    eyre::ensure!(
+        !(present && (tui || step || ci.is_enabled())),
+        "a script can't be presented with the dashboard, while stepping through instructions, \
+            or printing CI annotations"
+    );
+    Ok(())
+}
+
+/// Environment variables for the asciicast's "env" header section.
+///
+/// `header_env` takes precedence over the other values, except for `shell`.
+fn header_environment(
+    environment: Vec<EnvVar>,
+    environment_capture: Vec<String>,
+    header_env: Vec<EnvVar>,
+    shell: String,
+) -> HashMap<String, String> {
+    let mut env: HashMap<_, _> = environment.into_iter().map_into().collect();
+    for env_var in environment_capture {
+        env.entry(env_var)
+            .or_insert_with_key(|key| std::env::var(key).unwrap_or_default());
+    }
+    env.extend(header_env.into_iter().map_into());
+    env.insert(String::from("SHELL"), shell);
+    env
+}
+
+/// The terminal size for running the `instructions`, choosing the width for `width: auto`
+/// and taking the missing dimensions from the current terminal.
+fn script_terminal_size(
+    width: Option<Width>,
+    height: Option<u16>,
+    instructions: &[Instruction],
+    prompt: &str,
+    secondary_prompt: &str,
+    shell: &Shell,
+) -> color_eyre::Result<(u16, u16)> {
+    let width = width.map(|width| match width {
+        Width::Columns(columns) => columns,
+        Width::Auto => auto_width(instructions, prompt, secondary_prompt, shell),
+    });
+    terminal_size(width, height).ok_or(eyre::eyre!(
+        "terminal width or height not provided and could not get terminal size"
+    ))
+}
+
+/// Narrowest width chosen by `width: auto`
+const MIN_AUTO_WIDTH: u16 = 80;
+
+/// Width which fits the longest line of the typed commands, including the prompt and
+/// line split, so they don't wrap. At least [`MIN_AUTO_WIDTH`].
+fn auto_width(
+    instructions: &[Instruction],
+    prompt: &str,
+    secondary_prompt: &str,
+    shell: &Shell,
+) -> u16 {
+    let prompt = console::measure_text_width(prompt);
+    let secondary_prompt = console::measure_text_width(secondary_prompt);
+    let line_split = console::measure_text_width(shell.line_split());
+    let longest = instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Command {
+                command, hidden, ..
+            } if !hidden => Some(command),
+            Instruction::Interactive { command, .. } => Some(command),
+            _ => None,
+        })
+        .flat_map(|command| {
+            let lines = match command {
+                Command::SingleLine(line) => vec![line.as_str()],
+                Command::MultiLine(lines) => lines.iter().map(String::as_str).collect(),
+                Command::Control(_) => Vec::new(),
+            };
+            let last = lines.len().saturating_sub(1);
+            lines.into_iter().enumerate().map(move |(index, line)| {
+                let prefix = if index == 0 { prompt } else { secondary_prompt };
+                let suffix = if index == last { 0 } else { line_split };
+                prefix + console::measure_text_width(line) + suffix
+            })
+        })
+        .max()
+        .unwrap_or_default();
+    // Room for the cursor after the last char.
+    u16::try_from(longest + 1)
+        .unwrap_or(u16::MAX)
+        .max(MIN_AUTO_WIDTH)
+}
+
+/// The given size, with the current terminal's size for the missing dimensions.
+///
+/// Read from stderr, as stdout may be the asciicast.
 fn terminal_size(width: Option<u16>, height: Option<u16>) -> Option<(u16, u16)> {
     match (width, height) {
         (Some(width), Some(height)) => Some((width, height)),
-        (None, _) | (_, None) => Term::stdout()
+        (None, _) | (_, None) => Term::stderr()
             .size_checked()
             .map(|(rows, columns)| (width.unwrap_or(columns), height.unwrap_or(rows))),
     }
 }
 
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Args, Deserialize, Debug, Clone)]
 pub struct Settings {
-    /// Terminal width
+    /// Terminal width, or "auto" to fit the longest line of the typed commands
     ///
     /// Default is the width of the current terminal
-    #[arg(long)]
+    #[arg(long, value_parser = de::width::parse)]
     #[serde(default)]
-    width: Option<u16>,
+    width: Option<Width>,
 
     /// Terminal height
     ///
@@ -138,6 +722,36 @@ pub struct Settings {
     #[serde(default)]
     title: Option<String>,
 
+    /// Variables to expand in the commands, keys, markers, title, and prompt,
+    /// where they are referenced as `${NAME}`
+    ///
+    /// If there are duplicates, the last value will take precedent
+    #[arg(long = "var", value_name = "NAME=VALUE")]
+    #[serde(default, deserialize_with = "de::env_map::deserialize")]
+    variables: Vec<EnvVar>,
+
+    /// Files to download before running the script, cached across runs
+    ///
+    /// Only settable in the input file
+    #[arg(skip)]
+    #[serde(default)]
+    fetch: Vec<fetch::Fetch>,
+
+    /// Files or directories created by the script to copy to the `--artifacts-dir` after it
+    /// runs, i.e. a generated config to show next to the asciicast
+    ///
+    /// Relative paths are relative to the directory the shell starts in,
+    /// and paths starting with "~/" to its home directory, the sandbox's if enabled
+    #[arg(long, value_name = "PATH")]
+    #[serde(default)]
+    artifacts: Vec<PathBuf>,
+
+    /// Directory to copy the `--artifacts` to, created if it doesn't exist,
+    /// defaults to "artifacts"
+    #[arg(long, value_name = "DIR")]
+    #[serde(default)]
+    artifacts_dir: Option<PathBuf>,
+
     /// Shell to use for running commands
     ///
     /// Will be listed in the asciicast's "env" header section as "SHELL"
@@ -148,6 +762,38 @@ pub struct Settings {
     #[serde(default)]
     shell: Shell,
 
+    /// Program and arguments to run the shell with, i.e. to run it in the project's devshell
+    ///
+    /// The shell's program is added to the end, and its prompt is still set,
+    /// i.e. "direnv exec ." runs "direnv exec . bash".
+    /// "nix" is short for "nix develop --command"
+    #[arg(long, value_name = "COMMAND", value_parser = de::shell_wrapper::parse)]
+    #[serde(default)]
+    shell_wrapper: Option<ShellWrapper>,
+
+    /// Backend used to run the shell
+    ///
+    /// "pty" runs the shell in a pseudo-terminal owned by autocast,
+    /// "tmux" runs the shell in a detached tmux pane,
+    /// "replay" does not run a shell and replays output from `--replay-fixtures`
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(default)]
+    backend: Backend,
+
+    /// Existing tmux session to run the shell in a new window of, for the tmux backend
+    ///
+    /// The window is created on the default tmux server, so the shell starts with
+    /// the session's environment.
+    /// By default the shell runs on a new tmux server of its own
+    #[arg(long, value_name = "SESSION")]
+    #[serde(default)]
+    tmux_session: Option<String>,
+
+    /// YAML file mapping commands to their output for the replay backend
+    #[arg(long, value_name = "PATH")]
+    #[serde(default)]
+    replay_fixtures: Option<PathBuf>,
+
     /// Environment variables to use in the shell process
     ///
     /// Will be listed in the asciicast's "env" header section
@@ -171,6 +817,126 @@ pub struct Settings {
     #[serde(default)]
     environment_capture: Vec<String>,
 
+    /// Value of "SHELL" in the asciicast's "env" header section
+    ///
+    /// Default is the path of the shell program, does not change the shell which is run
+    #[arg(long, value_name = "VALUE")]
+    #[serde(default)]
+    header_shell: Option<String>,
+
+    /// Extra values for the asciicast's "env" header section,
+    /// which are not set in the shell process
+    ///
+    /// Take precedence over `--environment` and `--environment-capture` values
+    ///
+    /// Use `--header-shell` to set "SHELL"
+    #[arg(long, value_name = "NAME=VALUE")]
+    #[serde(default, deserialize_with = "de::env_map::deserialize")]
+    header_env: Vec<EnvVar>,
+
+    /// Prevent the shell from writing the commands run to the user's history file
+    ///
+    /// Only supported by the built-in shells, python requires version 3.13 or newer
+    #[arg(long)]
+    #[serde(default)]
+    disable_history: bool,
+
+    /// Don't set the default environment of the built-in shells
+    ///
+    /// By default, `PAGER` and `GIT_PAGER` are set to "cat" so commands don't wait for input,
+    /// and history expansion is disabled in bash and zsh so `!` is typed as is
+    #[arg(long)]
+    #[serde(default)]
+    disable_default_environment: bool,
+
+    /// Set `CLICOLOR_FORCE=1` so commands use colors even if they don't detect a terminal
+    #[arg(long)]
+    #[serde(default)]
+    force_color: bool,
+
+    /// Run the shell with a temporary home directory
+    ///
+    /// HOME and the XDG base directories are set to the temporary directory,
+    /// so the user's dotfiles can't change the shell's prompt, aliases, or colors
+    #[arg(long)]
+    #[serde(default)]
+    sandbox: bool,
+
+    /// Directory to copy into the sandbox's home directory, implies `--sandbox`
+    #[arg(long, value_name = "DIR")]
+    #[serde(default)]
+    sandbox_template: Option<PathBuf>,
+
+    /// Refuse to run commands which match a dangerous command pattern,
+    /// i.e. "rm -rf", "curl ... | sh", or "dd of=",
+    /// unless the instruction has `allow_dangerous: true`
+    ///
+    /// Used to protect CI runners from typos in contributed scripts
+    #[arg(long)]
+    #[serde(default)]
+    safe: bool,
+
+    /// Regex of commands to refuse in `--safe` mode, in addition to the built-in patterns
+    #[arg(long, value_name = "REGEX")]
+    #[serde(default)]
+    dangerous_commands: Vec<String>,
+
+    /// Run an HTTP proxy for the shell, set with the `HTTP_PROXY` and `HTTPS_PROXY` variables,
+    /// which records plain HTTP responses to `--proxy-fixtures` or replays them
+    ///
+    /// Used to make commands which make network requests deterministic and work offline
+    ///
+    /// HTTPS requests can't be recorded, they are refused and fail the recording
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(default)]
+    proxy: proxy::Mode,
+
+    /// YAML file the proxy records responses to, or replays them from
+    #[arg(long, value_name = "PATH")]
+    #[serde(default)]
+    proxy_fixtures: Option<PathBuf>,
+
+    /// Version of the asciicast file format to create
+    ///
+    /// Version 3 files end with an exit event containing the shell's exit status
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(default)]
+    asciicast_version: asciicast::Version,
+
+    /// Exit status to use in the exit event of asciicast v3 files instead of the shell's
+    #[arg(long, value_name = "STATUS", allow_negative_numbers = true)]
+    #[serde(default)]
+    exit_code: Option<i32>,
+
+    /// Program to edit the events with before they are written
+    ///
+    /// Each event is written to the program's stdin as a line of JSON, i.e. [1.5, "o", "hello"],
+    /// where the time is since the start of the asciicast
+    ///
+    /// The program's stdout is parsed the same way and replaces the events
+    #[arg(long, value_name = "COMMAND")]
+    #[serde(default)]
+    post_edit: Option<String>,
+
+    /// Collapse output which rewrites the same line, like progress bars and spinners,
+    /// into the last rewrite, reducing the number of events
+    #[arg(long)]
+    #[serde(default)]
+    collapse_rewrites: bool,
+
+    /// When collapsing rewrites, also keep every nth rewrite, 0 only keeps the last
+    #[arg(long, value_name = "N", default_value_t)]
+    #[serde(default)]
+    collapse_keep_every: u32,
+
+    /// Maximum time between events, longer pauses are shortened to it
+    ///
+    /// Unlike an idle time limit in the asciicast's header, which not all players honor,
+    /// the times of the events are changed, i.e. "2s"
+    #[arg(long, value_name = "DURATION", value_parser = de::duration::parse)]
+    #[serde(default, with = "de::duration::option")]
+    max_idle: Option<Duration>,
+
     /// Default time between key presses when writing commands
     ///
     /// Can be specified in seconds (s), milliseconds (ms), or microseconds (us)
@@ -180,11 +946,105 @@ pub struct Settings {
     #[serde(default = "default_type_speed", with = "de::duration")]
     type_speed: Duration,
 
+    /// Pause between typing the last character of a command and pressing enter,
+    /// like someone checking the command before running it
+    #[arg(long, value_name = "DELAY", default_value = "0s", value_parser = de::duration::parse)]
+    #[serde(default, with = "de::duration")]
+    pre_enter_delay: Duration,
+
+    /// Take twice as long to type characters which are two columns wide,
+    /// i.e. CJK characters and emoji, so text in those scripts is paced more naturally
+    #[arg(long)]
+    #[serde(default)]
+    double_width_pacing: bool,
+
+    /// Speed up typing by this factor
+    ///
+    /// The time between key presses is divided by the factor, i.e. "2" types twice as fast
+    #[arg(long, value_name = "FACTOR", default_value_t = DEFAULT_SPEED_FACTOR)]
+    #[serde(default = "default_speed_factor")]
+    typing_speed_factor: f64,
+
+    /// Speed up command output by this factor
+    ///
+    /// The time between output from the shell is divided by the factor,
+    /// i.e. "0.5" plays output at half speed
+    #[arg(long, value_name = "FACTOR", default_value_t = DEFAULT_SPEED_FACTOR)]
+    #[serde(default = "default_speed_factor")]
+    output_speed_factor: f64,
+
+    /// Merge the output events at the start of the asciicast which occur within one
+    /// keystroke of the first, like the initial prompt and the first typed character
+    ///
+    /// Useful as they would otherwise be separate events,
+    /// which may flash in players that render event by event
+    #[arg(long)]
+    #[serde(default)]
+    coalesce_leading_events: bool,
+
+    /// What to do with output the shell produced after the last instruction,
+    /// like messages from background jobs
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(default)]
+    trailing_output: TrailingOutput,
+
+    /// What to do with command output which is not valid UTF-8, like binary files
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(default)]
+    binary_output: BinaryOutput,
+
+    /// What to do with the line break the terminal echoes at the start of a command's output,
+    /// after the line break of the typed command
+    ///
+    /// The Windows console echoes the line ending sent after each command,
+    /// which players show as a doubled blank line
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(default)]
+    echoed_newline: EchoedNewline,
+
+    /// SGR parameters used to style stderr output of commands with `highlight_stderr`,
+    /// i.e. "31" for red or "1;33" for bold yellow
+    #[arg(long, value_name = "SGR", default_value = DEFAULT_STDERR_STYLE)]
+    #[serde(default = "default_stderr_style")]
+    stderr_style: String,
+
+    /// Add markers when interactive commands enter and exit the alternate screen,
+    /// like TUIs such as vim or htop do
+    #[arg(long)]
+    #[serde(default)]
+    alt_screen_markers: bool,
+
+    /// Clear the screen after interactive commands exit the alternate screen
+    #[arg(long)]
+    #[serde(default)]
+    clear_after_alt_screen: bool,
+
     /// The shell prompt to use in the asciicast output
     #[arg(long, default_value = DEFAULT_PROMPT)]
     #[serde(default = "default_prompt")]
     prompt: String,
 
+    /// A colored prompt to use in the asciicast output, instead of `--prompt`
+    #[arg(long, value_enum)]
+    #[serde(default)]
+    prompt_preset: Option<PromptPreset>,
+
+    /// Use the shell's actual prompt, captured when it is first detected,
+    /// instead of `--prompt`
+    ///
+    /// For bash, the prompt set by the user's dotfiles is kept, see `--sandbox` to avoid them
+    #[arg(long)]
+    #[serde(default)]
+    capture_prompt: bool,
+
+    /// Wrap typed commands which are wider than the terminal onto multiple lines,
+    /// using the line split and secondary prompt like a multiline command
+    ///
+    /// Only changes how commands are shown, they are still sent to the shell as one line
+    #[arg(long)]
+    #[serde(default)]
+    wrap_commands: bool,
+
     /// The shell secondary prompt to use in the asciicast output
     #[arg(long, default_value = DEFAULT_SECONDARY_PROMPT)]
     #[serde(default = "default_secondary_prompt")]
@@ -198,6 +1058,72 @@ pub struct Settings {
     #[arg(long, default_value = DEFAULT_TIMEOUT, value_parser = de::duration::parse)]
     #[serde(default = "default_timeout", with = "de::duration")]
     timeout: Duration,
+
+    /// Number of times to retry starting the shell and detecting its prompt if it fails
+    ///
+    /// Useful in slow environments, like containers which are still starting
+    #[arg(long, value_name = "RETRIES", default_value_t)]
+    #[serde(default)]
+    spawn_retries: u32,
+
+    /// Time to wait before retrying to start the shell
+    #[arg(long, value_name = "DELAY", default_value = DEFAULT_SPAWN_RETRY_DELAY, value_parser = de::duration::parse)]
+    #[serde(default = "default_spawn_retry_delay", with = "de::duration")]
+    spawn_retry_delay: Duration,
+
+    /// Disable the terminal's software flow control (IXON),
+    /// so sending ^S and ^Q reaches the program instead of pausing and resuming output
+    ///
+    /// Only supported by the pty backend on unix
+    #[arg(long)]
+    #[serde(default)]
+    disable_flow_control: bool,
+
+    /// Disable the terminal echoing control characters as "^C" (ECHOCTL)
+    ///
+    /// Only supported by the pty backend on unix
+    #[arg(long)]
+    #[serde(default)]
+    disable_echoctl: bool,
+
+    /// Pause before each instruction, showing it and the last output,
+    /// and wait for a key to run it, skip it, or abort
+    ///
+    /// Useful for debugging scripts, requires a terminal
+    ///
+    /// Only an option, so a script can't be committed with it turned on
+    #[arg(long)]
+    #[serde(skip)]
+    step: bool,
+
+    /// Print annotations for a CI service, grouping the output of each instruction
+    /// with the time it took and marking the instruction which failed
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(default)]
+    ci: ci::Ci,
+
+    /// Show a dashboard while recording, with the shell's live output
+    /// next to the status and time of each instruction, instead of progress bars
+    ///
+    /// Requires a terminal
+    #[arg(long)]
+    #[serde(default)]
+    tui: bool,
+
+    /// After running, print how long each instruction waited for the shell's prompt,
+    /// or for its output to settle, compared to the timeout
+    ///
+    /// Use it to tune `--timeout` from measurements, i.e. after intermittent timeouts in CI.
+    /// On Linux, the CPU time and peak memory of the commands are also listed
+    #[arg(long)]
+    #[serde(default)]
+    timing_report: bool,
+
+    /// After running, print where the time was spent: spawning the shell, typing,
+    /// waiting for each instruction's output, post-processing, and writing the output
+    #[arg(long)]
+    #[serde(default)]
+    profile_run: bool,
 }
 
 const DEFAULT_TYPE_SPEED_MILLIS: u64 = 100;
@@ -206,6 +1132,16 @@ const fn default_type_speed() -> Duration {
     Duration::from_millis(DEFAULT_TYPE_SPEED_MILLIS)
 }
 
+const DEFAULT_SPEED_FACTOR: f64 = 1.0;
+const fn default_speed_factor() -> f64 {
+    DEFAULT_SPEED_FACTOR
+}
+
+const DEFAULT_STDERR_STYLE: &str = "31";
+fn default_stderr_style() -> String {
+    String::from(DEFAULT_STDERR_STYLE)
+}
+
 const DEFAULT_PROMPT: &str = "$ ";
 fn default_prompt() -> String {
     String::from(DEFAULT_PROMPT)
@@ -222,6 +1158,12 @@ const fn default_timeout() -> Duration {
     Duration::from_secs(DEFAULT_TIMEOUT_SECS)
 }
 
+const DEFAULT_SPAWN_RETRY_DELAY_SECS: u64 = 1;
+const DEFAULT_SPAWN_RETRY_DELAY: &str = "1s";
+const fn default_spawn_retry_delay() -> Duration {
+    Duration::from_secs(DEFAULT_SPAWN_RETRY_DELAY_SECS)
+}
+
 impl Merge for Settings {
     /// Merges `other` into self, `other` takes priority, ignoring defaults in other
     ///
@@ -230,45 +1172,158 @@ impl Merge for Settings {
     /// ```
     /// let mut settings = Settings::default();
     /// let other = Settings {
-    ///     width: Some(100),
+    ///     width: Some(Width::Columns(100)),
     ///     ..Default::default()
     /// }
     ///
     /// settings.merge(other);
-    /// assert_eq!(settings.width, Some(100));
+    /// assert_eq!(settings.width, Some(Width::Columns(100)));
     /// ```
+    // Most of the lines destructure the settings, so none are missed.
+    #[allow(clippy::too_many_lines)]
     fn merge(&mut self, other: Self) {
         let Self {
             width,
             height,
             title,
+            variables,
+            fetch,
+            artifacts,
+            artifacts_dir,
             shell,
+            shell_wrapper,
+            backend,
+            tmux_session,
+            replay_fixtures,
             environment,
             environment_capture,
+            header_shell,
+            header_env,
+            disable_history,
+            disable_default_environment,
+            force_color,
+            sandbox,
+            sandbox_template,
+            safe,
+            dangerous_commands,
+            proxy,
+            proxy_fixtures,
+            asciicast_version,
+            exit_code,
+            post_edit,
+            collapse_rewrites,
+            collapse_keep_every,
+            max_idle,
             type_speed,
+            pre_enter_delay,
+            double_width_pacing,
+            typing_speed_factor,
+            output_speed_factor,
+            coalesce_leading_events,
+            trailing_output,
+            binary_output,
+            echoed_newline,
+            stderr_style,
+            alt_screen_markers,
+            clear_after_alt_screen,
             prompt,
+            prompt_preset,
+            capture_prompt,
+            wrap_commands,
             secondary_prompt,
             timeout,
+            spawn_retries,
+            spawn_retry_delay,
+            disable_flow_control,
+            disable_echoctl,
+            step,
+            ci,
+            tui,
+            timing_report,
+            profile_run,
         } = other;
 
         self.width.merge(width);
         self.height.merge(height);
         self.title.merge(title);
+        self.variables.merge(variables);
+        self.fetch.merge(fetch);
+        self.artifacts.merge(artifacts);
+        self.artifacts_dir.merge(artifacts_dir);
         self.shell.merge(shell);
+        self.shell_wrapper.merge(shell_wrapper);
+        self.backend.merge(backend);
+        self.tmux_session.merge(tmux_session);
+        self.replay_fixtures.merge(replay_fixtures);
         self.environment.merge(environment);
         self.environment_capture.merge(environment_capture);
+        self.header_shell.merge(header_shell);
+        self.header_env.merge(header_env);
+        self.disable_history.merge(disable_history);
+        self.disable_default_environment
+            .merge(disable_default_environment);
+        self.force_color.merge(force_color);
+        self.sandbox.merge(sandbox);
+        self.sandbox_template.merge(sandbox_template);
+        self.safe.merge(safe);
+        self.dangerous_commands.merge(dangerous_commands);
+        self.proxy.merge(proxy);
+        self.proxy_fixtures.merge(proxy_fixtures);
+        self.asciicast_version.merge(asciicast_version);
+        self.exit_code.merge(exit_code);
+        self.post_edit.merge(post_edit);
+        self.collapse_rewrites.merge(collapse_rewrites);
+        if collapse_keep_every != 0 {
+            self.collapse_keep_every = collapse_keep_every;
+        }
+        self.max_idle.merge(max_idle);
         if type_speed != default_type_speed() {
             self.type_speed = type_speed;
         }
+        if pre_enter_delay != Duration::ZERO {
+            self.pre_enter_delay = pre_enter_delay;
+        }
+        self.double_width_pacing.merge(double_width_pacing);
+        if typing_speed_factor != DEFAULT_SPEED_FACTOR {
+            self.typing_speed_factor = typing_speed_factor;
+        }
+        if output_speed_factor != DEFAULT_SPEED_FACTOR {
+            self.output_speed_factor = output_speed_factor;
+        }
+        self.coalesce_leading_events.merge(coalesce_leading_events);
+        self.trailing_output.merge(trailing_output);
+        self.binary_output.merge(binary_output);
+        self.echoed_newline.merge(echoed_newline);
+        if stderr_style != DEFAULT_STDERR_STYLE {
+            self.stderr_style = stderr_style;
+        }
+        self.alt_screen_markers.merge(alt_screen_markers);
+        self.clear_after_alt_screen.merge(clear_after_alt_screen);
         if prompt != DEFAULT_PROMPT {
             self.prompt = prompt;
         }
+        self.prompt_preset.merge(prompt_preset);
+        self.capture_prompt.merge(capture_prompt);
+        self.wrap_commands.merge(wrap_commands);
         if secondary_prompt != DEFAULT_SECONDARY_PROMPT {
             self.secondary_prompt = secondary_prompt;
         }
         if timeout != default_timeout() {
             self.timeout = timeout;
         }
+        if spawn_retries != 0 {
+            self.spawn_retries = spawn_retries;
+        }
+        if spawn_retry_delay != default_spawn_retry_delay() {
+            self.spawn_retry_delay = spawn_retry_delay;
+        }
+        self.disable_flow_control.merge(disable_flow_control);
+        self.disable_echoctl.merge(disable_echoctl);
+        self.step.merge(step);
+        self.ci.merge(ci);
+        self.tui.merge(tui);
+        self.timing_report.merge(timing_report);
+        self.profile_run.merge(profile_run);
     }
 }
 
@@ -278,13 +1333,61 @@ impl Default for Settings {
             width: None,
             height: None,
             title: None,
+            variables: Vec::new(),
+            fetch: Vec::new(),
+            artifacts: Vec::new(),
+            artifacts_dir: None,
             shell: Shell::default(),
+            shell_wrapper: None,
+            backend: Backend::default(),
+            tmux_session: None,
+            replay_fixtures: None,
             environment: Vec::new(),
             environment_capture: Vec::new(),
+            header_shell: None,
+            header_env: Vec::new(),
+            disable_history: false,
+            disable_default_environment: false,
+            force_color: false,
+            sandbox: false,
+            sandbox_template: None,
+            safe: false,
+            dangerous_commands: Vec::new(),
+            proxy: proxy::Mode::default(),
+            proxy_fixtures: None,
+            asciicast_version: asciicast::Version::default(),
+            exit_code: None,
+            post_edit: None,
+            collapse_rewrites: false,
+            collapse_keep_every: 0,
+            max_idle: None,
             type_speed: default_type_speed(),
+            pre_enter_delay: Duration::ZERO,
+            double_width_pacing: false,
+            typing_speed_factor: DEFAULT_SPEED_FACTOR,
+            output_speed_factor: DEFAULT_SPEED_FACTOR,
+            coalesce_leading_events: false,
+            trailing_output: TrailingOutput::default(),
+            binary_output: BinaryOutput::default(),
+            echoed_newline: EchoedNewline::default(),
+            stderr_style: default_stderr_style(),
+            alt_screen_markers: false,
+            clear_after_alt_screen: false,
             prompt: default_prompt(),
+            prompt_preset: None,
+            capture_prompt: false,
+            wrap_commands: false,
             secondary_prompt: default_secondary_prompt(),
             timeout: default_timeout(),
+            spawn_retries: 0,
+            spawn_retry_delay: default_spawn_retry_delay(),
+            disable_flow_control: false,
+            disable_echoctl: false,
+            step: false,
+            ci: ci::Ci::Off,
+            tui: false,
+            timing_report: false,
+            profile_run: false,
         }
     }
 }
@@ -293,13 +1396,19 @@ impl Default for Settings {
 enum Shell {
     #[default]
     Bash,
+    Zsh,
+    Nu,
+    Cmd,
     Python,
     #[value(skip)]
     Custom {
         program: String,
         args: Vec<String>,
-        prompt: String,
+        /// The first prompt is the primary prompt
+        prompts: Vec<String>,
         line_split: String,
+        /// Sent between the lines of a multiline command
+        line_continuation: String,
         quit_command: Option<String>,
     },
 }
@@ -314,6 +1423,9 @@ impl Display for Shell {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::Bash => f.write_str("bash"),
+            Self::Zsh => f.write_str("zsh"),
+            Self::Nu => f.write_str("nu"),
+            Self::Cmd => f.write_str("cmd"),
             Self::Python => f.write_str("python"),
             Self::Custom { program, args, .. } => f.write_str(
                 &iter::once(program)
@@ -340,51 +1452,256 @@ impl Merge for Shell {
 }
 
 impl Shell {
+    /// Text to put before and after a command so each line of its stderr is marked,
+    /// see [`run::Options::stderr_wrapper`].
+    ///
+    /// As stderr is piped through a loop, it is no longer a terminal, so programs may stop
+    /// coloring it, and its lines may be reordered relative to stdout. Interactive programs
+    /// may also behave differently, so the wrapper is only used by commands which opt in.
+    ///
+    /// Only supported by bash and zsh.
+    fn stderr_wrapper(&self) -> Option<(&'static str, &'static str)> {
+        // stderr is swapped with stdout to pipe it through the loop, which marks each line.
+        // The command's exit status is kept for `$?`.
+        match self {
+            Self::Bash => Some((
+                "{ { ",
+                " ; } 2>&1 1>&3 3>&- | while IFS= read -r line || [ -n \"$line\" ]; \
+                do printf '\\016%s\\017\\n' \"$line\"; done; (exit \"${PIPESTATUS[0]}\"); } 3>&1",
+            )),
+            Self::Zsh => Some((
+                "{ { ",
+                " ; } 2>&1 1>&3 3>&- | while IFS= read -r line || [ -n \"$line\" ]; \
+                do printf '\\016%s\\017\\n' \"$line\"; done; (exit \"${pipestatus[1]}\"); } 3>&1",
+            )),
+            Self::Nu | Self::Cmd | Self::Python | Self::Custom { .. } => None,
+        }
+    }
+
     fn line_split(&self) -> &str {
         match self {
-            Self::Bash | Self::Python => " \\",
+            Self::Bash | Self::Zsh | Self::Python => " \\",
+            // Nushell continues commands over multiple lines inside brackets instead.
+            Self::Nu => "",
+            Self::Cmd => " ^",
             Self::Custom { line_split, .. } => line_split,
         }
     }
 
+    /// What is sent to the shell between the lines of a multiline command,
+    /// as opposed to the [`line_split`](Self::line_split) which is shown.
+    fn line_continuation(&self) -> &str {
+        match self {
+            Self::Bash | Self::Zsh | Self::Nu | Self::Cmd | Self::Python => " ",
+            Self::Custom {
+                line_continuation, ..
+            } => line_continuation,
+        }
+    }
+
     fn program(&self) -> &str {
         match self {
             Self::Bash => "bash",
+            Self::Zsh => "zsh",
+            Self::Nu => "nu",
+            Self::Cmd => "cmd",
             Self::Python => "python",
             Self::Custom { program, .. } => program,
         }
     }
 
-    fn spawn<I, K, V>(
-        self,
-        timeout: Duration,
-        environment: I,
-        width: u16,
-        height: u16,
-    ) -> color_eyre::Result<ShellSession>
+    /// Path of the shell's program, if it can be found, otherwise the program.
+    fn path(&self) -> String {
+        let program = self.program();
+        which::which(program).map_or_else(
+            |_| String::from(program),
+            |program| program.to_string_lossy().into_owned(),
+        )
+    }
+
+    /// The part of the shell's prompt which is shown, used when capturing the prompt.
+    ///
+    /// The known prompts of bash, zsh, nu, and cmd are added to the end of the user's prompt,
+    /// so they are not shown.
+    fn displayed_prompt(&self) -> &str {
+        match self {
+            Self::Bash | Self::Zsh | Self::Nu | Self::Cmd => "",
+            Self::Python => ">>> ",
+            Self::Custom { prompts, .. } => &prompts[0],
+        }
+    }
+
+    /// Spawns the shell with driver `D`.
+    ///
+    /// Custom shells only use the wrapper, timeout, terminal, and binary output `options`.
+    fn spawn<D, I, K, V>(self, environment: I, options: spawn::Options) -> color_eyre::Result<D>
     where
+        D: Spawn,
         I: IntoIterator<Item = (K, V)>,
         K: AsRef<OsStr>,
         V: AsRef<OsStr>,
     {
         match self {
-            Self::Bash => spawn::bash(timeout, environment, width, height),
-            Self::Python => spawn::python(timeout, environment, width, height),
+            Self::Bash => spawn::bash(environment, options),
+            Self::Zsh => spawn::zsh(environment, options),
+            Self::Nu => spawn::nu(environment, options),
+            Self::Cmd => spawn::cmd(environment, options),
+            Self::Python => spawn::python(environment, options),
             Self::Custom {
                 program,
                 args,
-                prompt,
+                prompts,
                 line_split: _,
+                line_continuation: _,
                 quit_command,
             } => {
-                let mut command = process::Command::new(program);
+                let mut command = spawn::command(&program, options.wrapper);
                 command.args(args).envs(environment);
-                ShellSession::spawn(command, width, height, prompt, quit_command, timeout)
+                D::spawn(
+                    command,
+                    options.terminal,
+                    prompts,
+                    quit_command,
+                    options.timeout,
+                    options.binary_output,
+                )
             }
         }
     }
 }
 
+/// Program and arguments the shell is run with, i.e. `nix develop --command`,
+/// see [`spawn::command()`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct ShellWrapper(Vec<String>);
+
+impl<'de> Deserialize<'de> for ShellWrapper {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(de::shell_wrapper::Visitor)
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Backend {
+    /// Run the shell in a pseudo-terminal
+    #[default]
+    Pty,
+    /// Run the shell in a detached tmux pane
+    Tmux,
+    /// Replay canned output for each command without running a shell
+    Replay,
+}
+
+/// How to handle shell output which is not valid UTF-8, like binary files
+#[derive(ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum BinaryOutput {
+    /// Replace invalid data with the replacement character
+    #[default]
+    Lossy,
+    /// Replace each invalid byte with an escape, i.e. "\x89"
+    Escape,
+    /// Show the output in the format of `hexdump -C`
+    Hexdump,
+    /// Return an error
+    Error,
+}
+
+impl Merge for BinaryOutput {
+    fn merge(&mut self, other: Self) {
+        if other != Self::default() {
+            *self = other;
+        }
+    }
+}
+
+/// What to do with output the shell produced after the last instruction
+#[derive(ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum TrailingOutput {
+    /// Discard the output with a warning
+    #[default]
+    Warn,
+    /// Add the output to the end of the asciicast, before the shell's last prompt
+    Append,
+    /// Discard the output
+    Discard,
+}
+
+impl Merge for TrailingOutput {
+    fn merge(&mut self, other: Self) {
+        if other != Self::default() {
+            *self = other;
+        }
+    }
+}
+
+/// What to do with the line break the terminal echoes at the start of a command's output
+#[derive(ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum EchoedNewline {
+    /// Strip it on Windows, keep it on other platforms
+    #[default]
+    Auto,
+    /// Keep it in the output
+    Keep,
+    /// Strip it from the output
+    Strip,
+}
+
+impl EchoedNewline {
+    /// Whether the echoed line break is stripped on this platform.
+    fn strips(self) -> bool {
+        match self {
+            Self::Auto => cfg!(windows),
+            Self::Keep => false,
+            Self::Strip => true,
+        }
+    }
+}
+
+/// Colored prompts, so they don't have to be written with escape sequences
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum PromptPreset {
+    /// Bold cyan "❯"
+    Arrow,
+    /// Green user and host, blue directory, and "$"
+    Classic,
+    /// Dim "$"
+    Minimal,
+    /// Directory in a blue segment, ending in an arrow, needs a powerline font
+    Powerline,
+}
+
+impl PromptPreset {
+    fn prompt(self) -> String {
+        String::from(match self {
+            Self::Arrow => "\x1b[1;36m❯\x1b[0m ",
+            Self::Classic => "\x1b[1;32muser@demo\x1b[0m:\x1b[1;34m~\x1b[0m$ ",
+            Self::Minimal => "\x1b[2m$\x1b[0m ",
+            Self::Powerline => "\x1b[30;44m ~ \x1b[0;34m\u{e0b0}\x1b[0m ",
+        })
+    }
+}
+
+impl Merge for EchoedNewline {
+    fn merge(&mut self, other: Self) {
+        if other != Self::default() {
+            *self = other;
+        }
+    }
+}
+
+impl Merge for Backend {
+    fn merge(&mut self, other: Self) {
+        if other != Self::default() {
+            *self = other;
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 struct EnvVar {
     name: String,
@@ -427,16 +1744,72 @@ enum Instruction {
         hidden: bool,
         #[serde(default, with = "de::duration::option")]
         type_speed: Option<Duration>,
+        #[serde(default)]
+        confirm: Vec<String>,
+        /// Style stderr by running the command with [`Shell::stderr_wrapper()`],
+        /// which changes its output, see its limits
+        #[serde(default)]
+        highlight_stderr: bool,
+        #[serde(default)]
+        output_filter: Option<OutputFilter>,
+        /// Run the command even if it is dangerous, see `--safe`
+        #[serde(default)]
+        allow_dangerous: bool,
     },
     Interactive {
         command: Command,
         keys: Vec<Key>,
         #[serde(default, with = "de::duration::option")]
         type_speed: Option<Duration>,
+        /// Run the command even if it is dangerous, see `--safe`
+        #[serde(default)]
+        allow_dangerous: bool,
+    },
+    /// Runs a hidden command and stores its trimmed output in the variable `var`,
+    /// which later instructions reference as `${var}`
+    Capture {
+        command: Command,
+        var: String,
+        /// Run the command even if it is dangerous, see `--safe`
+        #[serde(default)]
+        allow_dangerous: bool,
+    },
+    /// Runs the `then` instructions if each variable matches its regex,
+    /// otherwise the `otherwise` instructions, see [`Instruction::branch()`]
+    If {
+        #[serde(with = "de::var_matches")]
+        var_matches: Vec<(String, Regex)>,
+        then: Vec<Instruction>,
+        #[serde(default, rename = "else")]
+        otherwise: Vec<Instruction>,
     },
     Wait(#[serde(with = "de::duration")] Duration),
+    Settle(#[serde(with = "de::duration")] Duration),
     Marker(String),
+    /// Sets the terminal's title, which renderers with window chrome can show as a caption
+    Title(String),
+    /// Resizes the terminal, i.e. to emulate zooming into a pane,
+    /// sizes which aren't given are the script's `width` or `height`
+    Resize {
+        width: Option<u16>,
+        height: Option<u16>,
+    },
     Clear,
+    /// Path of a script whose instructions are included in place of this one,
+    /// see [`Script::resolve_includes()`]
+    Include(PathBuf),
+}
+
+/// Lines to remove from a command's output.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+struct OutputFilter {
+    /// Lines matching any of these are removed
+    #[serde(default, with = "de::regex_set")]
+    drop: RegexSet,
+    /// If not empty, lines not matching any of these are removed
+    #[serde(default, with = "de::regex_set")]
+    keep: RegexSet,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -452,11 +1825,44 @@ impl<'de> Deserialize<'de> for Command {
     }
 }
 
+impl Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SingleLine(line) => f.write_str(line),
+            Self::MultiLine(lines) => f.write_str(&lines.join(" ")),
+            Self::Control(control) => {
+                let char = match u8::from(*control) {
+                    127 => '?',
+                    byte @ 0..=31 => char::from(byte + 0x40),
+                    byte => char::from(byte),
+                };
+                write!(f, "^{char}")
+            }
+        }
+    }
+}
+
+/// Terminal width setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Width {
+    Columns(u16),
+    /// Fit the longest line of the typed commands
+    Auto,
+}
+
+impl<'de> Deserialize<'de> for Width {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(de::width::Visitor)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum Key {
     Char(char),
     Control(ControlCode),
     Wait(Duration),
+    /// Text referencing variables, each char of which is a key once they are expanded
+    Text(String),
 }
 
 impl<'de> Deserialize<'de> for Key {
@@ -478,8 +1884,142 @@ impl<T> Merge for Option<T> {
     }
 }
 
+impl Merge for asciicast::Version {
+    fn merge(&mut self, other: Self) {
+        if other != Self::default() {
+            *self = other;
+        }
+    }
+}
+
+impl Merge for bool {
+    fn merge(&mut self, other: Self) {
+        *self |= other;
+    }
+}
+
 impl<T> Merge for Vec<T> {
     fn merge(&mut self, other: Self) {
         self.extend(other);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_from_toml() {
+        let script = Script::try_from_toml(
+            r#"
+            [settings]
+            width = 100
+            shell = "zsh"
+            timeout = "5s"
+
+            [[instructions]]
+            Command = { command = "ls", hidden = true }
+
+            [[instructions]]
+            Command = { command = ["echo a", "b"] }
+
+            [[instructions]]
+            Interactive = { command = { Control = "C" }, keys = ["q", "^D", { Wait = "1s" }] }
+
+            [[instructions]]
+            Wait = "500ms"
+
+            [[instructions]]
+            Marker = "done"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(script.settings.width, Some(Width::Columns(100)));
+        assert!(matches!(script.settings.shell, Shell::Zsh));
+        assert_eq!(script.settings.timeout, Duration::from_secs(5));
+        assert!(matches!(
+            &script.instructions[..],
+            [
+                Instruction::Command {
+                    command: Command::SingleLine(_),
+                    hidden: true,
+                    ..
+                },
+                Instruction::Command {
+                    command: Command::MultiLine(_),
+                    ..
+                },
+                Instruction::Interactive {
+                    command: Command::Control(ControlCode::EndOfText),
+                    keys,
+                    ..
+                },
+                Instruction::Wait(_),
+                Instruction::Marker(_),
+            ] if keys == &[
+                Key::Char('q'),
+                Key::Control(ControlCode::EndOfTransmission),
+                Key::Wait(Duration::from_secs(1)),
+            ]
+        ));
+    }
+
+    #[test]
+    fn try_from_json() {
+        let script = Script::try_from_json(
+            r#"{
+                "settings": { "width": "auto", "type_speed": "50ms" },
+                "instructions": [
+                    { "Command": { "command": "ls" } },
+                    { "Interactive": { "command": "vim", "keys": ["i", "^[", { "Wait": "1s" }] } },
+                    "Clear"
+                ]
+            }"#
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(script.settings.width, Some(Width::Auto));
+        assert_eq!(script.settings.type_speed, Duration::from_millis(50));
+        assert!(matches!(
+            &script.instructions[..],
+            [
+                Instruction::Command { .. },
+                Instruction::Interactive { keys, .. },
+                Instruction::Clear,
+            ] if keys.len() == 3
+        ));
+    }
+
+    #[test]
+    fn auto_width() {
+        let script = Script::try_from_yaml(
+            format!(
+                "
+                instructions:
+                  - !Command
+                    command: echo {long}
+                    hidden: true
+                  - !Command
+                    command:
+                      - echo {short}
+                      - {short}
+                  - !Command
+                    command: ^C
+                ",
+                long = "a".repeat(200),
+                short = "a".repeat(100),
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        // "$ echo ", 100 chars, " \", and the cursor.
+        assert_eq!(
+            super::auto_width(&script.instructions, "$ ", "> ", &Shell::Bash),
+            110
+        );
+        assert_eq!(
+            super::auto_width(&script.instructions[2..], "$ ", "> ", &Shell::Bash),
+            MIN_AUTO_WIDTH
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Copying files which a script creates out of the demo's environment after it runs,
+//! so they can be shown next to the asciicast.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::sandbox::copy_dir;
+
+/// Directory the artifacts are copied to if one isn't given
+pub const DEFAULT_DIR: &str = "artifacts";
+
+/// Copies each of the `artifacts` into `dir`, keeping their file names.
+///
+/// Paths starting with "~/" are in the `home` directory, the user's if there isn't a sandbox.
+/// Artifacts which can't be copied are warned about, so the asciicast is still created.
+pub fn copy(artifacts: &[PathBuf], dir: &Path, home: Option<&Path>) {
+    if artifacts.is_empty() {
+        return;
+    }
+    let home = home.map(Path::to_path_buf).or_else(dirs::home_dir);
+    for artifact in artifacts {
+        let from = match (artifact.strip_prefix("~"), &home) {
+            (Ok(path), Some(home)) => home.join(path),
+            _ => artifact.clone(),
+        };
+        match copy_artifact(&from, dir) {
+            Ok(to) => eprintln!("Copied artifact: {}", to.display()),
+            Err(error) => eprintln!(
+                "warning: could not copy artifact `{}`: {error}",
+                artifact.display()
+            ),
+        }
+    }
+}
+
+/// Copies the file or directory `from` into `dir`, returning where it was copied to.
+fn copy_artifact(from: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let name = from
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let to = dir.join(name);
+    fs::create_dir_all(dir)?;
+    if fs::metadata(from)?.is_dir() {
+        fs::create_dir_all(&to)?;
+        copy_dir(from, &to)?;
+    } else {
+        fs::copy(from, &to)?;
+    }
+    Ok(to)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn copy() -> io::Result<()> {
+        let home = TempDir::new()?;
+        fs::create_dir(home.path().join("project"))?;
+        fs::write(home.path().join("project/config.toml"), "config")?;
+        fs::write(home.path().join("notes.txt"), "notes")?;
+        let dir = TempDir::new()?;
+        let dir = dir.path().join("artifacts");
+
+        super::copy(
+            &[
+                PathBuf::from("~/notes.txt"),
+                PathBuf::from("~/project"),
+                PathBuf::from("~/missing.txt"),
+            ],
+            &dir,
+            Some(home.path()),
+        );
+        assert_eq!(fs::read_to_string(dir.join("notes.txt"))?, "notes");
+        assert_eq!(
+            fs::read_to_string(dir.join("project/config.toml"))?,
+            "config"
+        );
+        assert!(!dir.join("missing.txt").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+//! Measuring how long each instruction takes to run, to suggest waits and a timeout.
+
+use std::{cell::RefCell, fmt::Write, time::Duration};
+
+use color_eyre::eyre;
+
+use super::{
+    parse::{sequence_items, top_level_sections},
+    Command, Instruction, Script,
+};
+
+/// Time given to read each line of a command's output
+const READ_TIME_PER_LINE: Duration = Duration::from_millis(300);
+const MIN_WAIT: Duration = Duration::from_secs(1);
+const MAX_WAIT: Duration = Duration::from_secs(5);
+/// The suggested timeout is the slowest instruction's time multiplied by this
+const TIMEOUT_FACTOR: u32 = 2;
+
+/// How long an instruction took to run and how much output it had.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Measurement {
+    duration: Duration,
+    output_lines: usize,
+}
+
+impl Measurement {
+    /// Measures an `instruction` which took `duration` to run and produced the `events`' data,
+    /// which includes the typed command and the prompt.
+    pub fn new(duration: Duration, instruction: &Instruction, events: &str) -> Self {
+        let typed_lines = match instruction {
+            Instruction::Command {
+                command: Command::MultiLine(lines),
+                ..
+            } => lines.len(),
+            Instruction::Command { .. } | Instruction::Interactive { .. } => 1,
+            _ => 0,
+        };
+        Self {
+            duration,
+            output_lines: events.matches('\n').count().saturating_sub(typed_lines),
+        }
+    }
+
+    /// Time to wait after the instruction so its output can be read, if it had any.
+    fn suggested_wait(self) -> Option<Duration> {
+        if self.output_lines == 0 {
+            return None;
+        }
+        let lines = u32::try_from(self.output_lines).unwrap_or(u32::MAX);
+        Some(
+            READ_TIME_PER_LINE
+                .saturating_mul(lines)
+                .clamp(MIN_WAIT, MAX_WAIT),
+        )
+    }
+}
+
+impl Script {
+    /// Runs the script without typing delays, measuring how long each instruction takes.
+    ///
+    /// Returns a copy of `yaml`, the script's source, annotated with the measurements,
+    /// a suggested timeout, and a suggested `!Wait` after each command whose output
+    /// isn't already followed by one.
+    pub fn calibrate(&self, yaml: &str) -> color_eyre::Result<String> {
+        let mut script = self.clone();
+        script.settings.type_speed = Duration::ZERO;
+        script.settings.pre_enter_delay = Duration::ZERO;
+        script.settings.step = false;
+
+        let measurements = RefCell::new(Vec::new());
+        super::run_script(script, Some(&measurements), false, None, None)?;
+        annotate(yaml, &self.instructions, &measurements.into_inner())
+    }
+}
+
+/// Adds the measurements and suggestions to the script's source.
+fn annotate(
+    yaml: &str,
+    instructions: &[Instruction],
+    measurements: &[Measurement],
+) -> color_eyre::Result<String> {
+    let lines: Vec<&str> = yaml.lines().collect();
+    let sections = top_level_sections(&lines);
+    let items = sections
+        .iter()
+        .find(|(key, _)| *key == "instructions")
+        .map(|(_, section)| sequence_items(&lines, section.clone()))
+        .unwrap_or_default();
+    eyre::ensure!(
+        items.len() == instructions.len() && instructions.len() == measurements.len(),
+        "could not find each instruction in the input file"
+    );
+
+    // Lines to add before each line of the source, by line number.
+    let mut additions = vec![Vec::new(); lines.len() + 1];
+    // Line of the existing timeout, which is replaced.
+    let mut replaced = None;
+
+    let slowest = measurements
+        .iter()
+        .map(|measurement| measurement.duration)
+        .max()
+        .unwrap_or_default();
+    let timeout = (slowest * TIMEOUT_FACTOR).as_secs() + 1;
+    let comment = format!("# calibrated: the slowest instruction took {slowest:.2?}");
+    if let Some((_, section)) = sections.iter().find(|(key, _)| *key == "settings") {
+        let settings = &lines[section.clone()][1..];
+        let indent = settings
+            .iter()
+            .find(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map_or(2, |line| line.len() - line.trim_start().len());
+        let timeout_line = settings.iter().position(|line| {
+            line.len() - line.trim_start().len() == indent
+                && line.trim_start().starts_with("timeout:")
+        });
+        replaced = timeout_line.map(|line| section.start + 1 + line);
+        additions[replaced.unwrap_or(section.start + 1)]
+            .push(format!("{:indent$}timeout: {timeout}s {comment}", ""));
+    } else {
+        let line_num = sections.first().map_or(0, |(_, section)| section.start);
+        additions[line_num].push(format!("settings:\n  timeout: {timeout}s {comment}"));
+    }
+
+    for (index, ((item, instruction), measurement)) in
+        items.iter().zip(instructions).zip(measurements).enumerate()
+    {
+        let indent = item.marker - 1;
+        let mut annotation = format!(
+            "{:indent$}# calibrated: took {:.2?}",
+            "", measurement.duration
+        );
+        if measurement.output_lines > 0 {
+            write!(
+                annotation,
+                ", {} line(s) of output",
+                measurement.output_lines
+            )
+            .expect("writing to a string can't fail");
+        }
+        additions[item.start].push(annotation);
+
+        let shows_output = matches!(
+            instruction,
+            Instruction::Command { hidden: false, .. } | Instruction::Interactive { .. }
+        );
+        let waits_next = matches!(instructions.get(index + 1), Some(Instruction::Wait(_)));
+        let wait = measurement
+            .suggested_wait()
+            .filter(|_| shows_output && !waits_next);
+        if let Some(wait) = wait {
+            // After the item's last line, before any trailing blank lines or comments.
+            let end = item
+                .range
+                .clone()
+                .rev()
+                .find(|&line| {
+                    let line = lines[line].trim_start();
+                    !line.is_empty() && !line.starts_with('#')
+                })
+                .map_or(item.range.end, |line| line + 1);
+            additions[end].push(format!(
+                "{:indent$}- !Wait {}ms # calibrated: time to read the output",
+                "",
+                wait.as_millis()
+            ));
+        }
+    }
+
+    let mut annotated = String::with_capacity(yaml.len());
+    for (line_num, additions) in additions.iter().enumerate() {
+        for addition in additions {
+            annotated.push_str(addition);
+            annotated.push('\n');
+        }
+        if let Some(line) = lines.get(line_num).filter(|_| replaced != Some(line_num)) {
+            annotated.push_str(line);
+            annotated.push('\n');
+        }
+    }
+    Ok(annotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotate() -> color_eyre::Result<()> {
+        let yaml = "\
+settings:
+  width: 80
+  timeout: 5s
+
+instructions:
+  - !Command
+    command: ls
+  # comment
+  - !Command
+    command: sleep 1
+  - !Wait 1s
+";
+        let script = Script::try_from_yaml(yaml.as_bytes())?;
+        let measurements = [
+            Measurement {
+                duration: Duration::from_millis(10),
+                output_lines: 5,
+            },
+            Measurement {
+                duration: Duration::from_millis(1500),
+                output_lines: 1,
+            },
+            Measurement {
+                duration: Duration::ZERO,
+                output_lines: 0,
+            },
+        ];
+        let annotated = super::annotate(yaml, &script.instructions, &measurements)?;
+        assert_eq!(
+            annotated,
+            "\
+settings:
+  width: 80
+  timeout: 4s # calibrated: the slowest instruction took 1.50s
+
+instructions:
+  # calibrated: took 10.00ms, 5 line(s) of output
+  - !Command
+    command: ls
+  - !Wait 1500ms # calibrated: time to read the output
+  # comment
+  # calibrated: took 1.50s, 1 line(s) of output
+  - !Command
+    command: sleep 1
+  # calibrated: took 0.00ns
+  - !Wait 1s
+"
+        );
+        Script::try_from_yaml(annotated.as_bytes())?;
+
+        assert!(super::annotate(yaml, &script.instructions, &measurements[1..]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+//! Reporting the progress of instructions to a CI service,
+//! so the output of a failed instruction is readable in the CI's log.
+//!
+//! Annotations are printed to stderr, like the progress bars,
+//! so they don't mix with an asciicast written to stdout.
+
+use std::time::Duration;
+
+use clap::ValueEnum;
+use color_eyre::eyre;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{step, Instruction, Merge};
+
+/// CI service to print annotations for
+#[derive(ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Ci {
+    /// Don't print annotations
+    #[default]
+    Off,
+    /// GitHub Actions workflow commands
+    Github,
+}
+
+impl Merge for Ci {
+    fn merge(&mut self, other: Self) {
+        if other != Self::default() {
+            *self = other;
+        }
+    }
+}
+
+impl Ci {
+    pub fn is_enabled(self) -> bool {
+        self != Self::Off
+    }
+
+    /// Prints the output of an instruction which ran successfully.
+    pub fn instruction(self, num: usize, instruction: &Instruction, output: &str, time: Duration) {
+        match self {
+            Self::Off => {}
+            Self::Github => eprint!("{}", github_group(num, instruction, output, time)),
+        }
+    }
+
+    /// Prints the error of an instruction which failed.
+    pub fn error(
+        self,
+        num: usize,
+        instruction: &Instruction,
+        error: &eyre::Report,
+        time: Duration,
+    ) {
+        match self {
+            Self::Off => {}
+            Self::Github => eprintln!("{}", github_error(num, instruction, error, time)),
+        }
+    }
+}
+
+/// A collapsible group, titled with the instruction and how long it took, containing its output.
+///
+/// Workflow commands are stopped around the output, so lines in it like `::endgroup::`
+/// or `::add-mask::` aren't run.
+/// The token to resume them is the output's hash, so the output can't contain it.
+fn github_group(num: usize, instruction: &Instruction, output: &str, time: Duration) -> String {
+    let mut output = output.replace("\r\n", "\n");
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+    let token = format!("{:x}", Sha256::digest(&output));
+    format!(
+        "::group::Instruction {num}: {} ({time:.2?})\n\
+            ::stop-commands::{token}\n{output}::{token}::\n::endgroup::\n",
+        step::describe(instruction)
+    )
+}
+
+fn github_error(
+    num: usize,
+    instruction: &Instruction,
+    error: &eyre::Report,
+    time: Duration,
+) -> String {
+    let title = escape_property(&format!("Instruction {num} failed"));
+    let message = escape_data(&format!(
+        "{} failed after {time:.2?}: {error:#}",
+        step::describe(instruction)
+    ));
+    format!("::error title={title}::{message}")
+}
+
+/// Escapes the message of a workflow command.
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property of a workflow command, i.e. the title.
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::Command, *};
+
+    #[test]
+    fn github() {
+        let instruction = Instruction::Command {
+            command: Command::SingleLine(String::from("ls")),
+            hidden: false,
+            type_speed: None,
+            confirm: Vec::new(),
+            highlight_stderr: false,
+            output_filter: None,
+            allow_dangerous: false,
+        };
+        let time = Duration::from_millis(1500);
+        let token = format!("{:x}", Sha256::digest("::endgroup::\nb\n"));
+        assert_eq!(
+            github_group(1, &instruction, "::endgroup::\r\nb", time),
+            format!(
+                "::group::Instruction 1: Command `ls` (1.50s)\n::stop-commands::{token}\n\
+                    ::endgroup::\nb\n::{token}::\n::endgroup::\n"
+            )
+        );
+
+        let error = eyre::eyre!("timed out\nwaiting for 100%").wrap_err("error running");
+        assert_eq!(
+            github_error(1, &instruction, &error, time),
+            "::error title=Instruction 1 failed::Command `ls` failed after 1.50s: \
+                error running: timed out%0Awaiting for 100%25"
+        );
+    }
+}
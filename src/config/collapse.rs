@@ -0,0 +1,74 @@
+//! Collapsing progress bars and spinners, which rewrite the same line many times,
+//! so they don't bloat the asciicast with events.
+
+use crate::asciicast::{Event, EventType};
+
+/// Removes output events which only rewrite the current line and are followed by another
+/// rewrite, keeping the last of each run of consecutive rewrites.
+///
+/// A rewrite is output with a carriage return and no line break, so each rewrite is
+/// assumed to redraw the whole line. If `keep_every` is not 0, every nth rewrite in a run
+/// is also kept, to keep a sense of progress.
+///
+/// Event times are since the start of the asciicast, so removing events doesn't change
+/// when the others occur.
+pub fn collapse_rewrites(events: &mut Vec<Event>, keep_every: u32) {
+    let keep_every = usize::try_from(keep_every).unwrap_or(usize::MAX);
+    let is_rewrite = |event: &Event| {
+        event.event_type == EventType::Output
+            && event.data.contains('\r')
+            && !event.data.contains('\n')
+    };
+
+    let mut run = 0;
+    let mut keep = Vec::with_capacity(events.len());
+    for (index, event) in events.iter().enumerate() {
+        if !is_rewrite(event) {
+            run = 0;
+            keep.push(true);
+            continue;
+        }
+        run += 1;
+        let last = !events.get(index + 1).is_some_and(is_rewrite);
+        keep.push(last || (keep_every != 0 && run % keep_every == 0));
+    }
+
+    let mut keep = keep.into_iter();
+    events.retain(|_| keep.next().unwrap_or(true));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn collapse_rewrites() {
+        let output = |millis, data: &str| Event::output(Duration::from_millis(millis), data.into());
+        let events = vec![
+            output(0, "downloading\r\n"),
+            output(1, "\r10%"),
+            output(2, "\r20%"),
+            output(3, "\r30%"),
+            output(4, "\r40%"),
+            output(5, "\r50%"),
+            output(6, "\r\n"),
+            output(7, "\rdone"),
+        ];
+
+        let mut collapsed = events.clone();
+        super::collapse_rewrites(&mut collapsed, 0);
+        let times = |events: &[Event]| {
+            events
+                .iter()
+                .map(|event| event.time.as_millis())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(times(&collapsed), [0, 5, 6, 7]);
+
+        let mut collapsed = events;
+        super::collapse_rewrites(&mut collapsed, 2);
+        assert_eq!(times(&collapsed), [0, 2, 4, 5, 6, 7]);
+    }
+}
@@ -0,0 +1,109 @@
+//! Choosing which instructions to run at run time with `!If` instructions,
+//! so a script can adapt to its environment, i.e. a package which is already installed.
+
+use regex::Regex;
+
+use super::{EnvVar, Instruction};
+
+impl Instruction {
+    /// The instructions of the branch an `!If` instruction runs, or [`None`] for other
+    /// instructions.
+    ///
+    /// The `then` branch is run if each variable in `var_matches` is one of the `variables`
+    /// and matches its regex. Later `variables` override earlier ones with the same name.
+    pub(super) fn branch(&self, variables: &[EnvVar]) -> Option<&[Self]> {
+        let Self::If {
+            var_matches,
+            then,
+            otherwise,
+        } = self
+        else {
+            return None;
+        };
+        if matches(var_matches, variables) {
+            Some(then)
+        } else {
+            Some(otherwise)
+        }
+    }
+
+    /// The instruction followed by the instructions in its branches, recursively.
+    pub(super) fn flatten(&self) -> Vec<&Self> {
+        let mut instructions = vec![self];
+        if let Self::If {
+            then, otherwise, ..
+        } = self
+        {
+            instructions.extend(then.iter().chain(otherwise).flat_map(Self::flatten));
+        }
+        instructions
+    }
+}
+
+/// Each of the `instructions` followed by the instructions in its branches, recursively,
+/// with the number of the instruction in `instructions` they are in.
+pub(super) fn numbered(
+    instructions: &[Instruction],
+) -> impl Iterator<Item = (usize, &Instruction)> {
+    instructions
+        .iter()
+        .enumerate()
+        .flat_map(|(num, instruction)| {
+            instruction
+                .flatten()
+                .into_iter()
+                .map(move |instruction| (num, instruction))
+        })
+}
+
+fn matches(var_matches: &[(String, Regex)], variables: &[EnvVar]) -> bool {
+    var_matches.iter().all(|(name, regex)| {
+        variables
+            .iter()
+            .rev()
+            .find(|variable| variable.name == *name)
+            .is_some_and(|variable| regex.is_match(&variable.value))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn branch() -> serde_yaml::Result<()> {
+        let instruction: Instruction = serde_yaml::from_str(
+            "
+            !If
+              var_matches:
+                installed: ^yes$
+                version: ^1\\.
+              then:
+                - !Marker then
+              else:
+                - !Marker else
+                - !If
+                  var_matches: {}
+                  then:
+                    - !Clear
+            ",
+        )?;
+        let branch = |variables: &[&str]| {
+            let variables: Vec<_> = variables.iter().copied().map(EnvVar::from).collect();
+            match instruction.branch(&variables) {
+                Some([Instruction::Marker(marker), ..]) => marker.clone(),
+                _ => String::new(),
+            }
+        };
+        assert_eq!(branch(&["installed=yes", "version=1.2"]), "then");
+        assert_eq!(branch(&["installed=yes", "version=2.0"]), "else");
+        assert_eq!(
+            branch(&["installed=no", "installed=yes", "version=1.0"]),
+            "then"
+        );
+        assert_eq!(branch(&["version=1.0"]), "else");
+        assert_eq!(instruction.flatten().len(), 5);
+        assert!(Instruction::Clear.branch(&[]).is_none());
+        Ok(())
+    }
+}
@@ -25,10 +25,16 @@ macro_rules! map_fields {
 }
 
 pub mod command;
-pub mod duration;
+pub mod env_map;
 pub mod key;
+pub mod matrix;
+pub mod regex_set;
 pub mod shell;
+pub mod shell_wrapper;
+pub mod var_matches;
+pub mod width;
 
+pub use autocast::duration;
 use expectrl::ControlCode;
 use itertools::Itertools;
 use serde::de::{self, Unexpected, VariantAccess};
@@ -38,7 +44,7 @@ fn parse_control<E: de::Error>(control: &str) -> Result<ControlCode, E> {
         .chars()
         .exactly_one()
         .map_err(|_| E::invalid_value(Unexpected::Str(control), &"single control char"))?;
-    char.try_into().map_err(|_| invalid_control(char))
+    char.try_into().map_err(|()| invalid_control(char))
 }
 
 fn control_from_variant<'de, V>(variant: V) -> Result<ControlCode, V::Error>
@@ -47,7 +53,7 @@ where
     V::Error: de::Error,
 {
     let char: char = variant.newtype_variant()?;
-    char.try_into().map_err(|_| invalid_control(char))
+    char.try_into().map_err(|()| invalid_control(char))
 }
 
 fn invalid_control<E: de::Error>(char: char) -> E {
@@ -1,7 +1,10 @@
 use std::fmt;
 
 use serde::{
-    de::{self, EnumAccess, SeqAccess, Unexpected, VariantAccess},
+    de::{
+        self, value::MapAccessDeserializer, EnumAccess, MapAccess, SeqAccess, Unexpected,
+        VariantAccess,
+    },
     Deserialize,
 };
 
@@ -23,7 +26,7 @@ impl<'de> de::Visitor<'de> for Visitor {
     type Value = Command;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a string or enum")
+        formatter.write_str("a string, sequence, or enum")
     }
 
     fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
@@ -54,6 +57,11 @@ impl<'de> de::Visitor<'de> for Visitor {
         Ok(Command::MultiLine(strings))
     }
 
+    /// A map with a single variant, i.e. `{ Control = "C" }` in TOML which has no tags
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.visit_enum(MapAccessDeserializer::new(map))
+    }
+
     fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
         let (tag, variant) = data.variant()?;
         match tag {
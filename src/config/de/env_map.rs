@@ -0,0 +1,48 @@
+use std::fmt;
+
+use serde::{de, Deserializer};
+
+use crate::config::EnvVar;
+
+/// Deserializes a map of environment variable names to values, keeping their order.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<EnvVar>, D::Error> {
+    deserializer.deserialize_map(Visitor)
+}
+
+#[derive(Debug)]
+struct Visitor;
+
+impl<'de> de::Visitor<'de> for Visitor {
+    type Value = Vec<EnvVar>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of environment variable names to values")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut env_vars = Vec::with_capacity(map.size_hint().unwrap_or_default());
+        while let Some((name, value)) = map.next_entry()? {
+            env_vars.push(EnvVar { name, value });
+        }
+        Ok(env_vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order() -> serde_yaml::Result<()> {
+        let env_vars = deserialize(serde_yaml::Deserializer::from_str("B: b\nA: a"))?;
+        let env_vars: Vec<(String, String)> = env_vars.into_iter().map(Into::into).collect();
+        assert_eq!(
+            env_vars,
+            [
+                (String::from("B"), String::from("b")),
+                (String::from("A"), String::from("a"))
+            ]
+        );
+        Ok(())
+    }
+}
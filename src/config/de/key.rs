@@ -2,7 +2,7 @@ use std::fmt;
 
 use itertools::Itertools;
 use serde::{
-    de::{self, EnumAccess, VariantAccess},
+    de::{self, value::MapAccessDeserializer, EnumAccess, MapAccess, VariantAccess},
     Deserialize,
 };
 
@@ -36,20 +36,27 @@ impl<'de> de::Visitor<'de> for Visitor {
             Ok(Key::Control(parse_control(control)?))
         } else if let Ok(char) = v.chars().exactly_one() {
             Ok(Key::Char(char))
+        } else if v.contains("${") {
+            Ok(Key::Text(String::from(v)))
         } else {
             let duration = duration::parse(v).map_err(E::custom)?;
             Ok(Key::Wait(duration))
         }
     }
 
+    /// A map with a single variant, i.e. `{ Wait = "1s" }` in TOML which has no tags
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.visit_enum(MapAccessDeserializer::new(map))
+    }
+
     fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
         let (tag, variant) = data.variant()?;
         match tag {
             Variant::Char => Ok(Key::Char(variant.newtype_variant()?)),
             Variant::Control => Ok(Key::Control(control_from_variant(variant)?)),
             Variant::Wait => {
-                let wait: &str = variant.newtype_variant()?;
-                let duration = duration::parse(wait).map_err(de::Error::custom)?;
+                let wait: String = variant.newtype_variant()?;
+                let duration = duration::parse(&wait).map_err(de::Error::custom)?;
                 Ok(Key::Wait(duration))
             }
         }
@@ -0,0 +1,29 @@
+use std::fmt;
+
+use serde::{de, Deserializer};
+
+/// Deserializes a map of variable names to lists of values, keeping their order.
+pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(String, Vec<String>)>, D::Error> {
+    deserializer.deserialize_map(Visitor)
+}
+
+#[derive(Debug)]
+struct Visitor;
+
+impl<'de> de::Visitor<'de> for Visitor {
+    type Value = Vec<(String, Vec<String>)>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of variable names to lists of values")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut matrix = Vec::with_capacity(map.size_hint().unwrap_or_default());
+        while let Some(entry) = map.next_entry()? {
+            matrix.push(entry);
+        }
+        Ok(matrix)
+    }
+}
@@ -0,0 +1,8 @@
+use regex::RegexSet;
+use serde::{de::Error, Deserialize, Deserializer};
+
+/// Deserializes a list of regular expressions into a [`RegexSet`].
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RegexSet, D::Error> {
+    let patterns = Vec::<String>::deserialize(deserializer)?;
+    RegexSet::new(patterns).map_err(D::Error::custom)
+}
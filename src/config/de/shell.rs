@@ -11,11 +11,21 @@ use crate::config::Shell;
 #[serde(variant_identifier)]
 enum Variant {
     Bash,
+    Zsh,
+    Nu,
+    Cmd,
     Python,
     Custom,
 }
 
-const CUSTOM_FIELDS: &[&str] = &["program", "args", "prompt", "line_split", "quit_command"];
+const CUSTOM_FIELDS: &[&str] = &[
+    "program",
+    "args",
+    "prompt",
+    "line_split",
+    "line_continuation",
+    "quit_command",
+];
 
 #[derive(Deserialize)]
 #[serde(field_identifier, rename_all = "snake_case")]
@@ -24,6 +34,7 @@ enum CustomField {
     Args,
     Prompt,
     LineSplit,
+    LineContinuation,
     QuitCommand,
 }
 
@@ -40,10 +51,13 @@ impl<'de> de::Visitor<'de> for Visitor {
     fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
         match v {
             "bash" | "Bash" => Ok(Shell::Bash),
+            "zsh" | "Zsh" => Ok(Shell::Zsh),
+            "nu" | "Nu" => Ok(Shell::Nu),
+            "cmd" | "Cmd" => Ok(Shell::Cmd),
             "python" | "Python" => Ok(Shell::Python),
             _ => Err(E::invalid_value(
                 de::Unexpected::Str(v),
-                &"supported shell (e.g. bash or python) or a custom shell",
+                &"supported shell (e.g. bash, zsh, nu, cmd, or python) or a custom shell",
             )),
         }
     }
@@ -55,13 +69,24 @@ impl<'de> de::Visitor<'de> for Visitor {
     fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
         let (tag, variant) = data.variant()?;
         match tag {
-            Variant::Bash => variant.unit_variant().map(|_| Shell::Bash),
-            Variant::Python => variant.unit_variant().map(|_| Shell::Python),
+            Variant::Bash => variant.unit_variant().map(|()| Shell::Bash),
+            Variant::Zsh => variant.unit_variant().map(|()| Shell::Zsh),
+            Variant::Nu => variant.unit_variant().map(|()| Shell::Nu),
+            Variant::Cmd => variant.unit_variant().map(|()| Shell::Cmd),
+            Variant::Python => variant.unit_variant().map(|()| Shell::Python),
             Variant::Custom => variant.struct_variant(CUSTOM_FIELDS, CustomVisitor),
         }
     }
 }
 
+/// A custom shell's prompt, or a list of prompts
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Prompts {
+    One(String),
+    Many(Vec<String>),
+}
+
 /// Visitor for deserializing [`Shell::Custom`]
 struct CustomVisitor;
 
@@ -75,8 +100,9 @@ impl<'de> de::Visitor<'de> for CustomVisitor {
     fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
         let mut program = None;
         let mut args = None;
-        let mut prompt = None;
+        let mut prompt: Option<Prompts> = None;
         let mut line_split = None;
+        let mut line_continuation = None;
         let mut quit_command = None;
         map_fields!(
             map,
@@ -84,18 +110,31 @@ impl<'de> de::Visitor<'de> for CustomVisitor {
             (CustomField::Args, args, "args"),
             (CustomField::Prompt, prompt, "prompt"),
             (CustomField::LineSplit, line_split, "line_split"),
+            (
+                CustomField::LineContinuation,
+                line_continuation,
+                "line_continuation"
+            ),
             (CustomField::QuitCommand, quit_command, "quit_command"),
         )?;
         let program = program.ok_or_else(|| de::Error::missing_field("program"))?;
         let args = args.unwrap_or_default();
-        let prompt = prompt.ok_or_else(|| de::Error::missing_field("prompt"))?;
+        let prompts = match prompt.ok_or_else(|| de::Error::missing_field("prompt"))? {
+            Prompts::One(prompt) => vec![prompt],
+            Prompts::Many(prompts) if prompts.is_empty() => {
+                return Err(de::Error::invalid_length(0, &"at least one prompt"));
+            }
+            Prompts::Many(prompts) => prompts,
+        };
         let line_split = line_split.ok_or_else(|| de::Error::missing_field("line_split"))?;
+        let line_continuation = line_continuation.unwrap_or_else(|| String::from(" "));
 
         Ok(Shell::Custom {
             program,
             args,
-            prompt,
+            prompts,
             line_split,
+            line_continuation,
             quit_command,
         })
     }
@@ -108,6 +147,9 @@ mod tests {
     #[test]
     fn visit_str() -> serde_yaml::Result<()> {
         assert_eq!(serde_yaml::from_str::<Shell>("bash")?, Shell::Bash);
+        assert_eq!(serde_yaml::from_str::<Shell>("zsh")?, Shell::Zsh);
+        assert_eq!(serde_yaml::from_str::<Shell>("nu")?, Shell::Nu);
+        assert_eq!(serde_yaml::from_str::<Shell>("cmd")?, Shell::Cmd);
         assert_eq!(serde_yaml::from_str::<Shell>("python")?, Shell::Python);
         assert!(serde_yaml::from_str::<Shell>("custom").is_err());
         Ok(())
@@ -122,6 +164,7 @@ mod tests {
             - arg
             prompt: prompt
             line_split: split
+            line_continuation: \"\\\\\\n\"
             quit_command: quit
             ",
         )?;
@@ -130,8 +173,9 @@ mod tests {
             Shell::Custom {
                 program: String::from("program"),
                 args: vec![String::from("arg")],
-                prompt: String::from("prompt"),
+                prompts: vec![String::from("prompt")],
                 line_split: String::from("split"),
+                line_continuation: String::from("\\\n"),
                 quit_command: Some(String::from("quit"))
             }
         );
@@ -158,12 +202,30 @@ mod tests {
             Shell::Custom {
                 program: String::from("program"),
                 args: Vec::new(),
-                prompt: String::from("prompt"),
+                prompts: vec![String::from("prompt")],
                 line_split: String::from("split"),
+                line_continuation: String::from(" "),
                 quit_command: None
             }
         );
         assert!(serde_yaml::from_str::<Shell>("!Custom").is_err());
         Ok(())
     }
+
+    #[test]
+    fn multiple_prompts() -> serde_yaml::Result<()> {
+        let shell: Shell = serde_yaml::from_str(
+            "
+            program: ipython
+            prompt: ['In [', '(Pdb) ']
+            line_split: ''
+            ",
+        )?;
+        let Shell::Custom { prompts, .. } = shell else {
+            panic!("expected custom shell");
+        };
+        assert_eq!(prompts, ["In [", "(Pdb) "]);
+        assert!(serde_yaml::from_str::<Shell>("program: p\nprompt: []\nline_split: ''").is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,64 @@
+use std::fmt;
+
+use serde::de::{self, SeqAccess};
+
+use crate::config::ShellWrapper;
+
+/// Arguments of the `nix` shorthand, which runs the shell in the project's devshell
+const NIX: &[&str] = &["nix", "develop", "--command"];
+
+/// Parses a wrapper command, split on whitespace, or "nix" for `nix develop --command`.
+#[allow(clippy::unnecessary_wraps)] // clap value parsers return a `Result`
+pub fn parse(s: &str) -> Result<ShellWrapper, std::convert::Infallible> {
+    let s = s.trim();
+    let args = if s == "nix" {
+        NIX.iter().copied().map(String::from).collect()
+    } else {
+        s.split_whitespace().map(String::from).collect()
+    };
+    Ok(ShellWrapper(args))
+}
+
+pub(in crate::config) struct Visitor;
+
+impl<'de> de::Visitor<'de> for Visitor {
+    type Value = ShellWrapper;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("command string or list of arguments")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        parse(v).map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut args = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(arg) = seq.next_element()? {
+            args.push(arg);
+        }
+        Ok(ShellWrapper(args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize() {
+        assert_eq!(serde_yaml::from_str::<ShellWrapper>("nix").unwrap().0, NIX);
+        assert_eq!(
+            serde_yaml::from_str::<ShellWrapper>("direnv exec .")
+                .unwrap()
+                .0,
+            ["direnv", "exec", "."]
+        );
+        assert_eq!(
+            serde_yaml::from_str::<ShellWrapper>("[devbox, run, --]")
+                .unwrap()
+                .0,
+            ["devbox", "run", "--"]
+        );
+    }
+}
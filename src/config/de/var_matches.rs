@@ -0,0 +1,31 @@
+use std::fmt;
+
+use regex::Regex;
+use serde::{de, Deserializer};
+
+/// Deserializes a map of variable names to regular expressions, keeping their order.
+pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(String, Regex)>, D::Error> {
+    deserializer.deserialize_map(Visitor)
+}
+
+#[derive(Debug)]
+struct Visitor;
+
+impl<'de> de::Visitor<'de> for Visitor {
+    type Value = Vec<(String, Regex)>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of variable names to regular expressions")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut var_matches = Vec::with_capacity(map.size_hint().unwrap_or_default());
+        while let Some((name, pattern)) = map.next_entry::<String, String>()? {
+            let regex = Regex::new(&pattern).map_err(de::Error::custom)?;
+            var_matches.push((name, regex));
+        }
+        Ok(var_matches)
+    }
+}
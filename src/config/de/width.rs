@@ -0,0 +1,61 @@
+use std::{fmt, num::ParseIntError};
+
+use serde::de::{self, Unexpected};
+use thiserror::Error;
+
+use crate::config::Width;
+
+pub fn parse(s: &str) -> Result<Width, ParseError> {
+    let s = s.trim();
+    if s == "auto" {
+        Ok(Width::Auto)
+    } else {
+        Ok(Width::Columns(s.parse()?))
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("the width must be an integer or \"auto\"")]
+pub struct ParseError(#[from] ParseIntError);
+
+pub(in crate::config) struct Visitor;
+
+impl de::Visitor<'_> for Visitor {
+    type Value = Width;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("u16 or \"auto\"")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        u16::try_from(v)
+            .map(Width::Columns)
+            .map_err(|_| E::invalid_value(Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        u16::try_from(v)
+            .map(Width::Columns)
+            .map_err(|_| E::invalid_value(Unexpected::Signed(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        parse(v).map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize() {
+        assert_eq!(
+            serde_yaml::from_str::<Width>("80").unwrap(),
+            Width::Columns(80)
+        );
+        assert_eq!(serde_yaml::from_str::<Width>("auto").unwrap(), Width::Auto);
+        assert!(serde_yaml::from_str::<Width>("wide").is_err());
+        assert!(serde_yaml::from_str::<Width>("-1").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! Probing that control codes have their expected effect in the script's shell,
+//! so differences between platforms and terminal settings are caught before
+//! they show up as glitches in a demo.
+
+use std::{
+    ffi::OsStr,
+    fmt::{self, Display},
+    thread,
+    time::Duration,
+};
+
+use color_eyre::eyre::{self, Context};
+use expectrl::{session::OsProcess, ControlCode};
+
+use super::{
+    driver::{SessionDriver, Terminal},
+    spawn::{self, ShellSession},
+    Command, EnvVar, Script, Shell,
+};
+
+/// Printed on its own line by the commands sent after a control code when it worked
+const PASSED: &str = "autocast-doctor";
+
+/// Time for a command to start, so the control code isn't read by the shell's line editor
+const START_DELAY: Duration = Duration::from_millis(200);
+
+/// A control code which is sent to a shell to check that it has the expected effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Probe {
+    control: ControlCode,
+    /// What the control code is expected to do
+    effect: &'static str,
+    /// Typed before the control code
+    before: &'static str,
+    /// Whether `before` is run, so the control code is sent to the running command,
+    /// instead of editing the line
+    running: bool,
+    /// Sent as a line after the control code, prints [`PASSED`] if it had its effect
+    after: &'static str,
+}
+
+/// The control codes with an effect in the terminal or the shell's line editor.
+const PROBES: &[Probe] = &[
+    Probe {
+        control: ControlCode::EndOfText,
+        effect: "interrupts the running command",
+        before: "sleep 3600",
+        running: true,
+        after: "echo autocast-doctor",
+    },
+    Probe {
+        control: ControlCode::FileSeparator,
+        effect: "quits the running command",
+        before: "sleep 3600",
+        running: true,
+        after: "echo autocast-doctor",
+    },
+    Probe {
+        control: ControlCode::Substitute,
+        effect: "suspends the running command",
+        before: "sleep 3600",
+        running: true,
+        after: "kill -KILL %1; echo autocast-doctor",
+    },
+    Probe {
+        control: ControlCode::EndOfTransmission,
+        effect: "ends the running command's input",
+        before: "cat",
+        running: true,
+        after: "echo autocast-doctor",
+    },
+    Probe {
+        control: ControlCode::NegativeAcknowledge,
+        effect: "erases the line",
+        before: "echo wrong",
+        running: false,
+        after: "echo autocast-doctor",
+    },
+    Probe {
+        control: ControlCode::EndOfTransmissionBlock,
+        effect: "erases the last word",
+        before: "echo autocast-doctor wrong",
+        running: false,
+        after: "",
+    },
+    Probe {
+        control: ControlCode::Backspace,
+        effect: "erases the last char",
+        before: "echo autocast-doctorx",
+        running: false,
+        after: "",
+    },
+    Probe {
+        control: ControlCode::Delete,
+        effect: "erases the last char",
+        before: "echo autocast-doctorx",
+        running: false,
+        after: "",
+    },
+];
+
+impl Probe {
+    /// Sends the control code to the shell session and checks its output.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shell's prompt is not detected, i.e. because the running
+    /// command was not stopped, or [`PASSED`] was not printed.
+    fn run<D: SessionDriver>(&self, shell_session: &mut D) -> color_eyre::Result<()> {
+        shell_session.reset();
+        let mut output = String::new();
+        if self.running {
+            shell_session.send_line(self.before)?;
+            thread::sleep(START_DELAY);
+            shell_session.send([u8::from(self.control)])?;
+            let events = shell_session
+                .read_until_prompt()
+                .wrap_err("the command was not stopped")?;
+            output.extend(events.into_iter().map(|event| event.data));
+        } else {
+            shell_session.send(self.before)?;
+            shell_session.send([u8::from(self.control)])?;
+        }
+        shell_session.send_line(self.after)?;
+        let events = shell_session
+            .read_until_prompt()
+            .wrap_err("could not detect prompt")?;
+        output.extend(events.into_iter().map(|event| event.data));
+
+        eyre::ensure!(
+            output
+                .lines()
+                .any(|line| line.trim_end_matches('\r') == PASSED),
+            "unexpected output: {output:?}"
+        );
+        Ok(())
+    }
+}
+
+/// Whether a control code had its expected effect.
+#[derive(Debug)]
+pub struct Outcome {
+    probe: Probe,
+    error: Option<eyre::Report>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Probe {
+            control, effect, ..
+        } = self.probe;
+        write!(f, "{} {effect}: ", Command::Control(control))?;
+        match &self.error {
+            None => write!(f, "ok"),
+            Some(error) => write!(f, "failed, {error:#}"),
+        }
+    }
+}
+
+impl Script {
+    /// Sends each control code to a scratch shell, started like the script's,
+    /// and checks that it has its expected effect.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the script's shell is not bash or zsh, which the probes are written for.
+    pub fn doctor(&self) -> color_eyre::Result<Vec<Outcome>> {
+        let settings = &self.settings;
+        eyre::ensure!(
+            matches!(settings.shell, Shell::Bash | Shell::Zsh),
+            "the control code probes only support bash and zsh, not {}",
+            settings.shell
+        );
+        let wrapper = settings.shell_wrapper.clone().unwrap_or_default();
+        let terminal = Terminal {
+            width: 80,
+            height: 24,
+            disable_flow_control: settings.disable_flow_control,
+            disable_echoctl: settings.disable_echoctl,
+        };
+
+        let outcomes = PROBES
+            .iter()
+            .map(|&probe| {
+                // Each probe gets its own shell, so one which fails doesn't affect the rest.
+                let result = settings
+                    .shell
+                    .clone()
+                    .spawn::<ShellSession<OsProcess, _>, _, _, _>(
+                        settings
+                            .environment
+                            .iter()
+                            .map(|EnvVar { name, value }| (OsStr::new(name), OsStr::new(value))),
+                        spawn::Options {
+                            wrapper: &wrapper.0,
+                            timeout: settings.timeout,
+                            terminal,
+                            disable_history: true,
+                            disable_history_expansion: !settings.disable_default_environment,
+                            keep_prompt: false,
+                            binary_output: settings.binary_output,
+                        },
+                    )
+                    .wrap_err("could not start shell")
+                    .and_then(|mut shell_session| {
+                        probe.run(&mut shell_session)?;
+                        shell_session.quit().wrap_err("could not exit shell")?;
+                        Ok(())
+                    });
+                // A shell which is stuck is killed when it's dropped.
+                Outcome {
+                    probe,
+                    error: result.err(),
+                }
+            })
+            .collect();
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::driver::mock::Mock;
+
+    use super::*;
+
+    #[test]
+    fn probe() {
+        let mut mock = Mock::new("PROMPT", ["PROMPT", "autocast-doctor\r\nPROMPT"]);
+        PROBES[0].run(&mut mock).unwrap();
+        assert_eq!(mock.sent, b"sleep 3600\n\x03echo autocast-doctor\n");
+
+        let mut mock = Mock::new("PROMPT", ["wrongecho autocast-doctor\r\nPROMPT"]);
+        assert!(PROBES[4].run(&mut mock).is_err());
+    }
+}
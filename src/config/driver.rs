@@ -0,0 +1,292 @@
+pub mod replay;
+
+use std::{io, process::Command, thread, time::Duration};
+
+use color_eyre::eyre::Context;
+use thiserror::Error;
+
+use crate::asciicast::Event;
+
+use super::BinaryOutput;
+
+/// A backend which runs a shell session for running instructions.
+pub trait SessionDriver: Sized {
+    /// Maximum amount of time to wait for the prompt.
+    fn timeout(&self) -> Duration;
+
+    /// Creates a new output [`Event`], updating when the last event occurred to now.
+    fn new_event(&mut self, data: String) -> Event;
+
+    /// Reset the time when the last event occurred to now.
+    fn reset(&mut self);
+
+    /// Send the buffer to the shell's stdin.
+    fn send(&mut self, buf: impl AsRef<[u8]>) -> io::Result<()>;
+
+    /// Reads the shell's output.
+    /// Returns whether the prompt was detected.
+    fn read(&mut self) -> io::Result<(Option<Event>, bool)>;
+
+    /// The last prompt detected as the shell printed it, if it was not the shell's primary prompt.
+    fn alternate_prompt(&self) -> Option<&str> {
+        None
+    }
+
+    /// Output on the line before the prompt, when it was first detected.
+    /// This is the part of the shell's actual prompt before the detected prompt.
+    fn first_prompt_line(&self) -> Option<&str> {
+        None
+    }
+
+    /// Resize the session's terminal.
+    fn resize(&mut self, width: u16, height: u16) -> color_eyre::Result<()>;
+
+    /// Process ID of the shell, if it runs as a local process,
+    /// to sample the resource usage of the commands it runs.
+    fn pid(&self) -> Option<u32> {
+        None
+    }
+
+    /// Sends the quit command to the shell.
+    /// Blocks until the shell has exited.
+    ///
+    /// Returns the shell's exit status, if known.
+    fn quit(&mut self) -> color_eyre::Result<Option<i32>>;
+
+    /// Send the line to the shell's stdin, adding a new line to the end.
+    fn send_line(&mut self, line: impl AsRef<[u8]>) -> io::Result<()> {
+        #[cfg(not(windows))]
+        const LINE_ENDING: &str = "\n";
+        #[cfg(windows)]
+        const LINE_ENDING: &str = "\r\n";
+
+        // Sent at once, so backends which send each write separately only send once.
+        let mut line = line.as_ref().to_vec();
+        line.extend_from_slice(LINE_ENDING.as_bytes());
+        self.send(line)
+    }
+
+    /// Reads the shell's output, blocking until the prompt is detected,
+    /// or the timeout is surpassed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the timeout is surpassed or there was an IO error
+    /// while reading the shell output.
+    fn read_until_prompt(&mut self) -> color_eyre::Result<Vec<Event>> {
+        let start = std::time::Instant::now();
+        let mut events = Vec::new();
+        let mut backoff = Backoff::default();
+        loop {
+            let (event, prompt) = self.read().wrap_err("error reading shell output")?;
+            if prompt {
+                events.extend(event);
+                return Ok(events);
+            }
+            if let Some(event) = event {
+                events.push(event);
+                backoff.reset();
+            } else {
+                backoff.wait();
+            }
+            if start.elapsed() > self.timeout() {
+                return Err(Timeout { output: events }.into());
+            }
+        }
+    }
+}
+
+/// A [`SessionDriver`] which runs a shell process.
+pub trait Spawn: SessionDriver {
+    /// Spawn a new session running `command` in a `terminal`.
+    /// Blocks until one of the shell's `prompts` is read.
+    ///
+    /// The first prompt is the shell's primary prompt.
+    /// Output which is not valid UTF-8 is handled according to `binary_output`.
+    fn spawn(
+        command: Command,
+        terminal: Terminal,
+        prompts: Vec<String>,
+        quit_command: Option<String>,
+        timeout: Duration,
+        binary_output: BinaryOutput,
+    ) -> color_eyre::Result<Self>;
+}
+
+/// Error when the prompt is not read before the timeout.
+#[derive(Error, Debug)]
+#[error("timeout elapsed")]
+pub struct Timeout {
+    /// Output read before the timeout
+    pub output: Vec<Event>,
+}
+
+/// The terminal a session runs in.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Terminal {
+    pub width: u16,
+    pub height: u16,
+    /// Clear IXON, so ^S and ^Q are sent to the program
+    pub disable_flow_control: bool,
+    /// Clear ECHOCTL, so control characters are not echoed as "^C"
+    pub disable_echoctl: bool,
+}
+
+#[cfg(test)]
+impl Terminal {
+    /// A terminal with the system's default settings.
+    pub const fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            disable_flow_control: false,
+            disable_echoctl: false,
+        }
+    }
+}
+
+/// Sleeps for exponentially increasing amounts of time while there is no output to read,
+/// so waiting for a long running command doesn't spin.
+///
+/// The delay is capped at [`Backoff::MAX`] so events are still timed accurately.
+#[derive(Debug)]
+pub struct Backoff {
+    delay: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self { delay: Self::MIN }
+    }
+}
+
+impl Backoff {
+    const MIN: Duration = Duration::from_micros(100);
+    pub const MAX: Duration = Duration::from_millis(20);
+
+    /// Sleeps for the current delay and increases it.
+    pub fn wait(&mut self) {
+        self.wait_at_most(Self::MAX);
+    }
+
+    /// Sleeps for the current delay, but no longer than `max`, and increases it.
+    pub fn wait_at_most(&mut self, max: Duration) {
+        thread::sleep(self.delay.min(max));
+        self.delay = (self.delay * 2).min(Self::MAX);
+    }
+
+    /// Resets the delay after output was read.
+    pub fn reset(&mut self) {
+        self.delay = Self::MIN;
+    }
+}
+
+/// Deterministic [`SessionDriver`] for testing.
+#[cfg(test)]
+pub mod mock {
+    use std::{collections::VecDeque, io, mem, time::Duration};
+
+    use crate::asciicast::Event;
+
+    use super::SessionDriver;
+
+    /// Time between each event created by [`Mock`].
+    pub const EVENT_TIME: Duration = Duration::from_millis(1);
+
+    /// A [`SessionDriver`] which returns canned output for each read.
+    #[derive(Debug, Default)]
+    pub struct Mock {
+        pub prompt: String,
+        /// Output returned by each call to [`SessionDriver::read()`].
+        pub reads: VecDeque<String>,
+        /// Everything sent to the session.
+        pub sent: Vec<u8>,
+        /// Echo what is sent back as output, like a terminal does for a command reading input.
+        pub echo: bool,
+        /// Sent but not yet read back, if `echo` is true.
+        echoed: String,
+        pub size: (u16, u16),
+        pub quit: bool,
+        /// Returned by [`SessionDriver::quit()`].
+        pub exit_status: Option<i32>,
+    }
+
+    impl Mock {
+        pub fn new<I>(prompt: &str, reads: I) -> Self
+        where
+            I: IntoIterator,
+            I::Item: Into<String>,
+        {
+            Self {
+                prompt: String::from(prompt),
+                reads: reads.into_iter().map(Into::into).collect(),
+                ..Self::default()
+            }
+        }
+    }
+
+    impl SessionDriver for Mock {
+        fn timeout(&self) -> Duration {
+            Duration::from_secs(1)
+        }
+
+        fn new_event(&mut self, data: String) -> Event {
+            Event::output(EVENT_TIME, data)
+        }
+
+        fn reset(&mut self) {}
+
+        fn send(&mut self, buf: impl AsRef<[u8]>) -> io::Result<()> {
+            self.sent.extend_from_slice(buf.as_ref());
+            if self.echo {
+                let echoed = String::from_utf8_lossy(buf.as_ref()).replace("\r\n", "\n");
+                self.echoed.push_str(&echoed.replace('\n', "\r\n"));
+            }
+            Ok(())
+        }
+
+        fn read(&mut self) -> io::Result<(Option<Event>, bool)> {
+            if !self.echoed.is_empty() {
+                let echoed = mem::take(&mut self.echoed);
+                return Ok((Some(self.new_event(echoed)), false));
+            }
+            let Some(data) = self.reads.pop_front() else {
+                return Ok((None, false));
+            };
+            match data.strip_suffix(&self.prompt) {
+                Some("") => Ok((None, true)),
+                Some(data) => Ok((Some(self.new_event(String::from(data))), true)),
+                None => Ok((Some(self.new_event(data)), false)),
+            }
+        }
+
+        fn resize(&mut self, width: u16, height: u16) -> color_eyre::Result<()> {
+            self.size = (width, height);
+            Ok(())
+        }
+
+        fn quit(&mut self) -> color_eyre::Result<Option<i32>> {
+            self.quit = true;
+            Ok(self.exit_status)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let mut backoff = Backoff::default();
+        backoff.wait_at_most(Duration::ZERO);
+        assert_eq!(backoff.delay, Backoff::MIN * 2);
+        for _ in 0..20 {
+            backoff.wait_at_most(Duration::ZERO);
+        }
+        assert_eq!(backoff.delay, Backoff::MAX);
+        backoff.reset();
+        assert_eq!(backoff.delay, Backoff::MIN);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs, io,
+    path::Path,
+    time::Duration,
+};
+
+use color_eyre::eyre::Context;
+
+use crate::asciicast::Event;
+
+use super::SessionDriver;
+
+/// A [`SessionDriver`] which replays canned output for each command instead of running a shell.
+///
+/// Every line sent to the session is looked up in the fixtures,
+/// the matching output is returned followed by the prompt.
+/// Lines without a fixture are an error, so stale fixtures don't silently change the output.
+#[derive(Debug, Default)]
+pub struct Replay {
+    fixtures: HashMap<String, String>,
+    line: Vec<u8>,
+    pending: VecDeque<String>,
+}
+
+impl Replay {
+    /// Creates a [`Replay`] from a YAML file mapping commands to their output.
+    ///
+    /// Line feeds in the output are converted to carriage return and line feed,
+    /// like a terminal would.
+    pub fn from_fixtures(path: &Path) -> color_eyre::Result<Self> {
+        let file = fs::File::open(path)
+            .wrap_err_with(|| format!("could not open replay fixtures `{}`", path.display()))?;
+        let fixtures: HashMap<String, String> = serde_yaml::from_reader(io::BufReader::new(file))
+            .wrap_err("could not parse replay fixtures")?;
+        Ok(Self::new(fixtures))
+    }
+
+    fn new(fixtures: HashMap<String, String>) -> Self {
+        let fixtures = fixtures
+            .into_iter()
+            .map(|(command, output)| (command, output.replace("\r\n", "\n").replace('\n', "\r\n")))
+            .collect();
+        Self {
+            fixtures,
+            ..Self::default()
+        }
+    }
+}
+
+impl SessionDriver for Replay {
+    fn timeout(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn new_event(&mut self, data: String) -> Event {
+        Event::output(Duration::ZERO, data)
+    }
+
+    fn reset(&mut self) {}
+
+    fn send(&mut self, buf: impl AsRef<[u8]>) -> io::Result<()> {
+        for byte in buf.as_ref() {
+            match byte {
+                b'\n' => {
+                    let line = String::from_utf8_lossy(&self.line);
+                    let output = self.fixtures.get(line.as_ref()).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("no replay fixture for the command {line:?}"),
+                        )
+                    })?;
+                    self.pending.push_back(output.clone());
+                    self.line.clear();
+                }
+                b'\r' => {}
+                byte => self.line.push(*byte),
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&mut self) -> io::Result<(Option<Event>, bool)> {
+        match self.pending.pop_front() {
+            Some(output) if output.is_empty() => Ok((None, true)),
+            Some(output) => Ok((Some(self.new_event(output)), true)),
+            None => Ok((None, false)),
+        }
+    }
+
+    fn resize(&mut self, _: u16, _: u16) -> color_eyre::Result<()> {
+        Ok(())
+    }
+
+    fn quit(&mut self) -> color_eyre::Result<Option<i32>> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::{asciicast, config::Script};
+
+    use super::*;
+
+    #[test]
+    fn unknown_command() {
+        let mut replay = Replay::default();
+        let error = replay.send_line("unknown").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().contains("\"unknown\""));
+    }
+
+    #[test]
+    fn full_pipeline() -> color_eyre::Result<()> {
+        let mut fixtures = tempfile::Builder::new().suffix(".yaml").tempfile()?;
+        fixtures.write_all(b"echo hello: \"hello\\n\"\nclear: \"\"\n")?;
+
+        let script = format!(
+            "
+            settings:
+              width: 80
+              height: 24
+              type_speed: 1s
+              backend: replay
+              replay_fixtures: {}
+            instructions:
+              - !Command
+                command: echo hello
+              - !Command
+                command: clear
+                hidden: true
+            ",
+            fixtures.path().display()
+        );
+        let script = Script::try_from_yaml(script.as_bytes())?;
+        let cast = asciicast::File::try_from(script)?;
+
+        let mut output = Vec::new();
+        cast.write(&mut output)?;
+        let output = String::from_utf8(output)?;
+        let events: Vec<_> = output.lines().skip(1).collect();
+        assert_eq!(
+            events,
+            [
+                r#"[0.000000, "o", "$ "]"#,
+                r#"[1.000000, "o", "e"]"#,
+                r#"[2.000000, "o", "c"]"#,
+                r#"[3.000000, "o", "h"]"#,
+                r#"[4.000000, "o", "o"]"#,
+                r#"[5.000000, "o", " "]"#,
+                r#"[6.000000, "o", "h"]"#,
+                r#"[7.000000, "o", "e"]"#,
+                r#"[8.000000, "o", "l"]"#,
+                r#"[9.000000, "o", "l"]"#,
+                r#"[10.000000, "o", "o"]"#,
+                r#"[11.000000, "o", "\r\n"]"#,
+                r#"[11.000000, "o", "hello\r\n"]"#,
+                r#"[11.000000, "o", "$ "]"#,
+                r#"[12.000000, "o", "\r\n"]"#,
+            ]
+        );
+        Ok(())
+    }
+}
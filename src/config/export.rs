@@ -0,0 +1,249 @@
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use itertools::Itertools;
+
+use super::{Command, Instruction, Script};
+
+/// Formats a [`Script`] can be exported to
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Runnable shell script
+    #[default]
+    Sh,
+    /// Markdown snippet of the commands
+    Md,
+}
+
+impl Script {
+    /// Writes the script's instructions to `writer` in the given [`Format`].
+    pub fn export(&self, format: Format, mut writer: impl Write) -> io::Result<()> {
+        match format {
+            Format::Sh => self.export_sh(&mut writer)?,
+            Format::Md => self.export_md(&mut writer)?,
+        }
+        writer.flush()
+    }
+
+    fn export_sh(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "#!/usr/bin/env {}", self.settings.shell.program())?;
+        if let Some(title) = &self.settings.title {
+            writeln!(writer, "# {title}")?;
+        }
+        for env_var in &self.settings.environment {
+            writeln!(
+                writer,
+                "export {}={}",
+                env_var.name,
+                quote_sh(&env_var.value)
+            )?;
+        }
+
+        export_sh_instructions(&self.instructions, self.settings.shell.line_split(), writer)
+    }
+
+    fn export_md(&self, writer: &mut impl Write) -> io::Result<()> {
+        if let Some(title) = &self.settings.title {
+            writeln!(writer, "# {title}\n")?;
+        }
+
+        let line_split = self.settings.shell.line_split();
+        let mut fence_open = false;
+        for instruction in &self.instructions {
+            let command = match instruction {
+                Instruction::Command {
+                    command,
+                    hidden: false,
+                    ..
+                }
+                | Instruction::Interactive { command, .. } => command.export(line_split),
+                Instruction::Marker(marker) => {
+                    if fence_open {
+                        writeln!(writer, "```\n")?;
+                        fence_open = false;
+                    }
+                    writeln!(writer, "## {marker}\n")?;
+                    continue;
+                }
+                Instruction::Command { hidden: true, .. }
+                | Instruction::Capture { .. }
+                | Instruction::If { .. }
+                | Instruction::Title(_)
+                | Instruction::Resize { .. }
+                | Instruction::Wait(_)
+                | Instruction::Settle(_)
+                | Instruction::Clear
+                | Instruction::Include(_) => continue,
+            };
+
+            if let Some(command) = command {
+                if !fence_open {
+                    writeln!(writer, "```{}", self.settings.shell.program())?;
+                    fence_open = true;
+                }
+                writeln!(writer, "{command}")?;
+            }
+        }
+        if fence_open {
+            writeln!(writer, "```")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the `instructions` as lines of a shell script.
+fn export_sh_instructions(
+    instructions: &[Instruction],
+    line_split: &str,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    for instruction in instructions {
+        match instruction {
+            Instruction::Command { command, .. } => match command.export(line_split) {
+                Some(command) => writeln!(writer, "{command}")?,
+                None => writeln!(writer, "# control code: {command}")?,
+            },
+            Instruction::Interactive { command, .. } => {
+                let command = command
+                    .export(line_split)
+                    .unwrap_or_else(|| command.to_string());
+                writeln!(writer, "# interactive: {command}")?;
+            }
+            Instruction::Capture { command, var, .. } => match command.export(line_split) {
+                Some(command) => writeln!(writer, "{var}=$({command})")?,
+                None => writeln!(writer, "# capture control code: {command}")?,
+            },
+            Instruction::If {
+                var_matches,
+                then,
+                otherwise,
+            } => {
+                let condition = var_matches
+                    .iter()
+                    .map(|(name, regex)| {
+                        format!(
+                            "printf '%s' \"${name}\" | grep -Eq {}",
+                            quote_sh(regex.as_str())
+                        )
+                    })
+                    .join(" && ");
+                writeln!(writer, "if {condition}; then")?;
+                export_sh_branch(then, line_split, writer)?;
+                if !otherwise.is_empty() {
+                    writeln!(writer, "else")?;
+                    export_sh_branch(otherwise, line_split, writer)?;
+                }
+                writeln!(writer, "fi")?;
+            }
+            Instruction::Marker(marker) => writeln!(writer, "\n# {marker}")?,
+            Instruction::Title(title) => {
+                writeln!(writer, "printf '\\033]2;%s\\007' {}", quote_sh(title))?;
+            }
+            Instruction::Wait(_)
+            | Instruction::Settle(_)
+            | Instruction::Resize { .. }
+            | Instruction::Clear
+            | Instruction::Include(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the instructions of a branch of an `!If` instruction, which can't be empty.
+fn export_sh_branch(
+    instructions: &[Instruction],
+    line_split: &str,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    let mut branch = Vec::new();
+    export_sh_instructions(instructions, line_split, &mut branch)?;
+    let has_command = branch
+        .split(|byte| *byte == b'\n')
+        .any(|line| !line.is_empty() && !line.starts_with(b"#"));
+    if !has_command {
+        writeln!(writer, ":")?;
+    }
+    writer.write_all(&branch)
+}
+
+impl Command {
+    /// The command as it would be typed into a script file.
+    /// Returns [`None`] for control codes.
+    fn export(&self, line_split: &str) -> Option<String> {
+        match self {
+            Self::SingleLine(line) => Some(line.clone()),
+            Self::MultiLine(lines) => Some(lines.iter().join(&format!("{line_split}\n"))),
+            Self::Control(_) => None,
+        }
+    }
+}
+
+/// Quotes `value` with single quotes for use in a POSIX shell.
+fn quote_sh(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(script: &str, format: Format) -> String {
+        let script = Script::try_from_yaml(script.as_bytes()).unwrap();
+        let mut output = Vec::new();
+        script.export(format, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    const SCRIPT: &str = "
+        settings:
+          environment:
+            - name: HELLO
+              value: it's me
+        instructions:
+          - !Command
+            command: cd /tmp
+            hidden: true
+          - !Marker Greeting
+          - !Command
+            command:
+              - echo $HELLO &&
+              - echo done
+          - !Wait 1s
+          - !Interactive
+            command: nano
+            keys:
+              - ^X
+          - !Command
+            command: ^C
+        ";
+
+    #[test]
+    fn sh() {
+        assert_eq!(
+            export(SCRIPT, Format::Sh),
+            "#!/usr/bin/env bash\n\
+            export HELLO='it'\\''s me'\n\
+            cd /tmp\n\
+            \n# Greeting\n\
+            echo $HELLO && \\\n\
+            echo done\n\
+            # interactive: nano\n\
+            # control code: ^C\n"
+        );
+    }
+
+    #[test]
+    fn md() {
+        assert_eq!(
+            export(SCRIPT, Format::Md),
+            "## Greeting\n\n\
+            ```bash\n\
+            echo $HELLO && \\\n\
+            echo done\n\
+            nano\n\
+            ```\n"
+        );
+    }
+}
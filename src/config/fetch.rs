@@ -0,0 +1,142 @@
+//! Downloading files a script needs before it is run, cached across runs.
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{self, Context};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// A file to download before running the script.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Fetch {
+    url: String,
+    /// Where to put the file, relative to the current directory
+    path: PathBuf,
+    /// Expected SHA-256 hash of the file, as hex
+    sha256: String,
+}
+
+/// Makes sure each file exists with the expected hash, downloading it if needed.
+///
+/// Downloaded files are cached by their hash in the user's cache directory.
+pub fn fetch_all(files: &[Fetch]) -> color_eyre::Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let cache = dirs::cache_dir()
+        .ok_or(eyre::eyre!("could not find cache directory"))?
+        .join("autocast")
+        .join("fetch");
+    for file in files {
+        file.fetch(&cache)
+            .wrap_err_with(|| format!("could not fetch `{}`", file.url))?;
+    }
+    Ok(())
+}
+
+impl Fetch {
+    fn fetch(&self, cache: &Path) -> color_eyre::Result<()> {
+        let sha256 = self.sha256.to_ascii_lowercase();
+        if hash_file(&self.path).ok().as_ref() == Some(&sha256) {
+            return Ok(());
+        }
+
+        let cached = cache.join(&sha256);
+        if hash_file(&cached).ok().as_ref() != Some(&sha256) {
+            let mut data = Vec::new();
+            ureq::get(&self.url)
+                .call()?
+                .into_reader()
+                .read_to_end(&mut data)
+                .wrap_err("could not read response")?;
+            let actual = format!("{:x}", Sha256::digest(&data));
+            eyre::ensure!(
+                actual == sha256,
+                "SHA-256 of downloaded file is {actual}, expected {sha256}"
+            );
+            fs::create_dir_all(cache).wrap_err("could not create cache directory")?;
+            fs::write(&cached, data).wrap_err("could not write to cache")?;
+            eprintln!("       Fetched: {}", self.url);
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&cached, &self.path)
+            .wrap_err_with(|| format!("could not copy to `{}`", self.path.display()))?;
+        Ok(())
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Serves `body` to each of `count` requests.
+    fn serve(body: &'static str, count: usize) -> io::Result<String> {
+        let server = TcpListener::bind(("127.0.0.1", 0))?;
+        let url = format!("http://{}/file.txt", server.local_addr()?);
+        thread::spawn(move || -> io::Result<()> {
+            for _ in 0..count {
+                let (stream, _) = server.accept()?;
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line)? > 2 {
+                    line.clear();
+                }
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                )?;
+            }
+            Ok(())
+        });
+        Ok(url)
+    }
+
+    #[test]
+    fn fetch() -> color_eyre::Result<()> {
+        let dir = TempDir::new()?;
+        let cache = dir.path().join("cache");
+        let file = Fetch {
+            // Only one request is served, so the second fetch must use the cache.
+            url: serve("hello", 1)?,
+            path: dir.path().join("out/hello.txt"),
+            sha256: format!("{:x}", Sha256::digest("hello")),
+        };
+        file.fetch(&cache)?;
+        assert_eq!(fs::read_to_string(&file.path)?, "hello");
+
+        fs::write(&file.path, "changed")?;
+        file.fetch(&cache)?;
+        assert_eq!(fs::read_to_string(&file.path)?, "hello");
+
+        let wrong = Fetch {
+            url: serve("hello", 1)?,
+            path: dir.path().join("wrong.txt"),
+            sha256: format!("{:x}", Sha256::digest("goodbye")),
+        };
+        assert!(wrong.fetch(&cache).is_err());
+        assert!(!wrong.path.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+//! The user's config file of default settings, for every script they run,
+//! i.e. "~/.config/autocast/config.yaml".
+
+use std::{
+    fs, io, mem,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::Context;
+
+use super::{Merge, Script, Settings};
+
+/// Path of the config file in the user's config directory.
+fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|config| config.join("autocast").join("config.yaml"))
+}
+
+impl Script {
+    /// Uses the settings of the user's config file as defaults,
+    /// the script's settings take priority.
+    ///
+    /// Nothing changes if the config file doesn't exist.
+    pub fn merge_global_settings(&mut self) -> color_eyre::Result<()> {
+        let Some(path) = path() else {
+            return Ok(());
+        };
+        if let Some(settings) = read(&path)? {
+            let script = mem::replace(&mut self.settings, settings);
+            self.settings.merge(script);
+        }
+        Ok(())
+    }
+}
+
+/// Reads the settings in the config file at `path`,
+/// with relative paths resolved against its directory.
+fn read(path: &Path) -> color_eyre::Result<Option<Settings>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => {
+            return Err(error)
+                .wrap_err_with(|| format!("could not read config file `{}`", path.display()))
+        }
+    };
+    let settings = serde_yaml::from_str(&contents)
+        .wrap_err_with(|| format!("could not parse config file `{}`", path.display()))?;
+    let mut config = Script {
+        settings,
+        matrix: Vec::new(),
+        instructions: Vec::new(),
+    };
+    config.resolve_paths(path.parent().unwrap_or(Path::new("")));
+    Ok(Some(config.settings))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn read() -> color_eyre::Result<()> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join("config.yaml");
+        assert!(super::read(&path)?.is_none());
+
+        fs::write(&path, "type_speed: 50ms\nsandbox_template: template\n")?;
+        let settings = super::read(&path)?.expect("config file exists");
+        assert_eq!(settings.type_speed, Duration::from_millis(50));
+        assert_eq!(settings.sandbox_template, Some(dir.path().join("template")));
+
+        fs::write(&path, "type_speed: fast\n")?;
+        assert!(super::read(&path).is_err());
+        Ok(())
+    }
+}
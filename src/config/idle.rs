@@ -0,0 +1,39 @@
+//! Clamping the idle time between events, so commands which stall don't leave long pauses
+//! in the asciicast, unlike the header's idle time limit which not all players honor.
+
+use std::time::Duration;
+
+use crate::asciicast::Event;
+
+/// Shortens each gap between events, and before the first event, to at most `max`,
+/// moving the following events earlier.
+pub fn clamp(events: &mut [Event], max: Duration) {
+    let mut previous = Duration::ZERO;
+    let mut removed = Duration::ZERO;
+    for event in events {
+        removed += event.time.saturating_sub(previous).saturating_sub(max);
+        previous = event.time;
+        event.time = event.time.saturating_sub(removed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp() {
+        let mut events: Vec<_> = [500, 3000, 3100, 10_000]
+            .into_iter()
+            .map(|millis| Event::output(Duration::from_millis(millis), String::new()))
+            .collect();
+        super::clamp(&mut events, Duration::from_secs(2));
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event.time.as_millis())
+                .collect::<Vec<_>>(),
+            [500, 2500, 2600, 4600]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Splicing the instructions of other scripts into a script with `!Include` instructions,
+//! so setup shared by a suite of demos isn't duplicated.
+
+use std::{
+    mem,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{self, Context};
+
+use super::{Instruction, Script};
+
+impl Script {
+    /// Replaces each `!Include` instruction with the instructions of the script it includes,
+    /// recursively. The included scripts' settings are ignored.
+    ///
+    /// `path` is the path of this script, and its included paths are relative to `base_dir`,
+    /// i.e. its directory or `--base-dir`. The paths included by included scripts are
+    /// relative to the directory of the script which includes them.
+    /// Included scripts are read with `read`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an included script can't be read, or includes itself.
+    pub fn resolve_includes(
+        &mut self,
+        path: &Path,
+        base_dir: &Path,
+        read: &impl Fn(&Path) -> color_eyre::Result<Script>,
+    ) -> color_eyre::Result<()> {
+        self.resolve_includes_from(path, base_dir, read, &mut Vec::new())
+    }
+
+    /// `stack` is the canonical and given path of each script currently being included,
+    /// to detect cycles.
+    fn resolve_includes_from(
+        &mut self,
+        path: &Path,
+        base_dir: &Path,
+        read: &impl Fn(&Path) -> color_eyre::Result<Script>,
+        stack: &mut Vec<(PathBuf, PathBuf)>,
+    ) -> color_eyre::Result<()> {
+        if !self
+            .instructions
+            .iter()
+            .flat_map(Instruction::flatten)
+            .any(|instruction| matches!(instruction, Instruction::Include(_)))
+        {
+            return Ok(());
+        }
+
+        let canonical = path
+            .canonicalize()
+            .wrap_err_with(|| format!("could not find `{}`", path.display()))?;
+        if let Some(start) = stack.iter().position(|(other, _)| *other == canonical) {
+            let cycle = stack[start..]
+                .iter()
+                .map(|(_, path)| path.display().to_string())
+                .chain([path.display().to_string()])
+                .collect::<Vec<_>>();
+            eyre::bail!("include cycle: {}", cycle.join(" -> "));
+        }
+        stack.push((canonical, path.to_path_buf()));
+
+        self.instructions = resolve(mem::take(&mut self.instructions), base_dir, read, stack)?;
+
+        stack.pop();
+        Ok(())
+    }
+}
+
+/// Replaces the `!Include` instructions in the `instructions`, and in the branches of their
+/// `!If` instructions, with the instructions of the scripts they include.
+fn resolve(
+    instructions: Vec<Instruction>,
+    base_dir: &Path,
+    read: &impl Fn(&Path) -> color_eyre::Result<Script>,
+    stack: &mut Vec<(PathBuf, PathBuf)>,
+) -> color_eyre::Result<Vec<Instruction>> {
+    let mut resolved = Vec::with_capacity(instructions.len());
+    for instruction in instructions {
+        match instruction {
+            Instruction::Include(include) => {
+                let include = base_dir.join(include);
+                let mut script = read(&include)
+                    .wrap_err_with(|| format!("could not include `{}`", include.display()))?;
+                let include_dir = include.parent().unwrap_or(Path::new(""));
+                script.resolve_includes_from(&include, include_dir, read, stack)?;
+                resolved.extend(script.instructions);
+            }
+            Instruction::If {
+                var_matches,
+                then,
+                otherwise,
+            } => resolved.push(Instruction::If {
+                var_matches,
+                then: resolve(then, base_dir, read, stack)?,
+                otherwise: resolve(otherwise, base_dir, read, stack)?,
+            }),
+            instruction => resolved.push(instruction),
+        }
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn read(path: &Path) -> color_eyre::Result<Script> {
+        Ok(Script::try_from_yaml(fs::File::open(path)?)?)
+    }
+
+    #[test]
+    fn resolve_includes() -> color_eyre::Result<()> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join("demo.yaml");
+        fs::create_dir(dir.path().join("common"))?;
+        fs::write(
+            &path,
+            "instructions:\n  - !Include common/setup.yaml\n  - !Marker demo\n",
+        )?;
+        fs::write(
+            dir.path().join("common/setup.yaml"),
+            "settings:\n  width: 100\ninstructions:\n  - !Marker setup\n  - !Include clean.yaml\n",
+        )?;
+        fs::write(
+            dir.path().join("common/clean.yaml"),
+            "instructions:\n  - !Marker clean\n",
+        )?;
+
+        let mut script = read(&path)?;
+        script.resolve_includes(&path, dir.path(), &read)?;
+        let markers: Vec<_> = script
+            .instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Marker(marker) => marker.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(markers, ["setup", "clean", "demo"]);
+        assert_eq!(script.settings.width, None);
+
+        // Relative to the base directory instead of the script's directory.
+        fs::create_dir(dir.path().join("scripts"))?;
+        let other = dir.path().join("scripts/other.yaml");
+        fs::write(&other, "instructions:\n  - !Include common/clean.yaml\n")?;
+        let mut script = read(&other)?;
+        script.resolve_includes(&other, dir.path(), &read)?;
+        assert!(matches!(
+            script.instructions.as_slice(),
+            [Instruction::Marker(marker)] if marker == "clean"
+        ));
+
+        fs::write(
+            dir.path().join("common/clean.yaml"),
+            "instructions:\n  - !Include ../demo.yaml\n",
+        )?;
+        let mut script = read(&path)?;
+        let error = script
+            .resolve_includes(&path, dir.path(), &read)
+            .unwrap_err();
+        assert!(format!("{error:#}").contains("include cycle: "));
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+//! Extracting a script from the code blocks of a Markdown file,
+//! so the documentation is the source of the demo.
+//!
+//! Code blocks tagged "autocast" contain YAML, either a sequence of instructions or a map
+//! with settings and instructions. Code blocks tagged "console" contain a shell session,
+//! where each line starting with "$ " is a command and the other lines are its output.
+
+use color_eyre::eyre::{self, Context};
+use serde::Deserialize;
+
+use super::{Command, Instruction, Script, Settings};
+
+/// Part of a script in an "autocast" code block.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Fragment {
+    #[serde(default)]
+    settings: Option<Settings>,
+    #[serde(default)]
+    instructions: Vec<Instruction>,
+}
+
+/// A fenced code block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CodeBlock<'a> {
+    /// First word of the info string
+    language: &'a str,
+    /// Line number of the opening fence, starting from 1
+    line: usize,
+    lines: Vec<&'a str>,
+}
+
+impl Script {
+    /// Creates a script from the "autocast" and "console" code blocks of a Markdown file,
+    /// in the order they appear.
+    pub fn try_from_markdown(markdown: &str) -> color_eyre::Result<Self> {
+        let mut script = Self {
+            settings: Settings::default(),
+            matrix: Vec::new(),
+            instructions: Vec::new(),
+        };
+        for block in code_blocks(markdown) {
+            match block.language {
+                "autocast" => {
+                    let Fragment {
+                        settings,
+                        instructions,
+                    } = parse_autocast(&block.lines).wrap_err_with(|| {
+                        format!("could not parse the code block at line {}", block.line)
+                    })?;
+                    if let Some(settings) = settings {
+                        script.merge_settings(settings);
+                    }
+                    script.instructions.extend(instructions);
+                }
+                "console" => script.instructions.extend(console_commands(&block.lines)),
+                _ => {}
+            }
+        }
+        eyre::ensure!(
+            !script.instructions.is_empty(),
+            "no instructions found, add code blocks tagged \"autocast\" or \"console\""
+        );
+        Ok(script)
+    }
+}
+
+/// The code blocks of the `markdown` which are fenced with backticks or tildes.
+fn code_blocks(markdown: &str) -> Vec<CodeBlock<'_>> {
+    let mut blocks = Vec::new();
+    let mut lines = markdown.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let Some((opening, info)) = fence(line) else {
+            continue;
+        };
+        let mut block = CodeBlock {
+            language: info.split_whitespace().next().unwrap_or_default(),
+            line: index + 1,
+            lines: Vec::new(),
+        };
+        // An unclosed block continues to the end of the document.
+        for (_, line) in lines.by_ref() {
+            // Closed by a fence of the same char which is at least as long.
+            let closes = fence(line)
+                .is_some_and(|(closing, info)| info.is_empty() && closing.starts_with(opening));
+            if closes {
+                break;
+            }
+            block.lines.push(line);
+        }
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Splits a fence line into the fence and the info string.
+fn fence(line: &str) -> Option<(&str, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let line = &line[indent..];
+    let char = line
+        .chars()
+        .next()
+        .filter(|char| matches!(char, '`' | '~'))?;
+    let length = line.len() - line.trim_start_matches(char).len();
+    if length < 3 {
+        return None;
+    }
+    let (fence, info) = line.split_at(length);
+    Some((fence, info.trim()))
+}
+
+fn parse_autocast(lines: &[&str]) -> serde_yaml::Result<Fragment> {
+    let yaml = lines.join("\n");
+    let is_sequence = lines
+        .iter()
+        .map(|line| line.trim_start())
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .is_some_and(|line| line.starts_with('-'));
+    if is_sequence {
+        Ok(Fragment {
+            settings: None,
+            instructions: serde_yaml::from_str(&yaml)?,
+        })
+    } else {
+        serde_yaml::from_str(&yaml)
+    }
+}
+
+/// Commands of a shell session, lines ending with "\" are continued on the next line.
+fn console_commands(lines: &[&str]) -> Vec<Instruction> {
+    let mut commands = Vec::new();
+    let mut lines = lines.iter();
+    while let Some(line) = lines.next() {
+        let Some(mut line) = line.strip_prefix("$ ") else {
+            continue;
+        };
+        let mut command = Vec::new();
+        while let Some(continued) = line.strip_suffix('\\') {
+            command.push(continued.trim_end().to_owned());
+            let Some(next) = lines.next() else {
+                line = "";
+                break;
+            };
+            line = next.strip_prefix("> ").unwrap_or(next);
+        }
+        command.push(line.to_owned());
+        command.retain(|line| !line.is_empty());
+        let command = match <[String; 1]>::try_from(command) {
+            Ok([line]) => Command::SingleLine(line),
+            Err(lines) => Command::MultiLine(lines),
+        };
+        commands.push(Instruction::Command {
+            command,
+            hidden: false,
+            type_speed: None,
+            confirm: Vec::new(),
+            highlight_stderr: false,
+            output_filter: None,
+            allow_dangerous: false,
+        });
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn try_from_markdown() -> color_eyre::Result<()> {
+        let markdown = "\
+# Demo
+
+```autocast
+settings:
+  width: 100
+  type_speed: 50ms
+```
+
+Install it:
+
+```console
+$ cargo install \\
+> autocast
+    Updating crates.io index
+$ autocast --help
+```
+
+```sh
+not included
+```
+
+````autocast
+- !Wait 1s
+- !Marker done
+````
+";
+        let script = Script::try_from_markdown(markdown)?;
+        assert_eq!(script.settings.type_speed, Duration::from_millis(50));
+        let commands: Vec<_> = script
+            .instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Command { command, .. } => command.to_string(),
+                Instruction::Wait(wait) => format!("wait {wait:?}"),
+                Instruction::Marker(marker) => format!("marker {marker}"),
+                _ => String::new(),
+            })
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "cargo install autocast",
+                "autocast --help",
+                "wait 1s",
+                "marker done"
+            ]
+        );
+
+        let error = Script::try_from_markdown("text\n\n```autocast\n- !Wait\n```\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "could not parse the code block at line 3"
+        );
+        assert!(Script::try_from_markdown("```sh\nls\n```\n").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+//! Expanding a script with a matrix of variable values into a script for each combination,
+//! so demos of several versions or subcommands are created from one script.
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre;
+use itertools::Itertools;
+
+use super::{variables, EnvVar, Script};
+
+impl Script {
+    /// A script for each combination of the values of the variables in the script's matrix,
+    /// with the combination added to its variables, and the output file for it.
+    ///
+    /// The output file for a combination is `out_file` with its `${name}` references to the
+    /// matrix's variables expanded. A script without a matrix is returned as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a variable in the matrix has no values, or the output files of
+    /// the combinations aren't unique, i.e. `out_file` doesn't reference each variable.
+    pub fn matrix(&self, out_file: &Path) -> color_eyre::Result<Vec<(Self, PathBuf)>> {
+        if self.matrix.is_empty() {
+            return Ok(vec![(self.clone(), out_file.to_path_buf())]);
+        }
+        if let Some((name, _)) = self.matrix.iter().find(|(_, values)| values.is_empty()) {
+            eyre::bail!("matrix variable `{name}` has no values");
+        }
+
+        let out_file = out_file.to_string_lossy();
+        let mut scripts: Vec<(Self, PathBuf)> = Vec::new();
+        let combinations = self
+            .matrix
+            .iter()
+            .map(|(name, values)| values.iter().map(move |value| (name, value)))
+            .multi_cartesian_product();
+        for combination in combinations {
+            let combination: Vec<_> = combination
+                .into_iter()
+                .map(|(name, value)| EnvVar {
+                    name: name.clone(),
+                    value: value.clone(),
+                })
+                .collect();
+            let combination_out_file = PathBuf::from(variables::expand(&out_file, &combination));
+            eyre::ensure!(
+                scripts
+                    .iter()
+                    .all(|(_, path)| *path != combination_out_file),
+                "the output file `{}` is the same for several combinations of the matrix, \
+                    reference each matrix variable in it, i.e. `demo-${{{}}}.cast`",
+                combination_out_file.display(),
+                self.matrix[0].0,
+            );
+            let mut script = self.clone();
+            script.matrix.clear();
+            script.settings.variables.extend(combination);
+            scripts.push((script, combination_out_file));
+        }
+        Ok(scripts)
+    }
+
+    /// The `out_file` with a reference to each of the matrix's variables added to its name,
+    /// i.e. "demo.cast" becomes "demo-${version}.cast".
+    pub fn matrix_out_file(&self, out_file: &Path) -> PathBuf {
+        let mut file_name = out_file.file_stem().unwrap_or_default().to_os_string();
+        for (name, _) in &self.matrix {
+            file_name.push(format!("-${{{name}}}"));
+        }
+        if let Some(extension) = out_file.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        out_file.with_file_name(file_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matrix() -> color_eyre::Result<()> {
+        let script = Script::try_from_yaml(
+            "
+            settings:
+              variables:
+                shell: bash
+            matrix:
+              version: [\"1.0\", \"2.0\"]
+              command: [init, build]
+            instructions:
+              - !Command
+                command: tool-${version} ${command}
+            "
+            .as_bytes(),
+        )?;
+        let scripts = script.matrix(Path::new("demo-${version}-${command}.cast"))?;
+        let out_files: Vec<_> = scripts
+            .iter()
+            .map(|(_, path)| path.to_string_lossy())
+            .collect();
+        assert_eq!(
+            out_files,
+            [
+                "demo-1.0-init.cast",
+                "demo-1.0-build.cast",
+                "demo-2.0-init.cast",
+                "demo-2.0-build.cast",
+            ]
+        );
+        let variables: Vec<_> = scripts[1]
+            .0
+            .settings
+            .variables
+            .iter()
+            .map(|variable| format!("{}={}", variable.name, variable.value))
+            .collect();
+        assert_eq!(variables, ["shell=bash", "version=1.0", "command=build"]);
+
+        let error = script
+            .matrix(Path::new("demo-${version}.cast"))
+            .unwrap_err();
+        assert!(error.to_string().contains("`demo-1.0.cast`"));
+        assert_eq!(
+            script.matrix_out_file(Path::new("casts/demo.cast")),
+            Path::new("casts/demo-${version}-${command}.cast")
+        );
+        Ok(())
+    }
+}
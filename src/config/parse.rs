@@ -0,0 +1,302 @@
+//! Strict parsing of a [`Script`] which reports every error instead of only the first
+
+use std::fmt::{self, Display};
+
+use thiserror::Error;
+
+use super::{Instruction, Script, Settings};
+
+impl Script {
+    /// Parses a YAML script, collecting the errors of every invalid section and instruction.
+    ///
+    /// # Errors
+    ///
+    /// Returns all of the errors found, with their line and column when known.
+    pub fn parse_strict(yaml: &str) -> Result<Self, ParseErrors> {
+        let error = match Self::try_from_yaml(yaml.as_bytes()) {
+            Ok(script) => return Ok(script),
+            Err(error) => ParseError::from(error),
+        };
+
+        // Syntax errors or a document that is not a map can't be split up.
+        if serde_yaml::from_str::<serde_yaml::Mapping>(yaml).is_err() {
+            return Err(ParseErrors(vec![error]));
+        }
+
+        let lines: Vec<&str> = yaml.lines().collect();
+        let mut errors = Vec::new();
+        for (key, section) in top_level_sections(&lines) {
+            match key {
+                "settings" => {
+                    let fragment = fragment(&lines, section.clone(), section.start, key.len() + 1);
+                    if let Err(error) = serde_yaml::from_str::<Settings>(&fragment) {
+                        errors.push(ParseError::from(error).with_path(String::from(key)));
+                    }
+                }
+                "instructions" => {
+                    for (index, item) in sequence_items(&lines, section).into_iter().enumerate() {
+                        let fragment = fragment(&lines, item.range, item.start, item.marker);
+                        if let Err(error) = serde_yaml::from_str::<Instruction>(&fragment) {
+                            let mut error = ParseError::from(error);
+                            // Errors at the root of the fragment may not have a location.
+                            error.location.get_or_insert(Location {
+                                line: item.start + 1,
+                                column: item.marker + 2,
+                            });
+                            errors.push(error.with_path(format!("{key}[{index}]")));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if errors.is_empty() {
+            errors.push(error);
+        }
+        Err(ParseErrors(errors))
+    }
+}
+
+/// All errors found while parsing a [`Script`] with [`Script::parse_strict()`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub struct ParseErrors(pub Vec<ParseError>);
+
+impl Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (num, error) in self.0.iter().enumerate() {
+            if num != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An error found while parsing a [`Script`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Section or instruction of the script the error is in
+    pub path: Option<String>,
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{location}: ")?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "{path}: ")?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+impl From<serde_yaml::Error> for ParseError {
+    fn from(error: serde_yaml::Error) -> Self {
+        let location = error.location().map(|location| Location {
+            line: location.line(),
+            column: location.column(),
+        });
+        let mut message = error.to_string();
+        if let Some(location) = &location {
+            let suffix = format!(" at {location}");
+            if message.ends_with(&suffix) {
+                message.truncate(message.len() - suffix.len());
+            }
+        }
+        Self {
+            path: None,
+            message,
+            location,
+        }
+    }
+}
+
+impl ParseError {
+    /// Renders the line of `source` the error is on, with a caret pointing to the column.
+    /// `name` is the name of the source, usually the file path.
+    ///
+    /// Returns [`None`] if the error does not have a location or it's not in `source`.
+    ///
+    /// # Example
+    ///
+    /// ```text
+    ///  --> demo.yaml:2:10
+    ///   |
+    /// 2 |   width: wide
+    ///   |          ^ width: invalid value: string "wide", expected u16 or "auto"
+    /// ```
+    pub fn snippet(&self, source: &str, name: &str) -> Option<String> {
+        let Location { line, column } = self.location?;
+        let source_line = source.lines().nth(line.checked_sub(1)?)?;
+        let gutter = " ".repeat(line.to_string().len());
+        let caret_indent: String = source_line
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(|char| if char == '\t' { '\t' } else { ' ' })
+            .collect();
+        let message = &self.message;
+
+        Some(format!(
+            "{gutter}--> {name}:{line}:{column}\n\
+            {gutter} |\n\
+            {line} | {source_line}\n\
+            {gutter} | {caret_indent}^ {message}"
+        ))
+    }
+
+    fn with_path(mut self, path: String) -> Self {
+        self.path = Some(path);
+        self
+    }
+}
+
+/// Line and column of a [`ParseError`], both start at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {} column {}", self.line, self.column)
+    }
+}
+
+type Range = std::ops::Range<usize>;
+
+/// Finds the keys of the top level map and the range of lines each key's section spans.
+pub(super) fn top_level_sections<'a>(lines: &[&'a str]) -> Vec<(&'a str, Range)> {
+    let mut sections: Vec<(&str, Range)> = Vec::new();
+    for (line_num, line) in lines.iter().enumerate() {
+        if line.starts_with(char::is_whitespace) || line.starts_with('#') {
+            continue;
+        }
+        if let Some((key, _)) = line.split_once(':') {
+            if let Some((_, section)) = sections.last_mut() {
+                section.end = line_num;
+            }
+            sections.push((key.trim(), line_num..lines.len()));
+        }
+    }
+    sections
+}
+
+/// An item of a YAML block sequence
+pub(super) struct Item {
+    /// Lines the item spans
+    pub range: Range,
+    /// Line the item starts on
+    pub start: usize,
+    /// Length of the item's indent and "-" marker
+    pub marker: usize,
+}
+
+/// Finds the items of a block sequence in `section`.
+pub(super) fn sequence_items(lines: &[&str], section: Range) -> Vec<Item> {
+    let mut items: Vec<Item> = Vec::new();
+    let mut indent = None;
+    let end = section.end;
+    for line_num in section {
+        let line = lines[line_num];
+        let trimmed = line.trim_start();
+        let line_indent = line.len() - trimmed.len();
+        let is_item = trimmed.starts_with("- ") || trimmed == "-";
+        if is_item && indent.map_or(true, |indent| indent == line_indent) {
+            indent = Some(line_indent);
+            if let Some(item) = items.last_mut() {
+                item.range.end = line_num;
+            }
+            items.push(Item {
+                range: line_num..end,
+                start: line_num,
+                marker: line_indent + 1,
+            });
+        }
+    }
+    items
+}
+
+/// Creates a YAML document of only the lines in `range`, keeping their positions.
+///
+/// All other lines are blanked and the first `blank` characters of the `start` line
+/// are replaced with spaces.
+fn fragment(lines: &[&str], range: Range, start: usize, blank: usize) -> String {
+    let mut fragment = String::new();
+    for (line_num, line) in lines.iter().enumerate() {
+        if line_num == start {
+            fragment.push_str(&" ".repeat(blank));
+            fragment.push_str(line.get(blank..).unwrap_or_default());
+        } else if range.contains(&line_num) {
+            fragment.push_str(line);
+        }
+        fragment.push('\n');
+    }
+    fragment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid() {
+        assert!(Script::parse_strict("instructions:\n  - !Clear\n").is_ok());
+    }
+
+    #[test]
+    fn syntax_error() {
+        let errors = Script::parse_strict("instructions: [\n").unwrap_err();
+        assert_eq!(errors.0.len(), 1);
+        assert!(errors.0[0].location.is_some());
+    }
+
+    #[test]
+    fn snippet() {
+        let source = "settings:\n  width: wide\ninstructions: []\n";
+        let errors = Script::parse_strict(source).unwrap_err();
+        assert_eq!(
+            errors.0[0].snippet(source, "test.yaml").unwrap(),
+            " --> test.yaml:2:10\n  \
+              |\n\
+            2 |   width: wide\n  \
+              |          ^ width: invalid value: string \"wide\", expected u16 or \"auto\""
+        );
+    }
+
+    #[test]
+    fn all_errors() {
+        let errors = Script::parse_strict(
+            "settings:
+  type_speed: fast
+instructions:
+  - !Command
+    command: ls
+  - !Wait 1
+  - !Clear
+  - !Unknown
+",
+        )
+        .unwrap_err();
+        let locations: Vec<_> = errors
+            .0
+            .iter()
+            .map(|error| error.location.map(|location| location.line))
+            .collect();
+        assert_eq!(locations, [Some(2), Some(6), Some(8)]);
+        let paths: Vec<_> = errors.0.iter().map(|error| error.path.as_deref()).collect();
+        assert_eq!(
+            paths,
+            [
+                Some("settings"),
+                Some("instructions[1]"),
+                Some("instructions[3]")
+            ]
+        );
+    }
+}
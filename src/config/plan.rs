@@ -0,0 +1,156 @@
+//! Planning how a script would be run, with its settings resolved,
+//! without spawning the shell, i.e. to check a script before a long recording.
+
+use std::{fmt::Write as _, time::Duration};
+
+use color_eyre::eyre;
+
+use super::{auto_width, step, terminal_size, Instruction, PromptPreset, Script, Width};
+
+impl Script {
+    /// Describes the effective shell, terminal size, and prompt, and each instruction
+    /// with its type speed and the timeout for the shell's prompt, if it has them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the terminal width or height is not provided
+    /// and the terminal size could not be found.
+    pub fn plan(&self) -> color_eyre::Result<String> {
+        let settings = &self.settings;
+        let prompt = settings
+            .prompt_preset
+            .map_or_else(|| settings.prompt.clone(), PromptPreset::prompt);
+        let width = settings.width.map(|width| match width {
+            Width::Columns(columns) => columns,
+            Width::Auto => auto_width(
+                &self.instructions,
+                &prompt,
+                &settings.secondary_prompt,
+                &settings.shell,
+            ),
+        });
+        let (width, height) = terminal_size(width, settings.height).ok_or(eyre::eyre!(
+            "terminal width or height not provided and could not get terminal size"
+        ))?;
+
+        let mut plan = String::new();
+        let _ = writeln!(
+            plan,
+            "Shell: {} ({})",
+            settings.shell,
+            settings.shell.path()
+        );
+        let _ = writeln!(plan, "Terminal size: {width}x{height}");
+        let _ = writeln!(plan, "Prompt: {prompt:?}");
+        let _ = write!(plan, "Instructions:");
+        for (num, instruction) in self.instructions.iter().enumerate() {
+            let _ = write!(plan, "\n  {num} ");
+            self.plan_instruction(&mut plan, instruction, 2);
+        }
+        Ok(plan)
+    }
+
+    /// Adds the description of the `instruction` to the `plan`,
+    /// with the instructions in its branches on the following lines, indented further.
+    fn plan_instruction(&self, plan: &mut String, instruction: &Instruction, indent: usize) {
+        let settings = &self.settings;
+        let type_speed = |type_speed: Option<Duration>| {
+            type_speed
+                .unwrap_or(settings.type_speed)
+                .div_f64(settings.typing_speed_factor)
+        };
+
+        plan.push_str(&step::describe(instruction));
+        match instruction {
+            Instruction::Command {
+                type_speed: speed,
+                hidden: false,
+                ..
+            }
+            | Instruction::Interactive {
+                type_speed: speed, ..
+            } => {
+                let _ = write!(
+                    plan,
+                    ": type speed {:?}, timeout {:?}",
+                    type_speed(*speed),
+                    settings.timeout
+                );
+            }
+            Instruction::Command { hidden: true, .. }
+            | Instruction::Capture { .. }
+            | Instruction::Settle(_)
+            | Instruction::Resize { .. } => {
+                let _ = write!(plan, ": timeout {:?}", settings.timeout);
+            }
+            Instruction::If {
+                then, otherwise, ..
+            } => {
+                for (branch, instructions) in [("then", then), ("else", otherwise)] {
+                    if instructions.is_empty() {
+                        continue;
+                    }
+                    let _ = write!(plan, "\n{:indent$}  {branch}:", "");
+                    for instruction in instructions {
+                        let _ = write!(plan, "\n{:indent$}    ", "");
+                        self.plan_instruction(plan, instruction, indent + 4);
+                    }
+                }
+            }
+            Instruction::Wait(_)
+            | Instruction::Marker(_)
+            | Instruction::Title(_)
+            | Instruction::Clear
+            | Instruction::Include(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan() {
+        let script = Script::try_from_yaml(
+            "
+            settings:
+              width: 80
+              height: 24
+              type_speed: 100ms
+              typing_speed_factor: 2
+              timeout: 5s
+              shell:
+                program: autocast-missing-shell
+                prompt: '$ '
+                line_split: ' \\'
+            instructions:
+              - !Command
+                command: ls
+              - !Wait 1s
+              - !If
+                var_matches:
+                  os: linux
+                then:
+                  - !Interactive
+                    command: vim
+                    keys: [q, ^M]
+                    type_speed: 50ms
+            "
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            script.plan().unwrap(),
+            "Shell: autocast-missing-shell (autocast-missing-shell)\n\
+                Terminal size: 80x24\n\
+                Prompt: \"$ \"\n\
+                Instructions:\n  \
+                0 Command `ls`: type speed 50ms, timeout 5s\n  \
+                1 Wait 1s\n  \
+                2 If os matches `linux`\n    \
+                then:\n      \
+                Interactive `vim` with 2 keys: type speed 25ms, timeout 5s"
+        );
+    }
+}
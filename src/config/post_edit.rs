@@ -0,0 +1,137 @@
+//! Editing a recording's events with an external program before they are written.
+
+use std::{
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    process::{Command, Stdio},
+    thread,
+};
+
+use color_eyre::eyre::{self, Context};
+
+use crate::asciicast::Event;
+
+/// Pipes `events` through `command` as JSON lines, returning the events it outputs.
+///
+/// `command` is split on whitespace into the program and its arguments.
+/// Each event is a line with its absolute time, i.e. `[1.5, "o", "hello"]`,
+/// and each line of the program's output is parsed the same way, blank lines are ignored.
+pub fn post_edit(events: &[Event], command: &str) -> color_eyre::Result<Vec<Event>> {
+    let mut args = command.split_whitespace();
+    let program = args
+        .next()
+        .ok_or(eyre::eyre!("post edit command is empty"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .wrap_err_with(|| format!("could not run post edit command `{command}`"))?;
+
+    let stdin = child.stdin.take().expect("stdin is piped");
+    let input = events
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<serde_json::Result<Vec<_>>>()
+        .wrap_err("could not serialize events")?;
+    // Written from another thread so a program which outputs before reading everything
+    // can't fill its stdout pipe and block.
+    let writer = thread::spawn(move || -> io::Result<()> {
+        let mut stdin = BufWriter::new(stdin);
+        for line in input {
+            writeln!(stdin, "{line}")?;
+        }
+        stdin.flush()
+    });
+
+    // Likewise, read from another thread so a program which writes a lot to stderr
+    // before closing stdout can't fill its stderr pipe and block.
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let error_reader = thread::spawn(move || -> io::Result<Vec<u8>> {
+        let mut error = Vec::new();
+        stderr.read_to_end(&mut error)?;
+        Ok(error)
+    });
+
+    // All output is read before it is parsed, so the program isn't blocked by a parse error.
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let lines: Vec<_> = BufReader::new(stdout).lines().collect();
+    let events = lines
+        .into_iter()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|(num, line)| {
+            let line = line.wrap_err("could not read post edit command output")?;
+            serde_json::from_str(&line).wrap_err_with(|| {
+                format!(
+                    "could not parse line {} of post edit command output",
+                    num + 1
+                )
+            })
+        })
+        .collect::<color_eyre::Result<Vec<Event>>>();
+
+    let status = child
+        .wait()
+        .wrap_err("error waiting for post edit command")?;
+    let write_result = writer.join().expect("post edit writer thread panicked");
+    let error = error_reader
+        .join()
+        .expect("post edit error reader thread panicked")
+        .unwrap_or_default();
+    if !status.success() {
+        eyre::bail!(
+            "post edit command exited with {status}: {}",
+            String::from_utf8_lossy(&error).trim()
+        );
+    }
+    // A program may exit successfully without reading all of its input.
+    if let Err(error) = write_result {
+        if error.kind() != io::ErrorKind::BrokenPipe {
+            return Err(error).wrap_err("could not write events to post edit command");
+        }
+    }
+    events
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn events() -> Vec<Event> {
+        vec![
+            Event::output(Duration::from_millis(500), String::from("hello\r\n")),
+            Event::marker(Duration::from_secs(1), String::from("marker")),
+        ]
+    }
+
+    #[test]
+    fn round_trip() -> color_eyre::Result<()> {
+        assert_eq!(post_edit(&events(), "cat")?, events());
+        Ok(())
+    }
+
+    #[test]
+    fn edit() -> color_eyre::Result<()> {
+        let edited = post_edit(&events(), "grep -v marker")?;
+        assert_eq!(edited, events()[..1]);
+        Ok(())
+    }
+
+    #[test]
+    fn failure() {
+        assert!(post_edit(&events(), "false").is_err());
+    }
+
+    #[test]
+    fn large_stderr() -> color_eyre::Result<()> {
+        // More than a pipe buffer is written to stderr before stdout is closed.
+        let mut script = tempfile::NamedTempFile::new()?;
+        writeln!(script, "head -c 1000000 /dev/zero >&2; cat")?;
+        let command = format!("sh {}", script.path().display());
+        assert_eq!(post_edit(&events(), &command)?, events());
+        Ok(())
+    }
+}
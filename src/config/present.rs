@@ -0,0 +1,69 @@
+//! Presenting a script live in the terminal while recording it,
+//! advancing instruction by instruction on keypress.
+
+use std::{io, thread};
+
+use color_eyre::eyre;
+use console::{Key, Term};
+
+use crate::asciicast::{self, Event, EventType};
+
+use super::{step::Action, Instruction, Script};
+
+impl Script {
+    /// Runs the script, showing it in the terminal as it is recorded.
+    ///
+    /// Waits for a key before each command, so the presenter can talk between them.
+    pub fn present(self) -> color_eyre::Result<asciicast::File> {
+        eyre::ensure!(
+            Term::stderr().is_term(),
+            "presenting a script requires a terminal"
+        );
+        super::run_script(self, None, true, None, None)
+    }
+}
+
+/// Whether the presentation pauses before the `instruction`,
+/// only commands which are shown are waited for.
+pub fn pauses(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Command { hidden: false, .. } | Instruction::Interactive { .. }
+    )
+}
+
+/// Waits for the presenter to continue.
+pub fn wait_for_key() -> io::Result<Action> {
+    let term = Term::stderr();
+    loop {
+        match term.read_key()? {
+            Key::Enter | Key::Char(' ') | Key::ArrowRight | Key::PageDown => {
+                return Ok(Action::Run)
+            }
+            Key::Char('s') => return Ok(Action::Skip),
+            Key::Char('q') | Key::Escape => return Ok(Action::Abort),
+            _ => {}
+        }
+    }
+}
+
+/// Clears the terminal and shows the first `prompt` to start the presentation.
+pub fn start(prompt: &str) -> io::Result<()> {
+    let term = Term::stderr();
+    term.clear_screen()?;
+    term.write_str(prompt)?;
+    term.flush()
+}
+
+/// Shows the output `events` in the terminal, at the pace they are recorded at.
+pub fn play(events: &[Event]) -> io::Result<()> {
+    let term = Term::stderr();
+    for event in events {
+        if event.event_type != EventType::Output {
+            continue;
+        }
+        thread::sleep(event.time);
+        term.write_str(&event.data)?;
+    }
+    term.flush()
+}
@@ -0,0 +1,180 @@
+//! Probing that the script's shell starts and responds, without running the script,
+//! as a fast check before a long recording, i.e. in CI.
+
+use std::{
+    fmt::{self, Display},
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::{self, Context};
+use expectrl::session::OsProcess;
+
+use super::{
+    default_environment,
+    driver::{SessionDriver, Spawn, Terminal},
+    proxy::Proxy,
+    sandbox::Sandbox,
+    script_terminal_size,
+    spawn::ShellSession,
+    Backend, PromptPreset, Script, Shell, ShellStart,
+};
+
+/// Printed by the command sent to the shell
+const ECHOED: &str = "autocast-probe";
+
+/// How long the script's shell took to start and respond to a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Probe {
+    shell: String,
+    /// Time until the shell's prompt was detected
+    start: Duration,
+    /// Time from sending a command until the shell's next prompt
+    round_trip: Duration,
+}
+
+impl Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Shell {} started and its prompt was detected in {:.2?}, \
+                a command's round trip took {:.2?}",
+            self.shell, self.start, self.round_trip
+        )
+    }
+}
+
+impl Script {
+    /// Starts the script's shell like a recording would, checks that its prompt is detected,
+    /// and times a command which prints a known string, then quits the shell.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shell could not be started, its prompt was not detected,
+    /// or the command's output was not as expected.
+    pub fn probe(&self) -> color_eyre::Result<Probe> {
+        match self.settings.backend {
+            Backend::Pty => self.probe_with::<ShellSession<OsProcess, _>>(),
+            #[cfg(unix)]
+            Backend::Tmux => self.probe_with::<ShellSession<super::spawn::tmux::Tmux, _>>(),
+            #[cfg(not(unix))]
+            Backend::Tmux => Err(eyre::eyre!("the tmux backend is only supported on unix")),
+            Backend::Replay => Err(eyre::eyre!(
+                "the replay backend does not run a shell, so there is nothing to probe"
+            )),
+        }
+    }
+
+    /// Starts the shell with the terminal size, environment, sandbox, proxy, and retries
+    /// a recording would use. The proxy is stopped without writing any fixtures.
+    fn probe_with<D: Spawn>(&self) -> color_eyre::Result<Probe> {
+        let settings = &self.settings;
+        let prompt = settings
+            .prompt_preset
+            .map_or_else(|| settings.prompt.clone(), PromptPreset::prompt);
+        let (width, height) = script_terminal_size(
+            settings.width,
+            settings.height,
+            &self.instructions,
+            &prompt,
+            &settings.secondary_prompt,
+            &settings.shell,
+        )?;
+        let sandbox = Sandbox::start(settings.sandbox, settings.sandbox_template.as_deref())?;
+        let proxy = Proxy::start(settings.proxy, settings.proxy_fixtures.as_deref())?;
+        let shell_start = ShellStart {
+            shell: settings.shell.clone(),
+            shell_wrapper: settings.shell_wrapper.clone().unwrap_or_default(),
+            tmux_session: settings.tmux_session.clone(),
+            environment: &settings.environment,
+            sandbox: sandbox.as_ref(),
+            proxy: proxy.as_ref(),
+            default_environment: default_environment(
+                &settings.shell,
+                settings.disable_default_environment,
+                settings.force_color,
+            ),
+            disable_history: settings.disable_history,
+            disable_history_expansion: !settings.disable_default_environment,
+            capture_prompt: settings.capture_prompt,
+            binary_output: settings.binary_output,
+            terminal: Terminal {
+                width,
+                height,
+                disable_flow_control: settings.disable_flow_control,
+                disable_echoctl: settings.disable_echoctl,
+            },
+            timeout: settings.timeout,
+            spawn_retries: settings.spawn_retries,
+            spawn_retry_delay: settings.spawn_retry_delay,
+        };
+
+        let start = Instant::now();
+        let mut shell_session = shell_start.spawn::<D>()?;
+        let start = start.elapsed();
+
+        let round_trip = round_trip(&mut shell_session, &settings.shell)?;
+        shell_session.quit().wrap_err("could not exit shell")?;
+        Ok(Probe {
+            shell: settings.shell.to_string(),
+            start,
+            round_trip,
+        })
+    }
+}
+
+/// Sends a command which prints [`ECHOED`] and times how long until the shell's prompt.
+///
+/// # Errors
+///
+/// Returns an error if the prompt was not detected or [`ECHOED`] was not printed.
+fn round_trip<D: SessionDriver>(
+    shell_session: &mut D,
+    shell: &Shell,
+) -> color_eyre::Result<Duration> {
+    shell_session.reset();
+    let start = Instant::now();
+    shell_session
+        .send_line(echo(shell))
+        .wrap_err("could not send command to shell")?;
+    let events = shell_session
+        .read_until_prompt()
+        .wrap_err("could not detect prompt after a command")?;
+    let round_trip = start.elapsed();
+
+    let output: String = events.into_iter().map(|event| event.data).collect();
+    eyre::ensure!(
+        output
+            .lines()
+            .any(|line| line.trim_end_matches('\r') == ECHOED),
+        "unexpected output from the shell: {output:?}"
+    );
+    Ok(round_trip)
+}
+
+/// A command which prints [`ECHOED`] in the `shell`.
+fn echo(shell: &Shell) -> String {
+    match shell {
+        Shell::Python => format!("print({ECHOED:?})"),
+        Shell::Bash | Shell::Zsh | Shell::Nu | Shell::Cmd | Shell::Custom { .. } => {
+            format!("echo {ECHOED}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::driver::mock::Mock;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut mock = Mock::new("PROMPT", ["autocast-probe\r\nPROMPT"]);
+        super::round_trip(&mut mock, &Shell::Bash).unwrap();
+        assert_eq!(mock.sent, b"echo autocast-probe\n");
+
+        let mut mock = Mock::new("PROMPT", ["command not found\r\nPROMPT"]);
+        assert!(super::round_trip(&mut mock, &Shell::Python).is_err());
+        assert_eq!(mock.sent, b"print(\"autocast-probe\")\n");
+    }
+}
@@ -0,0 +1,119 @@
+//! Profiling where the time of a run is spent, to see why a script takes longer to run
+//! than its commands do.
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt::{self, Display},
+    time::{Duration, Instant},
+};
+
+use super::{step, Instruction};
+
+/// Number of instructions which waited the longest to list
+const SLOWEST: usize = 5;
+
+/// Time spent in each phase of a run.
+#[derive(Debug)]
+pub struct Profile {
+    start: Instant,
+    spawn: Cell<Duration>,
+    /// Sending commands and keys to the shell
+    typing: Cell<Duration>,
+    post_processing: Cell<Duration>,
+    /// Wait of the current instruction
+    wait: Cell<Duration>,
+    /// Number, description, and wait of each instruction which waited for the shell
+    waits: RefCell<Vec<(usize, String, Duration)>>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            spawn: Cell::default(),
+            typing: Cell::default(),
+            post_processing: Cell::default(),
+            wait: Cell::default(),
+            waits: RefCell::default(),
+        }
+    }
+
+    pub fn add_spawn(&self, duration: Duration) {
+        self.spawn.set(self.spawn.get() + duration);
+    }
+
+    pub fn add_typing(&self, duration: Duration) {
+        self.typing.set(self.typing.get() + duration);
+    }
+
+    /// Adds time the current instruction spent waiting for the shell's output.
+    pub fn add_wait(&self, duration: Duration) {
+        self.wait.set(self.wait.get() + duration);
+    }
+
+    pub fn add_post_processing(&self, duration: Duration) {
+        self.post_processing
+            .set(self.post_processing.get() + duration);
+    }
+
+    /// Records the wait of the `instruction` which just finished.
+    pub fn finish_instruction(&self, num: usize, instruction: &Instruction) {
+        let wait = self.wait.take();
+        if !wait.is_zero() {
+            self.waits
+                .borrow_mut()
+                .push((num, step::describe(instruction), wait));
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let waits = self.waits.borrow();
+        let total_wait: Duration = waits.iter().map(|(_, _, wait)| *wait).sum();
+        writeln!(f, "Profile, {:.2?} in total:", self.start.elapsed())?;
+        writeln!(f, "  spawning the shell: {:.2?}", self.spawn.get())?;
+        writeln!(f, "  typing commands and keys: {:.2?}", self.typing.get())?;
+        write!(f, "  waiting for output: {total_wait:.2?}")?;
+        let mut slowest: Vec<_> = waits.iter().collect();
+        slowest.sort_by_key(|(_, _, wait)| std::cmp::Reverse(*wait));
+        for (num, description, wait) in slowest.into_iter().take(SLOWEST) {
+            write!(f, "\n    instruction {num} {description}: {wait:.2?}")?;
+        }
+        write!(
+            f,
+            "\n  post-processing events: {:.2?}",
+            self.post_processing.get()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let profile = Profile::new();
+        profile.add_spawn(Duration::from_millis(100));
+        profile.add_typing(Duration::from_millis(5));
+        profile.add_wait(Duration::from_secs(2));
+        profile.finish_instruction(0, &Instruction::Settle(Duration::from_secs(1)));
+        profile.finish_instruction(1, &Instruction::Clear);
+        profile.add_wait(Duration::from_secs(3));
+        profile.finish_instruction(2, &Instruction::Settle(Duration::from_secs(2)));
+        let profile = profile.to_string();
+        let lines: Vec<_> = profile.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            [
+                "  spawning the shell: 100.00ms",
+                "  typing commands and keys: 5.00ms",
+                "  waiting for output: 5.00s",
+                "    instruction 2 Settle 2s: 3.00s",
+                "    instruction 0 Settle 1s: 2.00s",
+                "  post-processing events: 0.00ns",
+            ]
+        );
+    }
+}
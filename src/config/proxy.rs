@@ -0,0 +1,572 @@
+//! HTTP proxy which records responses to a fixtures file, or replays them,
+//! so commands making network requests produce the same output offline.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write as _,
+    fs,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+};
+
+use clap::ValueEnum;
+use color_eyre::{
+    eyre::{self, Context},
+    Section,
+};
+use serde::{Deserialize, Serialize};
+
+use super::Merge;
+
+/// Whether to run the proxy for the shell, and what it does
+#[derive(ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Don't run a proxy
+    #[default]
+    Off,
+    /// Forward requests and record the responses to the proxy fixtures
+    Record,
+    /// Respond to requests with the responses from the proxy fixtures
+    Replay,
+}
+
+impl Merge for Mode {
+    fn merge(&mut self, other: Self) {
+        if other != Self::default() {
+            *self = other;
+        }
+    }
+}
+
+/// A request and the raw HTTP response it received.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Exchange {
+    method: String,
+    url: String,
+    /// Stored as base64, so responses which are not valid UTF-8, like images, are kept intact
+    #[serde(with = "base64_bytes")]
+    response: Vec<u8>,
+}
+
+#[derive(Debug)]
+enum State {
+    Record {
+        exchanges: Vec<Exchange>,
+        /// Addresses of the HTTPS requests, which are encrypted so they can't be recorded
+        https: Vec<String>,
+    },
+    /// Responses for each method and URL, in the order they were recorded.
+    /// The last response is repeated once the others have been used.
+    Replay(HashMap<(String, String), VecDeque<Vec<u8>>>),
+}
+
+/// A running proxy, listening on localhost.
+#[derive(Debug)]
+pub struct Proxy {
+    address: SocketAddr,
+    fixtures: PathBuf,
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+}
+
+impl Proxy {
+    /// Starts the proxy, if `mode` is not [`Mode::Off`].
+    ///
+    /// In replay mode the exchanges are read from `fixtures`,
+    /// in record mode they are written to it by [`Proxy::finish()`].
+    pub fn start(mode: Mode, fixtures: Option<&Path>) -> color_eyre::Result<Option<Self>> {
+        if mode == Mode::Off {
+            return Ok(None);
+        }
+        let fixtures = fixtures
+            .ok_or(eyre::eyre!(
+                "the proxy requires proxy fixtures to be provided"
+            ))?
+            .to_path_buf();
+
+        let state = if mode == Mode::Record {
+            State::Record {
+                exchanges: Vec::new(),
+                https: Vec::new(),
+            }
+        } else {
+            let file = fs::File::open(&fixtures).wrap_err_with(|| {
+                format!("could not open proxy fixtures `{}`", fixtures.display())
+            })?;
+            let exchanges: Vec<Exchange> = serde_yaml::from_reader(BufReader::new(file))
+                .wrap_err("could not parse proxy fixtures")?;
+            State::Replay(replay_state(exchanges))
+        };
+        let state = Arc::new(Mutex::new(state));
+
+        let listener =
+            TcpListener::bind(("127.0.0.1", 0)).wrap_err("could not start proxy listener")?;
+        let address = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let listener = {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || listen(&listener, &state, &stop))
+        };
+
+        Ok(Some(Self {
+            address,
+            fixtures,
+            state,
+            stop,
+            listener: Some(listener),
+        }))
+    }
+
+    /// Environment variables which point programs in the shell to the proxy.
+    pub fn environment(&self) -> Vec<(&'static str, String)> {
+        let url = format!("http://{}", self.address);
+        ["HTTP_PROXY", "http_proxy", "HTTPS_PROXY", "https_proxy"]
+            .into_iter()
+            .map(|name| (name, url.clone()))
+            .collect()
+    }
+
+    /// Stops the proxy, writing the recorded exchanges to the fixtures in record mode.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an HTTPS request was made while recording,
+    /// as its response could not be recorded and would fail when replayed.
+    pub fn finish(mut self) -> color_eyre::Result<()> {
+        self.stop_listener();
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let State::Record { exchanges, https } = &*state {
+            if !https.is_empty() {
+                return Err(eyre::eyre!(
+                    "the proxy can't record HTTPS requests, made to: {}",
+                    https.join(", ")
+                ))
+                .suggestion("use plain http:// URLs for the requests to record");
+            }
+            let file = fs::File::create(&self.fixtures).wrap_err_with(|| {
+                format!(
+                    "could not create proxy fixtures `{}`",
+                    self.fixtures.display()
+                )
+            })?;
+            serde_yaml::to_writer(BufWriter::new(file), exchanges)
+                .wrap_err("could not write proxy fixtures")?;
+        }
+        Ok(())
+    }
+
+    fn stop_listener(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            self.stop.store(true, Ordering::SeqCst);
+            // Wakes the listener so it sees it should stop.
+            let _ = TcpStream::connect(self.address);
+            let _ = listener.join();
+        }
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.stop_listener();
+    }
+}
+
+fn replay_state(exchanges: Vec<Exchange>) -> HashMap<(String, String), VecDeque<Vec<u8>>> {
+    let mut responses: HashMap<_, VecDeque<_>> = HashMap::new();
+    for Exchange {
+        method,
+        url,
+        response,
+    } in exchanges
+    {
+        responses
+            .entry((method, url))
+            .or_default()
+            .push_back(response);
+    }
+    responses
+}
+
+/// Accepts connections until `stop` is set, handling each on its own thread.
+fn listen(listener: &TcpListener, state: &Arc<Mutex<State>>, stop: &AtomicBool) {
+    let mut connections = Vec::new();
+    for stream in listener.incoming() {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let state = Arc::clone(state);
+        connections.push(thread::spawn(move || {
+            if let Err(error) = handle(stream, &state) {
+                eprintln!("warning: proxy connection failed: {error}");
+            }
+        }));
+    }
+    // Requests still being handled are finished so they are recorded.
+    for connection in connections {
+        let _ = connection.join();
+    }
+}
+
+/// Handles one request, the connection is closed after the response.
+fn handle(stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let mut client = BufReader::new(stream);
+    let request = Request::read(&mut client)?;
+    let lock = || state.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let mut guard = lock();
+    match &mut *guard {
+        State::Record { https, .. } if request.method == "CONNECT" => {
+            // Refused, and reported by `Proxy::finish()`, instead of passed through unrecorded.
+            https.push(request.url.clone());
+            drop(guard);
+            let message = format!(
+                "autocast proxy: HTTPS to {} can't be recorded\n",
+                request.url
+            );
+            client.get_mut().write_all(bad_gateway(&message).as_bytes())
+        }
+        State::Record { exchanges, .. } => {
+            // Reserved now so exchanges are recorded in the order they were requested,
+            // without blocking other connections while waiting for the response.
+            let index = exchanges.len();
+            exchanges.push(Exchange {
+                method: request.method.clone(),
+                url: request.url.clone(),
+                response: Vec::new(),
+            });
+            drop(guard);
+
+            // Failures are recorded too, so they are replayed the same way.
+            let response = request.forward().unwrap_or_else(|error| {
+                let message = format!("autocast proxy: could not forward request: {error}\n");
+                bad_gateway(&message).into_bytes()
+            });
+            client.get_mut().write_all(&response)?;
+
+            if let State::Record { exchanges, .. } = &mut *lock() {
+                exchanges[index].response = response;
+            }
+            Ok(())
+        }
+        State::Replay(responses) => {
+            let key = (request.method.clone(), request.url.clone());
+            let response = match responses.get_mut(&key) {
+                Some(queue) if queue.len() > 1 => queue.pop_front(),
+                Some(queue) => queue.front().cloned(),
+                None => None,
+            };
+            drop(guard);
+            let response = response.unwrap_or_else(|| {
+                let message = if request.method == "CONNECT" {
+                    format!(
+                        "autocast proxy: HTTPS to {} can't be replayed\n",
+                        request.url
+                    )
+                } else {
+                    format!(
+                        "autocast proxy: no recorded response for {} {}\n",
+                        request.method, request.url
+                    )
+                };
+                bad_gateway(&message).into_bytes()
+            });
+            client.get_mut().write_all(&response)
+        }
+    }
+}
+
+/// A request read from a client of the proxy.
+#[derive(Debug, PartialEq, Eq)]
+struct Request {
+    method: String,
+    /// Absolute URL, or `host:port` for `CONNECT`
+    url: String,
+    version: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn read(reader: &mut impl BufRead) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(url), Some(version)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("invalid request line"));
+        };
+        let (method, url, version) = (method.to_owned(), url.to_owned(), version.to_owned());
+
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| invalid("invalid header"))?;
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+
+        let chunked = headers.iter().any(|(name, value)| {
+            name.eq_ignore_ascii_case("transfer-encoding")
+                && value.to_ascii_lowercase().contains("chunked")
+        });
+        let body = if chunked {
+            let body = read_chunked(reader)?;
+            // Forwarded with its length, as the chunks are decoded.
+            headers.retain(|(name, _)| {
+                !name.eq_ignore_ascii_case("transfer-encoding")
+                    && !name.eq_ignore_ascii_case("content-length")
+            });
+            headers.push((String::from("Content-Length"), body.len().to_string()));
+            body
+        } else {
+            let length = headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                .map(|(_, value)| value.parse().map_err(|_| invalid("invalid content length")))
+                .transpose()?
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            body
+        };
+
+        Ok(Self {
+            method,
+            url,
+            version,
+            headers,
+            body,
+        })
+    }
+
+    /// Sends the request to the server in its URL and returns the whole response.
+    fn forward(&self) -> io::Result<Vec<u8>> {
+        let (host, path) = split_url(&self.url).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("only http URLs can be proxied: {}", self.url),
+            )
+        })?;
+        let mut server = TcpStream::connect(host_port(host, 80))?;
+
+        let mut request = format!("{} {path} {}\r\n", self.method, self.version);
+        for (name, value) in &self.headers {
+            // The connection is closed after the response.
+            let skipped = ["connection", "proxy-connection", "keep-alive"];
+            if !skipped.iter().any(|skip| name.eq_ignore_ascii_case(skip)) {
+                let _ = write!(request, "{name}: {value}\r\n");
+            }
+        }
+        request.push_str("Connection: close\r\n\r\n");
+        server.write_all(request.as_bytes())?;
+        server.write_all(&self.body)?;
+
+        let mut response = Vec::new();
+        server.read_to_end(&mut response)?;
+        Ok(response)
+    }
+}
+
+/// Splits an `http://` URL into its host and path.
+fn split_url(url: &str) -> Option<(&str, &str)> {
+    let rest = url.strip_prefix("http://")?;
+    Some(match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    })
+}
+
+/// Adds the default port to `host` if it doesn't have one.
+fn host_port(host: &str, default_port: u16) -> String {
+    if host
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+    {
+        host.to_owned()
+    } else {
+        format!("{host}:{default_port}")
+    }
+}
+
+/// Reads a body with the chunked transfer encoding, ignoring chunk extensions and trailers.
+fn read_chunked(reader: &mut impl BufRead) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size"))?;
+        if size == 0 {
+            break;
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        // The line break after the chunk's data.
+        line.clear();
+        reader.read_line(&mut line)?;
+    }
+    // The trailers end with an empty line.
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            return Ok(body);
+        }
+    }
+}
+
+fn bad_gateway(message: &str) -> String {
+    format!(
+        "HTTP/1.1 502 Bad Gateway\r\n\
+        Content-Type: text/plain\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\r\n{message}",
+        message.len()
+    )
+}
+
+/// (De)serializes bytes as a base64 string.
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn get(proxy: &Proxy, url: &str) -> io::Result<String> {
+        let mut stream = TcpStream::connect(proxy.address)?;
+        write!(stream, "GET {url} HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    }
+
+    #[test]
+    fn read_request() -> io::Result<()> {
+        let raw = "POST http://example.com/api HTTP/1.1\r\n\
+            Content-Length: 4\r\n\r\nbody";
+        let request = Request::read(&mut raw.as_bytes())?;
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.url, "http://example.com/api");
+        assert_eq!(request.headers, [("Content-Length".into(), "4".into())]);
+        assert_eq!(request.body, b"body");
+
+        let raw = "POST http://example.com/api HTTP/1.1\r\n\
+            Transfer-Encoding: chunked\r\n\r\n\
+            4;name=value\r\nbody\r\n6\r\n chunk\r\n0\r\nTrailer: value\r\n\r\n";
+        let request = Request::read(&mut raw.as_bytes())?;
+        assert_eq!(request.headers, [("Content-Length".into(), "10".into())]);
+        assert_eq!(request.body, b"body chunk");
+        Ok(())
+    }
+
+    #[test]
+    fn binary_response() -> color_eyre::Result<()> {
+        let exchange = Exchange {
+            method: String::from("GET"),
+            url: String::from("http://example.com/image.png"),
+            response: vec![0x89, b'P', b'N', b'G', 0xff],
+        };
+        let yaml = serde_yaml::to_string(&exchange)?;
+        assert!(yaml.contains("response: iVBOR/8="));
+        assert_eq!(serde_yaml::from_str::<Exchange>(&yaml)?, exchange);
+        Ok(())
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            split_url("http://example.com:8080/a?b"),
+            Some(("example.com:8080", "/a?b"))
+        );
+        assert_eq!(split_url("http://example.com"), Some(("example.com", "/")));
+        assert_eq!(split_url("https://example.com"), None);
+        assert_eq!(host_port("example.com", 443), "example.com:443");
+        assert_eq!(host_port("[::1]:80", 443), "[::1]:80");
+    }
+
+    #[test]
+    fn record_and_replay() -> color_eyre::Result<()> {
+        // A server which responds to one request.
+        let server = TcpListener::bind(("127.0.0.1", 0))?;
+        let url = format!("http://{}/hello", server.local_addr()?);
+        let server = thread::spawn(move || -> io::Result<()> {
+            let (stream, _) = server.accept()?;
+            let mut reader = BufReader::new(stream);
+            Request::read(&mut reader)?;
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello")
+        });
+
+        let dir = TempDir::new()?;
+        let fixtures = dir.path().join("proxy.yaml");
+        let proxy = Proxy::start(Mode::Record, Some(&fixtures))?.expect("proxy is on");
+        let response = get(&proxy, &url)?;
+        assert!(response.ends_with("\r\n\r\nhello"));
+        server.join().expect("server panicked")?;
+        proxy.finish()?;
+
+        // The server is gone, so the response must come from the fixtures.
+        let proxy = Proxy::start(Mode::Replay, Some(&fixtures))?.expect("proxy is on");
+        assert_eq!(get(&proxy, &url)?, response);
+        assert_eq!(get(&proxy, &url)?, response);
+        assert!(get(&proxy, "http://localhost/missing")?.starts_with("HTTP/1.1 502"));
+        proxy.finish()?;
+
+        assert!(Proxy::start(Mode::Off, None)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn https_is_not_recorded() -> color_eyre::Result<()> {
+        let dir = TempDir::new()?;
+        let fixtures = dir.path().join("proxy.yaml");
+        let proxy = Proxy::start(Mode::Record, Some(&fixtures))?.expect("proxy is on");
+        let mut stream = TcpStream::connect(proxy.address)?;
+        write!(stream, "CONNECT example.com:443 HTTP/1.1\r\n\r\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert!(response.starts_with("HTTP/1.1 502"));
+
+        let error = proxy.finish().expect_err("HTTPS was requested");
+        assert!(error.to_string().contains("example.com:443"));
+        assert!(!fixtures.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+//! Sampling the resource usage of the commands the shell runs, from procfs on Linux,
+//! so a demo can double as a rough performance check.
+//!
+//! Elsewhere, and for backends without a local shell process, nothing is sampled.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Time between samples of the memory used
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Clock ticks per second of the times in procfs, which is fixed for userspace
+const USER_HZ: u64 = 100;
+
+/// Resource usage of the commands run during an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    /// User and system CPU time of the shell and its descendants
+    pub cpu_time: Duration,
+    /// Largest total resident memory of the shell's descendants, in bytes
+    pub peak_rss: u64,
+}
+
+impl Display for Usage {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CPU time {:.2?}, peak memory {:.1} MiB",
+            self.cpu_time,
+            self.peak_rss as f64 / 1024.0 / 1024.0
+        )
+    }
+}
+
+/// Samples the memory of the shell's descendants in a background thread until finished.
+///
+/// As memory is sampled periodically, commands which finish quickly may not be measured.
+#[derive(Debug)]
+pub struct Sampler {
+    shell: u32,
+    start_cpu_ticks: u64,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<u64>,
+}
+
+impl Sampler {
+    /// Starts sampling the descendants of the `shell` process.
+    ///
+    /// Returns [`None`] if procfs could not be read, i.e. when not on Linux.
+    pub fn start(shell: u32) -> Option<Self> {
+        let start_cpu_ticks = cpu_ticks(&processes()?, shell);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let stop = Arc::clone(&stop);
+            move || {
+                let mut peak_rss = 0;
+                loop {
+                    if let Some(processes) = processes() {
+                        peak_rss = peak_rss.max(rss(&processes, shell));
+                    }
+                    if stop.load(Ordering::Relaxed) {
+                        return peak_rss;
+                    }
+                    thread::sleep(SAMPLE_INTERVAL);
+                }
+            }
+        });
+        Some(Self {
+            shell,
+            start_cpu_ticks,
+            stop,
+            thread,
+        })
+    }
+
+    /// Stops sampling, returning the usage since the sampler was started.
+    pub fn finish(self) -> Usage {
+        self.stop.store(true, Ordering::Relaxed);
+        let peak_rss = self.thread.join().unwrap_or_default();
+        let ticks = processes().map_or(self.start_cpu_ticks, |processes| {
+            cpu_ticks(&processes, self.shell)
+        });
+        Usage {
+            cpu_time: Duration::from_millis(
+                ticks.saturating_sub(self.start_cpu_ticks) * 1000 / USER_HZ,
+            ),
+            peak_rss,
+        }
+    }
+}
+
+/// A process's fields from `/proc/<pid>/stat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stat {
+    parent: u32,
+    /// User and system time of the process, and of its children which it waited for
+    cpu_ticks: u64,
+}
+
+impl Stat {
+    /// Parses the contents of `/proc/<pid>/stat`.
+    fn parse(stat: &str) -> Option<Self> {
+        // The command's name is in parentheses and may contain spaces and parentheses.
+        let (_, fields) = stat.rsplit_once(')')?;
+        let fields: Vec<_> = fields.split_whitespace().collect();
+        let field = |index: usize| fields.get(index)?.parse::<u64>().ok();
+        // Indices are of the fields after the command's name, from proc(5):
+        // ppid, then utime, stime, cutime, and cstime.
+        Some(Self {
+            parent: fields.get(1)?.parse().ok()?,
+            cpu_ticks: field(11)? + field(12)? + field(13)? + field(14)?,
+        })
+    }
+}
+
+/// All processes, by their ID.
+fn processes() -> Option<HashMap<u32, Stat>> {
+    let processes = fs::read_dir("/proc")
+        .ok()?
+        .filter_map(|entry| {
+            let pid = entry.ok()?.file_name().to_str()?.parse().ok()?;
+            let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+            Some((pid, Stat::parse(&stat)?))
+        })
+        .collect();
+    Some(processes)
+}
+
+/// IDs of the descendants of the `root` process.
+fn descendants(processes: &HashMap<u32, Stat>, root: u32) -> Vec<u32> {
+    let mut descendants = vec![root];
+    let mut index = 0;
+    while let Some(&parent) = descendants.get(index) {
+        descendants.extend(
+            processes
+                .iter()
+                .filter(|(_, stat)| stat.parent == parent)
+                .map(|(&pid, _)| pid),
+        );
+        index += 1;
+    }
+    descendants.remove(0);
+    descendants
+}
+
+/// CPU time of the `shell` and its descendants.
+///
+/// Finished descendants are counted in the times of the process which waited for them.
+fn cpu_ticks(processes: &HashMap<u32, Stat>, shell: u32) -> u64 {
+    descendants(processes, shell)
+        .into_iter()
+        .chain([shell])
+        .filter_map(|pid| processes.get(&pid))
+        .map(|stat| stat.cpu_ticks)
+        .sum()
+}
+
+/// Resident memory of the `shell`'s descendants, in bytes.
+fn rss(processes: &HashMap<u32, Stat>, shell: u32) -> u64 {
+    descendants(processes, shell)
+        .into_iter()
+        .filter_map(|pid| {
+            let status = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+            let kib = status
+                .lines()
+                .find_map(|line| line.strip_prefix("VmRSS:"))?
+                .split_whitespace()
+                .next()?
+                .parse::<u64>()
+                .ok()?;
+            Some(kib * 1024)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_stat() {
+        let stat = "4242 (my (odd) cmd) S 4200 4242 4200 34816 4242 4194304 120 0 0 0 \
+            25 5 3 2 20 0 1 0 123456 9875456 256 18446744073709551615";
+        assert_eq!(
+            Stat::parse(stat),
+            Some(Stat {
+                parent: 4200,
+                cpu_ticks: 35,
+            })
+        );
+        assert_eq!(Stat::parse("4242 (cmd) S"), None);
+    }
+
+    #[test]
+    fn descendants() {
+        let stat = |parent| Stat {
+            parent,
+            cpu_ticks: 1,
+        };
+        let processes = [
+            (1, stat(0)),
+            (2, stat(1)),
+            (3, stat(2)),
+            (4, stat(1)),
+            (5, stat(9)),
+        ]
+        .into_iter()
+        .collect();
+        let mut descendants = super::descendants(&processes, 1);
+        descendants.sort_unstable();
+        assert_eq!(descendants, [2, 3, 4]);
+        assert_eq!(cpu_ticks(&processes, 1), 4);
+    }
+}
@@ -1,82 +1,519 @@
 use std::{
-    io, iter,
+    borrow::Cow,
+    cell::RefCell,
+    fmt, io, iter,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+    thread,
     time::{Duration, Instant},
 };
 
-use color_eyre::eyre::Context;
-use indicatif::{MultiProgress, ProgressDrawTarget, ProgressIterator, ProgressStyle};
+use color_eyre::eyre::{self, Context};
+use indicatif::{
+    MultiProgress, ProgressDrawTarget, ProgressIterator, ProgressState, ProgressStyle,
+};
 use itertools::Itertools;
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::asciicast::{Event, EventType};
+
+use super::{
+    calibrate::Measurement,
+    ci::Ci,
+    driver::{Backoff, SessionDriver, Timeout},
+    present,
+    profile::Profile,
+    resources::Sampler,
+    step,
+    timing_report::Report,
+    transcript::Transcript,
+    tui::Dashboard,
+    Command, EnvVar, Instruction, Key, OutputFilter, SharedProgress, TrailingOutput,
+};
+
+/// Settings for how instructions are rendered into events.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Options<'a> {
+    pub prompt: &'a str,
+    pub secondary_prompt: &'a str,
+    pub type_speed: Duration,
+    /// Added to the time of the line break which ends a typed command
+    pub pre_enter_delay: Duration,
+    /// Whether characters two columns wide take twice as long to type
+    pub double_width_pacing: bool,
+    pub line_split: &'a str,
+    /// Sent to the shell between the lines of a multiline command
+    pub line_continuation: &'a str,
+    /// Time between typed characters is divided by this
+    pub typing_speed_factor: f64,
+    /// Time between output from the shell is divided by this
+    pub output_speed_factor: f64,
+    /// Merge the output events at the start which occur at time zero
+    pub coalesce_leading_events: bool,
+    pub trailing_output: TrailingOutput,
+    /// Add markers when interactive commands enter and exit the alternate screen
+    pub alt_screen_markers: bool,
+    /// Clear the screen after interactive commands exit the alternate screen
+    pub clear_after_alt_screen: bool,
+    /// Remove the shell's own prompt from the end of each command's output,
+    /// as it is replaced by `prompt`
+    pub strip_prompt_line: bool,
+    /// Remove the line break the terminal echoes at the start of each command's output,
+    /// as the typed command already ends with one
+    pub strip_echoed_newline: bool,
+    /// Text to put before and after a command so each line of its stderr is wrapped in
+    /// [`STDERR_START`] and [`STDERR_END`], if the shell supports it
+    pub stderr_wrapper: Option<(&'a str, &'a str)>,
+    /// SGR parameters used to highlight stderr, i.e. "31" for red
+    pub stderr_style: &'a str,
+    /// The script's terminal width and height, which `!Resize` instructions default to
+    pub size: (u16, u16),
+    /// Wrap typed commands to fit in this many columns
+    pub wrap_width: Option<u16>,
+    /// Pause before each instruction until the user chooses to run, skip, or abort it
+    pub step: bool,
+    /// Show each instruction in the terminal as it is recorded, waiting for a key before
+    /// each command
+    pub present: bool,
+    /// CI service to report the output and time of each instruction to
+    pub ci: Ci,
+    /// Print how long each instruction waited for the shell, compared to the timeout
+    pub timing_report: bool,
+    /// Where to push a measurement of each instruction, when calibrating
+    pub measurements: Option<&'a RefCell<Vec<Measurement>>>,
+    /// Dashboard to show the status of each instruction on
+    pub dashboard: Option<&'a Dashboard>,
+    /// Where to add the time spent in each phase of the run
+    pub profile: Option<&'a Profile>,
+    /// The script's variables, which `!Capture` instructions add to,
+    /// set by [`instructions()`] if [`None`]
+    pub captures: Option<&'a RefCell<Vec<EnvVar>>>,
+    /// Where to add the typed commands and their output
+    pub transcript: Option<&'a RefCell<Transcript>>,
+    /// Progress bars to add the script's to, instead of drawing its own
+    pub progress: Option<SharedProgress<'a>>,
+}
+
+impl Options<'_> {
+    /// Speeds up the typing `events` by the typing speed factor.
+    fn typing(&self, events: impl Iterator<Item = Event>) -> impl Iterator<Item = Event> {
+        scale(events, self.typing_speed_factor)
+    }
+
+    /// Speeds up the shell output `events` by the output speed factor.
+    fn output(&self, events: impl Iterator<Item = Event>) -> impl Iterator<Item = Event> {
+        scale(events, self.output_speed_factor)
+    }
+
+    /// Where to draw the progress bars.
+    fn progress_draw_target(&self) -> ProgressDrawTarget {
+        // The annotations replace the progress bars in CI logs, as does the dashboard
+        // or presentation. Otherwise they're drawn to stderr, so the asciicast can be
+        // written to stdout.
+        if self.ci.is_enabled() || self.dashboard.is_some() || self.present {
+            ProgressDrawTarget::hidden()
+        } else {
+            ProgressDrawTarget::stderr()
+        }
+    }
+
+    /// Whether each instruction's events are collected once it runs,
+    /// so its output can be shown or measured.
+    fn collects_output(&self) -> bool {
+        self.step || self.present || self.ci.is_enabled() || self.measurements.is_some()
+    }
+
+    /// Adds the `command` and its `output` to the transcript, if writing one.
+    fn transcribe(&self, command: &Command, type_speed: Duration, output: &[Event]) {
+        if let Some(transcript) = self.transcript {
+            let typed = command
+                .events(type_speed, *self)
+                .map(|event| event.data)
+                .collect();
+            transcript
+                .borrow_mut()
+                .push(command.to_string(), typed, output);
+        }
+    }
+
+    /// Pushes a measurement of the `instruction` to the measurements, if calibrating.
+    fn measure(&self, instruction: &Instruction, duration: Duration, events: &str) {
+        if let Some(measurements) = self.measurements {
+            measurements
+                .borrow_mut()
+                .push(Measurement::new(duration, instruction, events));
+        }
+    }
+
+    /// The wrapper to highlight a command's stderr with, if it is `highlighted`.
+    fn stderr_wrapper(&self, highlighted: bool) -> color_eyre::Result<Option<(&str, &str)>> {
+        if highlighted {
+            self.stderr_wrapper.map(Some).ok_or(eyre::eyre!(
+                "highlighting stderr is not supported by the shell"
+            ))
+        } else {
+            Ok(None)
+        }
+    }
 
-use crate::asciicast::Event;
+    /// Calls `f`, adding the time it took to the profile with `add`, if the run is profiled.
+    fn timed<T>(&self, add: fn(&Profile, Duration), f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        if let Some(profile) = self.profile {
+            add(profile, start.elapsed());
+        }
+        result
+    }
 
-use super::{spawn::ShellSession, Command, Instruction, Key};
+    /// Calls `update` with the dashboard, if it is shown.
+    fn update_dashboard(
+        &self,
+        update: impl FnOnce(&Dashboard) -> io::Result<()>,
+    ) -> color_eyre::Result<()> {
+        self.dashboard
+            .map_or(Ok(()), update)
+            .wrap_err("could not draw dashboard")
+    }
+}
 
-pub(super) fn instructions<'a, I>(
+fn scale(events: impl Iterator<Item = Event>, factor: f64) -> impl Iterator<Item = Event> {
+    events.map(move |mut event| {
+        event.time = event.time.div_f64(factor);
+        event
+    })
+}
+
+pub(super) fn instructions<'a, I, D>(
     instructions: I,
-    prompt: &str,
-    secondary_prompt: &str,
-    type_speed: Duration,
-    line_split: &str,
-    shell_session: &mut ShellSession,
+    options: Options,
+    shell_session: &mut D,
 ) -> color_eyre::Result<Vec<Event>>
 where
     I: IntoIterator<Item = &'a Instruction>,
     I::IntoIter: ExactSizeIterator,
+    D: SessionDriver,
 {
+    // When the running instruction started, to show its time compared to the timeout.
+    let running = Arc::new(Mutex::new(None));
     let mut instructions = instructions
         .into_iter()
         .progress()
-        .with_style(progress_style())
-        .with_prefix("Instructions");
+        .with_style(instructions_progress_style(
+            shell_session.timeout(),
+            Arc::clone(&running),
+        ))
+        .with_prefix(options.progress.map_or_else(
+            || String::from("Instructions"),
+            |progress| progress.name.to_owned(),
+        ));
 
-    let multi_progress = MultiProgress::with_draw_target(ProgressDrawTarget::stdout());
+    let multi_progress = options.progress.map_or_else(
+        || MultiProgress::with_draw_target(options.progress_draw_target()),
+        |progress| progress.bars.clone(),
+    );
     instructions.progress = multi_progress.add(instructions.progress);
     instructions
         .progress
         .enable_steady_tick(Duration::from_secs(1));
 
-    instructions
+    if options.step {
+        step::check_terminal()?;
+    }
+    if options.present {
+        present::start(options.prompt).wrap_err("could not start presentation")?;
+    }
+    let captures = RefCell::default();
+    let options = Options {
+        captures: options.captures.or(Some(&captures)),
+        ..options
+    };
+    let mut last_output = String::new();
+    let mut report = options
+        .timing_report
+        .then(|| Report::new(shell_session.timeout(), options.size));
+
+    let result = instructions
         .enumerate()
         .map(|(num, instruction)| {
-            instruction
-                .run(
-                    prompt,
-                    secondary_prompt,
-                    type_speed,
-                    line_split,
-                    shell_session,
-                    &multi_progress,
-                )
-                .wrap_err_with(|| format!("error running instruction {num}"))
+            let Some(shown) = pause(
+                num,
+                instruction,
+                options,
+                &last_output,
+                &multi_progress,
+                shell_session,
+            )?
+            else {
+                return Ok::<_, eyre::Report>(Events::None);
+            };
+
+            options.update_dashboard(|dashboard| dashboard.start_instruction(num))?;
+            let sampler = report
+                .as_ref()
+                .and(shell_session.pid())
+                .and_then(Sampler::start);
+            let start = Instant::now();
+            set_running(&running, Some(start));
+            let events = instruction
+                .run_expanded(options, shell_session, &multi_progress)
+                .wrap_err_with(|| format!("error running instruction {num}"));
+            if let Err(error) = &events {
+                options.ci.error(num, instruction, error, start.elapsed());
+            }
+            set_running(&running, None);
+            if let Some(report) = &mut report {
+                let usage = sampler.map(Sampler::finish);
+                report.push(num, instruction, start.elapsed(), events.is_err(), usage);
+            }
+            if let Some(profile) = options.profile {
+                profile.finish_instruction(num, instruction);
+            }
+            let events = events?;
+            options.update_dashboard(|dashboard| dashboard.finish_instruction(num))?;
+            if !options.collects_output() || matches!(events, Events::Wait(_)) {
+                options.measure(instruction, start.elapsed(), "");
+                return Ok(events);
+            }
+            // Collected so the output can be shown before the next instruction.
+            let wait = events.wait();
+            let events = events.collect_vec();
+            last_output = events
+                .iter()
+                .filter(|event| event.event_type == EventType::Output)
+                .map(|event| event.data.as_str())
+                .collect();
+            options
+                .ci
+                .instruction(num, instruction, &last_output, start.elapsed());
+            options.measure(instruction, start.elapsed(), &last_output);
+            if options.present {
+                present::play(events.get(shown..).unwrap_or_default())
+                    .wrap_err("could not show output")?;
+            }
+            Ok(Events::OutputThenWait(events.into_iter(), wait))
         })
-        .process_results(|events| {
-            let mut wait_time = Duration::ZERO;
-            let events = events.flat_map(|mut events| {
-                if let Events::Wait(wait) = events {
-                    wait_time += wait;
-                }
-                let first = events.next().map(|mut event| {
-                    event.time += wait_time;
-                    wait_time = Duration::ZERO;
-                    event
-                });
-                first.into_iter().chain(events)
-            });
+        .process_results(|events| add_waits(events));
+    if let Some(report) = report {
+        multi_progress.suspend(|| eprintln!("{report}"));
+    }
+    let (events, wait_time) = result?;
+    finish(events, wait_time, options, shell_session)
+}
+
+/// Adds the first prompt and the output after the last instruction to the `events`
+/// of the instructions, and makes their times absolute.
+fn finish<D: SessionDriver>(
+    events: Vec<Event>,
+    wait_time: Duration,
+    options: Options,
+    shell_session: &mut D,
+) -> color_eyre::Result<Vec<Event>> {
+    let trailing = trailing_output(shell_session, options.trailing_output)
+        .wrap_err("error reading output after the last instruction")?;
+
+    // The trailing output is shown before the shell's last prompt, as it would be printed.
+    let mut events = events;
+    let last_prompt = events
+        .iter()
+        .rposition(|event| event.event_type == EventType::Output && event.data == options.prompt)
+        .unwrap_or(events.len());
+    events.splice(
+        last_prompt..last_prompt,
+        options.output(trailing.into_iter()),
+    );
+
+    let prompt = Event::output(Duration::ZERO, String::from(options.prompt));
+    let last_line = options.typing(iter::once(Event::outputln(options.type_speed)));
+    let mut events = iter::once(prompt)
+        .chain(events)
+        .chain(last_line)
+        .scan(Duration::ZERO, |time, mut event| {
+            event.time += *time;
+            *time = event.time;
+            Some(event)
+        })
+        .collect_vec();
+    if let Some(last) = events.last_mut() {
+        last.time += wait_time;
+    }
+    if let Some(transcript) = options.transcript {
+        transcript.borrow_mut().place(&events);
+    }
+    if options.coalesce_leading_events {
+        // The first typed character is one keystroke after the initial prompt.
+        let keystroke = options.type_speed.div_f64(options.typing_speed_factor);
+        coalesce_leading(&mut events, keystroke);
+    }
+    Ok(events)
+}
 
-            let mut events = iter::once(Event::output(Duration::ZERO, String::from(prompt)))
-                .chain(events)
-                .chain(iter::once(Event::outputln(type_speed)))
-                .scan(Duration::ZERO, |time, mut event| {
-                    event.time += *time;
-                    *time = event.time;
-                    Some(event)
-                })
-                .collect_vec();
-            if let Some(last) = events.last_mut() {
-                last.time += wait_time;
+/// Flattens the events of each instruction, adding the time of waits to the next event.
+///
+/// Also returns the time of waits after the last event.
+fn add_waits<Co, Cl>(events: impl Iterator<Item = Events<Co, Cl>>) -> (Vec<Event>, Duration)
+where
+    Events<Co, Cl>: Iterator<Item = Event>,
+{
+    let mut wait_time = Duration::ZERO;
+    let mut flattened = Vec::new();
+    for mut events in events {
+        let wait = events.wait();
+        if let Some(mut first) = events.next() {
+            first.time += wait_time;
+            wait_time = Duration::ZERO;
+            flattened.push(first);
+            flattened.extend(events);
+        }
+        wait_time += wait;
+    }
+    (flattened, wait_time)
+}
+
+/// Pauses before the `instruction` when stepping through instructions or presenting,
+/// until the user chooses what to do with it.
+///
+/// Returns [`None`] if the instruction is skipped, otherwise the number of its events
+/// which were already shown in the presentation.
+fn pause<D: SessionDriver>(
+    num: usize,
+    instruction: &Instruction,
+    options: Options,
+    last_output: &str,
+    multi_progress: &MultiProgress,
+    shell_session: &mut D,
+) -> color_eyre::Result<Option<usize>> {
+    let action = if options.step {
+        multi_progress.suspend(|| step::prompt(num, instruction, last_output))
+    } else if options.present && present::pauses(instruction) {
+        present::wait_for_key()
+    } else {
+        Ok(step::Action::Run)
+    };
+    match action.wrap_err("could not read key")? {
+        step::Action::Run => {}
+        step::Action::Skip => return Ok(None),
+        step::Action::Abort => eyre::bail!("aborted at instruction {num}"),
+    }
+    let shown = if options.present {
+        // Typed before the command runs, like it would be by the presenter.
+        let typed = instruction.typed_events(options);
+        present::play(&typed).wrap_err("could not show instruction")?;
+        typed.len()
+    } else {
+        0
+    };
+    if options.step || options.present {
+        // Time spent paused is not part of the recording.
+        shell_session.reset();
+    }
+    Ok(Some(shown))
+}
+
+/// How long the shell must be quiet after the last instruction for its trailing output
+/// to be considered complete.
+const TRAILING_OUTPUT_QUIET: Duration = Duration::from_millis(100);
+
+/// Reads any output the shell produced after the last instruction, like messages from
+/// background jobs, and handles it according to `trailing_output`.
+///
+/// Output is read until the shell is quiet for [`TRAILING_OUTPUT_QUIET`],
+/// or the session's timeout elapses.
+///
+/// Returns the events to add to the asciicast.
+fn trailing_output<D: SessionDriver>(
+    shell_session: &mut D,
+    trailing_output: TrailingOutput,
+) -> io::Result<Vec<Event>> {
+    let mut events = Vec::new();
+    if trailing_output == TrailingOutput::Discard {
+        return Ok(events);
+    }
+    let start = Instant::now();
+    let mut last_read = start;
+    let mut backoff = Backoff::default();
+    while last_read.elapsed() < TRAILING_OUTPUT_QUIET && start.elapsed() < shell_session.timeout() {
+        match shell_session.read()? {
+            (Some(event), _) => {
+                events.push(event);
+                last_read = Instant::now();
+                backoff.reset();
+            }
+            (None, true) => {
+                last_read = Instant::now();
+                backoff.reset();
             }
-            events
+            (None, false) => {
+                backoff.wait_at_most(TRAILING_OUTPUT_QUIET.saturating_sub(last_read.elapsed()));
+            }
+        }
+    }
+
+    match trailing_output {
+        TrailingOutput::Append => Ok(events),
+        TrailingOutput::Warn => {
+            if !events.is_empty() {
+                let output: String = events.into_iter().map(|event| event.data).collect();
+                eprintln!(
+                    "warning: discarded output after the last instruction: {output:?}\n\
+                    use `--trailing-output append` to add it to the asciicast"
+                );
+            }
+            Ok(Vec::new())
+        }
+        TrailingOutput::Discard => Ok(Vec::new()),
+    }
+}
+
+/// Merges the output events at the start of `events` which occur `within` the time of the
+/// first event into it, so players that render event by event don't flash.
+fn coalesce_leading(events: &mut Vec<Event>, within: Duration) {
+    let Some(first) = events.first().map(|event| event.time) else {
+        return;
+    };
+    let leading = events
+        .iter()
+        .take_while(|event| {
+            event.time.saturating_sub(first) <= within && event.event_type == EventType::Output
         })
+        .count();
+    if leading > 1 {
+        let data: String = events.drain(1..leading).map(|event| event.data).collect();
+        events[0].data.push_str(&data);
+    }
+}
+
+/// Finds the times of output events where `prompt` starts a line, but isn't the rendered prompt.
+///
+/// This usually means the shell's prompt was detected in the wrong place,
+/// splitting a command's output from its events incorrectly.
+pub(super) fn misplaced_prompts(events: &[Event], prompt: &str) -> Vec<Duration> {
+    if prompt.is_empty() {
+        return Vec::new();
+    }
+
+    let mut line_start = false;
+    let mut times = Vec::new();
+    for event in events {
+        if event.event_type != EventType::Output {
+            continue;
+        }
+        if event.data == prompt {
+            line_start = false;
+            continue;
+        }
+        let mut lines = event.data.split('\n');
+        let first = lines.next().unwrap_or_default();
+        let misplaced =
+            (line_start && first.starts_with(prompt)) || lines.any(|line| line.starts_with(prompt));
+        if misplaced {
+            times.push(event.time);
+        }
+        line_start = event.data.ends_with('\n');
+    }
+    times
 }
 
 fn progress_style() -> ProgressStyle {
@@ -84,77 +521,663 @@ fn progress_style() -> ProgressStyle {
         .expect("invalid progress style template")
 }
 
+/// [`progress_style()`] with how long the `running` instruction has taken compared to the
+/// `timeout`, i.e. "14s/30s", so it's clear whether a long command will time out.
+fn instructions_progress_style(
+    timeout: Duration,
+    running: Arc<Mutex<Option<Instant>>>,
+) -> ProgressStyle {
+    ProgressStyle::with_template("{prefix:>12}: {wide_bar} {pos:>3}/{len:3} [{elapsed}]{timeout}")
+        .expect("invalid progress style template")
+        .with_key(
+            "timeout",
+            move |_: &ProgressState, write: &mut dyn fmt::Write| {
+                let start = *running.lock().unwrap_or_else(PoisonError::into_inner);
+                if let Some(start) = start {
+                    let _ = write!(write, " {}s/{timeout:?}", start.elapsed().as_secs());
+                }
+            },
+        )
+}
+
+/// Sets when the running instruction started, or [`None`] once it finished.
+fn set_running(running: &Mutex<Option<Instant>>, start: Option<Instant>) {
+    *running.lock().unwrap_or_else(PoisonError::into_inner) = start;
+}
+
 impl Instruction {
-    fn run<'a>(
+    /// The events of typing the instruction's command, which its events start with.
+    fn typed_events(&self, options: Options) -> Vec<Event> {
+        match self {
+            Self::Command {
+                command,
+                type_speed,
+                ..
+            }
+            | Self::Interactive {
+                command,
+                type_speed,
+                ..
+            } => {
+                let type_speed = type_speed.unwrap_or(options.type_speed);
+                options
+                    .typing(command.events(type_speed, options))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Runs the instruction with the variables captured by earlier instructions expanded.
+    fn run_expanded<'a, D: SessionDriver>(
         &'a self,
-        prompt: &'a str,
-        secondary_prompt: &'a str,
-        default_type_speed: Duration,
-        line_split: &'a str,
-        shell_session: &mut ShellSession,
+        options: Options<'a>,
+        shell_session: &mut D,
         multi_progress: &MultiProgress,
     ) -> color_eyre::Result<Events<impl Iterator<Item = Event> + 'a, impl Iterator<Item = Event>>>
     {
+        let expanded = options
+            .captures
+            .and_then(|captures| self.with_variables(&captures.borrow()));
+        let Some(expanded) = expanded else {
+            return self.run(options, shell_session, multi_progress);
+        };
+        // Collected as the events borrow the expanded instruction.
+        let events = expanded
+            .run(options, shell_session, multi_progress)?
+            .collect_vec();
+        Ok(Events::Output(events.into_iter()))
+    }
+
+    fn run<'a, D: SessionDriver>(
+        &'a self,
+        options: Options<'a>,
+        shell_session: &mut D,
+        multi_progress: &MultiProgress,
+    ) -> color_eyre::Result<Events<impl Iterator<Item = Event> + 'a, impl Iterator<Item = Event>>>
+    {
+        let Options {
+            prompt,
+            type_speed: default_type_speed,
+            ..
+        } = options;
         match self {
             Self::Command {
                 command,
                 hidden,
                 type_speed,
+                confirm,
+                highlight_stderr,
+                output_filter,
+                allow_dangerous: _,
             } => {
-                command
-                    .send(shell_session)
+                let type_speed = type_speed.unwrap_or(default_type_speed);
+                let stderr_wrapper = options.stderr_wrapper(*highlight_stderr)?;
+                options
+                    .timed(Profile::add_typing, || {
+                        command.send_wrapped(
+                            shell_session,
+                            options.line_continuation,
+                            stderr_wrapper,
+                        )
+                    })
                     .wrap_err("could not send command to shell")?;
-                let mut output = shell_session
-                    .read_until_prompt()
+                let mut output = options
+                    .timed(Profile::add_wait, || {
+                        read_command_output(shell_session, confirm, type_speed)
+                    })
+                    .map_err(waiting_for_input)
                     .wrap_err("could not read shell output")?;
 
                 if *hidden {
                     return Ok(Events::None);
                 }
+                if options.strip_echoed_newline {
+                    strip_leading_newline(&mut output);
+                }
+                if *highlight_stderr {
+                    highlight_stderr_lines(&mut output, options.stderr_style);
+                }
+                if let Some(filter) = output_filter {
+                    filter_output(&mut output, filter);
+                }
 
-                output.push(shell_session.new_event(String::from(prompt)));
-                let type_speed = type_speed.unwrap_or(default_type_speed);
-                let events = command
-                    .events(type_speed, secondary_prompt, line_split)
-                    .chain(output);
-
-                Ok(Events::Command(events))
+                let events = command_events(command, type_speed, output, shell_session, options);
+                Ok(events)
             }
             Self::Interactive {
                 command,
                 keys,
                 type_speed,
+                allow_dangerous: _,
             } => {
-                command
-                    .send(shell_session)
+                options
+                    .timed(Profile::add_typing, || {
+                        command.send(shell_session, options.line_continuation)
+                    })
                     .wrap_err("could not send command to shell")?;
 
                 let type_speed = type_speed.map_or(default_type_speed, Into::into);
-                let mut output = keys_to_events(keys, type_speed, shell_session, multi_progress)?;
-
-                output.push(shell_session.new_event(String::from(prompt)));
-                let events = command
-                    .events(type_speed, secondary_prompt, line_split)
-                    .chain(output);
+                // The keys are sent while reading the output.
+                let mut output = options.timed(Profile::add_wait, || {
+                    keys_to_events(keys, type_speed, shell_session, multi_progress)
+                })?;
+                if options.strip_echoed_newline {
+                    strip_leading_newline(&mut output);
+                }
 
-                Ok(Events::Command(events))
+                let (mut output, exited) = alt_screen(output, options.alt_screen_markers);
+                if exited && options.clear_after_alt_screen {
+                    output.push(Event::output(Duration::ZERO, String::from(CLEAR)));
+                }
+                let events = command_events(command, type_speed, output, shell_session, options);
+                Ok(events)
             }
+            Self::If { .. } => run_branch(self, options, shell_session, multi_progress),
+            Self::Capture { command, var, .. } => capture(command, var, options, shell_session),
             Self::Wait(duration) => Ok(Events::Wait(*duration)),
+            Self::Settle(window) => {
+                let output = options
+                    .timed(Profile::add_wait, || settle(shell_session, *window))
+                    .wrap_err("output did not settle")?;
+                Ok(Events::Output(
+                    options.output(output.into_iter()).collect_vec().into_iter(),
+                ))
+            }
             Self::Marker(data) => Ok(Events::once(Event::marker(Duration::ZERO, data.clone()))),
+            Self::Title(title) => Ok(Events::once(title_event(title))),
+            Self::Resize { width, height } => resize(*width, *height, options, shell_session),
             Self::Clear => {
-                let clear =
-                    Event::output(default_type_speed, String::from("\r\x1b[H\x1b[2J\x1b[3J"));
+                let clear = Event::output(default_type_speed, String::from(CLEAR));
                 let prompt = Event::output(default_type_speed, String::from(prompt));
-                Ok(Events::Clear([clear, prompt].into_iter()))
+                Ok(Events::Clear(options.typing([clear, prompt].into_iter())))
+            }
+            Self::Include(path) => {
+                eyre::bail!("`!Include {}` was not resolved", path.display())
+            }
+        }
+    }
+}
+
+/// Runs the instructions of the branch the `!If` `instruction` chooses,
+/// based on the captured variables.
+fn run_branch<'a, Co, Cl, D: SessionDriver>(
+    instruction: &'a Instruction,
+    options: Options<'a>,
+    shell_session: &mut D,
+    multi_progress: &MultiProgress,
+) -> color_eyre::Result<Events<Co, Cl>> {
+    let branch = {
+        let captures = options.captures.map(RefCell::borrow);
+        instruction.branch(captures.as_deref().map_or(&[], Vec::as_slice))
+    };
+    let events = branch
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+        .map(|(num, instruction)| {
+            instruction
+                .run_expanded(options, shell_session, multi_progress)
+                .wrap_err_with(|| format!("error running branch instruction {num}"))
+        })
+        .collect::<color_eyre::Result<Vec<_>>>()?;
+    let (events, wait) = add_waits(events.into_iter());
+    Ok(Events::OutputThenWait(events.into_iter(), wait))
+}
+
+/// Runs the `command` and stores its output in the variable `var`.
+fn capture<Co, Cl, D: SessionDriver>(
+    command: &Command,
+    var: &str,
+    options: Options,
+    shell_session: &mut D,
+) -> color_eyre::Result<Events<Co, Cl>> {
+    options
+        .timed(Profile::add_typing, || {
+            command.send(shell_session, options.line_continuation)
+        })
+        .wrap_err("could not send command to shell")?;
+    let output = options
+        .timed(Profile::add_wait, || shell_session.read_until_prompt())
+        .map_err(waiting_for_input)
+        .wrap_err("could not read shell output")?;
+    if let Some(captures) = options.captures {
+        captures.borrow_mut().push(EnvVar {
+            name: String::from(var),
+            value: captured_value(&output),
+        });
+    }
+    Ok(Events::None)
+}
+
+/// The trimmed text of a captured command's `output`, without escape sequences.
+fn captured_value(output: &[Event]) -> String {
+    let output: String = output
+        .iter()
+        .filter(|event| event.event_type == EventType::Output)
+        .map(|event| event.data.as_str())
+        .collect();
+    console::strip_ansi_codes(&output)
+        .trim()
+        .replace("\r\n", "\n")
+}
+
+/// Resizes the shell's terminal, defaulting to the script's size.
+///
+/// Returns the resize event, followed by the output redrawn at the new size,
+/// i.e. by a TUI or the shell's prompt.
+fn resize<Co, Cl, D: SessionDriver>(
+    width: Option<u16>,
+    height: Option<u16>,
+    options: Options,
+    shell_session: &mut D,
+) -> color_eyre::Result<Events<Co, Cl>> {
+    let width = width.unwrap_or(options.size.0);
+    let height = height.unwrap_or(options.size.1);
+    shell_session
+        .resize(width, height)
+        .wrap_err("could not resize terminal")?;
+    let redraw = options
+        .timed(Profile::add_wait, || settle(shell_session, REDRAW_WINDOW))
+        .wrap_err("output did not settle after resizing")?;
+    let events = iter::once(Event::resize(Duration::ZERO, width, height))
+        .chain(options.output(redraw.into_iter()))
+        .collect_vec();
+    Ok(Events::Output(events.into_iter()))
+}
+
+/// The events of typing the `command`, followed by its `output` and the prompt.
+///
+/// The command and its output are also added to the transcript.
+fn command_events<'a, Cl, D: SessionDriver>(
+    command: &'a Command,
+    type_speed: Duration,
+    mut output: Vec<Event>,
+    shell_session: &mut D,
+    options: Options<'a>,
+) -> Events<impl Iterator<Item = Event> + 'a, Cl> {
+    options.transcribe(command, type_speed, &output);
+    push_prompt(&mut output, shell_session, options);
+    Events::Command(
+        options
+            .typing(command.events(type_speed, options))
+            .chain(options.output(output.into_iter())),
+    )
+}
+
+/// Creates the event for the prompt shown after a command.
+///
+/// If the shell ended the command with an alternate prompt, like a debugger's,
+/// it is shown instead of the configured `prompt`.
+fn rendered_prompt<D: SessionDriver>(shell_session: &mut D, prompt: &str) -> Event {
+    let prompt = String::from(shell_session.alternate_prompt().unwrap_or(prompt));
+    shell_session.new_event(prompt)
+}
+
+/// Pushes the [`rendered_prompt()`] to the end of a command's `output`.
+///
+/// If [`Options::strip_prompt_line`] is set, the shell's own prompt is removed first.
+fn push_prompt<D: SessionDriver>(output: &mut Vec<Event>, shell_session: &mut D, options: Options) {
+    let stripped = if options.strip_prompt_line && shell_session.alternate_prompt().is_none() {
+        strip_partial_line(output)
+    } else {
+        Duration::ZERO
+    };
+    let mut prompt = rendered_prompt(shell_session, options.prompt);
+    prompt.time += stripped;
+    output.push(prompt);
+}
+
+/// Removes the output after the last line break from the end of `output`.
+///
+/// Returns the time of the events which were removed entirely,
+/// so the next event can be moved to the same time.
+fn strip_partial_line(output: &mut Vec<Event>) -> Duration {
+    let mut stripped = Duration::ZERO;
+    while let Some(event) = output.last_mut() {
+        if event.event_type != EventType::Output {
+            break;
+        }
+        if let Some(index) = event.data.rfind('\n') {
+            event.data.truncate(index + 1);
+            break;
+        }
+        stripped += event.time;
+        output.pop();
+    }
+    stripped
+}
+
+/// Removes the line break from the start of `output`.
+///
+/// If the first event is left empty it is removed, and its time is added to the next event.
+fn strip_leading_newline(output: &mut Vec<Event>) {
+    let Some(first) = output.first_mut() else {
+        return;
+    };
+    if first.event_type != EventType::Output {
+        return;
+    }
+    let Some(rest) = first
+        .data
+        .strip_prefix("\r\n")
+        .or_else(|| first.data.strip_prefix('\n'))
+    else {
+        return;
+    };
+    first.data = rest.to_owned();
+    if first.data.is_empty() {
+        let time = output.remove(0).time;
+        if let Some(next) = output.first_mut() {
+            next.time += time;
+        }
+    }
+}
+
+/// Marks the start of a line of stderr output
+const STDERR_START: char = '\x0e';
+/// Marks the end of a line of stderr output
+const STDERR_END: char = '\x0f';
+
+/// Replaces the [`STDERR_START`] and [`STDERR_END`] markers in `output` with the `style`.
+///
+/// Each line is marked separately, so the markers are never split between events.
+fn highlight_stderr_lines(output: &mut [Event], style: &str) {
+    let start = format!("\x1b[{style}m");
+    for event in output {
+        event.data = event
+            .data
+            .replace(STDERR_START, &start)
+            .replace(STDERR_END, "\x1b[0m");
+    }
+}
+
+/// Removes the lines of `output` which the `filter` doesn't keep.
+///
+/// Lines end at each line break, or carriage return not followed by a line break,
+/// so each frame of a spinner or progress bar is its own line.
+/// A line split between events is kept in the event with its end.
+/// Events left empty are removed and their time is added to the next event.
+fn filter_output(output: &mut Vec<Event>, filter: &OutputFilter) {
+    let end_line = |line: &mut String, data: &mut String| {
+        if filter.keeps(line) {
+            data.push_str(line);
+        }
+        line.clear();
+    };
+
+    let mut line = String::new();
+    let mut delay = Duration::ZERO;
+    let mut filtered = Vec::with_capacity(output.len());
+    for mut event in output.drain(..) {
+        let mut data = String::new();
+        for char in event.data.chars() {
+            if line.ends_with('\r') && char != '\n' {
+                end_line(&mut line, &mut data);
+            }
+            line.push(char);
+            if char == '\n' {
+                end_line(&mut line, &mut data);
+            }
+        }
+        event.time += delay;
+        if data.is_empty() {
+            delay = event.time;
+        } else {
+            delay = Duration::ZERO;
+            event.data = data;
+            filtered.push(event);
+        }
+    }
+    // The last line, which may not have ended.
+    if !line.is_empty() && filter.keeps(&line) {
+        match filtered.last_mut() {
+            Some(last) if delay.is_zero() => last.data.push_str(&line),
+            _ => filtered.push(Event::output(delay, line)),
+        }
+    }
+    *output = filtered;
+}
+
+impl OutputFilter {
+    /// Whether the `line`, without its line ending, is kept.
+    fn keeps(&self, line: &str) -> bool {
+        let line = line.trim_end_matches(['\r', '\n']);
+        !self.drop.is_match(line) && (self.keep.is_empty() || self.keep.is_match(line))
+    }
+}
+
+/// Moves the cursor to the start and clears the screen and scrollback
+const CLEAR: &str = "\r\x1b[H\x1b[2J\x1b[3J";
+
+/// Creates the event of the OSC escape sequence which sets the terminal's title.
+///
+/// Control chars are removed from the `title`, so they can't end the sequence early.
+fn title_event(title: &str) -> Event {
+    let title: String = title.chars().filter(|char| !char.is_control()).collect();
+    Event::output(Duration::ZERO, format!("\x1b]2;{title}\x07"))
+}
+
+/// Escape sequences for entering the alternate screen buffer
+const ALT_SCREEN_ENTER: &[&str] = &["\x1b[?1049h", "\x1b[?1047h", "\x1b[?47h"];
+/// Escape sequences for exiting the alternate screen buffer
+const ALT_SCREEN_EXIT: &[&str] = &["\x1b[?1049l", "\x1b[?1047l", "\x1b[?47l"];
+
+/// Finds where `output` enters and exits the alternate screen buffer, used by TUIs like vim.
+///
+/// If `markers` is true, a marker is added after each event which enters or exits.
+/// Also returns whether the alternate screen was exited.
+fn alt_screen(output: Vec<Event>, markers: bool) -> (Vec<Event>, bool) {
+    let contains =
+        |data: &str, sequences: &[&str]| sequences.iter().any(|sequence| data.contains(sequence));
+
+    let mut exited = false;
+    let mut events = Vec::with_capacity(output.len());
+    for event in output {
+        let enter = contains(&event.data, ALT_SCREEN_ENTER);
+        let exit = contains(&event.data, ALT_SCREEN_EXIT);
+        exited |= exit;
+        events.push(event);
+        if markers {
+            if enter {
+                events.push(Event::marker(
+                    Duration::ZERO,
+                    String::from("Enter alternate screen"),
+                ));
+            }
+            if exit {
+                events.push(Event::marker(
+                    Duration::ZERO,
+                    String::from("Exit alternate screen"),
+                ));
+            }
+        }
+    }
+    (events, exited)
+}
+
+/// Reads and records the shell's output until none has been received for `window`.
+///
+/// # Errors
+///
+/// Returns an error if the output doesn't settle before the shell session's timeout.
+fn settle<D: SessionDriver>(
+    shell_session: &mut D,
+    window: Duration,
+) -> color_eyre::Result<Vec<Event>> {
+    let start = Instant::now();
+    let mut last_output = start;
+    let mut events = Vec::new();
+    let mut backoff = Backoff::default();
+    loop {
+        let (event, _) = shell_session
+            .read()
+            .wrap_err("error reading shell output")?;
+        if let Some(event) = event {
+            events.push(event);
+            last_output = Instant::now();
+            backoff.reset();
+        } else {
+            let remaining = window.saturating_sub(last_output.elapsed());
+            if remaining.is_zero() {
+                return Ok(events);
             }
+            backoff.wait_at_most(remaining);
+        }
+        if start.elapsed() > shell_session.timeout() {
+            eyre::bail!("timeout elapsed");
         }
     }
 }
 
-fn keys_to_events(
+/// How long output must stop for after resizing before the redraw is considered done
+const REDRAW_WINDOW: Duration = Duration::from_millis(100);
+
+/// Patterns of common confirmation prompts, compared case-insensitively
+const CONFIRMATION_PATTERNS: &[&str] = &["[y/n]", "(y/n)", "[yes/no]", "(yes/no)", "[y/n/q]"];
+
+/// Reads a command's output until the prompt, answering the `confirm` prompts if there are any.
+fn read_command_output<D: SessionDriver>(
+    shell_session: &mut D,
+    confirm: &[String],
+    type_speed: Duration,
+) -> color_eyre::Result<Vec<Event>> {
+    if confirm.is_empty() {
+        shell_session.read_until_prompt()
+    } else {
+        read_until_prompt_confirming(shell_session, confirm, type_speed)
+    }
+}
+
+/// Reads the shell's output until the prompt is detected, like
+/// [`SessionDriver::read_until_prompt()`], sending the next reply whenever the
+/// last line of output is a confirmation prompt.
+///
+/// Replies are sent after waiting `type_speed`, and are added to the output
+/// when the terminal echoes them.
+fn read_until_prompt_confirming<D: SessionDriver>(
+    shell_session: &mut D,
+    replies: &[String],
+    type_speed: Duration,
+) -> color_eyre::Result<Vec<Event>> {
+    let mut replies = replies.iter();
+    let start = Instant::now();
+    let mut events = Vec::new();
+    let mut output = String::new();
+    let mut backoff = Backoff::default();
+    loop {
+        let (event, prompt) = shell_session
+            .read()
+            .wrap_err("error reading shell output")?;
+        let read = event.is_some();
+        if let Some(event) = event {
+            output.push_str(&event.data);
+            events.push(event);
+        }
+        if prompt {
+            return Ok(events);
+        }
+        if is_confirmation(&output) {
+            if let Some(reply) = replies.next() {
+                output.clear();
+                thread::sleep(type_speed);
+                shell_session
+                    .send_line(reply)
+                    .wrap_err("could not send confirmation reply")?;
+            }
+        }
+        if read {
+            backoff.reset();
+        } else {
+            backoff.wait();
+        }
+        if start.elapsed() > shell_session.timeout() {
+            return Err(Timeout { output: events }.into());
+        }
+    }
+}
+
+/// Whether the last line of `output` is a confirmation prompt.
+fn is_confirmation(output: &str) -> bool {
+    let last_line = output
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    CONFIRMATION_PATTERNS
+        .iter()
+        .any(|pattern| last_line.contains(pattern))
+}
+
+/// Adds guidance to a [`Timeout`] error if the command's output shows it is waiting for input
+/// in a pager or a full screen program, like an editor.
+fn waiting_for_input(error: eyre::Report) -> eyre::Report {
+    let Some(timeout) = error.downcast_ref::<Timeout>() else {
+        return error;
+    };
+    let output: String = timeout
+        .output
+        .iter()
+        .map(|event| event.data.as_str())
+        .collect();
+    if is_pager(&output) {
+        error.wrap_err(
+            "the command appears to be waiting for input in a pager, \
+                set `PAGER=cat` and `GIT_PAGER=cat` in the environment \
+                or use `!Interactive` to send it keys",
+        )
+    } else if in_alt_screen(&output) {
+        error.wrap_err(
+            "the command appears to be waiting for input in a full screen program, \
+                like an editor, use `!Interactive` to send it keys",
+        )
+    } else {
+        error
+    }
+}
+
+/// Whether the last line of `output`, without escape sequences, is a pager's prompt.
+fn is_pager(output: &str) -> bool {
+    static ESCAPE_SEQUENCE: OnceLock<Regex> = OnceLock::new();
+    static PAGER_PROMPT: OnceLock<Regex> = OnceLock::new();
+    let output = ESCAPE_SEQUENCE
+        .get_or_init(|| {
+            Regex::new(r"\x1b(\[[0-9;?]*[ -/]*[@-~]|[()][0-9A-Za-z]|[=>])")
+                .expect("invalid escape sequence pattern")
+        })
+        .replace_all(output, "");
+    let last_line = output
+        .rsplit(['\n', '\r'])
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    // less, more, and less's prompt when showing part of the input
+    PAGER_PROMPT
+        .get_or_init(|| {
+            Regex::new(r"^(:|\(END\)|--More--.*|.*lines \d+-\d+.*)$")
+                .expect("invalid pager prompt pattern")
+        })
+        .is_match(last_line)
+}
+
+/// Whether `output` entered the alternate screen buffer and did not exit it.
+fn in_alt_screen(output: &str) -> bool {
+    let last = |sequences: &[&str]| {
+        sequences
+            .iter()
+            .filter_map(|sequence| output.rfind(sequence))
+            .max()
+    };
+    match (last(ALT_SCREEN_ENTER), last(ALT_SCREEN_EXIT)) {
+        (Some(enter), Some(exit)) => enter > exit,
+        (enter, _) => enter.is_some(),
+    }
+}
+
+fn keys_to_events<D: SessionDriver>(
     keys: &[Key],
     type_speed: Duration,
-    shell_session: &mut ShellSession,
+    shell_session: &mut D,
     multi_progress: &MultiProgress,
 ) -> color_eyre::Result<Vec<Event>> {
     let mut keys = keys
@@ -166,16 +1189,26 @@ fn keys_to_events(
 
     let mut events = Vec::new();
     let mut next = Instant::now() + type_speed;
+    let mut backoff = Backoff::default();
     loop {
         let (event, prompt) = shell_session
             .read()
             .wrap_err("error reading shell output")?;
+        let read = event.is_some();
         events.extend(event);
         if prompt {
             return Ok(events);
         }
         keys.progress.tick();
-        if Instant::now() >= next {
+        let now = Instant::now();
+        if now < next {
+            if read {
+                backoff.reset();
+            } else {
+                // Don't sleep past when the next key should be sent.
+                backoff.wait_at_most(next - now);
+            }
+        } else {
             if let Some(key) = keys.next() {
                 key.send(shell_session).wrap_err("error sending key")?;
                 if let Key::Wait(wait) = key {
@@ -200,6 +1233,9 @@ fn keys_to_events(
 enum Events<Co, Cl> {
     Command(Co),
     Clear(Cl),
+    Output(std::vec::IntoIter<Event>),
+    /// Output followed by a wait, i.e. from a branch of an `!If` instruction
+    OutputThenWait(std::vec::IntoIter<Event>, Duration),
     Once(iter::Once<Event>),
     Wait(Duration),
     None,
@@ -216,6 +1252,7 @@ where
         match self {
             Self::Command(iter) => iter.next(),
             Self::Clear(iter) => iter.next(),
+            Self::Output(iter) | Self::OutputThenWait(iter, _) => iter.next(),
             Self::Once(iter) => iter.next(),
             Self::Wait(_) | Self::None => None,
         }
@@ -225,6 +1262,7 @@ where
         match self {
             Self::Command(iter) => iter.size_hint(),
             Self::Clear(iter) => iter.size_hint(),
+            Self::Output(iter) | Self::OutputThenWait(iter, _) => iter.size_hint(),
             Self::Once(iter) => iter.size_hint(),
             Self::Wait(_) | Self::None => (0, Some(0)),
         }
@@ -235,100 +1273,773 @@ impl<Co, Cl> Events<Co, Cl> {
     fn once(event: Event) -> Self {
         Self::Once(iter::once(event))
     }
+
+    /// Time to wait after the events.
+    fn wait(&self) -> Duration {
+        match self {
+            Self::Wait(wait) | Self::OutputThenWait(_, wait) => *wait,
+            _ => Duration::ZERO,
+        }
+    }
 }
 
 impl Command {
-    fn send(&self, shell_session: &mut ShellSession) -> io::Result<()> {
+    fn send(
+        &self,
+        shell_session: &mut impl SessionDriver,
+        line_continuation: &str,
+    ) -> io::Result<()> {
+        self.send_wrapped(shell_session, line_continuation, None)
+    }
+
+    /// Sends the command, putting the `wrapper`'s prefix and suffix around it.
+    /// The lines of a multiline command are joined by the `line_continuation`.
+    /// Control codes are never wrapped.
+    fn send_wrapped(
+        &self,
+        shell_session: &mut impl SessionDriver,
+        line_continuation: &str,
+        wrapper: Option<(&str, &str)>,
+    ) -> io::Result<()> {
         shell_session.reset();
-        match self {
-            Self::SingleLine(line) => shell_session.send_line(line),
-            Self::MultiLine(lines) => shell_session.send_line(&lines.join(" ")),
-            Self::Control(control) => shell_session.send(control),
+        let line = match self {
+            Self::SingleLine(line) => Cow::Borrowed(line.as_str()),
+            Self::MultiLine(lines) => Cow::Owned(lines.join(line_continuation)),
+            Self::Control(control) => return shell_session.send(control),
+        };
+        match wrapper {
+            Some((prefix, suffix)) => shell_session.send_line(format!("{prefix}{line}{suffix}")),
+            None => shell_session.send_line(line.as_ref()),
         }
     }
 
     fn events<'a>(
         &'a self,
         type_speed: Duration,
-        secondary_prompt: &'a str,
-        line_split: &'a str,
+        options: Options<'a>,
     ) -> impl Iterator<Item = Event> + 'a {
-        match self {
-            Self::SingleLine(line) => {
-                CommandEvents::SingleLine(type_line(type_speed, line.chars()))
-            }
-            Self::MultiLine(lines) => {
-                let num_lines = lines.len();
-                let iter = lines.iter().enumerate().flat_map(move |(line_num, line)| {
-                    let secondary_prompt = (line_num != 0)
-                        .then(|| Event::output(type_speed, String::from(secondary_prompt)));
-
-                    let line_split = (line_num + 1 < num_lines)
-                        .then_some(line_split.chars())
-                        .into_iter()
-                        .flatten();
-
-                    secondary_prompt
-                        .into_iter()
-                        .chain(type_line(type_speed, line.chars().chain(line_split)))
-                });
-                CommandEvents::MultiLine(iter)
-            }
+        let Options {
+            secondary_prompt,
+            line_split,
+            wrap_width,
+            pre_enter_delay,
+            double_width_pacing,
+            ..
+        } = options;
+        let lines: Vec<&str> = match self {
+            Self::SingleLine(line) => vec![line],
+            Self::MultiLine(lines) => lines.iter().map(String::as_str).collect(),
             Self::Control(control) => {
                 let control: &str = control.as_ref();
-                CommandEvents::Control(type_line(type_speed, control.chars()))
+                return CommandEvents::Control(type_line(
+                    type_speed,
+                    control.graphemes(true),
+                    Duration::ZERO,
+                    false,
+                ));
             }
+        };
+        let lines = match wrap_width {
+            Some(width) => wrap_lines(&lines, usize::from(width), options),
+            None => lines,
+        };
+
+        let num_lines = lines.len();
+        let iter = lines
+            .into_iter()
+            .enumerate()
+            .flat_map(move |(line_num, line)| {
+                let secondary_prompt = (line_num != 0)
+                    .then(|| Event::output(type_speed, String::from(secondary_prompt)));
+
+                let last_line = line_num + 1 == num_lines;
+                let line_split = (!last_line)
+                    .then(|| line_split.graphemes(true))
+                    .into_iter()
+                    .flatten();
+                let enter_delay = if last_line {
+                    pre_enter_delay
+                } else {
+                    Duration::ZERO
+                };
+
+                secondary_prompt.into_iter().chain(type_line(
+                    type_speed,
+                    line.graphemes(true).chain(line_split),
+                    enter_delay,
+                    double_width_pacing,
+                ))
+            });
+        CommandEvents::Lines(iter)
+    }
+}
+
+/// Splits each of the command's `lines` at spaces so they fit in `width` columns
+/// after the prompt and with the line split.
+///
+/// Words longer than the available space are not split.
+fn wrap_lines<'a>(lines: &[&'a str], width: usize, options: Options) -> Vec<&'a str> {
+    // The prompts may be colored.
+    let line_split = console::measure_text_width(options.line_split);
+    let mut prefix = console::measure_text_width(options.prompt);
+    let mut wrapped = Vec::with_capacity(lines.len());
+    for mut line in lines.iter().copied() {
+        loop {
+            let available = width.saturating_sub(prefix + line_split);
+            prefix = console::measure_text_width(options.secondary_prompt);
+            // Byte index of the first char which doesn't fit.
+            let Some((overflow, _)) = line.char_indices().nth(available) else {
+                wrapped.push(line);
+                break;
+            };
+            let split = if line[overflow..].starts_with(' ') {
+                Some(overflow)
+            } else {
+                line[..overflow].rfind(' ')
+            };
+            let split = split
+                .filter(|&split| split > 0)
+                .or_else(|| line[overflow..].find(' ').map(|split| overflow + split));
+            let Some(split) = split else {
+                wrapped.push(line);
+                break;
+            };
+            wrapped.push(&line[..split]);
+            line = &line[split + 1..];
         }
     }
+    wrapped
 }
 
-fn type_line(
+/// Types each grapheme of the `line`, so characters with combining marks or emoji made of
+/// several chars are typed at once, then the line break after `enter_delay`.
+///
+/// If `double_width_pacing` is true, graphemes two columns wide, i.e. CJK characters,
+/// take twice the `type_speed`.
+fn type_line<'a, L>(
     type_speed: Duration,
-    line: impl IntoIterator<Item = char>,
-) -> impl Iterator<Item = Event> {
+    line: L,
+    enter_delay: Duration,
+    double_width_pacing: bool,
+) -> impl Iterator<Item = Event> + 'a
+where
+    L: IntoIterator<Item = &'a str>,
+    L::IntoIter: 'a,
+{
     line.into_iter()
-        .map(move |char| Event::output(type_speed, String::from(char)))
-        .chain(iter::once(Event::outputln(type_speed)))
+        .map(move |grapheme| {
+            let wide = double_width_pacing && grapheme.width() > 1;
+            let time = if wide { type_speed * 2 } else { type_speed };
+            Event::output(time, String::from(grapheme))
+        })
+        .chain(iter::once(Event::outputln(type_speed + enter_delay)))
 }
 
 #[derive(Debug, Clone)]
-enum CommandEvents<S, M, C> {
-    SingleLine(S),
-    MultiLine(M),
+enum CommandEvents<L, C> {
+    Lines(L),
     Control(C),
 }
 
-impl<S, M, C> Iterator for CommandEvents<S, M, C>
+impl<L, C> Iterator for CommandEvents<L, C>
 where
-    S: Iterator<Item = Event>,
-    M: Iterator<Item = Event>,
+    L: Iterator<Item = Event>,
     C: Iterator<Item = Event>,
 {
     type Item = Event;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self {
-            Self::SingleLine(iter) => iter.next(),
-            Self::MultiLine(iter) => iter.next(),
+            Self::Lines(iter) => iter.next(),
             Self::Control(iter) => iter.next(),
         }
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
         match self {
-            Self::SingleLine(iter) => iter.size_hint(),
-            Self::MultiLine(iter) => iter.size_hint(),
+            Self::Lines(iter) => iter.size_hint(),
             Self::Control(iter) => iter.size_hint(),
         }
     }
 }
 
 impl Key {
-    fn send(&self, shell_session: &mut ShellSession) -> io::Result<()> {
+    fn send(&self, shell_session: &mut impl SessionDriver) -> io::Result<()> {
         match self {
             Self::Char(char) => shell_session.send([*char as u8]),
             Self::Control(control) => shell_session.send(control),
             Self::Wait(_) => Ok(()),
+            Self::Text(text) => shell_session.send(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::RegexSet;
+
+    use crate::config::driver::mock::Mock;
+
+    use super::*;
+
+    fn run(instructions: &str, mock: &mut Mock) -> Vec<Event> {
+        run_with_factors(instructions, mock, 1.0, 1.0)
+    }
+
+    fn run_with_factors(
+        instructions: &str,
+        mock: &mut Mock,
+        typing_speed_factor: f64,
+        output_speed_factor: f64,
+    ) -> Vec<Event> {
+        let instructions: Vec<Instruction> = serde_yaml::from_str(instructions).unwrap();
+        let options = Options {
+            typing_speed_factor,
+            output_speed_factor,
+            ..options()
+        };
+        super::instructions(&instructions, options, mock).unwrap()
+    }
+
+    fn options() -> Options<'static> {
+        Options {
+            prompt: "$ ",
+            secondary_prompt: "> ",
+            type_speed: Duration::from_millis(100),
+            pre_enter_delay: Duration::ZERO,
+            double_width_pacing: false,
+            line_split: " \\",
+            line_continuation: " ",
+            typing_speed_factor: 1.0,
+            output_speed_factor: 1.0,
+            coalesce_leading_events: false,
+            trailing_output: TrailingOutput::Append,
+            alt_screen_markers: false,
+            clear_after_alt_screen: false,
+            strip_prompt_line: false,
+            strip_echoed_newline: false,
+            stderr_wrapper: Some(("WRAP<", ">")),
+            stderr_style: "31",
+            size: (80, 24),
+            wrap_width: None,
+            step: false,
+            present: false,
+            ci: Ci::Off,
+            timing_report: false,
+            profile: None,
+            measurements: None,
+            dashboard: None,
+            captures: None,
+            transcript: None,
+            progress: None,
         }
     }
+
+    fn sent_lines(mock: &Mock) -> Vec<&str> {
+        std::str::from_utf8(&mock.sent).unwrap().lines().collect()
+    }
+
+    fn output(millis: u64, data: &str) -> Event {
+        Event::output(Duration::from_millis(millis), String::from(data))
+    }
+
+    #[test]
+    fn command() {
+        let mut mock = Mock::new("PROMPT", ["file\r\nPROMPT"]);
+        let events = run("- !Command\n  command: ls", &mut mock);
+        assert_eq!(
+            events,
+            [
+                output(0, "$ "),
+                output(100, "l"),
+                output(200, "s"),
+                output(300, "\r\n"),
+                output(301, "file\r\n"),
+                output(302, "$ "),
+                output(402, "\r\n"),
+            ]
+        );
+        assert_eq!(sent_lines(&mock), ["ls"]);
+    }
+
+    #[test]
+    fn command_echoed_newline() {
+        let instructions: Vec<Instruction> = serde_yaml::from_str(
+            "
+            - !Command
+              command: ls
+            - !Command
+              command: 'true'
+            ",
+        )
+        .unwrap();
+        // The Windows console echoes the line ending sent after each command.
+        let mut mock = Mock::new("PROMPT", ["\r\nfile\r\nPROMPT", "\r\n", "PROMPT"]);
+        let options = Options {
+            strip_echoed_newline: true,
+            ..options()
+        };
+        let events = super::instructions(&instructions, options, &mut mock).unwrap();
+        let data: String = events.iter().map(|event| event.data.as_str()).collect();
+        assert_eq!(data, "$ ls\r\nfile\r\n$ true\r\n$ \r\n");
+    }
+
+    #[test]
+    fn strip_leading_newline() {
+        let mut events = vec![output(1, "\r\nfile\r\n")];
+        super::strip_leading_newline(&mut events);
+        assert_eq!(events, [output(1, "file\r\n")]);
+
+        let mut events = vec![output(1, "\n"), output(2, "\r\n")];
+        super::strip_leading_newline(&mut events);
+        assert_eq!(events, [output(3, "\r\n")]);
+
+        let mut events = vec![output(1, "file\r\n")];
+        super::strip_leading_newline(&mut events);
+        assert_eq!(events, [output(1, "file\r\n")]);
+    }
+
+    #[test]
+    fn typed_events() {
+        let instructions: Vec<Instruction> = serde_yaml::from_str(
+            "
+            - !Command
+              command: ls
+              type_speed: 50ms
+            - !Wait 1s
+            ",
+        )
+        .unwrap();
+        let options = Options {
+            typing_speed_factor: 2.0,
+            ..options()
+        };
+        assert_eq!(
+            instructions[0].typed_events(options),
+            [output(25, "l"), output(25, "s"), output(25, "\r\n")]
+        );
+        assert!(instructions[1].typed_events(options).is_empty());
+    }
+
+    #[test]
+    fn pre_enter_delay() {
+        let options = Options {
+            pre_enter_delay: Duration::from_millis(500),
+            ..options()
+        };
+        let command = Command::MultiLine(vec![String::from("a"), String::from("b")]);
+        let times = command
+            .events(Duration::from_millis(100), options)
+            .map(|event| event.time.as_millis())
+            .collect_vec();
+        // Only the line break which runs the command is delayed.
+        assert_eq!(times, [100, 100, 100, 100, 100, 100, 600]);
+    }
+
+    #[test]
+    fn type_graphemes() {
+        let command = Command::SingleLine(String::from("e\u{301}\u{1f44d}\u{1f3fd}日"));
+        let events = |double_width_pacing| {
+            let options = Options {
+                double_width_pacing,
+                ..options()
+            };
+            command
+                .events(Duration::from_millis(100), options)
+                .map(|event| (event.time.as_millis(), event.data))
+                .collect_vec()
+        };
+        assert_eq!(
+            events(false),
+            [
+                (100, String::from("e\u{301}")),
+                (100, String::from("\u{1f44d}\u{1f3fd}")),
+                (100, String::from("日")),
+                (100, String::from("\r\n")),
+            ]
+        );
+        let times = events(true).into_iter().map(|(time, _)| time).collect_vec();
+        assert_eq!(times, [100, 200, 200, 100]);
+    }
+
+    #[test]
+    fn speed_factors() {
+        let mut mock = Mock::new("PROMPT", ["file\r\nPROMPT"]);
+        let events = run_with_factors("- !Command\n  command: ls", &mut mock, 2.0, 0.5);
+        assert_eq!(
+            events,
+            [
+                output(0, "$ "),
+                output(50, "l"),
+                output(100, "s"),
+                output(150, "\r\n"),
+                output(152, "file\r\n"),
+                output(154, "$ "),
+                output(204, "\r\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_output() {
+        let mut mock = Mock::new("PROMPT", ["PROMPT", "[1]+ Done\r\nPROMPT"]);
+        let events = run("- !Command\n  command: sleep 1 &", &mut mock);
+        let data: Vec<_> = events[events.len() - 3..]
+            .iter()
+            .map(|event| event.data.as_str())
+            .collect();
+        assert_eq!(data, ["[1]+ Done\r\n", "$ ", "\r\n"]);
+    }
+
+    #[test]
+    fn multi_line_command() {
+        let mut mock = Mock::new("PROMPT", ["PROMPT"]);
+        let events = run("- !Command\n  command: [a, b]", &mut mock);
+        let data: String = events.into_iter().map(|event| event.data).collect();
+        assert_eq!(data, "$ a \\\r\n> b\r\n$ \r\n");
+        assert_eq!(sent_lines(&mock), ["a b"]);
+
+        // The shown line split is independent of what is sent between lines.
+        let mut mock = Mock::new("PROMPT", ["PROMPT"]);
+        let instructions: Vec<Instruction> =
+            serde_yaml::from_str("- !Command\n  command: [a, b]").unwrap();
+        let options = Options {
+            line_continuation: "\\\n",
+            ..options()
+        };
+        let events = super::instructions(&instructions, options, &mut mock).unwrap();
+        let data: String = events.into_iter().map(|event| event.data).collect();
+        assert_eq!(data, "$ a \\\r\n> b\r\n$ \r\n");
+        assert_eq!(sent_lines(&mock), ["a\\", "b"]);
+    }
+
+    #[test]
+    fn hidden_wait_and_marker() {
+        let mut mock = Mock::new("PROMPT", ["setup\r\nPROMPT", "file\r\nPROMPT"]);
+        let events = run(
+            "
+            - !Command
+              command: setup
+              hidden: true
+            - !Wait 1s
+            - !Marker marker
+            - !Command
+              command: ls
+            ",
+            &mut mock,
+        );
+        assert_eq!(
+            events,
+            [
+                output(0, "$ "),
+                Event::marker(Duration::from_secs(1), String::from("marker")),
+                output(1100, "l"),
+                output(1200, "s"),
+                output(1300, "\r\n"),
+                output(1301, "file\r\n"),
+                output(1302, "$ "),
+                output(1402, "\r\n"),
+            ]
+        );
+        assert_eq!(sent_lines(&mock), ["setup", "ls"]);
+    }
+
+    #[test]
+    fn capture() {
+        let mut mock = Mock::new("PROMPT", ["\x1b[1m 4f2a \x1b[0m\r\nPROMPT", "\r\nPROMPT"]);
+        let events = run(
+            "
+            - !Capture
+              command: docker run -d demo
+              var: id
+            - !Command
+              command: echo ${id} ${HOME}
+            ",
+            &mut mock,
+        );
+        assert_eq!(
+            sent_lines(&mock),
+            ["docker run -d demo", "echo 4f2a ${HOME}"]
+        );
+        assert_eq!(events[1], output(100, "e"));
+    }
+
+    #[test]
+    fn branch() {
+        let mut mock = Mock::new("PROMPT", ["yes\r\nPROMPT", "PROMPT"]);
+        let events = run(
+            "
+            - !Capture
+              command: check
+              var: installed
+            - !If
+              var_matches:
+                installed: ^yes$
+              then:
+                - !Marker installed
+                - !Wait 1s
+              else:
+                - !Command
+                  command: install
+            - !Marker next
+            ",
+            &mut mock,
+        );
+        assert_eq!(sent_lines(&mock), ["check"]);
+        assert_eq!(
+            events[..3],
+            [
+                output(0, "$ "),
+                Event::marker(Duration::ZERO, String::from("installed")),
+                Event::marker(Duration::from_secs(1), String::from("next")),
+            ]
+        );
+    }
+
+    #[test]
+    fn title() {
+        let mut mock = Mock::new("PROMPT", [""; 0]);
+        let events = run("- !Title \"Step 2:\\a configuration\"", &mut mock);
+        assert_eq!(events[1], output(0, "\x1b]2;Step 2: configuration\x07"));
+    }
+
+    #[test]
+    fn resize() {
+        let mut mock = Mock::new("PROMPT", ["\x1b[H\x1b[2Jredrawn"]);
+        let events = run("- !Resize\n  height: 12", &mut mock);
+        assert_eq!(mock.size, (80, 12));
+        assert_eq!(
+            events[1..3],
+            [
+                Event::resize(Duration::ZERO, 80, 12),
+                output(1, "\x1b[H\x1b[2Jredrawn"),
+            ]
+        );
+    }
+
+    #[test]
+    fn confirm() {
+        let mut mock = Mock::new("PROMPT", ["Continue? [Y/n] ", "done\r\nPROMPT"]);
+        mock.echo = true;
+        let events = run(
+            "
+            - !Command
+              command: apt
+              type_speed: 1ms
+              confirm: [y]
+            ",
+            &mut mock,
+        );
+        let output: Vec<_> = events[5..9]
+            .iter()
+            .map(|event| event.data.as_str())
+            .collect();
+        // The reply is only in the output once, as echoed by the terminal.
+        assert_eq!(output, ["apt\r\n", "Continue? [Y/n] ", "y\r\n", "done\r\n"]);
+        assert_eq!(sent_lines(&mock), ["apt", "y"]);
+    }
+
+    #[test]
+    fn filter_output() {
+        let filter = OutputFilter {
+            drop: RegexSet::new([r"^\d+%$"]).unwrap(),
+            keep: RegexSet::empty(),
+        };
+        let mut events = vec![
+            output(1, "start\r\n10%\r"),
+            output(2, "50%\r"),
+            output(3, "100%\rdo"),
+            output(4, "ne\r\n"),
+        ];
+        super::filter_output(&mut events, &filter);
+        assert_eq!(events, [output(1, "start\r\n"), output(9, "done\r\n")]);
+
+        let filter = OutputFilter {
+            drop: RegexSet::empty(),
+            keep: RegexSet::new(["error"]).unwrap(),
+        };
+        let mut events = vec![output(1, "ok\nerror: oops\nok\nerror")];
+        super::filter_output(&mut events, &filter);
+        assert_eq!(events, [output(1, "error: oops\nerror")]);
+    }
+
+    #[test]
+    fn alt_screen() {
+        let vim = vec![
+            output(1, "\x1b[?1049hvim"),
+            output(2, "text"),
+            output(3, "\x1b[?1049l"),
+        ];
+        let (events, exited) = super::alt_screen(vim.clone(), false);
+        assert_eq!(events, vim);
+        assert!(exited);
+
+        let (events, _) = super::alt_screen(vim, true);
+        let types: Vec<_> = events.iter().map(|event| event.event_type.code()).collect();
+        assert_eq!(types, ["o", "m", "o", "o", "m"]);
+
+        let (_, exited) = super::alt_screen(vec![output(1, "\x1b[?1049h")], true);
+        assert!(!exited);
+    }
+
+    #[test]
+    fn is_confirmation() {
+        assert!(super::is_confirmation("Do you want to continue? [Y/n] "));
+        assert!(super::is_confirmation("line\nOverwrite? (yes/no) "));
+        assert!(!super::is_confirmation("[y/N]\nnext line"));
+    }
+
+    #[test]
+    fn waiting_for_input() {
+        let message = |data: &str| {
+            let error = eyre::Report::new(Timeout {
+                output: vec![output(1, data)],
+            });
+            super::waiting_for_input(error).to_string()
+        };
+        assert!(message("commit 1234\r\n\x1b[7m(END)\x1b[27m\x1b[K").contains("pager"));
+        assert!(message("line\r\n:\x1b[K").contains("pager"));
+        assert!(message("\x1b[?1049h~\r\n~\r\n").contains("full screen"));
+        assert_eq!(message("\x1b[?1049hvim\x1b[?1049l"), "timeout elapsed");
+        assert_eq!(message("Loading..."), "timeout elapsed");
+    }
+
+    #[test]
+    fn coalesce_leading() {
+        let mut events = vec![
+            output(0, "$ "),
+            output(0, "l"),
+            Event::marker(Duration::ZERO, String::from("marker")),
+            output(0, "s"),
+            output(1, "\r\n"),
+        ];
+        super::coalesce_leading(&mut events, Duration::ZERO);
+        assert_eq!(
+            events,
+            [
+                output(0, "$ l"),
+                Event::marker(Duration::ZERO, String::from("marker")),
+                output(0, "s"),
+                output(1, "\r\n"),
+            ]
+        );
+
+        // The first keystroke is merged with the prompt, but not the next.
+        let mut events = vec![output(0, "$ "), output(1, "l"), output(2, "s")];
+        super::coalesce_leading(&mut events, Duration::from_millis(1));
+        assert_eq!(events, [output(0, "$ l"), output(2, "s")]);
+    }
+
+    #[test]
+    fn misplaced_prompts() {
+        let events = [
+            output(0, "$ "),
+            output(1, "l"),
+            output(2, "s\r\n"),
+            output(3, "file\r\n$ more"),
+            output(4, "$ "),
+            output(5, "$ not a prompt"),
+            output(6, "\r\n"),
+            output(7, "$ stray"),
+        ];
+        assert_eq!(
+            super::misplaced_prompts(&events, "$ "),
+            [Duration::from_millis(3), Duration::from_millis(7)]
+        );
+    }
+
+    #[test]
+    fn settle() {
+        let mut mock = Mock::new("PROMPT", ["PROMPT", "starting\r\n", "ready\r\n"]);
+        let events = run(
+            "
+            - !Command
+              command: daemon &
+            - !Settle 10ms
+            ",
+            &mut mock,
+        );
+        let data: Vec<_> = events[events.len() - 3..]
+            .iter()
+            .map(|event| event.data.as_str())
+            .collect();
+        assert_eq!(data, ["starting\r\n", "ready\r\n", "\r\n"]);
+    }
+
+    #[test]
+    fn clear() {
+        let mut mock = Mock::new("PROMPT", Vec::<String>::new());
+        let events = run("- !Clear", &mut mock);
+        assert_eq!(
+            events,
+            [
+                output(0, "$ "),
+                output(100, "\r\x1b[H\x1b[2J\x1b[3J"),
+                output(200, "$ "),
+                output(300, "\r\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn strip_partial_line() {
+        let mut events = vec![output(1, "file\r\nuser"), output(2, "@host$ ")];
+        assert_eq!(
+            super::strip_partial_line(&mut events),
+            Duration::from_millis(2)
+        );
+        assert_eq!(events, [output(1, "file\r\n")]);
+
+        let mut events = vec![output(1, "user@host$ ")];
+        assert_eq!(
+            super::strip_partial_line(&mut events),
+            Duration::from_millis(1)
+        );
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn highlight_stderr() {
+        let mut mock = Mock::new("PROMPT", ["out\r\n\x0eerr\x0f\r\nPROMPT"]);
+        let events = run(
+            "- !Command\n  command: make\n  highlight_stderr: true",
+            &mut mock,
+        );
+        assert_eq!(sent_lines(&mock), ["WRAP<make>"]);
+        assert_eq!(events[6].data, "out\r\n\x1b[31merr\x1b[0m\r\n");
+    }
+
+    #[test]
+    fn wrap_lines() {
+        let options = Options {
+            type_speed: Duration::ZERO,
+            stderr_wrapper: None,
+            wrap_width: Some(14),
+            ..options()
+        };
+        assert_eq!(
+            super::wrap_lines(&["echo one two three"], 14, options),
+            ["echo one", "two three"]
+        );
+        assert_eq!(
+            super::wrap_lines(&["echo", "averyveryverylongword x"], 14, options),
+            ["echo", "averyveryverylongword", "x"]
+        );
+
+        let command = Command::SingleLine(String::from("echo one two"));
+        let typed: String = command
+            .events(Duration::ZERO, options)
+            .map(|event| event.data)
+            .collect();
+        assert_eq!(typed, "echo one \\\r\n> two\r\n");
+
+        let options = Options {
+            prompt: "\x1b[1;36m❯\x1b[0m ",
+            ..options
+        };
+        assert_eq!(
+            super::wrap_lines(&["echo one two three"], 14, options),
+            ["echo one", "two three"]
+        );
+    }
 }
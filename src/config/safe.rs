@@ -0,0 +1,159 @@
+//! Refusing to run dangerous commands, so a typo in a contributed script
+//! can't damage the machine running it, i.e. a CI runner.
+
+use color_eyre::{
+    eyre::{self, Context},
+    Section,
+};
+use regex::RegexSet;
+
+use super::{condition, Instruction, Key};
+
+/// Patterns of commands which are always refused in safe mode,
+/// `dangerous_commands` adds to them
+const DENYLIST: &[&str] = &[
+    // Recursive and forced `rm`, i.e. "rm -rf" or "rm -r -f".
+    r"\brm\s+(-\S+\s+)*-[a-zA-Z]*([rR][a-zA-Z]*f|f[a-zA-Z]*[rR])",
+    r"\brm\s+(-\S+\s+)*(-[rR]|--recursive)\s+(-\S+\s+)*(-f|--force)\b",
+    r"\brm\s+(-\S+\s+)*(-f|--force)\s+(-\S+\s+)*(-[rR]|--recursive)\b",
+    // Running a downloaded script.
+    r"\b(curl|wget)\b[^|]*\|\s*(sudo\s+)?(ba|da|z|fi)?sh\b",
+    // Writing to a device or file with `dd`.
+    r"\bdd\b.*\bof=",
+];
+
+/// Checks that none of the `instructions` match a dangerous command pattern,
+/// unless they allow it.
+pub fn check(
+    instructions: &[Instruction],
+    dangerous_commands: &[String],
+) -> color_eyre::Result<()> {
+    let refused = refused(instructions, dangerous_commands)?;
+    if refused.is_empty() {
+        return Ok(());
+    }
+    Err(eyre::eyre!(
+        "refusing to run {} dangerous instruction(s) in safe mode:\n  {}",
+        refused.len(),
+        refused.join("\n  ")
+    ))
+    .suggestion(
+        "if the instruction is intended, add `allow_dangerous: true` to it, \
+            or run without `--safe`",
+    )
+}
+
+/// A message for each of the `instructions` which would be refused in safe mode.
+pub fn refused(
+    instructions: &[Instruction],
+    dangerous_commands: &[String],
+) -> color_eyre::Result<Vec<String>> {
+    let denylist = RegexSet::new(
+        DENYLIST
+            .iter()
+            .copied()
+            .chain(dangerous_commands.iter().map(String::as_str)),
+    )
+    .wrap_err("invalid dangerous command pattern")?;
+    let mut refused = Vec::new();
+    for (num, instruction) in condition::numbered(instructions) {
+        let (command, keys) = match instruction {
+            Instruction::Command {
+                command,
+                allow_dangerous: false,
+                ..
+            }
+            | Instruction::Capture {
+                command,
+                allow_dangerous: false,
+                ..
+            } => (command, [].as_slice()),
+            Instruction::Interactive {
+                command,
+                keys,
+                allow_dangerous: false,
+                ..
+            } => (command, keys.as_slice()),
+            _ => continue,
+        };
+        let command = command.to_string();
+        if denylist.is_match(&command) {
+            refused.push(format!("instruction {num}, `{command}`, is dangerous"));
+        } else if let Some(line) = typed_lines(keys).find(|line| denylist.is_match(line)) {
+            refused.push(format!("instruction {num}, typing `{line}`, is dangerous"));
+        }
+    }
+    Ok(refused)
+}
+
+/// The text typed by `keys`, split into lines at control codes, like enter.
+fn typed_lines(keys: &[Key]) -> impl Iterator<Item = String> {
+    let mut lines = vec![String::new()];
+    for key in keys {
+        let line = lines.last_mut().expect("there is always a line");
+        match key {
+            Key::Char(char) => line.push(*char),
+            Key::Text(text) => line.push_str(text),
+            Key::Control(_) => lines.push(String::new()),
+            Key::Wait(_) => {}
+        }
+    }
+    lines.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use expectrl::ControlCode;
+
+    use super::{super::Command, *};
+
+    #[test]
+    fn refused() -> color_eyre::Result<()> {
+        let command = |command: &str, allow_dangerous| Instruction::Command {
+            command: Command::SingleLine(String::from(command)),
+            hidden: false,
+            type_speed: None,
+            confirm: Vec::new(),
+            highlight_stderr: false,
+            output_filter: None,
+            allow_dangerous,
+        };
+        let instructions = [
+            command("rm -rf target", false),
+            command("rm -v -r --force target", false),
+            command("rm -r target", false),
+            command("curl -sSf https://example.com/install | sh", false),
+            command("dd if=/dev/zero of=/dev/sda", false),
+            command("rm -rf target", true),
+            command("git push --force", false),
+            Instruction::Interactive {
+                command: Command::SingleLine(String::from("bash")),
+                keys: vec![
+                    Key::Text(String::from("ls")),
+                    Key::Control(ControlCode::CarriageReturn),
+                    Key::Text(String::from("rm -r")),
+                    Key::Wait(Duration::from_millis(100)),
+                    Key::Char('f'),
+                    Key::Text(String::from(" target")),
+                ],
+                type_speed: None,
+                allow_dangerous: false,
+            },
+        ];
+        assert_eq!(
+            super::refused(&instructions, &[String::from(r"push\s+--force")])?,
+            [
+                "instruction 0, `rm -rf target`, is dangerous",
+                "instruction 1, `rm -v -r --force target`, is dangerous",
+                "instruction 3, `curl -sSf https://example.com/install | sh`, is dangerous",
+                "instruction 4, `dd if=/dev/zero of=/dev/sda`, is dangerous",
+                "instruction 6, `git push --force`, is dangerous",
+                "instruction 7, typing `rm -rf target`, is dangerous",
+            ]
+        );
+        assert!(super::refused(&instructions, &[String::from("(")]).is_err());
+        Ok(())
+    }
+}
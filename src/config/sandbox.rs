@@ -0,0 +1,125 @@
+//! Isolated home directory for running the shell without the user's dotfiles.
+
+use std::{
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::Context;
+use tempfile::TempDir;
+
+/// A temporary home directory, removed when dropped.
+#[derive(Debug)]
+pub struct Sandbox {
+    home: TempDir,
+}
+
+/// XDG base directories, relative to the home directory.
+const XDG_DIRS: &[(&str, &str)] = &[
+    ("XDG_CONFIG_HOME", ".config"),
+    ("XDG_DATA_HOME", ".local/share"),
+    ("XDG_STATE_HOME", ".local/state"),
+    ("XDG_CACHE_HOME", ".cache"),
+];
+
+impl Sandbox {
+    /// Creates a sandbox if it is `enabled` or a `template` is given.
+    pub fn start(enabled: bool, template: Option<&Path>) -> color_eyre::Result<Option<Self>> {
+        (enabled || template.is_some())
+            .then(|| Self::new(template))
+            .transpose()
+    }
+
+    /// Creates a temporary home directory, copying the contents of `template` into it.
+    pub fn new(template: Option<&Path>) -> color_eyre::Result<Self> {
+        let home = tempfile::Builder::new()
+            .prefix("autocast-home-")
+            .tempdir()
+            .wrap_err("could not create sandbox home directory")?;
+
+        if let Some(template) = template {
+            copy_dir(template, home.path()).wrap_err_with(|| {
+                format!("could not copy sandbox template `{}`", template.display())
+            })?;
+        }
+        for (_, dir) in XDG_DIRS {
+            fs::create_dir_all(home.path().join(dir))
+                .wrap_err("could not create sandbox XDG directories")?;
+        }
+
+        Ok(Self { home })
+    }
+
+    /// The temporary home directory.
+    pub fn home(&self) -> &Path {
+        self.home.path()
+    }
+
+    /// Environment variables which point the shell to the sandbox.
+    pub fn environment(&self) -> Vec<(&'static OsStr, PathBuf)> {
+        let home = self.home.path();
+        let mut environment = vec![(OsStr::new("HOME"), home.to_path_buf())];
+        environment.extend(
+            XDG_DIRS
+                .iter()
+                .map(|(name, dir)| (OsStr::new(name), home.join(dir))),
+        );
+        environment
+    }
+}
+
+/// Recursively copies the contents of the `from` directory into the `to` directory.
+pub fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let to = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            fs::create_dir_all(&to)?;
+            copy_dir(&entry.path(), &to)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &to)?;
+        } else {
+            fs::copy(entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template() -> color_eyre::Result<()> {
+        let template = TempDir::new()?;
+        fs::create_dir(template.path().join(".config"))?;
+        fs::write(template.path().join(".config/rc"), "config")?;
+        fs::write(template.path().join(".bashrc"), "bashrc")?;
+
+        let sandbox = Sandbox::new(Some(template.path()))?;
+        let home = sandbox.home.path();
+        assert_eq!(fs::read_to_string(home.join(".bashrc"))?, "bashrc");
+        assert_eq!(fs::read_to_string(home.join(".config/rc"))?, "config");
+        assert!(home.join(".local/share").is_dir());
+
+        let environment = sandbox.environment();
+        assert_eq!(environment[0], (OsStr::new("HOME"), home.to_path_buf()));
+
+        let home = home.to_path_buf();
+        drop(sandbox);
+        assert!(!home.exists());
+        Ok(())
+    }
+}
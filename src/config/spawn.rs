@@ -1,81 +1,379 @@
+#[cfg(unix)]
+pub mod tmux;
+
 use std::{
     ffi::OsStr,
+    fmt::Write as _,
+    fs,
     io::{self, BufRead, BufReader, Read, Write},
+    mem,
     ops::{Deref, DerefMut},
     process::Command,
     time::{Duration, Instant},
 };
 
-use color_eyre::eyre::{self, Context};
-#[cfg(unix)]
-use expectrl::process::unix::UnixProcess;
+use color_eyre::eyre::Context;
 #[cfg(windows)]
 use expectrl::process::windows::WinProcess;
+#[cfg(unix)]
+use expectrl::{process::unix::UnixProcess, WaitStatus};
 use expectrl::{
     process::{NonBlocking, Process},
     session::{OsProcess, OsProcessStream},
 };
-use os_str_bytes::OsStrBytes;
 
 use crate::asciicast::Event;
 
-pub(super) fn bash<I, K, V>(
-    timeout: Duration,
-    environment: I,
-    width: u16,
-    height: u16,
-) -> color_eyre::Result<ShellSession>
+use super::{
+    driver::{SessionDriver, Spawn, Terminal},
+    BinaryOutput,
+};
+
+/// How to spawn one of the built-in shells.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Options<'a> {
+    /// Program and arguments the shell is run with, see [`command()`]
+    pub wrapper: &'a [String],
+    pub timeout: Duration,
+    pub terminal: Terminal,
+    /// Don't write commands to the user's history file
+    pub disable_history: bool,
+    /// Don't expand `!` in commands
+    pub disable_history_expansion: bool,
+    /// Add the known prompt to the end of the user's prompt instead of replacing it
+    pub keep_prompt: bool,
+    pub binary_output: BinaryOutput,
+}
+
+/// Spawns bash with a known prompt.
+///
+/// If `disable_history` is true, the history file is unset after bash starts so that
+/// commands are not written to the user's history when bash exits.
+///
+/// If `disable_history_expansion` is true, `!` is not expanded in commands.
+///
+/// If `keep_prompt` is true, the known prompt is added to the end of the user's prompt
+/// instead of replacing it.
+pub(super) fn bash<D, I, K, V>(environment: I, options: Options) -> color_eyre::Result<D>
 where
+    D: Spawn,
     I: IntoIterator<Item = (K, V)>,
     K: AsRef<OsStr>,
     V: AsRef<OsStr>,
 {
-    const PROMPT: &str = "AUTOCAST_PROMPT";
-    const PROMPT_COMMAND: &str =
-        "PS1=AUTOCAST_PROMPT; unset PROMPT_COMMAND; bind 'set enable-bracketed-paste off'";
+    const PROMPT_COMMAND: &str = "unset PROMPT_COMMAND; bind 'set enable-bracketed-paste off'";
+
+    let Options {
+        wrapper,
+        timeout,
+        terminal,
+        disable_history,
+        disable_history_expansion,
+        keep_prompt,
+        binary_output,
+    } = options;
 
-    let mut command = Command::new("bash");
+    let prompt = unique_prompt();
+    // `PROMPT_COMMAND` is run after any rc files, so `PS1` and `HISTFILE` can't be set again.
+    let mut prompt_command = if keep_prompt {
+        format!("PS1=\"${{PS1}}{prompt}\"; {PROMPT_COMMAND}")
+    } else {
+        format!("PS1={prompt}; {PROMPT_COMMAND}")
+    };
+    if disable_history {
+        prompt_command.push_str("; unset HISTFILE");
+    }
+    if disable_history_expansion {
+        prompt_command.push_str("; set +H");
+    }
+
+    let mut command = command("bash", wrapper);
     command
         .envs(environment)
-        .env("PS1", PROMPT)
-        .env("PROMPT_COMMAND", PROMPT_COMMAND);
+        .env("PROMPT_COMMAND", prompt_command);
+    if !keep_prompt {
+        command.env("PS1", &prompt);
+    }
 
-    ShellSession::spawn(
+    D::spawn(
         command,
-        width,
-        height,
-        String::from(PROMPT),
+        terminal,
+        vec![prompt],
         Some(String::from("exit")),
         timeout,
+        binary_output,
     )
 }
 
-pub(super) fn python<I, K, V>(
-    timeout: Duration,
-    environment: I,
-    width: u16,
-    height: u16,
-) -> color_eyre::Result<ShellSession>
+/// Spawns zsh with a known prompt.
+///
+/// zsh's startup files are run from a temporary `ZDOTDIR` which runs the user's files
+/// and then sets the prompt, so that it can't be changed by them.
+/// The line editor is disabled, so typed commands are not echoed and
+/// bracketed paste is not used.
+///
+/// If `disable_history` is true, the history file is unset so that
+/// commands are not written to the user's history when zsh exits.
+///
+/// If `disable_history_expansion` is true, `!` is not expanded in commands.
+///
+/// If `keep_prompt` is true, the known prompt is added to the end of the user's prompt
+/// instead of replacing it.
+pub(super) fn zsh<D, I, K, V>(environment: I, options: Options) -> color_eyre::Result<D>
+where
+    D: Spawn,
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    // Runs the user's `.zshenv` and keeps zsh reading from the temporary `ZDOTDIR`.
+    const ZSHENV: &str = r"AUTOCAST_ZDOTDIR=$ZDOTDIR
+ZDOTDIR=${AUTOCAST_USER_ZDOTDIR:-$HOME}
+[[ -r $ZDOTDIR/.zshenv ]] && source $ZDOTDIR/.zshenv
+AUTOCAST_USER_ZDOTDIR=$ZDOTDIR
+ZDOTDIR=$AUTOCAST_ZDOTDIR
+";
+    const ZSHRC: &str = r"ZDOTDIR=$AUTOCAST_USER_ZDOTDIR
+unset AUTOCAST_ZDOTDIR AUTOCAST_USER_ZDOTDIR
+[[ -r $ZDOTDIR/.zshrc ]] && source $ZDOTDIR/.zshrc
+unsetopt zle prompt_sp
+";
+
+    let Options {
+        wrapper,
+        timeout,
+        terminal,
+        disable_history,
+        disable_history_expansion,
+        keep_prompt,
+        binary_output,
+    } = options;
+
+    let prompt = unique_prompt();
+    let ps1 = if keep_prompt {
+        format!("AUTOCAST_PS1=\"${{PS1}}{prompt}\"")
+    } else {
+        format!("AUTOCAST_PS1={prompt}")
+    };
+    // The prompt is set before each prompt is shown, as themes often set it in a hook.
+    let mut zshrc = format!(
+        "{ZSHRC}{ps1}\n\
+        autocast_precmd() {{ PS1=$AUTOCAST_PS1; RPS1=; }}\n\
+        precmd_functions+=(autocast_precmd)\n"
+    );
+    if disable_history {
+        zshrc.push_str("unset HISTFILE\n");
+    }
+    if disable_history_expansion {
+        zshrc.push_str("unsetopt bang_hist\n");
+    }
+
+    let zdotdir = tempfile::Builder::new()
+        .prefix("autocast-zsh-")
+        .tempdir()
+        .wrap_err("could not create zsh startup directory")?;
+    fs::write(zdotdir.path().join(".zshenv"), ZSHENV)
+        .and_then(|()| fs::write(zdotdir.path().join(".zshrc"), zshrc))
+        .wrap_err("could not write zsh startup files")?;
+
+    let mut command = command("zsh", wrapper);
+    if let Some(user_zdotdir) = std::env::var_os("ZDOTDIR") {
+        command.env("AUTOCAST_USER_ZDOTDIR", user_zdotdir);
+    }
+    command.envs(environment).env("ZDOTDIR", zdotdir.path());
+
+    // The startup files are no longer needed once the prompt is detected.
+    D::spawn(
+        command,
+        terminal,
+        vec![prompt],
+        Some(String::from("exit")),
+        timeout,
+        binary_output,
+    )
+}
+
+/// Spawns nushell with a known prompt.
+///
+/// The prompt is set after nushell's config files are run, replacing the prompt indicator,
+/// and the multiline indicator is set to nushell's default, "::: ",
+/// so incomplete commands end with it instead of timing out.
+///
+/// If `disable_history` is true, nushell does not read or write its history file.
+///
+/// If `keep_prompt` is true, the prompt command from the user's config is kept,
+/// otherwise it is cleared.
+pub(super) fn nu<D, I, K, V>(environment: I, options: Options) -> color_eyre::Result<D>
+where
+    D: Spawn,
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    const MULTILINE_PROMPT: &str = "::: ";
+
+    let Options {
+        wrapper,
+        timeout,
+        terminal,
+        disable_history,
+        keep_prompt,
+        binary_output,
+        ..
+    } = options;
+
+    let prompt = unique_prompt();
+    let mut execute = format!(
+        "$env.config.show_banner = false; \
+        $env.config.bracketed_paste = false; \
+        $env.PROMPT_INDICATOR = \"{prompt}\"; \
+        $env.PROMPT_MULTILINE_INDICATOR = \"{MULTILINE_PROMPT}\"; \
+        $env.PROMPT_COMMAND_RIGHT = \"\"; \
+        hide-env -i TRANSIENT_PROMPT_COMMAND TRANSIENT_PROMPT_INDICATOR \
+        TRANSIENT_PROMPT_COMMAND_RIGHT TRANSIENT_PROMPT_MULTILINE_INDICATOR"
+    );
+    if !keep_prompt {
+        execute.push_str("; $env.PROMPT_COMMAND = \"\"");
+    }
+
+    let mut command = command("nu", wrapper);
+    if disable_history {
+        command.arg("--no-history");
+    }
+    command.arg("--execute").arg(execute).envs(environment);
+
+    D::spawn(
+        command,
+        terminal,
+        vec![prompt, String::from(MULTILINE_PROMPT)],
+        Some(String::from("exit")),
+        timeout,
+        binary_output,
+    )
+}
+
+/// Spawns cmd.exe with a known prompt.
+///
+/// Command echoing is turned off with `/Q`, and the prompt is set with the `PROMPT`
+/// environment variable. cmd.exe does not have a history file.
+///
+/// If `keep_prompt` is true, the known prompt is added to the end of the user's prompt,
+/// or cmd.exe's default prompt, instead of replacing it.
+pub(super) fn cmd<D, I, K, V>(environment: I, options: Options) -> color_eyre::Result<D>
+where
+    D: Spawn,
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    const DEFAULT_PROMPT: &str = "$P$G";
+
+    let Options {
+        wrapper,
+        timeout,
+        terminal,
+        keep_prompt,
+        binary_output,
+        ..
+    } = options;
+
+    let known_prompt = unique_prompt();
+    let mut command = command("cmd.exe", wrapper);
+    command.args(["/Q", "/K"]).envs(environment);
+    let prompt = if keep_prompt {
+        let mut prompt = command
+            .get_envs()
+            .find(|(name, _)| name.eq_ignore_ascii_case("PROMPT"))
+            .and_then(|(_, value)| value.map(OsStr::to_os_string))
+            .or_else(|| std::env::var_os("PROMPT"))
+            .unwrap_or_else(|| DEFAULT_PROMPT.into());
+        prompt.push(&known_prompt);
+        prompt
+    } else {
+        known_prompt.clone().into()
+    };
+    command.env("PROMPT", prompt);
+
+    D::spawn(
+        command,
+        terminal,
+        vec![known_prompt],
+        Some(String::from("exit")),
+        timeout,
+        binary_output,
+    )
+}
+
+/// Spawns an interactive python interpreter.
+///
+/// If `disable_history` is true, the history file is set to `/dev/null`,
+/// which is supported by python 3.13 and newer.
+pub(super) fn python<D, I, K, V>(environment: I, options: Options) -> color_eyre::Result<D>
 where
+    D: Spawn,
     I: IntoIterator<Item = (K, V)>,
     K: AsRef<OsStr>,
     V: AsRef<OsStr>,
 {
-    let mut command = Command::new("python");
+    let Options {
+        wrapper,
+        timeout,
+        terminal,
+        disable_history,
+        binary_output,
+        ..
+    } = options;
+    let mut command = command("python", wrapper);
     command.envs(environment);
+    if disable_history {
+        command.env("PYTHON_HISTORY", "/dev/null");
+    }
 
-    ShellSession::spawn(
+    D::spawn(
         command,
-        width,
-        height,
-        String::from(">>> "),
+        terminal,
+        vec![String::from(">>> ")],
         Some(String::from("exit()")),
         timeout,
+        binary_output,
     )
 }
 
+/// A command running `program`, after the `wrapper`'s program and arguments if there is one,
+/// i.e. `nix develop --command bash`.
+pub(super) fn command(program: &str, wrapper: &[String]) -> Command {
+    match wrapper.split_first() {
+        Some((wrapper, args)) => {
+            let mut command = Command::new(wrapper);
+            command.args(args).arg(program);
+            command
+        }
+        None => Command::new(program),
+    }
+}
+
+/// Creates a prompt which is unique to this run, so it is not mistaken for output
+/// which happens to contain it, and is not one which rc files would set.
+fn unique_prompt() -> String {
+    format!("AUTOCAST_PROMPT_{:032x}", fastrand::u128(..))
+}
+
 pub struct ShellSession<P = OsProcess, S = OsProcessStream> {
-    prompt: String,
+    /// Prompts which end a command's output, the first is the primary prompt
+    prompts: Vec<String>,
+    /// Index of the last prompt detected
+    matched_prompt: usize,
+    /// The last prompt detected as the shell printed it, from the prompt pattern
+    /// to the end of the output read, i.e. "In [2]: " for "In ["
+    printed_prompt: String,
+    /// Output since the last line break
+    line: String,
+    /// Output on the line before the prompt, when it was first detected
+    first_prompt_line: Option<String>,
+    binary_output: BinaryOutput,
+    /// Number of bytes read from the shell, the offset of the next output in the stream
+    offset: usize,
     quit_command: Option<String>,
     timeout: Duration,
     process: P,
@@ -100,19 +398,26 @@ impl<P, S> ShellSession<P, S> {
 
 impl<P, S: Read> ShellSession<P, S> {
     fn new(
-        prompt: String,
+        prompts: Vec<String>,
         quit_command: Option<String>,
         timeout: Duration,
         process: P,
         stream: S,
+        binary_output: BinaryOutput,
     ) -> Self {
         let now = Instant::now();
         Self {
-            prompt,
+            prompts,
+            matched_prompt: 0,
+            printed_prompt: String::new(),
+            line: String::new(),
+            first_prompt_line: None,
             quit_command,
             timeout,
             process,
             stream: Stream::new(stream),
+            binary_output,
+            offset: 0,
             last_event: now,
         }
     }
@@ -121,86 +426,108 @@ impl<P, S: Read> ShellSession<P, S> {
 impl<P, S: Read + NonBlocking> ShellSession<P, S> {
     /// Reads the shell's output, adding it to the event buffer.
     /// Returns whether the prompt was detected.
+    ///
+    /// Output which is not valid UTF-8 is decoded according to the [`BinaryOutput`] policy,
+    /// after the prompt is split off so it is never mangled.
     pub fn read(&mut self) -> io::Result<(Option<Event>, bool)> {
-        let data = self.stream.read_to_string()?;
+        let bytes = self.stream.read_available()?;
+        let offset = self.offset;
+        self.offset += bytes.len();
 
-        if data.is_empty() {
+        if bytes.is_empty() {
             Ok((None, false))
-        } else if let Some((data, _)) = data.rsplit_once(&self.prompt) {
+        } else if let Some((bytes, prompt, matched)) = self.split_prompt(&bytes) {
+            let data = decode(bytes, offset, self.binary_output)?;
+            self.matched_prompt = matched;
+            self.printed_prompt = String::from_utf8_lossy(prompt).into_owned();
+            self.update_line(&data);
+            let line = mem::take(&mut self.line);
+            self.first_prompt_line.get_or_insert(line);
             if data.is_empty() {
                 Ok((None, true))
             } else {
-                Ok((Some(self.new_event(String::from(data))), true))
+                Ok((Some(self.new_event(data)), true))
             }
         } else {
+            let data = decode(&bytes, offset, self.binary_output)?;
+            self.update_line(&data);
             Ok((Some(self.new_event(data)), false))
         }
     }
+}
 
-    /// Reads the shell's output, adding it to the event buffer,
-    /// blocking until the prompt is detected, or the timeout is surpassed.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the timeout is surpassed or there was an IO error
-    /// while reading the shell output.
-    pub fn read_until_prompt(&mut self) -> color_eyre::Result<Vec<Event>> {
-        let start = Instant::now();
-        let mut events = Vec::new();
-        loop {
-            let (event, prompt) = self.read().wrap_err("error reading shell output")?;
-            events.extend(event);
-            if prompt {
-                return Ok(events);
-            }
-            if start.elapsed() > self.timeout {
-                eyre::bail!("timeout elapsed");
-            }
+impl<P, S> ShellSession<P, S> {
+    /// Finds the prompt which occurs last in `data`, preferring longer prompts.
+    /// Returns the data before the prompt, the data from the prompt on,
+    /// and the index of the prompt.
+    fn split_prompt<'a>(&self, data: &'a [u8]) -> Option<(&'a [u8], &'a [u8], usize)> {
+        self.prompts
+            .iter()
+            .enumerate()
+            .filter_map(|(index, prompt)| {
+                rfind(data, prompt.as_bytes()).map(|start| (start, prompt.len(), index))
+            })
+            .max_by_key(|&(start, len, _)| (start, len))
+            .map(|(start, _, index)| (&data[..start], &data[start..], index))
+    }
+
+    /// Keeps track of the output since the last line break.
+    fn update_line(&mut self, data: &str) {
+        match data.rfind('\n') {
+            Some(index) => self.line = String::from(&data[index + 1..]),
+            None => self.line.push_str(data),
         }
     }
+
+    /// Output on the line before the prompt, when it was first detected.
+    pub fn first_prompt_line(&self) -> Option<&str> {
+        self.first_prompt_line.as_deref()
+    }
+
+    /// The last prompt detected as the shell printed it, if it was not the primary prompt.
+    pub fn alternate_prompt(&self) -> Option<&str> {
+        (self.matched_prompt != 0).then_some(self.printed_prompt.as_str())
+    }
 }
 
 impl<P, S: Write> ShellSession<P, S> {
     /// Send the buffer to the shell's stdin.
     pub fn send(&mut self, buf: impl AsRef<[u8]>) -> io::Result<()> {
-        self.stream.write_all(buf.as_ref())
-    }
-
-    /// Send the line to the shell's stdin, adding a new line to the end.
-    pub fn send_line(&mut self, line: impl AsRef<[u8]>) -> io::Result<()> {
-        #[cfg(not(windows))]
-        const LINE_ENDING: &str = "\n";
-        #[cfg(windows)]
-        const LINE_ENDING: &str = "\r\n";
-
-        self.send(line)?;
-        let line_ending: &OsStr = LINE_ENDING.as_ref();
-        self.send(line_ending.to_raw_bytes())
+        self.stream.write_all(buf.as_ref())?;
+        self.stream.flush()
     }
 }
 
-impl<P: Process + WindowSize> ShellSession<P, P::Stream>
+impl<P> Spawn for ShellSession<P, P::Stream>
 where
-    P::Stream: Read + NonBlocking,
+    P: Process<Command = Command> + WindowSize + Wait + Pid,
+    P::Stream: Read + Write + NonBlocking,
 {
-    /// Spawn a new [`ShellSession`] from a [`Command`].
-    /// Blocks until the shell's prompt is read.
-    pub fn spawn(
-        command: P::Command,
-        width: u16,
-        height: u16,
-        prompt: String,
+    fn spawn(
+        command: Command,
+        terminal: Terminal,
+        prompts: Vec<String>,
         quit_command: Option<String>,
         timeout: Duration,
+        binary_output: BinaryOutput,
     ) -> color_eyre::Result<Self> {
+        let mut command = command;
+        configure_terminal(&mut command, terminal);
         let mut process = P::spawn_command(command).wrap_err("could not spawn process")?;
         let stream = process
             .open_stream()
             .wrap_err("could not open process stream")?;
-        process
-            .set_window_size(width, height)
+        let mut shell_session = Self::new(
+            prompts,
+            quit_command,
+            timeout,
+            process,
+            stream,
+            binary_output,
+        );
+        shell_session
+            .resize(terminal.width, terminal.height)
             .wrap_err("could not set child terminal's size")?;
-        let mut shell_session = Self::new(prompt, quit_command, timeout, process, stream);
         shell_session
             .read_until_prompt()
             .wrap_err("could not detect prompt")?;
@@ -208,10 +535,48 @@ where
     }
 }
 
-impl<P: Process + Wait, S: Write> ShellSession<P, S> {
-    /// Sends the quit command to the shell.
-    /// Blocks until the shell process has exited.
-    pub fn quit(&mut self) -> color_eyre::Result<()> {
+impl<P> SessionDriver for ShellSession<P, P::Stream>
+where
+    P: Process<Command = Command> + WindowSize + Wait + Pid,
+    P::Stream: Read + Write + NonBlocking,
+{
+    fn alternate_prompt(&self) -> Option<&str> {
+        Self::alternate_prompt(self)
+    }
+
+    fn first_prompt_line(&self) -> Option<&str> {
+        Self::first_prompt_line(self)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn new_event(&mut self, data: String) -> Event {
+        Self::new_event(self, data)
+    }
+
+    fn reset(&mut self) {
+        Self::reset(self);
+    }
+
+    fn send(&mut self, buf: impl AsRef<[u8]>) -> io::Result<()> {
+        Self::send(self, buf)
+    }
+
+    fn read(&mut self) -> io::Result<(Option<Event>, bool)> {
+        Self::read(self)
+    }
+
+    fn resize(&mut self, width: u16, height: u16) -> color_eyre::Result<()> {
+        self.process.set_window_size(width, height)
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.process.pid()
+    }
+
+    fn quit(&mut self) -> color_eyre::Result<Option<i32>> {
         if let Some(quit_command) = &self.quit_command {
             let quit_command = quit_command.clone();
             self.send_line(quit_command)
@@ -220,12 +585,43 @@ impl<P: Process + Wait, S: Write> ShellSession<P, S> {
 
         self.process
             .wait(self.timeout)
-            .wrap_err("error waiting for shell to stop")?;
+            .wrap_err("error waiting for shell to stop")
+    }
+}
+
+/// Sets the terminal's flags in the child process before the shell is run,
+/// so the shell keeps them as the terminal's settings.
+#[cfg(unix)]
+fn configure_terminal(command: &mut Command, terminal: Terminal) {
+    use std::os::unix::process::CommandExt;
+
+    use nix::sys::termios::{self, InputFlags, LocalFlags, SetArg};
 
+    if !terminal.disable_flow_control && !terminal.disable_echoctl {
+        return;
+    }
+    let configure = move || {
+        // The terminal is the child's stdin.
+        let mut termios = termios::tcgetattr(0)?;
+        if terminal.disable_flow_control {
+            termios.input_flags.remove(InputFlags::IXON);
+        }
+        if terminal.disable_echoctl {
+            termios.local_flags.remove(LocalFlags::ECHOCTL);
+        }
+        termios::tcsetattr(0, SetArg::TCSANOW, &termios)?;
         Ok(())
+    };
+    // SAFETY: `tcgetattr()` and `tcsetattr()` are async-signal-safe and nothing is allocated.
+    unsafe {
+        command.pre_exec(configure);
     }
 }
 
+/// The terminal's flags can't be changed on this platform.
+#[cfg(not(unix))]
+fn configure_terminal(_: &mut Command, _: Terminal) {}
+
 pub trait WindowSize: Process {
     fn set_window_size(&mut self, width: u16, height: u16) -> color_eyre::Result<()>;
 }
@@ -250,24 +646,49 @@ impl WindowSize for WinProcess {
 
 pub trait Wait: Process {
     /// Waits for process to finish.
-    fn wait(&self, timeout: Duration) -> color_eyre::Result<()>;
+    /// Returns the process's exit status, if known.
+    fn wait(&self, timeout: Duration) -> color_eyre::Result<Option<i32>>;
 }
 
 #[cfg(unix)]
 impl Wait for UnixProcess {
-    fn wait(&self, _: Duration) -> color_eyre::Result<()> {
-        self.deref().wait().map(|_| ()).map_err(Into::into)
+    fn wait(&self, _: Duration) -> color_eyre::Result<Option<i32>> {
+        let status = match self.deref().wait()? {
+            WaitStatus::Exited(_, code) => Some(code),
+            // Like shells, report processes killed by a signal as 128 + the signal number.
+            WaitStatus::Signaled(_, signal, _) => Some(128 + signal as i32),
+            _ => None,
+        };
+        Ok(status)
     }
 }
 
 #[cfg(windows)]
 impl Wait for WinProcess {
-    fn wait(&self, timeout: Duration) -> color_eyre::Result<()> {
+    fn wait(&self, timeout: Duration) -> color_eyre::Result<Option<i32>> {
         let timeout = timeout.as_millis().try_into().unwrap_or(u32::MAX);
-        self.deref()
-            .wait(Some(timeout))
-            .map(|_| ())
-            .map_err(Into::into)
+        let code = self.deref().wait(Some(timeout))?;
+        Ok(Some(i32::from_ne_bytes(code.to_ne_bytes())))
+    }
+}
+
+pub trait Pid: Process {
+    /// The process's ID, if it is known.
+    fn pid(&self) -> Option<u32>;
+}
+
+#[cfg(unix)]
+impl Pid for UnixProcess {
+    fn pid(&self) -> Option<u32> {
+        u32::try_from(self.deref().pid().as_raw()).ok()
+    }
+}
+
+#[cfg(windows)]
+impl Pid for WinProcess {
+    /// Resource usage is only sampled on Linux, so the ID is not needed.
+    fn pid(&self) -> Option<u32> {
+        None
     }
 }
 
@@ -275,6 +696,8 @@ impl Wait for WinProcess {
 struct Stream<S> {
     inner: BufReader<S>,
     buffer: Vec<u8>,
+    /// Start of a UTF-8 character which was split between reads
+    incomplete: Vec<u8>,
 }
 
 impl<S: Read> Read for Stream<S> {
@@ -322,51 +745,211 @@ impl<S: Read> Stream<S> {
         Self {
             inner: BufReader::new(inner),
             buffer: vec![0; 1024],
+            incomplete: Vec::new(),
         }
     }
 }
 
 impl<S: Read + NonBlocking> Stream<S> {
-    fn read_to_string(&mut self) -> io::Result<String> {
-        let mut string = Vec::new();
+    /// Reads all available output.
+    ///
+    /// If the output ends partway through a UTF-8 character,
+    /// the start of the character is kept until the next read,
+    /// unless the end of the stream was reached.
+    fn read_available(&mut self) -> io::Result<Vec<u8>> {
+        let mut bytes = mem::take(&mut self.incomplete);
         self.set_non_blocking()?;
-        loop {
+        let end = loop {
             match self.inner.read(&mut self.buffer) {
-                Ok(0) => break,
-                Ok(bytes_read) => string.extend_from_slice(&self.buffer[..bytes_read]),
-                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Ok(0) => break true,
+                Ok(bytes_read) => bytes.extend_from_slice(&self.buffer[..bytes_read]),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break false,
                 Err(error) => return Err(error),
             }
-        }
+        };
         self.set_blocking()?;
-        let string = OsStr::assert_from_raw_bytes(string);
-        Ok(string.to_string_lossy().into())
+
+        if !end {
+            if let Err(error) = std::str::from_utf8(&bytes) {
+                if error.error_len().is_none() {
+                    self.incomplete = bytes.split_off(error.valid_up_to());
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/// Finds the start of the last occurrence of `needle` in `haystack`.
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(haystack.len());
+    }
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+/// Decodes `bytes`, which start at `offset` in the shell's output,
+/// handling invalid data according to the `binary_output` policy.
+fn decode(bytes: &[u8], offset: usize, binary_output: BinaryOutput) -> io::Result<String> {
+    if let Some(string) = decode_valid(bytes) {
+        return Ok(string);
+    }
+    match binary_output {
+        BinaryOutput::Lossy => Ok(String::from_utf8_lossy(bytes).into_owned()),
+        BinaryOutput::Escape => Ok(escape(bytes)),
+        BinaryOutput::Hexdump => Ok(hexdump_invalid(bytes, offset)),
+        BinaryOutput::Error => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "shell output is not valid UTF-8, see the `binary_output` setting",
+        )),
+    }
+}
+
+/// Decodes `bytes` if they are valid output of the platform, UTF-8 on unix.
+#[cfg(not(windows))]
+fn decode_valid(bytes: &[u8]) -> Option<String> {
+    std::str::from_utf8(bytes).ok().map(String::from)
+}
+
+/// Decodes `bytes` if they are valid output of the platform, which may contain
+/// unpaired surrogates on Windows.
+#[cfg(windows)]
+fn decode_valid(bytes: &[u8]) -> Option<String> {
+    use os_str_bytes::OsStrBytes;
+
+    OsStr::from_raw_bytes(bytes)
+        .ok()
+        .map(|string| string.to_string_lossy().into_owned())
+}
+
+/// Replaces each byte of invalid UTF-8 in `bytes` with an escape, i.e. `\x89`.
+fn escape(mut bytes: &[u8]) -> String {
+    let mut string = String::with_capacity(bytes.len());
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                string.push_str(valid);
+                return string;
+            }
+            Err(error) => {
+                let (valid, rest) = bytes.split_at(error.valid_up_to());
+                string.push_str(std::str::from_utf8(valid).expect("valid up to the error"));
+                let invalid_len = error.error_len().unwrap_or(rest.len());
+                for byte in &rest[..invalid_len] {
+                    let _ = write!(string, "\\x{byte:02x}");
+                }
+                bytes = &rest[invalid_len..];
+            }
+        }
+    }
+}
+
+/// Formats the part of `bytes` from the first to the last invalid UTF-8 with [`hexdump()`],
+/// keeping the text before and after it.
+fn hexdump_invalid(bytes: &[u8], offset: usize) -> String {
+    let Err(error) = std::str::from_utf8(bytes) else {
+        return String::from_utf8_lossy(bytes).into_owned();
+    };
+    let start = error.valid_up_to();
+    let mut end = start;
+    let mut rest = &bytes[start..];
+    while let Err(error) = std::str::from_utf8(rest) {
+        let invalid_end = error.valid_up_to() + error.error_len().unwrap_or(rest.len());
+        end += invalid_end;
+        rest = &rest[invalid_end..];
+    }
+
+    let mut string = String::from_utf8_lossy(&bytes[..start]).into_owned();
+    if !string.is_empty() && !string.ends_with('\n') {
+        string.push_str("\r\n");
+    }
+    string.push_str(&hexdump(&bytes[start..end], offset + start));
+    string.push_str(&String::from_utf8_lossy(&bytes[end..]));
+    string
+}
+
+/// Formats `bytes` like `hexdump -C`, with the offset from `start`, 16 bytes in hex,
+/// and the printable ASCII characters on each line.
+fn hexdump(bytes: &[u8], start: usize) -> String {
+    let mut string = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (index, byte) in chunk.iter().enumerate() {
+            let separator = if index == 8 { "  " } else { " " };
+            let _ = write!(hex, "{separator}{byte:02x}");
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    char::from(byte)
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let _ = write!(string, "{:08x} {hex:<49}  |{ascii}|\r\n", start + line * 16);
     }
+    string
 }
 
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
 
+    use os_str_bytes::OsStrBytes;
+
     use super::*;
 
     const TEST: &str = "test";
 
     #[cfg(target_os = "linux")]
     fn bash() -> color_eyre::Result<ShellSession> {
-        super::bash(
-            Duration::from_millis(500),
+        bash_with_history(true)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn bash_with_history(history: bool) -> color_eyre::Result<ShellSession> {
+        super::bash::<ShellSession, _, _, _>(
             std::iter::empty::<(&str, &str)>(),
-            80,
-            24,
+            Options {
+                wrapper: &[],
+                timeout: Duration::from_millis(500),
+                terminal: Terminal::new(80, 24),
+                disable_history: !history,
+                disable_history_expansion: true,
+                keep_prompt: false,
+                binary_output: BinaryOutput::Lossy,
+            },
         )
     }
 
+    #[test]
+    fn command() {
+        let command = super::command("bash", &[]);
+        assert_eq!(command.get_program(), "bash");
+        assert_eq!(command.get_args().count(), 0);
+
+        let wrapper = ["nix", "develop", "--command"].map(String::from);
+        let command = super::command("bash", &wrapper);
+        assert_eq!(command.get_program(), "nix");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["develop", "--command", "bash"]
+        );
+    }
+
     struct AlwaysNonBlocking<T>(T);
 
+    /// Has no more output for now at the end of `T`, instead of ending.
     impl<T: Read> Read for AlwaysNonBlocking<T> {
         fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-            self.0.read(buf)
+            match self.0.read(buf) {
+                Ok(0) if !buf.is_empty() => Err(io::ErrorKind::WouldBlock.into()),
+                result => result,
+            }
         }
     }
 
@@ -380,8 +963,34 @@ mod tests {
         }
     }
 
+    /// A stream which has ended, its reads return 0 bytes at the end of `T`.
+    struct Ended<T>(T);
+
+    impl<T: Read> Read for Ended<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl<T> NonBlocking for Ended<T> {
+        fn set_non_blocking(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn set_blocking(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
     fn non_blocking<S: Read>(prompt: String, stream: S) -> ShellSession<(), AlwaysNonBlocking<S>> {
-        ShellSession::new(prompt, None, Duration::ZERO, (), AlwaysNonBlocking(stream))
+        ShellSession::new(
+            vec![prompt],
+            None,
+            Duration::ZERO,
+            (),
+            AlwaysNonBlocking(stream),
+            BinaryOutput::Lossy,
+        )
     }
 
     fn empty_stream() -> ShellSession<(), AlwaysNonBlocking<io::Empty>> {
@@ -412,6 +1021,30 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn bash_disable_history() -> color_eyre::Result<()> {
+        let mut shell_session = bash_with_history(false)?;
+        shell_session.send_line("echo \"${HISTFILE-unset}\"")?;
+        let output = shell_session.read_until_prompt()?;
+        shell_session.quit()?;
+        let output: String = output.into_iter().map(|event| event.data).collect();
+        assert_eq!(output, "unset\r\n");
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn bash_disable_history_expansion() -> color_eyre::Result<()> {
+        let mut shell_session = bash()?;
+        shell_session.send_line("echo hello!test")?;
+        let output = shell_session.read_until_prompt()?;
+        shell_session.quit()?;
+        let output: String = output.into_iter().map(|event| event.data).collect();
+        assert_eq!(output, "hello!test\r\n");
+        Ok(())
+    }
+
     #[test]
     fn new_event() {
         let mut shell_session = empty_stream();
@@ -460,9 +1093,84 @@ mod tests {
     }
 
     #[test]
-    fn stream_read_to_string() {
+    fn decode() {
+        let bytes = b"ok\x89PNG";
+        assert_eq!(super::decode(b"ok", 0, BinaryOutput::Error).unwrap(), "ok");
+        assert_eq!(
+            super::decode(bytes, 0, BinaryOutput::Lossy).unwrap(),
+            "ok\u{fffd}PNG"
+        );
+        assert_eq!(
+            super::decode(bytes, 0, BinaryOutput::Escape).unwrap(),
+            "ok\\x89PNG"
+        );
+        // Only the invalid part is dumped, at its offset in the shell's output.
+        assert_eq!(
+            super::decode(bytes, 16, BinaryOutput::Hexdump).unwrap(),
+            format!("ok\r\n00000012  89{}  |.|\r\nPNG", " ".repeat(46))
+        );
+        assert_eq!(
+            super::decode(b"\x89\x50\xfe\n", 0, BinaryOutput::Hexdump).unwrap(),
+            format!("00000000  89 50 fe{}  |.P.|\r\n\n", " ".repeat(40))
+        );
+        assert!(super::decode(bytes, 0, BinaryOutput::Error).is_err());
+    }
+
+    #[test]
+    fn read_split_character() {
+        // "é" is split between reads
+        let mut stream = Stream::new(AlwaysNonBlocking(&[b'a', 0xc3][..]));
+        assert_eq!(stream.read_available().unwrap(), b"a");
+        stream.inner = BufReader::new(AlwaysNonBlocking(&[0xa9][..]));
+        assert_eq!(stream.read_available().unwrap(), "é".as_bytes());
+
+        // An incomplete character is not kept at the end of the stream.
+        let mut stream = Stream::new(Ended(&[b'a', 0xc3][..]));
+        assert_eq!(stream.read_available().unwrap(), [b'a', 0xc3]);
+    }
+
+    #[test]
+    fn read_first_prompt_line() {
+        let bytes = OsStr::new("motd\r\nuser@host$ PROMPT").to_raw_bytes();
+        let mut shell_session = non_blocking(String::from("PROMPT"), bytes.as_ref());
+        shell_session.read().unwrap();
+        assert_eq!(shell_session.first_prompt_line(), Some("user@host$ "));
+    }
+
+    #[test]
+    fn read_alternate_prompt() {
+        let bytes = OsStr::new("In [1]: x\r\n(Pdb) ").to_raw_bytes();
+        let mut shell_session = ShellSession::new(
+            vec![String::from("In ["), String::from("(Pdb) ")],
+            None,
+            Duration::ZERO,
+            (),
+            AlwaysNonBlocking(bytes.as_ref()),
+            BinaryOutput::Lossy,
+        );
+        let (event, prompt) = shell_session.read().unwrap();
+        assert_eq!(event.unwrap().data, "In [1]: x\r\n");
+        assert!(prompt);
+        assert_eq!(shell_session.alternate_prompt(), Some("(Pdb) "));
+
+        // The prompt is rendered as printed, not as the pattern which detected it.
+        let bytes = OsStr::new("x\r\nIn [2]: ").to_raw_bytes();
+        let mut shell_session = ShellSession::new(
+            vec![String::from("(Pdb) "), String::from("In [")],
+            None,
+            Duration::ZERO,
+            (),
+            AlwaysNonBlocking(bytes.as_ref()),
+            BinaryOutput::Lossy,
+        );
+        shell_session.read().unwrap();
+        assert_eq!(shell_session.alternate_prompt(), Some("In [2]: "));
+    }
+
+    #[test]
+    fn stream_read_available() {
         let bytes = test_bytes();
         let mut stream = Stream::new(AlwaysNonBlocking(bytes.as_ref()));
-        assert_eq!(stream.read_to_string().unwrap(), TEST);
+        assert_eq!(stream.read_available().unwrap(), TEST.as_bytes());
     }
 }
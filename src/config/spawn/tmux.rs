@@ -0,0 +1,359 @@
+//! A [`Process`] backed by a tmux pane instead of a PTY owned by autocast.
+//!
+//! Keys are sent with `tmux send-keys` and output is collected with `tmux pipe-pane`
+//! into a file in a private temporary directory, which is read from as the process's stream.
+//!
+//! By default the pane is in a detached session on its own tmux server. If the
+//! [`SESSION_ENV`] variable is set in the spawned command, the pane is a new window
+//! in that existing session on the default tmux server instead, so the shell starts
+//! in the session's environment.
+
+use std::{
+    ffi::OsStr,
+    fmt::Write as _,
+    fs,
+    io::{self, Read, Write},
+    iter, mem,
+    path::PathBuf,
+    process::{self, Command},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::{self, Context};
+use expectrl::process::{NonBlocking, Process};
+use itertools::Itertools;
+use tempfile::TempDir;
+
+use super::{Pid, Wait, WindowSize};
+
+/// Variable of the spawned command naming an existing tmux session to run it in,
+/// which is not passed on to the command.
+pub const SESSION_ENV: &str = "AUTOCAST_TMUX_SESSION";
+
+/// Number of tmux servers started by this process, used to create unique socket names.
+static SERVERS: AtomicUsize = AtomicUsize::new(0);
+
+/// A tmux pane, on autocast's own tmux server or the default one.
+#[derive(Debug, Clone)]
+struct Pane {
+    /// Socket name of autocast's own server, [`None`] for the default server
+    socket: Option<String>,
+    /// Unique ID of the pane, i.e. "%3"
+    id: String,
+}
+
+impl Pane {
+    /// Creates a [`Command`] for running tmux on the pane's server.
+    fn tmux(&self) -> Command {
+        tmux(self.socket.as_deref())
+    }
+
+    /// Runs the tmux `command` targeting the pane, with the `args` after the target.
+    fn run<I, S>(&self, command: &str, args: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut tmux = self.tmux();
+        tmux.args([command, "-t", self.id.as_str()]).args(args);
+        run(tmux).map(drop)
+    }
+}
+
+fn tmux(socket: Option<&str>) -> Command {
+    let mut command = Command::new("tmux");
+    if let Some(socket) = socket {
+        command.args(["-L", socket, "-f", "/dev/null"]);
+    }
+    command
+}
+
+/// Runs the tmux `command`, returning its output if it succeeded or an error if not.
+fn run(mut command: Command) -> io::Result<String> {
+    let output = command.output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "tmux exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ))
+    }
+}
+
+/// Quotes `value` with single quotes for use in a POSIX shell.
+fn quote(value: &OsStr) -> String {
+    format!("'{}'", value.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// A shell running in a tmux pane.
+#[derive(Debug)]
+pub struct Tmux {
+    pane: Pane,
+    /// Holds the output file, and removes it when the process is dropped
+    output_dir: TempDir,
+}
+
+impl Tmux {
+    fn output(&self) -> PathBuf {
+        self.output_dir.path().join("output")
+    }
+}
+
+impl Process for Tmux {
+    type Command = Command;
+    type Stream = Stream;
+
+    fn spawn<S: AsRef<str>>(cmd: S) -> io::Result<Self> {
+        let mut args = cmd.as_ref().split_whitespace();
+        let program = args.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Failed to parse a command")
+        })?;
+        let mut command = Command::new(program);
+        command.args(args);
+        Self::spawn_command(command)
+    }
+
+    fn spawn_command(command: Self::Command) -> io::Result<Self> {
+        // Only readable by the user, so the output can't be read or replaced by others.
+        let output_dir = tempfile::Builder::new()
+            .prefix("autocast-tmux-")
+            .tempdir()?;
+        let output = output_dir.path().join("output");
+        fs::File::create(&output)?;
+
+        let mut session = None;
+        // Echo is disabled to match a PTY spawned by expectrl, and the pane blocks on
+        // `read` until the stream is opened so no output is missed.
+        let environment = command
+            .get_envs()
+            .filter(|(key, value)| {
+                if *key == SESSION_ENV {
+                    session = value.map(OsStr::to_os_string);
+                    false
+                } else {
+                    true
+                }
+            })
+            .map(|(key, value)| match value {
+                Some(value) => {
+                    let mut pair = key.to_os_string();
+                    pair.push("=");
+                    pair.push(value);
+                    quote(&pair)
+                }
+                None => format!("-u {}", quote(key)),
+            })
+            .collect_vec();
+        let program = iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(quote);
+        let shell_command = format!(
+            "stty -echo; read -r _; exec env {}",
+            environment.into_iter().chain(program).join(" ")
+        );
+
+        let pane = if let Some(session) = session {
+            let mut new_window = tmux(None);
+            new_window
+                .args(["new-window", "-d", "-P", "-F", "#{pane_id}", "-t"])
+                .arg(session)
+                .arg(&shell_command);
+            Pane {
+                socket: None,
+                id: run(new_window)?,
+            }
+        } else {
+            let socket = format!(
+                "autocast-{}-{}",
+                process::id(),
+                SERVERS.fetch_add(1, Ordering::Relaxed)
+            );
+            let mut new_session = tmux(Some(&socket));
+            new_session
+                .args(["new-session", "-d", "-P", "-F", "#{pane_id}"])
+                .args(["-s", "autocast", &shell_command])
+                .args([";", "set-option", "-t", "autocast", "status", "off"]);
+            let id = run(new_session)?;
+            Pane {
+                socket: Some(socket),
+                id,
+            }
+        };
+
+        let tmux = Self { pane, output_dir };
+        tmux.pane.run(
+            "pipe-pane",
+            [
+                "-O".to_owned(),
+                format!("cat >> {}", quote(output.as_os_str())),
+            ],
+        )?;
+
+        Ok(tmux)
+    }
+
+    fn open_stream(&mut self) -> io::Result<Self::Stream> {
+        let mut stream = Stream {
+            pane: self.pane.clone(),
+            output: fs::File::open(self.output())?,
+            keys: Vec::new(),
+        };
+        stream.write_all(b"\n")?;
+        stream.flush()?;
+        Ok(stream)
+    }
+}
+
+impl WindowSize for Tmux {
+    fn set_window_size(&mut self, width: u16, height: u16) -> color_eyre::Result<()> {
+        self.pane
+            .run(
+                "resize-window",
+                ["-x", &width.to_string(), "-y", &height.to_string()],
+            )
+            .wrap_err("could not resize tmux window")
+    }
+}
+
+impl Wait for Tmux {
+    /// The exit status of the pane's process is not known.
+    fn wait(&self, timeout: Duration) -> color_eyre::Result<Option<i32>> {
+        let start = Instant::now();
+        loop {
+            // Fails once the pane is closed, when its process exits.
+            if self.pane.run("list-panes", iter::empty::<&str>()).is_err() {
+                return Ok(None);
+            }
+            if start.elapsed() > timeout {
+                eyre::bail!("timeout elapsed waiting for tmux pane to close");
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Pid for Tmux {
+    /// The pane's process, which the shell replaces with `exec`.
+    fn pid(&self) -> Option<u32> {
+        let mut command = self.pane.tmux();
+        command.args(["display-message", "-p", "-t", &self.pane.id, "#{pane_pid}"]);
+        run(command).ok()?.parse().ok()
+    }
+}
+
+impl Drop for Tmux {
+    fn drop(&mut self) {
+        // The pane or server has already exited if the shell was quit.
+        if self.pane.socket.is_some() {
+            let mut kill_server = self.pane.tmux();
+            kill_server.arg("kill-server");
+            let _ = run(kill_server);
+        } else {
+            let _ = self.pane.run("kill-pane", iter::empty::<&str>());
+        }
+    }
+}
+
+/// Stream of a [`Tmux`] pane.
+///
+/// Reads from the pane's piped output. Written keys are buffered until flushed,
+/// and then sent with a single `tmux send-keys`.
+#[derive(Debug)]
+pub struct Stream {
+    pane: Pane,
+    output: fs::File,
+    keys: Vec<u8>,
+}
+
+impl Read for Stream {
+    /// The end of the output file is not the end of the pane's output,
+    /// so reading there would block instead.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.output.read(buf)? {
+            0 if !buf.is_empty() => Err(io::ErrorKind::WouldBlock.into()),
+            bytes_read => Ok(bytes_read),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.keys.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.keys.is_empty() {
+            return Ok(());
+        }
+        let keys = mem::take(&mut self.keys)
+            .into_iter()
+            .fold(String::new(), |mut keys, byte| {
+                let _ = write!(keys, "{byte:02x} ");
+                keys
+            });
+        self.pane
+            .run("send-keys", iter::once("-H").chain(keys.split_whitespace()))
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl NonBlocking for Stream {
+    // Reading the output file never blocks, it is always non-blocking.
+    fn set_non_blocking(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_blocking(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{
+        driver::{SessionDriver, Terminal},
+        spawn::ShellSession,
+        BinaryOutput,
+    };
+
+    use super::*;
+
+    #[test]
+    fn bash_output() -> color_eyre::Result<()> {
+        if which::which("tmux").is_err() {
+            return Ok(());
+        }
+
+        let mut shell_session: ShellSession<Tmux, Stream> = super::super::bash(
+            std::iter::empty::<(&str, &str)>(),
+            super::super::Options {
+                wrapper: &[],
+                timeout: Duration::from_secs(5),
+                terminal: Terminal::new(80, 24),
+                disable_history: false,
+                disable_history_expansion: true,
+                keep_prompt: false,
+                binary_output: BinaryOutput::Lossy,
+            },
+        )?;
+        shell_session.send_line("echo test && tput cols")?;
+        let output = shell_session.read_until_prompt()?;
+        shell_session.quit()?;
+        let output: String = output.into_iter().map(|event| event.data).collect();
+        assert_eq!(output, "test\r\n80\r\n");
+        Ok(())
+    }
+}
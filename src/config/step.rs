@@ -0,0 +1,119 @@
+//! Pausing before each instruction to step through a script interactively.
+
+use std::{fmt::Write as _, io};
+
+use color_eyre::eyre;
+use console::{Key, Term};
+
+use super::Instruction;
+
+/// Maximum number of lines of the last output shown before each instruction.
+const OUTPUT_LINES: usize = 10;
+
+/// What to do with the next instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Run,
+    Skip,
+    Abort,
+}
+
+/// Checks that the user can be prompted, as keys can only be read from a terminal.
+pub fn check_terminal() -> color_eyre::Result<()> {
+    eyre::ensure!(
+        Term::stderr().is_term(),
+        "stepping through instructions requires a terminal"
+    );
+    Ok(())
+}
+
+/// Shows the next instruction and the last output from the shell,
+/// and waits for the user to choose what to do.
+pub fn prompt(num: usize, instruction: &Instruction, last_output: &str) -> io::Result<Action> {
+    let term = Term::stderr();
+    term.write_line(&message(num, instruction, last_output))?;
+    loop {
+        match term.read_key()? {
+            Key::Enter => return Ok(Action::Run),
+            Key::Char('s') => return Ok(Action::Skip),
+            Key::Char('a' | 'q') | Key::Escape => return Ok(Action::Abort),
+            _ => {}
+        }
+    }
+}
+
+fn message(num: usize, instruction: &Instruction, last_output: &str) -> String {
+    let mut message = String::new();
+    let lines = last_output.lines().collect::<Vec<_>>();
+    if !lines.is_empty() {
+        message.push_str("Last output:\n");
+        for line in &lines[lines.len().saturating_sub(OUTPUT_LINES)..] {
+            // Escape control characters so the output can't mess up the terminal.
+            let line = line.trim_end_matches('\r').escape_debug();
+            let _ = writeln!(message, "  | {line}");
+        }
+    }
+    let _ = write!(
+        message,
+        "Next instruction {num}: {}\n[Enter] run, [s] skip, [a] abort",
+        describe(instruction)
+    );
+    message
+}
+
+/// Short description of an instruction, i.e. "Command `ls -l`".
+pub fn describe(instruction: &Instruction) -> String {
+    match instruction {
+        Instruction::Command { command, .. } => format!("Command `{command}`"),
+        Instruction::Interactive { command, keys, .. } => {
+            format!("Interactive `{command}` with {} keys", keys.len())
+        }
+        Instruction::Capture { command, var, .. } => format!("Capture `{command}` into {var}"),
+        Instruction::If { var_matches, .. } => {
+            let conditions: Vec<_> = var_matches
+                .iter()
+                .map(|(name, regex)| format!("{name} matches `{regex}`"))
+                .collect();
+            format!("If {}", conditions.join(" and "))
+        }
+        Instruction::Wait(duration) => format!("Wait {duration:?}"),
+        Instruction::Settle(window) => format!("Settle {window:?}"),
+        Instruction::Marker(marker) => format!("Marker {marker:?}"),
+        Instruction::Title(title) => format!("Title {title:?}"),
+        Instruction::Resize { width, height } => match (width, height) {
+            (Some(width), Some(height)) => format!("Resize {width}x{height}"),
+            (Some(width), None) => format!("Resize width {width}"),
+            (None, Some(height)) => format!("Resize height {height}"),
+            (None, None) => String::from("Resize to the script's size"),
+        },
+        Instruction::Clear => String::from("Clear"),
+        Instruction::Include(path) => format!("Include `{}`", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{super::Command, *};
+
+    #[test]
+    fn message() {
+        let instruction = Instruction::Command {
+            command: Command::SingleLine(String::from("ls")),
+            hidden: false,
+            type_speed: None,
+            confirm: Vec::new(),
+            highlight_stderr: false,
+            output_filter: None,
+            allow_dangerous: false,
+        };
+        let output = (1..=12).fold(String::new(), |output, num| output + &format!("{num}\r\n"));
+        let message = super::message(3, &instruction, &output);
+        assert!(message.starts_with("Last output:\n  | 3\n"));
+        assert!(message.contains("  | 12\nNext instruction 3: Command `ls`\n"));
+
+        let message = super::message(0, &Instruction::Wait(Duration::from_secs(1)), "");
+        assert!(message.starts_with("Next instruction 0: Wait 1s\n"));
+    }
+}
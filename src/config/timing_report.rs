@@ -0,0 +1,199 @@
+//! Reporting how long each instruction waited for the shell, compared to the timeout,
+//! so the timeout can be tuned from measurements instead of guessed.
+//! The terminal sizes `!Resize` instructions switched between are listed too,
+//! as is the CPU time and peak memory of the commands, where they could be sampled.
+
+use std::{
+    fmt::{self, Display},
+    time::Duration,
+};
+
+use itertools::Itertools;
+
+use super::{resources::Usage, step, Instruction};
+
+/// Share of the timeout at which an instruction is marked as close to it
+const CLOSE_TO_TIMEOUT: f64 = 0.8;
+
+/// How long the instructions which wait for the shell took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    timeout: Duration,
+    /// The script's terminal size, followed by the size set by each `!Resize` instruction
+    sizes: Vec<(u16, u16)>,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    num: usize,
+    description: String,
+    duration: Duration,
+    failed: bool,
+    usage: Option<Usage>,
+}
+
+impl Report {
+    pub fn new(timeout: Duration, size: (u16, u16)) -> Self {
+        Self {
+            timeout,
+            sizes: vec![size],
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds the time the `instruction` took if it waits for the shell,
+    /// for the prompt or its output to settle.
+    ///
+    /// The size `!Resize` instructions set is also added, unless they `failed`.
+    pub fn push(
+        &mut self,
+        num: usize,
+        instruction: &Instruction,
+        duration: Duration,
+        failed: bool,
+        usage: Option<Usage>,
+    ) {
+        if let (Instruction::Resize { width, height }, false) = (instruction, failed) {
+            let (script_width, script_height) = self.sizes[0];
+            self.sizes.push((
+                width.unwrap_or(script_width),
+                height.unwrap_or(script_height),
+            ));
+        }
+        if matches!(
+            instruction,
+            Instruction::Command { .. }
+                | Instruction::Interactive { .. }
+                | Instruction::Capture { .. }
+                | Instruction::If { .. }
+                | Instruction::Settle(_)
+        ) {
+            self.entries.push(Entry {
+                num,
+                description: step::describe(instruction),
+                duration,
+                failed,
+                usage,
+            });
+        }
+    }
+
+    fn share_of_timeout(&self, duration: Duration) -> f64 {
+        duration.as_secs_f64() / self.timeout.as_secs_f64()
+    }
+
+    /// Twice the longest time an instruction took, rounded up to whole seconds.
+    fn suggested_timeout(&self) -> Option<Duration> {
+        let longest = self.entries.iter().map(|entry| entry.duration).max()?;
+        let doubled = longest * 2;
+        let secs = doubled.as_secs() + u64::from(doubled.subsec_nanos() > 0);
+        Some(Duration::from_secs(secs.max(1)))
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Timing report, timeout of {:?}:", self.timeout)?;
+        if self.sizes.len() > 1 {
+            let sizes = self
+                .sizes
+                .iter()
+                .map(|(width, height)| format!("{width}x{height}"))
+                .join(", ");
+            write!(f, "\n  terminal sizes: {sizes}")?;
+        }
+        if self.entries.is_empty() {
+            return write!(f, "\n  no instructions waited for the shell");
+        }
+        for entry in &self.entries {
+            let share = self.share_of_timeout(entry.duration);
+            write!(
+                f,
+                "\n  instruction {} {}: {:.2?}, {:.0}% of the timeout",
+                entry.num,
+                entry.description,
+                entry.duration,
+                share * 100.0
+            )?;
+            if let Some(usage) = entry.usage {
+                write!(f, ", {usage}")?;
+            }
+            if entry.failed {
+                write!(f, " (failed)")?;
+            } else if share >= CLOSE_TO_TIMEOUT {
+                write!(f, " (close to the timeout)")?;
+            }
+        }
+        if let Some(suggested) = self.suggested_timeout() {
+            write!(f, "\n  suggested timeout: {suggested:?}, twice the longest")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let instructions: Vec<Instruction> = serde_yaml::from_str(
+            "
+            - !Command
+              command: ls
+            - !Wait 1s
+            - !Command
+              command: cargo build
+            ",
+        )
+        .unwrap();
+        let mut report = Report::new(Duration::from_secs(3), (80, 24));
+        for (num, (instruction, millis)) in instructions.iter().zip([150, 1000, 2700]).enumerate() {
+            let usage = (num == 2).then_some(Usage {
+                cpu_time: Duration::from_millis(5120),
+                peak_rss: 300 * 1024 * 1024,
+            });
+            report.push(
+                num,
+                instruction,
+                Duration::from_millis(millis),
+                false,
+                usage,
+            );
+        }
+        assert_eq!(
+            report.to_string(),
+            "Timing report, timeout of 3s:\n  \
+                instruction 0 Command `ls`: 150.00ms, 5% of the timeout\n  \
+                instruction 2 Command `cargo build`: 2.70s, 90% of the timeout, \
+                CPU time 5.12s, peak memory 300.0 MiB (close to the timeout)\n  \
+                suggested timeout: 6s, twice the longest"
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        let instructions: Vec<Instruction> = serde_yaml::from_str(
+            "
+            - !Resize
+              width: 40
+              height: 12
+            - !Resize
+              height: 30
+            - !Resize {}
+            ",
+        )
+        .unwrap();
+        let mut report = Report::new(Duration::from_secs(3), (80, 24));
+        for (num, instruction) in instructions.iter().enumerate() {
+            report.push(num, instruction, Duration::ZERO, false, None);
+        }
+        assert_eq!(
+            report.to_string(),
+            "Timing report, timeout of 3s:\n  \
+                terminal sizes: 80x24, 40x12, 80x30, 80x24\n  \
+                no instructions waited for the shell"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! Writing a transcript of the commands typed in the asciicast and their output,
+//! so docs sites can offer an accessible alternative to the animation, i.e. for screen readers.
+
+use std::{cell::RefCell, io::Write, time::Duration};
+
+use serde::Serialize;
+
+use crate::asciicast::{self, Event, EventType};
+
+use super::{Script, SharedProgress};
+
+/// The commands typed in an asciicast and their output, as plain text.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct Transcript {
+    title: Option<String>,
+    commands: Vec<Entry>,
+    /// Commands which were run, but not yet found in the events
+    #[serde(skip)]
+    pending: Vec<Pending>,
+}
+
+/// A typed command and its output.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct Entry {
+    /// Seconds from the start of the asciicast until the command is typed
+    time: f64,
+    command: String,
+    /// Seconds from the start of the asciicast until the command's output
+    output_time: f64,
+    output: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Pending {
+    command: String,
+    /// Data of each of the events of typing the command
+    typed: Vec<String>,
+    output: String,
+}
+
+impl Script {
+    /// Runs the script, creating the asciicast and a [`Transcript`] of it.
+    ///
+    /// The script's progress bars are drawn with `progress`, if given.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the script could not be run.
+    pub fn run_transcribed(
+        self,
+        progress: Option<SharedProgress>,
+    ) -> color_eyre::Result<(asciicast::File, Transcript)> {
+        let transcript = RefCell::default();
+        let cast = super::run_script(self, None, false, Some(&transcript), progress)?;
+        let mut transcript: Transcript = transcript.into_inner();
+        transcript.title.clone_from(&cast.header.title);
+        Ok((cast, transcript))
+    }
+}
+
+impl Transcript {
+    /// Adds a `command`, typed with the `typed` events, and its `output`,
+    /// whose times are found with [`Transcript::place()`].
+    pub(super) fn push(&mut self, command: String, typed: Vec<String>, output: &[Event]) {
+        self.pending.push(Pending {
+            command,
+            typed,
+            output: plain_text(output),
+        });
+    }
+
+    /// Adds the commands which were run to the transcript, finding when they were typed in
+    /// the `events`, which have absolute times.
+    pub(super) fn place(&mut self, events: &[Event]) {
+        let mut cursor = 0;
+        for pending in self.pending.drain(..) {
+            let typed = pending.typed.len();
+            let start = (cursor..=events.len().saturating_sub(typed)).find(|&start| {
+                events[start..start + typed]
+                    .iter()
+                    .zip(&pending.typed)
+                    .all(|(event, data)| {
+                        event.event_type == EventType::Output && event.data == *data
+                    })
+            });
+            let Some(start) = start else {
+                continue;
+            };
+            let end = start + typed;
+            let time = |index: usize| {
+                events
+                    .get(index)
+                    .or(events.last())
+                    .map_or(Duration::ZERO, |event| event.time)
+                    .as_secs_f64()
+            };
+            self.commands.push(Entry {
+                time: time(start),
+                command: pending.command,
+                output_time: time(end),
+                output: pending.output,
+            });
+            cursor = end;
+        }
+    }
+
+    /// Writes the transcript as JSON to `writer`.
+    pub fn write(&self, mut writer: impl Write) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer).map_err(serde_json::Error::io)
+    }
+}
+
+/// The `output` as plain text, without escape sequences and with lines which were rewritten
+/// with carriage returns, i.e. progress bars, replaced by their last version.
+fn plain_text(output: &[Event]) -> String {
+    let output: String = output
+        .iter()
+        .filter(|event| event.event_type == EventType::Output)
+        .map(|event| event.data.as_str())
+        .collect();
+    console::strip_ansi_codes(&output)
+        .replace("\r\n", "\n")
+        .lines()
+        .map(|line| line.rsplit('\r').next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(millis: u64, data: &str) -> Event {
+        Event::output(Duration::from_millis(millis), String::from(data))
+    }
+
+    #[test]
+    fn place() {
+        let mut transcript = Transcript::default();
+        transcript.push(
+            String::from("ls"),
+            vec![String::from("l"), String::from("s"), String::from("\r\n")],
+            &[
+                output(0, "\x1b[1mfile\x1b[0m\r\n"),
+                output(0, "10%\r100%\r\n"),
+            ],
+        );
+        transcript.push(String::from("skipped"), vec![String::from("x")], &[]);
+        transcript.place(&[
+            output(0, "$ "),
+            output(100, "l"),
+            output(200, "s"),
+            output(300, "\r\n"),
+            output(350, "file\r\n100%\r\n"),
+            output(400, "$ "),
+        ]);
+        assert_eq!(
+            transcript.commands,
+            [Entry {
+                time: 0.1,
+                command: String::from("ls"),
+                output_time: 0.35,
+                output: String::from("file\n100%"),
+            }]
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! A dashboard shown while recording, with the shell's live output next to
+//! the status and time of each instruction.
+
+use std::{
+    cell::RefCell,
+    io::{self, Stderr},
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::{self, Context};
+use console::Term;
+use crossterm::{
+    cursor, execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame, Terminal,
+};
+
+use crate::{
+    asciicast::Event,
+    svg::term::{self, Cell, Screen},
+};
+
+use super::{driver::SessionDriver, step, Instruction};
+
+/// Minimum time between redraws
+const FRAME_TIME: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pending,
+    Running(Instant),
+    Done(Duration),
+}
+
+/// What the dashboard shows.
+#[derive(Debug)]
+struct State {
+    /// Description and status of each instruction
+    instructions: Vec<(String, Status)>,
+    /// The shell's output, as it would be shown in the recording's terminal
+    screen: Screen,
+    start: Instant,
+}
+
+impl State {
+    fn new(instructions: &[Instruction], width: u16, height: u16) -> Self {
+        Self {
+            instructions: instructions
+                .iter()
+                .map(|instruction| (step::describe(instruction), Status::Pending))
+                .collect(),
+            screen: Screen::new(width, height),
+            start: Instant::now(),
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let areas = Layout::new(
+            Direction::Horizontal,
+            [Constraint::Percentage(65), Constraint::Percentage(35)],
+        )
+        .split(frame.size());
+        let (output, instructions) = (areas[0], areas[1]);
+
+        let block = Block::default().borders(Borders::ALL).title(" Output ");
+        let rows = usize::from(block.inner(output).height);
+        frame.render_widget(Paragraph::new(self.output_lines(rows)).block(block), output);
+
+        let done = self
+            .instructions
+            .iter()
+            .filter(|(_, status)| matches!(status, Status::Done(_)))
+            .count();
+        let title = format!(
+            " Instructions {done}/{} ({:.0?}) ",
+            self.instructions.len(),
+            Duration::from_secs(self.start.elapsed().as_secs())
+        );
+        let items = self
+            .instructions
+            .iter()
+            .map(|(description, status)| ListItem::new(item(description, *status)));
+        let running = self
+            .instructions
+            .iter()
+            .position(|(_, status)| matches!(status, Status::Running(_)));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::default().borders(Borders::ALL).title(title))
+                .highlight_style(Style::new().add_modifier(Modifier::BOLD)),
+            instructions,
+            &mut ListState::default().with_selected(running),
+        );
+    }
+
+    /// The last `rows` lines of the screen, up to the last line which isn't blank.
+    fn output_lines(&self, rows: usize) -> Vec<Line<'static>> {
+        let lines = self.screen.lines();
+        let end = lines
+            .iter()
+            .rposition(|line| line.iter().any(|cell| cell.char != ' '))
+            .map_or(0, |row| row + 1);
+        lines[end.saturating_sub(rows)..end]
+            .iter()
+            .map(|line| output_line(line))
+            .collect()
+    }
+}
+
+fn item(description: &str, status: Status) -> Line<'static> {
+    let (symbol, time) = match status {
+        Status::Pending => (Span::raw("  "), String::new()),
+        Status::Running(start) => (
+            Span::styled("> ", Style::new().fg(Color::Yellow)),
+            format!(" ({:.1}s)", start.elapsed().as_secs_f64()),
+        ),
+        Status::Done(time) => (
+            Span::styled("✓ ", Style::new().fg(Color::Green)),
+            format!(" ({time:.2?})"),
+        ),
+    };
+    Line::from(vec![
+        symbol,
+        Span::raw(String::from(description)),
+        Span::styled(time, Style::new().add_modifier(Modifier::DIM)),
+    ])
+}
+
+/// Converts a line of the screen into spans of cells with the same style.
+fn output_line(line: &[Cell]) -> Line<'static> {
+    let mut spans: Vec<(String, term::Style)> = Vec::new();
+    for cell in line {
+        match spans.last_mut() {
+            Some((text, style)) if *style == cell.style => text.push(cell.char),
+            _ => spans.push((String::from(cell.char), cell.style)),
+        }
+    }
+    Line::from(
+        spans
+            .into_iter()
+            .map(|(text, style)| Span::styled(text, convert_style(style)))
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn convert_style(style: term::Style) -> Style {
+    let mut converted = Style::new()
+        .fg(convert_color(style.foreground))
+        .bg(convert_color(style.background));
+    for (enabled, modifier) in [
+        (style.bold, Modifier::BOLD),
+        (style.faint, Modifier::DIM),
+        (style.italic, Modifier::ITALIC),
+        (style.underline, Modifier::UNDERLINED),
+        (style.inverse, Modifier::REVERSED),
+    ] {
+        if enabled {
+            converted = converted.add_modifier(modifier);
+        }
+    }
+    converted
+}
+
+fn convert_color(color: term::Color) -> Color {
+    match color {
+        term::Color::Default => Color::Reset,
+        term::Color::Indexed(index) => Color::Indexed(index),
+        term::Color::Rgb(red, green, blue) => Color::Rgb(red, green, blue),
+    }
+}
+
+/// Shows the dashboard in the alternate screen of the terminal on stderr,
+/// until it is closed or dropped.
+#[derive(Debug)]
+pub struct Dashboard {
+    inner: RefCell<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// [`None`] once the dashboard is closed
+    terminal: Option<Terminal<CrosstermBackend<Stderr>>>,
+    state: State,
+    last_draw: Instant,
+}
+
+impl Dashboard {
+    /// Shows the dashboard for the `instructions`, which are run in a terminal
+    /// of `width` and `height`.
+    pub fn start(
+        instructions: &[Instruction],
+        width: u16,
+        height: u16,
+    ) -> color_eyre::Result<Self> {
+        eyre::ensure!(
+            Term::stderr().is_term(),
+            "showing the dashboard requires a terminal"
+        );
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))
+            .wrap_err("could not draw dashboard")?;
+        execute!(terminal.backend_mut(), EnterAlternateScreen, cursor::Hide)
+            .wrap_err("could not enter the alternate screen")?;
+        // Created before drawing so the terminal is restored if drawing fails.
+        let dashboard = Self {
+            inner: RefCell::new(Inner {
+                terminal: Some(terminal),
+                state: State::new(instructions, width, height),
+                last_draw: Instant::now(),
+            }),
+        };
+        dashboard.draw(true).wrap_err("could not draw dashboard")?;
+        Ok(dashboard)
+    }
+
+    /// Marks instruction `num` as running.
+    pub fn start_instruction(&self, num: usize) -> io::Result<()> {
+        self.set_status(num, Status::Running(Instant::now()))
+    }
+
+    /// Marks instruction `num` as done, with the time since it started.
+    pub fn finish_instruction(&self, num: usize) -> io::Result<()> {
+        let status = match self.inner.borrow().state.instructions.get(num) {
+            Some((_, Status::Running(start))) => Status::Done(start.elapsed()),
+            _ => Status::Done(Duration::ZERO),
+        };
+        self.set_status(num, status)
+    }
+
+    fn set_status(&self, num: usize, status: Status) -> io::Result<()> {
+        if let Some((_, old)) = self.inner.borrow_mut().state.instructions.get_mut(num) {
+            *old = status;
+        }
+        self.draw(true)
+    }
+
+    /// Writes output from the shell to the dashboard.
+    fn output(&self, data: &str) -> io::Result<()> {
+        self.inner.borrow_mut().state.screen.write(data);
+        self.draw(false)
+    }
+
+    /// Redraws the dashboard, if `force`d or enough time has passed since it was last drawn.
+    fn draw(&self, force: bool) -> io::Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        if !force && inner.last_draw.elapsed() < FRAME_TIME {
+            return Ok(());
+        }
+        inner.last_draw = Instant::now();
+        let Inner {
+            terminal, state, ..
+        } = &mut *inner;
+        if let Some(terminal) = terminal {
+            terminal.draw(|frame| state.render(frame))?;
+        }
+        Ok(())
+    }
+
+    /// Leaves the alternate screen, restoring what was on the terminal before.
+    pub fn close(&self) -> io::Result<()> {
+        let Some(mut terminal) = self.inner.borrow_mut().terminal.take() else {
+            return Ok(());
+        };
+        terminal.show_cursor()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.backend_mut().flush()
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// A session whose output is also shown on the [`Dashboard`].
+pub struct Watched<'a, D> {
+    session: &'a mut D,
+    dashboard: &'a Dashboard,
+}
+
+impl<'a, D> Watched<'a, D> {
+    pub fn new(session: &'a mut D, dashboard: &'a Dashboard) -> Self {
+        Self { session, dashboard }
+    }
+}
+
+impl<D: SessionDriver> SessionDriver for Watched<'_, D> {
+    fn timeout(&self) -> Duration {
+        self.session.timeout()
+    }
+
+    fn new_event(&mut self, data: String) -> Event {
+        self.session.new_event(data)
+    }
+
+    fn reset(&mut self) {
+        self.session.reset();
+    }
+
+    fn send(&mut self, buf: impl AsRef<[u8]>) -> io::Result<()> {
+        self.session.send(buf)
+    }
+
+    fn read(&mut self) -> io::Result<(Option<Event>, bool)> {
+        let (event, prompt) = self.session.read()?;
+        match &event {
+            Some(event) => self.dashboard.output(&event.data)?,
+            // Keeps the running time up to date.
+            None => self.dashboard.draw(false)?,
+        }
+        Ok((event, prompt))
+    }
+
+    fn alternate_prompt(&self) -> Option<&str> {
+        self.session.alternate_prompt()
+    }
+
+    fn first_prompt_line(&self) -> Option<&str> {
+        self.session.first_prompt_line()
+    }
+
+    fn resize(&mut self, width: u16, height: u16) -> color_eyre::Result<()> {
+        self.session.resize(width, height)
+    }
+
+    fn quit(&mut self) -> color_eyre::Result<Option<i32>> {
+        self.session.quit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+
+    use super::{super::Command, *};
+
+    #[test]
+    fn render() {
+        let instructions = [
+            Instruction::Command {
+                command: Command::SingleLine(String::from("ls")),
+                hidden: false,
+                type_speed: None,
+                confirm: Vec::new(),
+                highlight_stderr: false,
+                output_filter: None,
+                allow_dangerous: false,
+            },
+            Instruction::Wait(Duration::from_secs(1)),
+        ];
+        let mut state = State::new(&instructions, 20, 5);
+        state.instructions[0].1 = Status::Done(Duration::from_millis(1500));
+        state.screen.write("$ ls\r\n\x1b[31mfile\x1b[0m\r\n$ ");
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 6)).unwrap();
+        terminal.draw(|frame| state.render(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer.get(x, y).symbol())
+                    .collect()
+            })
+            .collect();
+        assert!(rows[0].contains("Output"));
+        assert!(rows[0].contains("Instructions 1/2"));
+        assert!(rows[1].starts_with("│$ ls"));
+        assert!(rows[1].contains("✓ Command `ls` (1.50s)"));
+        assert!(rows[2].starts_with("│file"));
+        assert!(rows[2].contains("  Wait 1s"));
+        assert_eq!(buffer.get(1, 2).fg, Color::Indexed(1));
+        assert!(rows[3].starts_with("│$ "));
+    }
+}
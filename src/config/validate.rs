@@ -0,0 +1,173 @@
+//! Checking that a script can be run without running it,
+//! for fast feedback, i.e. in pre-commit hooks.
+
+use super::{
+    auto_width, check_modes, check_speed_factors, condition, safe, terminal_size, Backend,
+    Instruction, Key, PromptPreset, Script, Width,
+};
+
+impl Script {
+    /// Checks the script's settings and instructions, that its shell can be found,
+    /// and that the terminal size is known, without spawning the shell.
+    ///
+    /// Returns a message for each problem found.
+    pub fn validate(&self) -> Vec<String> {
+        let settings = &self.settings;
+        let mut problems = Vec::new();
+
+        let checks = [
+            check_speed_factors(settings.typing_speed_factor, settings.output_speed_factor),
+            check_modes(settings.tui, settings.step, settings.ci, false),
+        ];
+        problems.extend(
+            checks
+                .into_iter()
+                .filter_map(Result::err)
+                .map(|error| error.to_string()),
+        );
+        if settings.timeout.is_zero() {
+            problems.push(String::from("timeout must be longer than zero"));
+        }
+        if settings.safe {
+            match safe::refused(&self.instructions, &settings.dangerous_commands) {
+                Ok(refused) => problems.extend(refused),
+                Err(error) => problems.push(format!("{error:#}")),
+            }
+        }
+
+        for (num, instruction) in condition::numbered(&self.instructions) {
+            let Instruction::Interactive { keys, .. } = instruction else {
+                continue;
+            };
+            // Keys are sent as a single byte.
+            for key in keys {
+                match key {
+                    Key::Char(char) if !char.is_ascii() => problems.push(format!(
+                        "instruction {num} has the key {char:?}, which is not ASCII, \
+                            type it as part of the command instead"
+                    )),
+                    Key::Char(_) | Key::Control(_) | Key::Wait(_) | Key::Text(_) => {}
+                }
+            }
+        }
+
+        if settings.tmux_session.is_some() && settings.backend != Backend::Tmux {
+            problems.push(String::from("`tmux_session` requires the tmux backend"));
+        }
+        match settings.backend {
+            Backend::Pty => {
+                // The shell may only be available within the wrapper, i.e. in a devshell.
+                if let Some(wrapper) = settings
+                    .shell_wrapper
+                    .as_ref()
+                    .and_then(|wrapper| wrapper.0.first())
+                {
+                    if which::which(wrapper).is_err() {
+                        problems.push(format!("shell wrapper `{wrapper}` was not found"));
+                    }
+                } else {
+                    let program = settings.shell.program();
+                    if which::which(program).is_err() {
+                        problems.push(format!("shell `{program}` was not found"));
+                    }
+                }
+            }
+            Backend::Tmux => {
+                if cfg!(not(unix)) {
+                    problems.push(String::from("the tmux backend is only supported on unix"));
+                } else if which::which("tmux").is_err() {
+                    problems.push(String::from("`tmux` was not found"));
+                }
+            }
+            Backend::Replay => {
+                if settings.replay_fixtures.is_none() {
+                    problems.push(String::from(
+                        "the replay backend requires replay fixtures to be provided",
+                    ));
+                }
+            }
+        }
+
+        let width = settings.width.map(|width| match width {
+            Width::Columns(columns) => columns,
+            Width::Auto => auto_width(
+                &self.instructions,
+                &settings
+                    .prompt_preset
+                    .map_or_else(|| settings.prompt.clone(), PromptPreset::prompt),
+                &settings.secondary_prompt,
+                &settings.shell,
+            ),
+        });
+        match terminal_size(width, settings.height) {
+            Some((0, _) | (_, 0)) => {
+                problems.push(String::from(
+                    "terminal width and height must be larger than zero",
+                ));
+            }
+            Some(_) => {}
+            None => problems.push(String::from(
+                "terminal width or height not provided and could not get terminal size",
+            )),
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        let script = Script::try_from_yaml(
+            "
+            settings:
+              width: 80
+              height: 24
+              backend: replay
+            instructions:
+              - !Interactive
+                command: less
+                keys: [q, ^C, 1s]
+            "
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            script.validate(),
+            ["the replay backend requires replay fixtures to be provided"]
+        );
+
+        let script = Script::try_from_yaml(
+            "
+            settings:
+              width: 0
+              height: 24
+              output_speed_factor: -1
+              shell_wrapper: autocast-missing-wrapper --command
+              shell:
+                program: autocast-missing-shell
+                prompt: '$ '
+                line_split: ' \\'
+            instructions:
+              - !Interactive
+                command: vim
+                keys: [é]
+            "
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            script.validate(),
+            [
+                "output speed factor must be a positive number, got -1",
+                "instruction 0 has the key 'é', which is not ASCII, \
+                    type it as part of the command instead",
+                "shell wrapper `autocast-missing-wrapper` was not found",
+                "terminal width and height must be larger than zero",
+            ]
+        );
+    }
+}
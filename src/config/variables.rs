@@ -0,0 +1,311 @@
+//! Expanding `${name}` references to the script's variables,
+//! so one script can create demos for different versions or binaries.
+//!
+//! `${ENV:NAME}` references in commands are expanded from the environment when the script
+//! is read, so paths and versions don't need to be hardcoded.
+
+use std::env;
+
+use color_eyre::eyre;
+
+use super::{Command, EnvVar, Instruction, Key, Script};
+
+/// Prefix of references to environment variables
+const ENV_PREFIX: &str = "${ENV:";
+
+impl Script {
+    /// Replaces the `${ENV:NAME}` references in the commands with the value of the
+    /// environment variable `NAME`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a referenced environment variable is not set.
+    pub fn expand_env(&mut self) -> color_eyre::Result<()> {
+        self.expand_env_with(|name| env::var(name).ok())
+    }
+
+    fn expand_env_with(
+        &mut self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> color_eyre::Result<()> {
+        for (num, instruction) in self.instructions.iter_mut().enumerate() {
+            instruction.expand_env_with(&lookup).map_err(|name| {
+                eyre::eyre!(
+                    "instruction {num} references environment variable `{name}`, \
+                        which is not set"
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the `${name}` references in the commands, keys, markers, title, and prompt
+    /// with the value of the variable `name`.
+    ///
+    /// References to names which aren't variables are kept, so shell variables like
+    /// `${HOME}` can still be used in commands.
+    pub(super) fn expand_variables(&mut self) {
+        let variables = &self.settings.variables;
+        if variables.is_empty() {
+            return;
+        }
+        if let Some(title) = &mut self.settings.title {
+            *title = expand(title, variables);
+        }
+        self.settings.prompt = expand(&self.settings.prompt, variables);
+
+        for instruction in &mut self.instructions {
+            instruction.expand_variables(variables);
+        }
+    }
+}
+
+impl Instruction {
+    /// Returns the name of the first environment variable `lookup` doesn't find.
+    fn expand_env_with(&mut self, lookup: &impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        let lines = match self {
+            Self::Command { command, .. }
+            | Self::Interactive { command, .. }
+            | Self::Capture { command, .. } => match command {
+                Command::SingleLine(line) => std::slice::from_mut(line),
+                Command::MultiLine(lines) => lines.as_mut_slice(),
+                Command::Control(_) => return Ok(()),
+            },
+            Self::If {
+                then, otherwise, ..
+            } => {
+                return then
+                    .iter_mut()
+                    .chain(otherwise)
+                    .try_for_each(|instruction| instruction.expand_env_with(lookup));
+            }
+            Self::Wait(_)
+            | Self::Settle(_)
+            | Self::Marker(_)
+            | Self::Title(_)
+            | Self::Resize { .. }
+            | Self::Clear
+            | Self::Include(_) => {
+                return Ok(());
+            }
+        };
+        for line in lines {
+            *line = expand_env(line, lookup)?;
+        }
+        Ok(())
+    }
+
+    fn expand_variables(&mut self, variables: &[EnvVar]) {
+        match self {
+            Self::Command { command, .. } | Self::Capture { command, .. } => {
+                expand_command(command, variables);
+            }
+            Self::If {
+                then, otherwise, ..
+            } => {
+                for instruction in then.iter_mut().chain(otherwise) {
+                    instruction.expand_variables(variables);
+                }
+            }
+            Self::Interactive { command, keys, .. } => {
+                expand_command(command, variables);
+                *keys = expand_keys(keys, variables);
+            }
+            Self::Marker(marker) => *marker = expand(marker, variables),
+            Self::Title(title) => *title = expand(title, variables),
+            Self::Wait(_)
+            | Self::Settle(_)
+            | Self::Resize { .. }
+            | Self::Clear
+            | Self::Include(_) => {}
+        }
+    }
+
+    /// The instruction with the `${name}` references to the `variables` replaced,
+    /// or [`None`] if there are none to replace.
+    ///
+    /// Used for variables captured while running the script. The instructions in the branches
+    /// of `!If` instructions are expanded when they are run.
+    pub(super) fn with_variables(&self, variables: &[EnvVar]) -> Option<Self> {
+        if variables.is_empty()
+            || matches!(
+                self,
+                Self::If { .. }
+                    | Self::Wait(_)
+                    | Self::Settle(_)
+                    | Self::Resize { .. }
+                    | Self::Clear
+                    | Self::Include(_)
+            )
+        {
+            return None;
+        }
+        let mut instruction = self.clone();
+        instruction.expand_variables(variables);
+        Some(instruction)
+    }
+}
+
+fn expand_command(command: &mut Command, variables: &[EnvVar]) {
+    match command {
+        Command::SingleLine(line) => *line = expand(line, variables),
+        Command::MultiLine(lines) => {
+            for line in lines {
+                *line = expand(line, variables);
+            }
+        }
+        Command::Control(_) => {}
+    }
+}
+
+/// Expands the text keys, typing each char of the expanded text as a key.
+///
+/// Text keys which still reference a variable are kept, as it may be captured later.
+fn expand_keys(keys: &[Key], variables: &[EnvVar]) -> Vec<Key> {
+    keys.iter()
+        .flat_map(|key| match key {
+            Key::Text(text) => {
+                let text = expand(text, variables);
+                if text.contains("${") {
+                    vec![Key::Text(text)]
+                } else {
+                    text.chars().map(Key::Char).collect()
+                }
+            }
+            key => vec![key.clone()],
+        })
+        .collect()
+}
+
+/// Replaces each `${name}` in the `text` with the value of the last variable named `name`.
+pub(super) fn expand(text: &str, variables: &[EnvVar]) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let reference = &rest[start + 2..];
+        let value = reference.find('}').and_then(|end| {
+            let name = &reference[..end];
+            variables
+                .iter()
+                .rev()
+                .find(|variable| variable.name == name)
+                .map(|variable| (&variable.value, end))
+        });
+        if let Some((value, end)) = value {
+            expanded.push_str(value);
+            rest = &reference[end + 1..];
+        } else {
+            expanded.push_str("${");
+            rest = reference;
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Replaces each `${ENV:NAME}` in the `text` with the value `lookup` returns for `NAME`.
+///
+/// Returns the name of the first variable which `lookup` doesn't find.
+fn expand_env(text: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(ENV_PREFIX) {
+        expanded.push_str(&rest[..start]);
+        let reference = &rest[start + ENV_PREFIX.len()..];
+        let Some(end) = reference.find('}') else {
+            expanded.push_str(ENV_PREFIX);
+            rest = reference;
+            continue;
+        };
+        let name = &reference[..end];
+        expanded.push_str(&lookup(name).ok_or_else(|| name.to_owned())?);
+        rest = &reference[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_variables() -> serde_yaml::Result<()> {
+        let mut script = Script::try_from_yaml(
+            r#"
+            settings:
+              title: "${name} ${version}"
+              variables:
+                name: autocast
+                version: "0.1.0"
+            instructions:
+              - !Command
+                command: ${name} --version && echo ${HOME}
+              - !Interactive
+                command: [vim, "${name}.txt"]
+                keys: [i, "${version}", ^C]
+              - !Marker "${name} ${unknown"
+            "#
+            .as_bytes(),
+        )?;
+        script
+            .settings
+            .variables
+            .push(EnvVar::from("version=0.2.0"));
+        script.expand_variables();
+
+        assert_eq!(script.settings.title.as_deref(), Some("autocast 0.2.0"));
+        let Instruction::Command { command, .. } = &script.instructions[0] else {
+            panic!("first instruction is a command");
+        };
+        assert_eq!(command.to_string(), "autocast --version && echo ${HOME}");
+        let Instruction::Interactive { command, keys, .. } = &script.instructions[1] else {
+            panic!("second instruction is interactive");
+        };
+        assert_eq!(command.to_string(), "vim autocast.txt");
+        assert_eq!(keys.len(), 7);
+        assert_eq!(keys[1], Key::Char('0'));
+        let Instruction::Marker(marker) = &script.instructions[2] else {
+            panic!("third instruction is a marker");
+        };
+        assert_eq!(marker, "autocast ${unknown");
+        Ok(())
+    }
+
+    #[test]
+    fn expand_env() -> color_eyre::Result<()> {
+        let lookup = |name: &str| (name == "HOME").then(|| String::from("/home/user"));
+        let mut script = Script::try_from_yaml(
+            r#"
+            instructions:
+              - !Command
+                command: ls ${ENV:HOME}/src ${HOME} ${ENV:HOME
+              - !Marker "${ENV:UNSET}"
+              - !Interactive
+                command: [vim, "${ENV:HOME}/notes.txt"]
+                keys: [i, ^C]
+            "#
+            .as_bytes(),
+        )?;
+        script.expand_env_with(lookup)?;
+        let Instruction::Command { command, .. } = &script.instructions[0] else {
+            panic!("first instruction is a command");
+        };
+        assert_eq!(command.to_string(), "ls /home/user/src ${HOME} ${ENV:HOME");
+        let Instruction::Interactive { command, .. } = &script.instructions[2] else {
+            panic!("third instruction is interactive");
+        };
+        assert_eq!(command.to_string(), "vim /home/user/notes.txt");
+
+        let mut script = Script::try_from_yaml(
+            "instructions:\n  - !Command\n    command: cd ${ENV:UNSET}\n".as_bytes(),
+        )?;
+        let error = script.expand_env_with(lookup).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "instruction 0 references environment variable `UNSET`, which is not set"
+        );
+        Ok(())
+    }
+}
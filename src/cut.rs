@@ -0,0 +1,200 @@
+//! Cutting events out of an existing asciicast, i.e. a long wait or a mistaken command,
+//! for small edits without regenerating it.
+//!
+//! The following events are moved earlier by the time cut. As output is removed,
+//! cutting part of a command which redraws the screen may leave it partially drawn.
+
+use std::{ops::Range, time::Duration};
+
+use color_eyre::eyre;
+
+use crate::asciicast::{self, EventType, EVENTS_HASH_ENV};
+
+/// Removes the events from `from` until `to`, or the end if not given.
+///
+/// # Errors
+///
+/// Returns an error if `to` is not after `from`.
+pub fn cut_time(
+    cast: &mut asciicast::File,
+    from: Duration,
+    to: Option<Duration>,
+) -> color_eyre::Result<()> {
+    if let Some(to) = to {
+        eyre::ensure!(
+            to > from,
+            "end of the cut, {to:?}, must be after its start, {from:?}"
+        );
+    }
+    let exit = take_exit(cast);
+    let start = cast.events.partition_point(|event| event.time < from);
+    let end = to.map_or(cast.events.len(), |to| {
+        cast.events.partition_point(|event| event.time < to)
+    });
+    remove(cast, start..end, to.unwrap_or(from).saturating_sub(from));
+    finish(cast, exit);
+    Ok(())
+}
+
+/// Removes the events of the `commands`, numbered from 1 in the order they were typed.
+///
+/// A command's events start with the `prompt` it was typed after, which must be
+/// an output event of its own as autocast records them, and end before the next prompt.
+///
+/// # Errors
+///
+/// Returns an error if a command is not in the asciicast.
+pub fn cut_commands(
+    cast: &mut asciicast::File,
+    prompt: &str,
+    commands: &[usize],
+) -> color_eyre::Result<()> {
+    let exit = take_exit(cast);
+    let prompts: Vec<_> = cast
+        .events
+        .iter()
+        .enumerate()
+        .filter(|(_, event)| event.event_type == EventType::Output && event.data == prompt)
+        .map(|(index, _)| index)
+        .collect();
+    // The last prompt is not followed by a command.
+    let count = prompts.len().saturating_sub(1);
+
+    let mut commands = commands.to_vec();
+    commands.sort_unstable();
+    commands.dedup();
+    if let Some(command) = commands
+        .iter()
+        .find(|command| !(1..=count).contains(*command))
+    {
+        eyre::bail!(
+            "command {command} is not in the asciicast, it has {count} command(s) \
+                after the prompt {prompt:?}"
+        );
+    }
+    // Removed from the last, so the indices of the earlier commands are kept.
+    for &command in commands.iter().rev() {
+        let (start, end) = (prompts[command - 1], prompts[command]);
+        let removed = cast.events[end]
+            .time
+            .saturating_sub(cast.events[start].time);
+        remove(cast, start..end, removed);
+    }
+    finish(cast, exit);
+    Ok(())
+}
+
+/// Removes the exit event, so it is not cut, returning it.
+fn take_exit(cast: &mut asciicast::File) -> Option<asciicast::Event> {
+    if cast
+        .events
+        .last()
+        .is_some_and(|event| event.event_type == EventType::Exit)
+    {
+        cast.events.pop()
+    } else {
+        None
+    }
+}
+
+/// Removes the events in `range`, moving the following events earlier by the `removed` time.
+fn remove(cast: &mut asciicast::File, range: Range<usize>, removed: Duration) {
+    let end = range.start;
+    cast.events.drain(range);
+    for event in &mut cast.events[end..] {
+        event.time = event.time.saturating_sub(removed);
+    }
+}
+
+/// Adds the `exit` event back at the new last event's time, and updates the header.
+///
+/// Like [`asciicast::File::append()`], the events hash is removed as it no longer matches.
+fn finish(cast: &mut asciicast::File, exit: Option<asciicast::Event>) {
+    let last = cast.events.last().map(|event| event.time);
+    if let Some(mut exit) = exit {
+        exit.time = last.unwrap_or_default();
+        cast.events.push(exit);
+    }
+    if cast.header.duration.is_some() {
+        cast.header.duration = last;
+    }
+    cast.header.env.remove(EVENTS_HASH_ENV);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cast() -> asciicast::File {
+        let output = |millis, data: &str| {
+            asciicast::Event::output(Duration::from_millis(millis), String::from(data))
+        };
+        asciicast::File {
+            version: asciicast::Version::V3,
+            header: asciicast::Header {
+                width: 80,
+                height: 24,
+                timestamp: None,
+                duration: Some(Duration::from_millis(700)),
+                idle_time_limit: None,
+                command: None,
+                title: None,
+                env: [(String::from(EVENTS_HASH_ENV), String::from("hash"))].into(),
+            },
+            events: vec![
+                output(0, "$ "),
+                output(100, "ls\r\n"),
+                output(200, "src\r\n"),
+                output(300, "$ "),
+                output(400, "sleep 1\r\n"),
+                output(600, "$ "),
+                output(700, "\r\n"),
+                asciicast::Event::exit(Duration::from_millis(700), 0),
+            ],
+        }
+    }
+
+    fn times(cast: &asciicast::File) -> Vec<u128> {
+        cast.events
+            .iter()
+            .map(|event| event.time.as_millis())
+            .collect()
+    }
+
+    #[test]
+    fn cut_time() {
+        let mut cut = cast();
+        super::cut_time(
+            &mut cut,
+            Duration::from_millis(150),
+            Some(Duration::from_millis(450)),
+        )
+        .unwrap();
+        assert_eq!(times(&cut), [0, 100, 300, 400, 400]);
+        assert_eq!(cut.header.duration, Some(Duration::from_millis(400)));
+        assert!(cut.header.env.is_empty());
+
+        let mut cut = cast();
+        super::cut_time(&mut cut, Duration::from_millis(300), None).unwrap();
+        assert_eq!(times(&cut), [0, 100, 200, 200]);
+
+        assert!(
+            super::cut_time(&mut cast(), Duration::from_secs(1), Some(Duration::ZERO)).is_err()
+        );
+    }
+
+    #[test]
+    fn cut_commands() {
+        let mut cut = cast();
+        super::cut_commands(&mut cut, "$ ", &[2]).unwrap();
+        assert_eq!(times(&cut), [0, 100, 200, 300, 400, 400]);
+        assert_eq!(cut.events[3].data, "$ ");
+        assert_eq!(cut.events[4].data, "\r\n");
+
+        let mut cut = cast();
+        super::cut_commands(&mut cut, "$ ", &[1, 2]).unwrap();
+        assert_eq!(times(&cut), [0, 100, 100]);
+
+        assert!(super::cut_commands(&mut cast(), "$ ", &[3]).is_err());
+    }
+}
@@ -0,0 +1,416 @@
+//! Comparing a regenerated asciicast to an existing one, ignoring what changes between runs,
+//! so demos can be used as regression tests for a CLI's output.
+//!
+//! The timestamp and the events hash are ignored, and times may differ by a tolerance.
+//! Output is compared as a whole, as the shell's output is read in different chunks each run.
+//!
+//! A [`Diff`] of two asciicasts ignores times entirely, for reviewing what a demo's output changed.
+
+use std::{
+    fmt::{self, Display},
+    mem,
+    time::Duration,
+};
+
+use crate::asciicast::{self, EventType, EVENTS_HASH_ENV};
+
+/// A difference between the existing asciicast and the regenerated one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drift {
+    /// What differs, i.e. "header width"
+    pub what: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.what, self.expected, self.actual
+        )
+    }
+}
+
+/// Compares the `actual` asciicast to the `expected` one.
+///
+/// Returns each difference found, times which are within the `tolerance` don't differ.
+pub fn compare(
+    expected: &asciicast::File,
+    actual: &asciicast::File,
+    tolerance: Duration,
+) -> Vec<Drift> {
+    let mut drifts = Drifts::default();
+    drifts.header(expected, actual);
+
+    let (expected_output, actual_output) = (output(expected), output(actual));
+    if let Some((num, (expected, actual))) = expected_output
+        .split_inclusive('\n')
+        .zip(actual_output.split_inclusive('\n'))
+        .enumerate()
+        .find(|(_, (expected, actual))| expected != actual)
+    {
+        drifts.check(
+            &format!("output line {}", num + 1),
+            format!("{expected:?}"),
+            format!("{actual:?}"),
+        );
+    } else {
+        drifts.check(
+            "output lines",
+            expected_output.split_inclusive('\n').count().to_string(),
+            actual_output.split_inclusive('\n').count().to_string(),
+        );
+    }
+
+    drifts.other_events(expected, actual, tolerance);
+
+    let (expected_duration, actual_duration) = (duration(expected), duration(actual));
+    if abs_diff(expected_duration, actual_duration) > tolerance {
+        drifts.check(
+            "duration",
+            format!("{expected_duration:.3?}"),
+            format!("{actual_duration:.3?}"),
+        );
+    }
+
+    drifts.0
+}
+
+#[derive(Debug, Default)]
+struct Drifts(Vec<Drift>);
+
+impl Drifts {
+    /// Adds a drift if `expected` and `actual` differ.
+    fn check(&mut self, what: &str, expected: String, actual: String) {
+        if expected != actual {
+            self.0.push(Drift {
+                what: String::from(what),
+                expected,
+                actual,
+            });
+        }
+    }
+
+    /// Compares the version and headers, except for the timestamp and events hash.
+    fn header(&mut self, expected: &asciicast::File, actual: &asciicast::File) {
+        let (header, other) = (&expected.header, &actual.header);
+        self.check(
+            "version",
+            format!("{:?}", expected.version),
+            format!("{:?}", actual.version),
+        );
+        self.check(
+            "terminal size",
+            format!("{}x{}", header.width, header.height),
+            format!("{}x{}", other.width, other.height),
+        );
+        self.check(
+            "title",
+            format!("{:?}", header.title),
+            format!("{:?}", other.title),
+        );
+        self.check(
+            "command",
+            format!("{:?}", header.command),
+            format!("{:?}", other.command),
+        );
+        self.check(
+            "idle time limit",
+            format!("{:?}", header.idle_time_limit),
+            format!("{:?}", other.idle_time_limit),
+        );
+        self.check("env", sorted_env(header), sorted_env(other));
+    }
+
+    /// Compares the events other than output, i.e. markers, and their times.
+    fn other_events(
+        &mut self,
+        expected: &asciicast::File,
+        actual: &asciicast::File,
+        tolerance: Duration,
+    ) {
+        let (expected_events, actual_events) = (other_events(expected), other_events(actual));
+        self.check(
+            "events other than output",
+            expected_events.len().to_string(),
+            actual_events.len().to_string(),
+        );
+        for (num, (expected, actual)) in expected_events.iter().zip(&actual_events).enumerate() {
+            let what = format!("event {} other than output", num + 1);
+            self.check(
+                &what,
+                format!("{} {:?}", expected.event_type.code(), expected.data),
+                format!("{} {:?}", actual.event_type.code(), actual.data),
+            );
+            if abs_diff(expected.time, actual.time) > tolerance {
+                self.check(
+                    &format!("{what} time"),
+                    format!("{:.3?}", expected.time),
+                    format!("{:.3?}", actual.time),
+                );
+            }
+        }
+    }
+}
+
+/// What changed between an `old` and a `new` asciicast, for reviewing a regenerated demo.
+///
+/// Times are ignored, the headers and events other than output are compared
+/// like [`compare()`], and the output is compared line by line as plain text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diff {
+    pub header: Vec<Drift>,
+    pub lines: Vec<DiffLine>,
+}
+
+/// A line of the output in a [`Diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+/// Number of unchanged lines shown around changed output lines.
+const CONTEXT: usize = 3;
+
+impl Diff {
+    pub fn new(old: &asciicast::File, new: &asciicast::File) -> Self {
+        let mut drifts = Drifts::default();
+        drifts.header(old, new);
+        drifts.other_events(old, new, Duration::MAX);
+
+        let (old, new) = (plain_lines(old), plain_lines(new));
+        Self {
+            header: drifts.0,
+            lines: diff_lines(&old, &new),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty()
+            && self
+                .lines
+                .iter()
+                .all(|line| matches!(line, DiffLine::Same(_)))
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for drift in &self.header {
+            writeln!(f, "{}: {} -> {}", drift.what, drift.expected, drift.actual)?;
+        }
+
+        // Only changed lines and the unchanged lines around them are shown.
+        let changed: Vec<_> = self
+            .lines
+            .iter()
+            .map(|line| !matches!(line, DiffLine::Same(_)))
+            .collect();
+        let shown = |index: usize| {
+            changed[index.saturating_sub(CONTEXT)..(index + CONTEXT + 1).min(changed.len())]
+                .contains(&true)
+        };
+        let mut skipped = false;
+        for (index, line) in self.lines.iter().enumerate() {
+            if !shown(index) {
+                skipped = true;
+                continue;
+            }
+            if mem::take(&mut skipped) {
+                writeln!(f, "  ...")?;
+            }
+            match line {
+                DiffLine::Same(line) => writeln!(f, "  {line}")?,
+                DiffLine::Removed(line) => writeln!(f, "- {line}")?,
+                DiffLine::Added(line) => writeln!(f, "+ {line}")?,
+            }
+        }
+        if skipped && changed.contains(&true) {
+            writeln!(f, "  ...")?;
+        }
+        Ok(())
+    }
+}
+
+/// The cast's output as lines of plain text, without escape sequences.
+fn plain_lines(cast: &asciicast::File) -> Vec<String> {
+    console::strip_ansi_codes(&output(cast))
+        .lines()
+        .map(|line| String::from(line.trim_end_matches('\r')))
+        .collect()
+}
+
+/// Diff of the `old` and `new` lines, keeping their longest common subsequence.
+fn diff_lines(old: &[String], new: &[String]) -> Vec<DiffLine> {
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..].
+    let mut common = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(DiffLine::Same(old[i].clone()));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(DiffLine::Removed(old[i].clone()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j].clone()));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// The header's env, without the events hash which changes with the times of events,
+/// sorted by name.
+fn sorted_env(header: &asciicast::Header) -> String {
+    let mut env: Vec<_> = header
+        .env
+        .iter()
+        .filter(|(name, _)| *name != EVENTS_HASH_ENV)
+        .collect();
+    env.sort();
+    format!("{env:?}")
+}
+
+/// All of the cast's output.
+fn output(cast: &asciicast::File) -> String {
+    cast.events
+        .iter()
+        .filter(|event| event.event_type == EventType::Output)
+        .map(|event| event.data.as_str())
+        .collect()
+}
+
+fn other_events(cast: &asciicast::File) -> Vec<&asciicast::Event> {
+    cast.events
+        .iter()
+        .filter(|event| event.event_type != EventType::Output)
+        .collect()
+}
+
+/// Time of the cast's last event.
+fn duration(cast: &asciicast::File) -> Duration {
+    cast.events
+        .last()
+        .map_or(Duration::ZERO, |event| event.time)
+}
+
+/// The difference between two durations, as `Duration::abs_diff()` is too new for Rust 1.71.
+fn abs_diff(a: Duration, b: Duration) -> Duration {
+    a.saturating_sub(b).max(b.saturating_sub(a))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    fn cast(events: Vec<asciicast::Event>) -> asciicast::File {
+        asciicast::File {
+            version: asciicast::Version::V2,
+            header: asciicast::Header {
+                width: 80,
+                height: 24,
+                timestamp: Some(SystemTime::now()),
+                duration: None,
+                idle_time_limit: None,
+                command: None,
+                title: None,
+                env: [(String::from(EVENTS_HASH_ENV), String::from("hash"))].into(),
+            },
+            events,
+        }
+    }
+
+    fn output(millis: u64, data: &str) -> asciicast::Event {
+        asciicast::Event::output(Duration::from_millis(millis), String::from(data))
+    }
+
+    #[test]
+    fn compare() {
+        let expected = cast(vec![
+            output(0, "$ "),
+            output(100, "hello\r\nwor"),
+            output(101, "ld\r\n"),
+            asciicast::Event::marker(Duration::from_millis(200), String::from("done")),
+        ]);
+        let mut actual = cast(vec![
+            output(0, "$ "),
+            output(150, "hello\r\n"),
+            output(160, "world\r\n"),
+            asciicast::Event::marker(Duration::from_millis(250), String::from("done")),
+        ]);
+        actual.header.env.clear();
+        let tolerance = Duration::from_millis(100);
+        assert!(super::compare(&expected, &actual, tolerance).is_empty());
+
+        actual.header.width = 100;
+        actual.events[2].data = String::from("word\r\n");
+        actual.events[3].time = Duration::from_secs(1);
+        assert_eq!(
+            super::compare(&expected, &actual, tolerance)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "terminal size: expected 80x24, got 100x24",
+                "output line 2: expected \"world\\r\\n\", got \"word\\r\\n\"",
+                "event 1 other than output time: expected 200.000ms, got 1.000s",
+                "duration: expected 200.000ms, got 1.000s",
+            ]
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let old = cast(vec![
+            output(0, "$ "),
+            output(100, "ls\r\n"),
+            output(200, "a\r\nb\r\nc\r\nd\r\ne\r\nf\r\ng\r\nh\r\n"),
+            output(300, "$ "),
+        ]);
+        let mut new = cast(vec![
+            output(0, "$ "),
+            output(500, "ls\r\n"),
+            output(
+                600,
+                "a\r\nb\r\nc\r\nd\r\n\x1b[1me2\x1b[0m\r\nf\r\ng\r\nh\r\n",
+            ),
+            output(700, "$ "),
+        ]);
+        assert!(Diff::new(&old, &old).is_empty());
+
+        new.header.title = Some(String::from("demo"));
+        assert_eq!(
+            Diff::new(&old, &new).to_string(),
+            "title: None -> Some(\"demo\")\n  \
+                ...\n  \
+                b\n  \
+                c\n  \
+                d\n\
+                - e\n\
+                + e2\n  \
+                f\n  \
+                g\n  \
+                h\n  \
+                ...\n"
+        );
+    }
+}
@@ -1,8 +1,17 @@
+//! Parsing durations like "10s", "500ms", or "100us", and deserializing them with serde,
+//! for script settings and verify specs.
+
 use std::{fmt, num::ParseIntError, time::Duration};
 
 use serde::{de, Deserializer};
 use thiserror::Error;
 
+/// Parses a duration made of an integer and a unit: s, ms, or us.
+///
+/// # Errors
+///
+/// Returns an error if the duration contains whitespace, has an unknown unit,
+/// or the amount is not an integer.
 pub fn parse(s: &str) -> Result<Duration, ParseError> {
     let s = s.trim();
     if s.contains(char::is_whitespace) {
@@ -28,6 +37,11 @@ pub enum ParseError {
     InvalidInt(#[from] ParseIntError),
 }
 
+/// Deserializes a duration from a string, for use with `#[serde(with = "duration")]`.
+///
+/// # Errors
+///
+/// Returns an error if the value is not a string which [`parse()`] accepts.
 pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
     deserializer.deserialize_str(Visitor)
 }
@@ -35,7 +49,7 @@ pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duratio
 #[derive(Debug)]
 struct Visitor;
 
-impl<'de> de::Visitor<'de> for Visitor {
+impl de::Visitor<'_> for Visitor {
     type Value = Duration;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
@@ -47,11 +61,17 @@ impl<'de> de::Visitor<'de> for Visitor {
     }
 }
 
+/// Deserializing optional durations, for use with `#[serde(with = "duration::option")]`.
 pub mod option {
     use std::{fmt, time::Duration};
 
     use serde::{de, Deserializer};
 
+    /// Deserializes an optional duration string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value is not a string which [`parse()`](super::parse) accepts.
     pub fn deserialize<'de, D: Deserializer<'de>>(
         deserializer: D,
     ) -> Result<Option<Duration>, D::Error> {
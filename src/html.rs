@@ -0,0 +1,122 @@
+//! Writing an asciicast as a single HTML page which plays it with asciinema-player,
+//! so a demo can be shared as one file or hosted on a static server.
+//!
+//! The asciicast is inlined as the player's data. The player's JS and CSS are inlined from
+//! a directory with the player's files, or loaded from a CDN if one isn't given.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use color_eyre::eyre::Context;
+
+use crate::{asciicast, svg};
+
+/// Version of asciinema-player loaded from the CDN
+const PLAYER_VERSION: &str = "3.8.0";
+const PLAYER_JS: &str = "asciinema-player.min.js";
+const PLAYER_CSS: &str = "asciinema-player.css";
+
+/// Writes an HTML page playing the `cast` to `writer`.
+///
+/// If `player` is given, it is the directory containing asciinema-player's
+/// "asciinema-player.min.js" and "asciinema-player.css" files, i.e. its npm package's
+/// "dist/bundle" directory, which are inlined.
+pub fn write(
+    cast: &asciicast::File,
+    player: Option<&Path>,
+    mut writer: impl Write,
+) -> color_eyre::Result<()> {
+    let (style, script) = if let Some(player) = player {
+        let read = |name| {
+            let path = player.join(name);
+            fs::read_to_string(&path)
+                .map(|contents| contents.trim_end().to_owned())
+                .wrap_err_with(|| {
+                    format!("could not read asciinema-player file `{}`", path.display())
+                })
+        };
+        (
+            format!("<style>\n{}\n</style>", read(PLAYER_CSS)?),
+            format!("<script>\n{}\n</script>", read(PLAYER_JS)?),
+        )
+    } else {
+        let cdn =
+            format!("https://cdn.jsdelivr.net/npm/asciinema-player@{PLAYER_VERSION}/dist/bundle");
+        (
+            format!("<link rel=\"stylesheet\" href=\"{cdn}/{PLAYER_CSS}\">"),
+            format!("<script src=\"{cdn}/{PLAYER_JS}\"></script>"),
+        )
+    };
+    let title = svg::escape(cast.header.title.as_deref().unwrap_or("autocast"));
+    let data = data(cast)?;
+    write!(
+        writer,
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{title}</title>
+{style}
+</head>
+<body>
+<div id=\"player\"></div>
+{script}
+<script>
+AsciinemaPlayer.create({{ data: {data} }}, document.getElementById(\"player\"));
+</script>
+</body>
+</html>
+"
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// The `cast` as a JS string literal, which can't end the script element it's in.
+fn data(cast: &asciicast::File) -> io::Result<String> {
+    let mut contents = Vec::new();
+    cast.write(&mut contents)?;
+    let contents = String::from_utf8_lossy(&contents);
+    let literal = serde_json::to_string(&contents)?;
+    Ok(literal.replace("</", "<\\/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use crate::asciicast::{Event, Header, Version};
+
+    use super::*;
+
+    #[test]
+    fn write() {
+        let cast = asciicast::File {
+            version: Version::V2,
+            header: Header {
+                width: 80,
+                height: 24,
+                timestamp: None,
+                duration: None,
+                idle_time_limit: None,
+                command: None,
+                title: Some(String::from("<demo>")),
+                env: HashMap::new(),
+            },
+            events: vec![Event::output(
+                Duration::from_secs(1),
+                String::from("</script>\r\n"),
+            )],
+        };
+        let mut html = Vec::new();
+        super::write(&cast, None, &mut html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains("<title>&lt;demo&gt;</title>"));
+        assert!(html.contains("asciinema-player@3.8.0/dist/bundle/asciinema-player.min.js"));
+        assert!(html.contains(r#"\"<\/script>\\r\\n\""#));
+        assert_eq!(html.matches("</script>").count(), 2);
+    }
+}
@@ -0,0 +1,69 @@
+//! Example scripts to start a new project from.
+
+use std::path::Path;
+
+use clap::ValueEnum;
+
+/// Kind of demo to create an example script for
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    /// Commands and their output, with replies to confirmation prompts
+    #[default]
+    Cli,
+    /// A full screen program driven with keys and waits
+    Tui,
+    /// A python session with expressions and multi-line blocks
+    Repl,
+}
+
+impl Template {
+    /// Contents of the example script.
+    pub const fn script(self) -> &'static str {
+        match self {
+            Self::Cli => include_str!("init/cli.yaml"),
+            Self::Tui => include_str!("init/tui.yaml"),
+            Self::Repl => include_str!("init/repl.yaml"),
+        }
+    }
+}
+
+/// Rules for make and just which create the asciicast from `script`.
+pub fn build_snippets(script: &Path) -> String {
+    let cast = script.with_extension("cast");
+    let (script, cast) = (script.display(), cast.display());
+    format!(
+        "\n\
+        Makefile:\n\
+        \n\
+        {cast}: {script}\n\
+        \tautocast --overwrite {script} {cast}\n\
+        \n\
+        or a justfile:\n\
+        \n\
+        demo:\n    \
+            autocast --overwrite {script} {cast}\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Script;
+
+    use super::*;
+
+    #[test]
+    fn templates_parse() {
+        for template in Template::value_variants() {
+            if let Err(errors) = Script::parse_strict(template.script()) {
+                panic!("{template:?} template is invalid: {errors}");
+            }
+        }
+    }
+
+    #[test]
+    fn build_snippets() {
+        let snippets = super::build_snippets(Path::new("docs/demo.yaml"));
+        assert!(snippets.contains("docs/demo.cast: docs/demo.yaml\n\tautocast --overwrite"));
+        assert!(snippets.contains("demo:\n    autocast --overwrite docs/demo.yaml docs/demo.cast"));
+    }
+}
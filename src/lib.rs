@@ -0,0 +1,23 @@
+//! Checking asciicast files against a spec of durations and output, from a crate's own tests,
+//! the same way as `autocast verify`.
+//!
+//! ```no_run
+//! use std::{fs, io::BufReader};
+//!
+//! use autocast::{asciicast, verify};
+//!
+//! # fn main() -> color_eyre::Result<()> {
+//! let cast = asciicast::File::read(BufReader::new(fs::File::open("demo.cast")?))?;
+//! let spec = verify::Spec::read("demo.spec.yaml".as_ref())?;
+//! verify::assert_cast_matches(&cast, &spec)?;
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(clippy::pedantic)]
+#![warn(clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
+
+pub mod asciicast;
+pub mod duration;
+pub mod verify;
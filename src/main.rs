@@ -2,52 +2,1222 @@
 #![warn(clippy::cargo)]
 #![allow(clippy::multiple_crate_versions)]
 
-mod asciicast;
+mod batch;
+mod bundle;
 mod config;
+mod cut;
+mod drift;
+mod html;
+mod init;
+mod man;
+mod speed;
+mod svg;
+mod ttyrec;
+mod typescript;
+mod upload;
 
 use std::{
+    collections::HashMap,
+    fmt::{self, Display},
     fs,
-    io::{BufReader, BufWriter},
-    path::PathBuf,
+    io::{self, BufWriter, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
 };
 
-use clap::Parser;
-use color_eyre::{eyre::Context, Help};
+use clap::{ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use color_eyre::{
+    config::{HookBuilder, Theme},
+    eyre::{self, Context},
+    Help, Section,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use sha2::{Digest, Sha256};
 
-use config::{Script, Settings};
+use autocast::{asciicast, verify};
+use bundle::Bundle;
+use config::{export, lint, transcript::Transcript, Script, Settings, SharedProgress};
 
 fn main() -> color_eyre::Result<()> {
-    color_eyre::install()?;
-
     let cli = Cli::parse();
 
-    let in_file = fs::File::open(&cli.in_file).wrap_err("could not open input file")?;
+    cli.color.install()?;
 
-    let mut script = Script::try_from_yaml(BufReader::new(in_file))
-        .wrap_err("could not parse input file as Script")?;
-    println!("Read from file: {}", cli.in_file.display());
-    script.merge_settings(cli.settings);
+    match cli.command {
+        Some(Command::Batch(batch)) => batch.run(),
+        Some(Command::Completions(completions)) => completions.run(),
+        Some(Command::Cut(cut)) => cut.run(),
+        Some(Command::Diff(diff)) => diff.run(),
+        Some(Command::Export(export)) => export.run(),
+        Some(Command::ImportTypescript(import)) => import.run(),
+        Some(Command::ImportTtyrec(import)) => import.run(),
+        Some(Command::Init(init)) => init.run(),
+        Some(Command::Lint(lint)) => lint.run(),
+        Some(Command::Man) => {
+            man::write(Cli::command(), io::stdout().lock()).wrap_err("could not write man page")
+        }
+        Some(Command::Present(present)) => present.run(),
+        Some(Command::Speed(speed)) => speed.run(),
+        Some(Command::Upload(upload)) => upload.run(),
+        Some(Command::Validate(validate)) => validate.run(),
+        Some(Command::Verify(verify)) => verify.run(),
+        None => cli.run.run(),
+    }
+}
 
-    let out_file = fs::File::options()
-        .write(true)
-        .create_new(!cli.overwrite)
-        .create(cli.overwrite)
-        .truncate(true)
-        .open(&cli.out_file)
-        .wrap_err("could not create/open output file")
-        .suggestion("use `--overwrite` if you wish to replace an existing file")?;
+#[derive(Parser, Debug, Clone)]
+#[command(
+    version,
+    author,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// When to use colors in error reports and progress bars
+    #[arg(long, value_enum, default_value_t, global = true)]
+    color: Color,
+
+    #[command(flatten)]
+    run: Run,
+}
+
+/// When to use colors in autocast's own output.
+/// Does not affect the recorded output.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Color {
+    /// Use colors when writing to a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    /// Enables or disables colors for progress bars and installs the error report hooks.
+    fn install(self) -> color_eyre::Result<()> {
+        match self {
+            // `console` already checks whether stdout and stderr are terminals.
+            Self::Auto => {}
+            Self::Always | Self::Never => {
+                let enabled = self == Self::Always;
+                console::set_colors_enabled(enabled);
+                console::set_colors_enabled_stderr(enabled);
+            }
+        }
+
+        // Reports and panics are written to stderr.
+        let theme = if console::colors_enabled_stderr() {
+            Theme::dark()
+        } else {
+            Theme::new()
+        };
+        HookBuilder::default().theme(theme).install()
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Create an asciicast file for each of several input files, i.e. all of a project's demos
+    Batch(Box<Batch>),
+
+    /// Print a completion script for a shell
+    ///
+    /// i.e. for bash, add `eval "$(autocast completions bash)"` to ~/.bashrc
+    Completions(Completions),
+
+    /// Cut a time range or commands out of an existing asciicast file,
+    /// i.e. to remove a long wait without regenerating it
+    Cut(Cut),
+
+    /// Show what changed between two asciicast files, ignoring times,
+    /// i.e. to review a regenerated demo
+    Diff(Diff),
+
+    /// Export a script's commands as a shell script or Markdown snippet
+    Export(Export),
+
+    /// Convert a typescript and timing file, created by `script --timing`, to an asciicast file
+    ImportTypescript(ImportTypescript),
+
+    /// Convert a ttyrec recording, i.e. from ttyrec or termrec, to an asciicast file
+    ImportTtyrec(ImportTtyrec),
+
+    /// Create an example input file to start a new demo from
+    Init(Init),
+
+    /// Check a script for common problems
+    Lint(Lint),
+
+    /// Print a man page, covering the options, input file format, and durations
+    ///
+    /// i.e. `autocast man > autocast.1`
+    Man,
+
+    /// Present a script live in the terminal, advancing on keypress, while recording it
+    Present(Box<Present>),
+
+    /// Change the speed of an existing asciicast file, i.e. `autocast speed 2x demo.cast out.cast`
+    Speed(Speed),
+
+    /// Upload an asciicast file to asciinema.org, or a self-hosted asciinema server
+    Upload(Upload),
+
+    /// Check that a script can be run without running it, i.e. in a pre-commit hook
+    Validate(Box<Validate>),
+
+    /// Check an asciicast file against a spec of durations and output, i.e. in CI
+    Verify(Verify),
+}
+
+/// Create an asciicast file from an input file
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Args, Debug, Clone)]
+struct Run {
+    #[command(flatten)]
+    settings: Settings,
+
+    /// Create an asciicast file for each terminal size, i.e. "80x24,120x30"
+    ///
+    /// The script is run once per size and the size is added to the output file name,
+    /// i.e. "demo.cast" becomes "demo-80x24.cast"
+    #[arg(long, value_name = "SIZES", value_delimiter = ',')]
+    sizes: Vec<Size>,
+
+    /// Run the script N times and keep the best take, chosen with `--select`
+    ///
+    /// Smooths over slow runs caused by the system, i.e. network jitter,
+    /// without re-running the script by hand
+    #[arg(long, value_name = "N", default_value = "1")]
+    takes: NonZeroUsize,
+
+    /// How the best of the `--takes` is chosen
+    #[arg(long, value_enum, default_value_t)]
+    select: Select,
+
+    /// Format of the output file, defaults to "svg" if the output file's extension is ".svg",
+    /// otherwise "asciicast"
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Compress the output file with gzip, which is the default if its extension is ".gz",
+    /// i.e. "demo.cast.gz"
+    #[arg(long)]
+    compress: bool,
 
-    let cast = asciicast::File::try_from(script).wrap_err("error running script")?;
-    cast.write(BufWriter::new(out_file))
+    /// Also create a ZIP archive containing the asciicast files, the other output files,
+    /// and the input file
+    #[arg(long, value_name = "PATH")]
+    bundle: Option<PathBuf>,
+
+    /// Add a hash of the events to the asciicast's "env" header section
+    /// and write the file's hash to a checksum file, i.e. "demo.cast.sha256"
+    ///
+    /// Used to detect when an asciicast file was edited after it was created
+    #[arg(long)]
+    checksum: bool,
+
+    /// Directory to resolve relative paths in the input file against,
+    /// defaults to the input file's directory
+    #[arg(long, value_name = "DIR")]
+    base_dir: Option<PathBuf>,
+
+    /// Run the input file without typing delays to measure how long each instruction takes,
+    /// and write a copy of it with suggested waits and timeout to PATH,
+    /// instead of creating the asciicast file
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["sizes", "format", "bundle", "checksum"]
+    )]
+    calibrate: Option<PathBuf>,
+
+    /// Check the input file for problems before running it, see `autocast lint`
+    ///
+    /// Stops if there are any errors
+    #[arg(long)]
+    lint: bool,
+
+    /// Check that control codes, i.e. "^C", have their expected effect in the input file's
+    /// shell before running it, each in a scratch shell
+    ///
+    /// Catches differences between platforms and terminal settings which would otherwise
+    /// show up as glitches in the demo. Stops if any control code fails
+    #[arg(long)]
+    doctor: bool,
+
+    /// Print the resolved shell, terminal size, and prompt, and each instruction with its
+    /// type speed and timeout, instead of creating the asciicast file
+    ///
+    /// Nothing is spawned, so it can be used to check a script before a long recording
+    #[arg(long = "dry-run", conflicts_with_all = ["sizes", "calibrate", "doctor"])]
+    plan: bool,
+
+    /// Start the input file's shell, check that its prompt is detected, and time a command's
+    /// round trip, instead of creating the asciicast file
+    ///
+    /// A fast check that the shell works, i.e. in CI before a long recording
+    #[arg(long, conflicts_with_all = ["sizes", "calibrate", "plan"])]
+    probe: bool,
+
+    /// Append the new events to the existing output asciicast file, after its last event
+    ///
+    /// The existing file's header is kept, so long demos can be built one script at a time
+    #[arg(long, conflicts_with_all = ["format", "overwrite"])]
+    append: bool,
+
+    /// Upload the asciicast file after creating it, see `autocast upload`
+    #[arg(long, conflicts_with_all = ["sizes", "calibrate"])]
+    upload: bool,
+
+    /// asciinema server to upload to, defaults to `ASCIINEMA_API_URL` or asciinema.org
+    #[arg(long, value_name = "URL", requires = "upload")]
+    upload_server: Option<String>,
+
+    /// Also write the output to a typescript file, like `script` creates,
+    /// for tools which don't support asciicast files
+    #[arg(long, value_name = "PATH", conflicts_with = "sizes")]
+    typescript: Option<PathBuf>,
+
+    /// Write a timing file for the `--typescript`, to replay it with `scriptreplay`
+    #[arg(long, value_name = "PATH", requires = "typescript")]
+    timing: Option<PathBuf>,
+
+    /// Also write a single HTML file which plays the asciicast with asciinema-player,
+    /// with the asciicast inlined
+    #[arg(long, value_name = "PATH", conflicts_with = "sizes")]
+    html: Option<PathBuf>,
+
+    /// Directory with asciinema-player's "asciinema-player.min.js" and "asciinema-player.css"
+    /// files to inline in the `--html` file, instead of loading them from a CDN
+    #[arg(long, value_name = "DIR", requires = "html")]
+    html_player: Option<PathBuf>,
+
+    /// Also write a JSON transcript for screen readers, listing each typed command and its
+    /// output as plain text, with their times
+    ///
+    /// Lets docs sites offer an accessible alternative to the animated asciicast
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["sizes", "append"])]
+    a11y: Option<PathBuf>,
+
+    /// Run the script and compare the asciicast to the existing output file, instead of
+    /// writing it, failing if they differ
+    ///
+    /// The timestamp is ignored and times may differ by `--check-tolerance`,
+    /// so demos can be used as regression tests for a CLI's output in CI
+    #[arg(
+        long,
+        conflicts_with_all = ["append", "upload", "bundle", "checksum", "typescript", "html", "a11y"]
+    )]
+    check: bool,
+
+    /// How much the times of events may differ with `--check`
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "1s",
+        value_parser = config::parse_duration
+    )]
+    check_tolerance: Duration,
+
+    /// Overwrite output file if it already exists
+    #[arg(long)]
+    overwrite: bool,
+
+    /// Input file to create the asciicast file with
+    // Only optional so that subcommands can be used instead.
+    #[arg(required = true)]
+    in_file: Option<PathBuf>,
+
+    /// Output asciicast file, or "-" to write to stdout
+    #[arg(required_unless_present_any = ["calibrate", "plan", "probe"])]
+    out_file: Option<PathBuf>,
+}
+
+impl Run {
+    #[allow(clippy::too_many_lines)]
+    fn run(self) -> color_eyre::Result<()> {
+        let Some(in_file) = self.in_file else {
+            unreachable!("clap requires the input file without a subcommand");
+        };
+
+        // Checked first so an existing bundle is not found after recording,
+        // but only created at the end so a failed run doesn't leave an empty archive.
+        if let Some(path) = &self.bundle {
+            ensure_new_file(path, self.overwrite).wrap_err("could not create bundle")?;
+        }
+
+        let base_dir = base_dir(&in_file, self.base_dir.as_deref());
+        let mut script = read_script_in(&in_file, base_dir)?;
+        eprintln!("Read from file: {}", in_file.display());
+        script.resolve_paths(base_dir);
+        script.merge_global_settings()?;
+        script.merge_settings(self.settings);
+        if self.lint {
+            let config = lint::Config::find(base_dir)?;
+            check_lints(&script, &config)?;
+        }
+        if self.doctor {
+            check_control_codes(&script)?;
+        }
+        if self.plan {
+            let plan = script.plan().wrap_err("could not plan script")?;
+            println!("{plan}");
+            return Ok(());
+        }
+        if self.probe {
+            let probe = script.probe().wrap_err("shell probe failed")?;
+            println!("{probe}");
+            return Ok(());
+        }
+        if let Some(path) = self.calibrate {
+            return calibrate(&script, &in_file, &path, self.overwrite);
+        }
+        let Some(out_file) = self.out_file else {
+            unreachable!("clap requires the output file unless calibrating, planning, or probing");
+        };
+        let scripts = script.matrix(&out_file)?;
+        eyre::ensure!(
+            scripts.len() == 1 || !is_stdout(&out_file),
+            "a script with a matrix needs an output file, not stdout"
+        );
+        eyre::ensure!(
+            !is_stdout(&out_file)
+                || (self.sizes.is_empty()
+                    && self.bundle.is_none()
+                    && !self.checksum
+                    && !self.append
+                    && !self.upload
+                    && !self.check),
+            "`--sizes`, `--bundle`, `--checksum`, `--append`, `--upload`, and `--check` \
+                need an output file, not stdout"
+        );
+
+        let format = self
+            .format
+            .unwrap_or_else(|| Format::from_extension(&out_file));
+        eyre::ensure!(
+            !self.upload || format == Format::Asciicast,
+            "only asciicast files can be uploaded"
+        );
+        eyre::ensure!(
+            !self.check || format == Format::Asciicast,
+            "only asciicast files can be checked"
+        );
+        let output = Output {
+            format,
+            overwrite: self.overwrite,
+            append: self.append,
+            compress: self.compress || is_gzip(&out_file),
+            checksum: self.checksum,
+            typescript: self.typescript.as_deref(),
+            timing: self.timing.as_deref(),
+            html: self.html.as_deref(),
+            html_player: self.html_player.as_deref(),
+            a11y: self.a11y.as_deref(),
+            check: self.check.then_some(self.check_tolerance),
+            progress: None,
+            takes: self.takes,
+            select: self.select,
+        };
+
+        let mut bundle = Bundle::default();
+        let mut add_to_bundle = |out_file: PathBuf| {
+            if self.checksum {
+                bundle.add(checksum_file(&out_file));
+            }
+            bundle.add(out_file);
+        };
+        for (script, out_file) in scripts {
+            if self.sizes.is_empty() {
+                record(script, &out_file, output)?;
+                if self.upload {
+                    let url = upload::upload(&out_file, self.upload_server.as_deref())?;
+                    eprintln!("   Uploaded to: {url}");
+                }
+                add_to_bundle(out_file);
+            } else {
+                for &size in &self.sizes {
+                    let mut script = script.clone();
+                    script.set_size(size.width, size.height);
+                    let out_file = size.out_file(&out_file);
+                    record(script, &out_file, output).wrap_err_with(|| {
+                        format!("error creating asciicast file for size {size}")
+                    })?;
+                    add_to_bundle(out_file);
+                }
+            }
+        }
+
+        if let Some(path) = self.bundle {
+            // The same for every script, so they are only added once.
+            let extra_outputs = [&self.typescript, &self.timing, &self.html, &self.a11y];
+            for path in extra_outputs.into_iter().flatten() {
+                bundle.add(path);
+            }
+            bundle.add(in_file);
+            let file = create_file(&path, self.overwrite).wrap_err("could not create bundle")?;
+            bundle.write(file).wrap_err("could not write bundle")?;
+            eprintln!("  Wrote bundle: {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct Batch {
+    #[command(flatten)]
+    settings: Settings,
+
+    /// Directory to write the asciicast files to, named after their input files,
+    /// i.e. "demo.yaml" becomes "demo.cast"
+    #[arg(short, long, value_name = "DIR")]
+    out_dir: PathBuf,
+
+    /// Format of the output files
+    #[arg(long, value_enum, default_value_t = Format::Asciicast)]
+    format: Format,
+
+    /// Run the rest of the input files after one fails, and list the failures at the end
+    #[arg(long)]
+    keep_going: bool,
+
+    /// Number of input files to run at the same time, each in its own shell
+    #[arg(short, long, value_name = "N", default_value = "1")]
+    jobs: NonZeroUsize,
+
+    /// Overwrite output files if they already exist
+    #[arg(long)]
+    overwrite: bool,
+
+    /// Input files, or directories whose YAML, TOML, and JSON files are all input files
+    #[arg(required = true)]
+    in_files: Vec<PathBuf>,
+}
+
+/// How to write the output file.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy)]
+struct Output<'a> {
+    format: Format,
+    overwrite: bool,
+    /// Append to the existing asciicast file, see [`asciicast::File::append()`]
+    append: bool,
+    /// Compress the output file with gzip
+    compress: bool,
+    /// Add the hash of the events to the asciicast's header and write the [`checksum_file()`]
+    checksum: bool,
+    /// Where to also write the output as a typescript, see [`typescript::write()`]
+    typescript: Option<&'a Path>,
+    /// Where to write the timing file for the `typescript`
+    timing: Option<&'a Path>,
+    /// Where to also write the output as an HTML page, see [`html::write()`]
+    html: Option<&'a Path>,
+    /// Directory of asciinema-player's files to inline in the `html`
+    html_player: Option<&'a Path>,
+    /// Where to also write the transcript of the commands, see [`Transcript`]
+    a11y: Option<&'a Path>,
+    /// Compare to the existing asciicast file with this tolerance instead of writing it,
+    /// see [`check()`]
+    check: Option<Duration>,
+    /// Progress bars shared with the other scripts running at the same time
+    progress: Option<SharedProgress<'a>>,
+    /// How many times to run the script, see [`best_take()`]
+    takes: NonZeroUsize,
+    select: Select,
+}
+
+/// Runs the script and writes the asciicast file, or SVG, to `out_file`.
+#[allow(clippy::too_many_lines)]
+fn record(script: Script, out_file: &Path, output: Output) -> color_eyre::Result<()> {
+    let Output {
+        format,
+        overwrite,
+        append,
+        compress,
+        checksum,
+        typescript,
+        timing,
+        html,
+        html_player,
+        a11y,
+        check,
+        // The rest are used by `best_take()`.
+        ..
+    } = output;
+
+    if let Some(tolerance) = check {
+        return self::check(script, out_file, output, tolerance);
+    }
+
+    // The existing file is only replaced once the script has run successfully.
+    let existing = append
+        .then(|| read_cast(out_file, compress))
+        .transpose()
+        .wrap_err("could not read asciicast file to append to")?;
+    let out = if append {
+        None
+    } else {
+        Some(create_output(out_file, overwrite)?)
+    };
+    // Only created once the asciicast is written, so a failed run doesn't leave an empty one.
+    if checksum {
+        ensure_new_file(&checksum_file(out_file), overwrite || append)
+            .wrap_err("could not create checksum file")?;
+    }
+    let typescript_out = typescript
+        .map(|path| create_file(path, overwrite || append))
+        .transpose()
+        .wrap_err("could not create typescript file")?;
+    let timing_out = timing
+        .map(|path| create_file(path, overwrite || append))
+        .transpose()
+        .wrap_err("could not create timing file")?;
+    let html_out = html
+        .map(|path| create_file(path, overwrite || append))
+        .transpose()
+        .wrap_err("could not create HTML file")?;
+    let a11y_out = a11y
+        .map(|path| create_file(path, overwrite))
+        .transpose()
+        .wrap_err("could not create transcript file")?;
+
+    let profile_run = script.profile_run();
+    let (mut cast, transcript) = best_take(script, output)?;
+    if let Some(existing) = existing {
+        cast = append_to(existing, cast)?;
+    }
+    let mut out = match out {
+        Some(out) => out,
+        None => create_output(out_file, true)?,
+    };
+    let serialization = Instant::now();
+    let mut contents = Vec::new();
+    match format {
+        Format::Asciicast => {
+            if checksum {
+                cast.add_events_hash().wrap_err("could not hash events")?;
+            }
+            cast.write(&mut contents)
+        }
+        Format::Svg => svg::write(&cast, &mut contents),
+    }
+    .and_then(|()| {
+        if compress {
+            contents = gzip(&contents)?;
+        }
+        out.write_all(&contents)
+    })
+    .and_then(|()| out.flush())
+    .wrap_err("could not write to output file")?;
+    if profile_run {
+        eprintln!("  writing the output: {:.2?}", serialization.elapsed());
+    }
+    if !is_stdout(out_file) {
+        eprintln!(" Wrote to file: {}", out_file.display());
+    }
+
+    if checksum {
+        let mut checksum_out = create_file(&checksum_file(out_file), true)
+            .wrap_err("could not create checksum file")?;
+        // The same format as `sha256sum`, so it can be checked with `sha256sum -c`.
+        let name = out_file.file_name().unwrap_or_default().to_string_lossy();
+        writeln!(checksum_out, "{:x}  {name}", Sha256::digest(&contents))
+            .wrap_err("could not write to checksum file")?;
+    }
+
+    if let Some(typescript_out) = typescript_out {
+        typescript::write(
+            &cast,
+            BufWriter::new(typescript_out),
+            timing_out.map(BufWriter::new),
+        )
+        .wrap_err("could not write typescript")?;
+    }
+
+    if let Some(html_out) = html_out {
+        html::write(&cast, html_player, BufWriter::new(html_out))
+            .wrap_err("could not write HTML file")?;
+    }
+
+    if let Some(a11y_out) = a11y_out {
+        transcript
+            .write(BufWriter::new(a11y_out))
+            .wrap_err("could not write transcript")?;
+    }
+
+    Ok(())
+}
+
+/// Runs the script and compares the asciicast to the existing one at `out_file`,
+/// returning an error if they differ, see [`drift::compare()`].
+fn check(
+    script: Script,
+    out_file: &Path,
+    output: Output,
+    tolerance: Duration,
+) -> color_eyre::Result<()> {
+    let existing = read_cast(out_file, output.compress)
+        .wrap_err("could not read asciicast file to check against")?;
+    let (cast, _) = best_take(script, output)?;
+    let drifts = drift::compare(&existing, &cast, tolerance);
+    for drift in &drifts {
+        eprintln!("{drift}");
+    }
+    eyre::ensure!(
+        drifts.is_empty(),
+        "asciicast file `{}` is out of date, found {} difference(s)",
+        out_file.display(),
+        drifts.len()
+    );
+    eprintln!(" Checked file: {}", out_file.display());
+    Ok(())
+}
+
+/// Appends the `cast` to the `existing` asciicast, which must have the same terminal size.
+fn append_to(
+    mut existing: asciicast::File,
+    cast: asciicast::File,
+) -> color_eyre::Result<asciicast::File> {
+    eyre::ensure!(
+        (existing.header.width, existing.header.height) == (cast.header.width, cast.header.height),
+        "terminal size {}x{} does not match the size of the asciicast file, {}x{}",
+        cast.header.width,
+        cast.header.height,
+        existing.header.width,
+        existing.header.height
+    );
+    existing.append(cast);
+    Ok(existing)
+}
+
+/// Runs the script `takes` times and returns the best asciicast, chosen by `select`,
+/// and its transcript.
+fn best_take(
+    script: Script,
+    Output {
+        takes,
+        select,
+        progress,
+        ..
+    }: Output,
+) -> color_eyre::Result<(asciicast::File, Transcript)> {
+    if takes.get() == 1 {
+        return script
+            .run_transcribed(progress)
+            .wrap_err("error running script");
+    }
+    let mut best: Option<(usize, u128, asciicast::File, Transcript)> = None;
+    for take in 1..=takes.get() {
+        eprintln!("  Running take: {take}/{takes}");
+        let (cast, transcript) = script
+            .clone()
+            .run_transcribed(progress)
+            .wrap_err_with(|| format!("error running script, take {take}"))?;
+        let score = select.score(&cast);
+        if best
+            .as_ref()
+            .map_or(true, |(_, best_score, ..)| score < *best_score)
+        {
+            best = Some((take, score, cast, transcript));
+        }
+    }
+    let (take, _, cast, transcript) = best.expect("there is at least one take");
+    eprintln!("  Kept take {take}: {}", select.describe(&cast));
+    Ok((cast, transcript))
+}
+
+/// How the best of multiple takes is chosen
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Select {
+    /// The take with the shortest duration
+    #[default]
+    Fastest,
+    /// The take with the smallest asciicast file
+    Smallest,
+}
+
+impl Select {
+    /// Score of the `cast` to compare takes with, lower is better.
+    fn score(self, cast: &asciicast::File) -> u128 {
+        match self {
+            Self::Fastest => cast_duration(cast).as_nanos(),
+            Self::Smallest => cast_size(cast) as u128,
+        }
+    }
+
+    /// Describes the `cast`'s score for the user.
+    fn describe(self, cast: &asciicast::File) -> String {
+        match self {
+            Self::Fastest => format!("{:.2?} long", cast_duration(cast)),
+            Self::Smallest => format!("{} bytes", cast_size(cast)),
+        }
+    }
+}
+
+/// Time of the `cast`'s last event.
+fn cast_duration(cast: &asciicast::File) -> Duration {
+    cast.events
+        .last()
+        .map_or(Duration::ZERO, |event| event.time)
+}
+
+/// Size of the `cast` when written as an asciicast file.
+fn cast_size(cast: &asciicast::File) -> usize {
+    let mut contents = Vec::new();
+    cast.write(&mut contents)
+        .expect("writing to a vec can't fail");
+    contents.len()
+}
+
+/// Runs the script to measure its instructions and writes the annotated copy of `in_file`.
+fn calibrate(
+    script: &Script,
+    in_file: &Path,
+    out_file: &Path,
+    overwrite: bool,
+) -> color_eyre::Result<()> {
+    eyre::ensure!(
+        ScriptFormat::from_extension(in_file) == ScriptFormat::Yaml,
+        "only YAML scripts can be calibrated"
+    );
+    let mut out = create_file(out_file, overwrite)?;
+    let yaml = fs::read_to_string(in_file).wrap_err("could not read input file")?;
+    let annotated = script
+        .calibrate(&yaml)
+        .wrap_err("error calibrating script")?;
+    out.write_all(annotated.as_bytes())
         .wrap_err("could not write to output file")?;
-    println!(" Wrote to file: {}", cli.out_file.display());
+    eprintln!(" Wrote to file: {}", out_file.display());
+    Ok(())
+}
+
+/// Format of the output file
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// asciicast file, played with asciinema
+    Asciicast,
+    /// Animated SVG image
+    Svg,
+}
+
+impl Format {
+    /// Extension of output files in the format.
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Asciicast => "cast",
+            Self::Svg => "svg",
+        }
+    }
+
+    /// The format for `out_file`, based on its extension.
+    fn from_extension(out_file: &Path) -> Self {
+        if out_file
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"))
+        {
+            Self::Svg
+        } else {
+            Self::Asciicast
+        }
+    }
+}
+
+/// Path of the checksum file for `out_file`, i.e. "demo.cast.sha256".
+fn checksum_file(out_file: &Path) -> PathBuf {
+    let mut path = out_file.as_os_str().to_os_string();
+    path.push(".sha256");
+    PathBuf::from(path)
+}
+
+/// Terminal size, parsed from `WIDTHxHEIGHT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Size {
+    width: u16,
+    height: u16,
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| format!("`{s}` is not in the format WIDTHxHEIGHT"))?;
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse()
+                .map_err(|error| format!("invalid size `{s}`: {error}"))
+        };
+        Ok(Self {
+            width: parse(width)?,
+            height: parse(height)?,
+        })
+    }
+}
+
+impl Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl Size {
+    /// Adds the size to the file name of `out_file`, before the extension.
+    ///
+    /// A ".gz" extension is kept with the extension before it, i.e. "demo.cast.gz"
+    /// becomes "demo-80x24.cast.gz".
+    fn out_file(self, out_file: &Path) -> PathBuf {
+        let gz = is_gzip(out_file);
+        let name = if gz {
+            Path::new(out_file.file_stem().unwrap_or_default())
+        } else {
+            out_file
+        };
+        let mut file_name = name.file_stem().unwrap_or_default().to_os_string();
+        file_name.push(format!("-{self}"));
+        if let Some(extension) = name.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        if gz {
+            file_name.push(".gz");
+        }
+        out_file.with_file_name(file_name)
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct Completions {
+    /// Shell to print the completion script for
+    #[arg(value_enum)]
+    shell: clap_complete::Shell,
+}
+
+impl Completions {
+    fn run(self) -> color_eyre::Result<()> {
+        let mut command = Cli::command();
+        let name = command.get_name().to_owned();
+        // Generated into a buffer, as writing to stdout directly panics on errors.
+        let mut script = Vec::new();
+        clap_complete::generate(self.shell, &mut command, name, &mut script);
+        io::stdout()
+            .write_all(&script)
+            .wrap_err("could not write completion script")
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(group(ArgGroup::new("cut").required(true).multiple(true)))]
+struct Cut {
+    /// Start of the time range to cut, defaults to the start of the asciicast
+    #[arg(long, value_name = "TIME", group = "cut", value_parser = config::parse_duration)]
+    from: Option<Duration>,
+
+    /// End of the time range to cut, defaults to the end of the asciicast
+    #[arg(long, value_name = "TIME", group = "cut", value_parser = config::parse_duration)]
+    to: Option<Duration>,
+
+    /// Command to cut, numbered from 1 in the order they were typed, may be given multiple times
+    ///
+    /// A command's events start with the prompt it was typed after and end before the next prompt
+    #[arg(
+        long = "command",
+        value_name = "N",
+        group = "cut",
+        conflicts_with_all = ["from", "to"]
+    )]
+    commands: Vec<usize>,
+
+    /// Prompt which the commands were typed after, used to find them with `--command`
+    #[arg(long, default_value = "$ ")]
+    prompt: String,
+
+    /// Overwrite output file if it already exists
+    #[arg(long)]
+    overwrite: bool,
+
+    /// Asciicast file to cut, may be gzip compressed with the ".gz" extension
+    in_file: PathBuf,
+
+    /// Output asciicast file, or "-" to write to stdout
+    out_file: PathBuf,
+}
+
+impl Cut {
+    fn run(self) -> color_eyre::Result<()> {
+        let mut cast = read_cast(&self.in_file, is_gzip(&self.in_file))?;
+        if self.commands.is_empty() {
+            cut::cut_time(&mut cast, self.from.unwrap_or_default(), self.to)?;
+        } else {
+            cut::cut_commands(&mut cast, &self.prompt, &self.commands)?;
+        }
+        write_cast(&cast, &self.out_file, self.overwrite)
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct Diff {
+    /// Original asciicast file, may be gzip compressed with the ".gz" extension
+    old_file: PathBuf,
+
+    /// Changed asciicast file, may be gzip compressed with the ".gz" extension
+    new_file: PathBuf,
+}
+
+impl Diff {
+    fn run(self) -> color_eyre::Result<()> {
+        let old = read_cast(&self.old_file, is_gzip(&self.old_file))?;
+        let new = read_cast(&self.new_file, is_gzip(&self.new_file))?;
+        let diff = drift::Diff::new(&old, &new);
+        if diff.is_empty() {
+            println!("No differences found");
+        } else {
+            print!("{diff}");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct Export {
+    /// Format to export the script's commands to
+    #[arg(short, long, value_enum, default_value_t)]
+    format: export::Format,
+
+    /// Overwrite output file if it already exists
+    #[arg(long)]
+    overwrite: bool,
+
+    /// Input file to export the commands of
+    in_file: PathBuf,
+
+    /// Output file, defaults to stdout
+    out_file: Option<PathBuf>,
+}
+
+impl Export {
+    fn run(self) -> color_eyre::Result<()> {
+        let script = read_script(&self.in_file)?;
+
+        let writer: Box<dyn Write> = match &self.out_file {
+            Some(out_file) => Box::new(BufWriter::new(create_file(out_file, self.overwrite)?)),
+            None => Box::new(io::stdout().lock()),
+        };
+        script
+            .export(self.format, writer)
+            .wrap_err("could not write exported script")
+    }
+}
+
+/// Terminal size and output file of an imported recording.
+#[derive(Args, Debug, Clone)]
+struct Import {
+    /// Terminal width, default is the width recorded in the file, or 80
+    #[arg(long)]
+    width: Option<u16>,
+
+    /// Terminal height, default is the height recorded in the file, or 24
+    #[arg(long)]
+    height: Option<u16>,
+
+    /// Overwrite output file if it already exists
+    #[arg(long)]
+    overwrite: bool,
+
+    /// Output asciicast file, compressed with gzip if its extension is ".gz"
+    #[arg(short, long)]
+    out_file: PathBuf,
+}
+
+impl Import {
+    /// Writes the imported `events` to the output file, with the size from the options,
+    /// or the `recorded_size` if not given.
+    fn write(
+        self,
+        events: Vec<asciicast::Event>,
+        recorded_size: (Option<u16>, Option<u16>),
+    ) -> color_eyre::Result<()> {
+        let cast = asciicast::File {
+            version: asciicast::Version::V2,
+            header: asciicast::Header {
+                width: self.width.or(recorded_size.0).unwrap_or(80),
+                height: self.height.or(recorded_size.1).unwrap_or(24),
+                timestamp: None,
+                duration: events.last().map(|event| event.time),
+                idle_time_limit: None,
+                command: None,
+                title: None,
+                env: HashMap::new(),
+            },
+            events,
+        };
+        write_cast(&cast, &self.out_file, self.overwrite)
+    }
+}
 
+/// Writes the `cast` to `out_file`, compressed with gzip if its extension is ".gz",
+/// or to stdout if it is "-".
+fn write_cast(cast: &asciicast::File, out_file: &Path, overwrite: bool) -> color_eyre::Result<()> {
+    let mut out = create_output(out_file, overwrite)?;
+    let mut contents = Vec::new();
+    cast.write(&mut contents)
+        .and_then(|()| {
+            if is_gzip(out_file) {
+                contents = gzip(&contents)?;
+            }
+            out.write_all(&contents)
+        })
+        .and_then(|()| out.flush())
+        .wrap_err("could not write to output file")?;
+    if !is_stdout(out_file) {
+        eprintln!(" Wrote to file: {}", out_file.display());
+    }
     Ok(())
 }
 
-#[derive(Parser, Debug, Clone)]
-#[command(version, author, about)]
-struct Cli {
+#[derive(Args, Debug, Clone)]
+struct ImportTypescript {
+    #[command(flatten)]
+    import: Import,
+
+    /// Typescript file, the output recorded by `script`
+    typescript: PathBuf,
+
+    /// Timing file, recorded by `script --timing` or `script --log-timing`
+    timing: PathBuf,
+}
+
+impl ImportTypescript {
+    fn run(self) -> color_eyre::Result<()> {
+        let typescript = fs::read(&self.typescript).wrap_err("could not read typescript")?;
+        let timing = fs::read_to_string(&self.timing).wrap_err("could not read timing file")?;
+        let recording =
+            typescript::read(&typescript, &timing).wrap_err("could not import typescript")?;
+        self.import.write(recording.events, recording.size)
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct ImportTtyrec {
+    #[command(flatten)]
+    import: Import,
+
+    /// ttyrec file
+    ttyrec: PathBuf,
+}
+
+impl ImportTtyrec {
+    fn run(self) -> color_eyre::Result<()> {
+        let ttyrec = fs::read(&self.ttyrec).wrap_err("could not read ttyrec file")?;
+        let events = ttyrec::read(&ttyrec).wrap_err("could not import ttyrec")?;
+        self.import.write(events, (None, None))
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct Init {
+    /// Kind of demo to create the example for
+    #[arg(short, long, value_enum, default_value_t)]
+    template: init::Template,
+
+    /// Overwrite output file if it already exists
+    #[arg(long)]
+    overwrite: bool,
+
+    /// Output input file
+    #[arg(default_value = "demo.yaml")]
+    out_file: PathBuf,
+}
+
+impl Init {
+    fn run(self) -> color_eyre::Result<()> {
+        create_file(&self.out_file, self.overwrite)?
+            .write_all(self.template.script().as_bytes())
+            .wrap_err("could not write to output file")?;
+        println!(" Wrote to file: {}", self.out_file.display());
+        println!(
+            "\nTo create the asciicast with make or just, add to a\n{}",
+            init::build_snippets(&self.out_file)
+        );
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct Lint {
+    /// Lint configuration file, defaults to the first "autocast-lint.yaml"
+    /// in the base directory or its parents
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Directory to resolve the input file's includes against and to search for
+    /// the lint configuration from, defaults to the input file's directory
+    #[arg(long, value_name = "DIR")]
+    base_dir: Option<PathBuf>,
+
+    /// Input file to check
+    in_file: PathBuf,
+}
+
+impl Lint {
+    fn run(self) -> color_eyre::Result<()> {
+        let base_dir = base_dir(&self.in_file, self.base_dir.as_deref());
+        let script = read_script_in(&self.in_file, base_dir)?;
+        let config = match &self.config {
+            Some(path) => lint::Config::read(path)?,
+            None => lint::Config::find(base_dir)?,
+        };
+        check_lints(&script, &config)?;
+        println!("Checked file: {}", self.in_file.display());
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct Validate {
+    #[command(flatten)]
+    settings: Settings,
+
+    /// Directory to resolve relative paths in the input file against,
+    /// defaults to the input file's directory
+    #[arg(long, value_name = "DIR")]
+    base_dir: Option<PathBuf>,
+
+    /// Input file to check
+    in_file: PathBuf,
+}
+
+impl Validate {
+    fn run(self) -> color_eyre::Result<()> {
+        let base_dir = base_dir(&self.in_file, self.base_dir.as_deref());
+        let mut script = read_script_in(&self.in_file, base_dir)?;
+        script.resolve_paths(base_dir);
+        script.merge_global_settings()?;
+        script.merge_settings(self.settings);
+        let problems = script.validate();
+        for problem in &problems {
+            eprintln!("error: {problem}");
+        }
+        eyre::ensure!(
+            problems.is_empty(),
+            "found {} problem(s) in `{}`",
+            problems.len(),
+            self.in_file.display()
+        );
+        println!("Validated file: {}", self.in_file.display());
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct Present {
     #[command(flatten)]
     settings: Settings,
 
@@ -55,20 +1225,332 @@ struct Cli {
     #[arg(long)]
     overwrite: bool,
 
-    /// Input file to create the asciicast file with
+    /// Directory to resolve relative paths in the input file against,
+    /// defaults to the input file's directory
+    #[arg(long, value_name = "DIR")]
+    base_dir: Option<PathBuf>,
+
+    /// Input file to present
     in_file: PathBuf,
 
-    /// Output asciicast file
+    /// Output asciicast file, defaults to the input file with the ".cast" extension
+    out_file: Option<PathBuf>,
+}
+
+impl Present {
+    fn run(self) -> color_eyre::Result<()> {
+        let out_file = self
+            .out_file
+            .unwrap_or_else(|| self.in_file.with_extension("cast"));
+        let base_dir = base_dir(&self.in_file, self.base_dir.as_deref());
+        let mut script = read_script_in(&self.in_file, base_dir)?;
+        script.resolve_paths(base_dir);
+        script.merge_global_settings()?;
+        script.merge_settings(self.settings);
+        // Created first so the presentation isn't lost if it can't be.
+        let mut out = create_output(&out_file, self.overwrite)?;
+
+        let cast = script.present().wrap_err("error presenting script")?;
+        cast.write(&mut out)
+            .and_then(|()| out.flush())
+            .wrap_err("could not write to output file")?;
+        if !is_stdout(&out_file) {
+            eprintln!("\n Wrote to file: {}", out_file.display());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct Speed {
+    /// How much faster to play the asciicast, i.e. "2x", or "0.5x" to slow it down
+    #[arg(value_parser = speed::parse_factor)]
+    factor: f64,
+
+    /// Only change the pauses longer than `--idle-threshold`, keeping the pace of typing and output
+    ///
+    /// Pauses are not shortened to less than the threshold
+    #[arg(long)]
+    idle_only: bool,
+
+    /// Pauses longer than this are changed with `--idle-only`
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "500ms",
+        value_parser = config::parse_duration
+    )]
+    idle_threshold: Duration,
+
+    /// Overwrite output file if it already exists
+    #[arg(long)]
+    overwrite: bool,
+
+    /// Asciicast file to change, may be gzip compressed with the ".gz" extension
+    in_file: PathBuf,
+
+    /// Output asciicast file, or "-" to write to stdout
     out_file: PathBuf,
 }
 
+impl Speed {
+    fn run(self) -> color_eyre::Result<()> {
+        let mut cast = read_cast(&self.in_file, is_gzip(&self.in_file))?;
+        let idle_threshold = self.idle_only.then_some(self.idle_threshold);
+        speed::change(&mut cast, self.factor, idle_threshold);
+        write_cast(&cast, &self.out_file, self.overwrite)
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct Upload {
+    /// asciinema server to upload to, defaults to `ASCIINEMA_API_URL` or asciinema.org
+    #[arg(long, value_name = "URL")]
+    server: Option<String>,
+
+    /// Asciicast file to upload
+    cast: PathBuf,
+}
+
+impl Upload {
+    fn run(self) -> color_eyre::Result<()> {
+        let url = upload::upload(&self.cast, self.server.as_deref())?;
+        println!("{url}");
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct Verify {
+    /// Spec file with the durations and output the asciicast file is expected to have
+    #[arg(long, value_name = "PATH")]
+    spec: PathBuf,
+
+    /// Asciicast file to check
+    cast: PathBuf,
+}
+
+impl Verify {
+    fn run(self) -> color_eyre::Result<()> {
+        let spec = verify::Spec::read(&self.spec)?;
+        let cast = read_cast(&self.cast, is_gzip(&self.cast))?;
+        verify::assert_cast_matches(&cast, &spec)?;
+        println!("Verified file: {}", self.cast.display());
+        Ok(())
+    }
+}
+
+/// Prints the script's lint findings, returning an error if any of them are errors.
+fn check_lints(script: &Script, config: &lint::Config) -> color_eyre::Result<()> {
+    let findings = script.lint(config);
+    for finding in &findings {
+        eprintln!("{finding}");
+    }
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.level == lint::Level::Error)
+        .count();
+    eyre::ensure!(errors == 0, "found {errors} lint error(s)");
+    Ok(())
+}
+
+/// Prints whether each control code had its expected effect in the script's shell,
+/// see [`Script::doctor()`].
+fn check_control_codes(script: &Script) -> color_eyre::Result<()> {
+    let outcomes = script.doctor().wrap_err("could not probe control codes")?;
+    for outcome in &outcomes {
+        eprintln!("{outcome}");
+    }
+    let failed = outcomes.iter().filter(|outcome| !outcome.passed()).count();
+    eyre::ensure!(
+        failed == 0,
+        "{failed} control code(s) did not have their expected effect"
+    );
+    Ok(())
+}
+
+/// Format of an input script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScriptFormat {
+    Yaml,
+    Toml,
+    Json,
+    Markdown,
+}
+
+impl ScriptFormat {
+    /// The format for `in_file`, based on its extension, defaulting to YAML.
+    fn from_extension(in_file: &Path) -> Self {
+        let extension = in_file.extension().unwrap_or_default();
+        if extension.eq_ignore_ascii_case("toml") {
+            Self::Toml
+        } else if extension.eq_ignore_ascii_case("json") {
+            Self::Json
+        } else if extension.eq_ignore_ascii_case("md") || extension.eq_ignore_ascii_case("markdown")
+        {
+            Self::Markdown
+        } else {
+            Self::Yaml
+        }
+    }
+}
+
+/// The directory to resolve the relative paths in the input file against,
+/// `base_dir` if given or the input file's directory.
+fn base_dir<'a>(in_file: &'a Path, base_dir: Option<&'a Path>) -> &'a Path {
+    base_dir.unwrap_or_else(|| in_file.parent().unwrap_or(Path::new("")))
+}
+
+/// Reads the script at `path`, in the format of its extension, with its includes resolved
+/// and the environment variables in its commands expanded.
+fn read_script(path: &Path) -> color_eyre::Result<Script> {
+    read_script_in(path, path.parent().unwrap_or(Path::new("")))
+}
+
+/// Reads the script at `path` like [`read_script()`], with its includes relative to `base_dir`.
+fn read_script_in(path: &Path, base_dir: &Path) -> color_eyre::Result<Script> {
+    let mut script = parse_script(path)?;
+    script.resolve_includes(path, base_dir, &parse_script)?;
+    script.expand_env()?;
+    Ok(script)
+}
+
+/// Parses the script at `path`, in the format of its extension.
+fn parse_script(path: &Path) -> color_eyre::Result<Script> {
+    let in_file = fs::read_to_string(path).wrap_err("could not read input file")?;
+    match ScriptFormat::from_extension(path) {
+        ScriptFormat::Yaml => {}
+        ScriptFormat::Toml => {
+            return Script::try_from_toml(&in_file)
+                .wrap_err("could not parse input file as Script");
+        }
+        ScriptFormat::Json => {
+            return Script::try_from_json(in_file.as_bytes())
+                .wrap_err("could not parse input file as Script");
+        }
+        ScriptFormat::Markdown => {
+            return Script::try_from_markdown(&in_file)
+                .wrap_err("could not create Script from Markdown file");
+        }
+    }
+    Script::parse_strict(&in_file).map_err(|errors| {
+        let name = path.display().to_string();
+        let snippets = errors
+            .0
+            .iter()
+            .filter_map(|error| error.snippet(&in_file, &name))
+            .collect::<Vec<_>>();
+        snippets.into_iter().fold(
+            eyre::Report::new(errors).wrap_err("could not parse input file as Script"),
+            Section::section,
+        )
+    })
+}
+
+/// Reads the asciicast file at `path`, decompressing it with gzip if `compressed`.
+fn read_cast(path: &Path, compressed: bool) -> color_eyre::Result<asciicast::File> {
+    let file =
+        fs::File::open(path).wrap_err_with(|| format!("could not open `{}`", path.display()))?;
+    let reader: Box<dyn io::Read> = if compressed {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    asciicast::File::read(io::BufReader::new(reader))
+        .wrap_err_with(|| format!("could not parse `{}` as an asciicast file", path.display()))
+}
+
+/// Whether `path` has the ".gz" extension, i.e. "demo.cast.gz".
+fn is_gzip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+fn gzip(contents: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(contents)?;
+    encoder.finish()
+}
+
+/// Whether `path` is "-", meaning stdout.
+fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Creates the output file, or locks stdout if `path` is "-".
+fn create_output(path: &Path, overwrite: bool) -> color_eyre::Result<Box<dyn Write>> {
+    if is_stdout(path) {
+        Ok(Box::new(io::stdout().lock()))
+    } else {
+        create_file(path, overwrite).map(|file| Box::new(file) as Box<dyn Write>)
+    }
+}
+
+/// Returns an error, like [`create_file()`], if `path` already exists and not `overwrite`.
+fn ensure_new_file(path: &Path, overwrite: bool) -> color_eyre::Result<()> {
+    if !overwrite && path.try_exists()? {
+        Err(io::Error::from(io::ErrorKind::AlreadyExists))
+            .wrap_err("could not create/open output file")
+            .suggestion("use `--overwrite` if you wish to replace an existing file")
+    } else {
+        Ok(())
+    }
+}
+
+fn create_file(path: &Path, overwrite: bool) -> color_eyre::Result<fs::File> {
+    fs::File::options()
+        .write(true)
+        .create_new(!overwrite)
+        .create(overwrite)
+        .truncate(true)
+        .open(path)
+        .wrap_err("could not create/open output file")
+        .suggestion("use `--overwrite` if you wish to replace an existing file")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn verify_cli() {
-        use clap::CommandFactory;
         Cli::command().debug_assert();
     }
+
+    #[test]
+    fn size() {
+        let size: Size = "80x24".parse().unwrap();
+        assert_eq!(
+            size,
+            Size {
+                width: 80,
+                height: 24
+            }
+        );
+        assert!("80".parse::<Size>().is_err());
+        assert!("80xtall".parse::<Size>().is_err());
+
+        assert_eq!(
+            size.out_file(Path::new("out/demo.cast")),
+            Path::new("out/demo-80x24.cast")
+        );
+        assert_eq!(size.out_file(Path::new("demo")), Path::new("demo-80x24"));
+        assert_eq!(
+            size.out_file(Path::new("demo.cast.gz")),
+            Path::new("demo-80x24.cast.gz")
+        );
+    }
+
+    #[test]
+    fn select() {
+        let cast = |events: &str| {
+            let cast = format!("{{\"version\": 2, \"width\": 80, \"height\": 24}}\n{events}");
+            asciicast::File::read(cast.as_bytes()).unwrap()
+        };
+        let fast = cast("[1.0, \"o\", \"a long line of output\"]\n");
+        let small = cast("[2.0, \"o\", \"a\"]\n");
+        assert!(Select::Fastest.score(&fast) < Select::Fastest.score(&small));
+        assert!(Select::Smallest.score(&small) < Select::Smallest.score(&fast));
+        assert_eq!(Select::Fastest.describe(&small), "2.00s long");
+    }
 }
@@ -0,0 +1,179 @@
+//! Generating a man page, for distro packagers, from the CLI's help and a description of
+//! the input file format.
+
+use std::io::{self, Write};
+
+use clap_mangen::{
+    roff::{bold, italic, roman, Inline, Roff},
+    Man,
+};
+
+/// Instructions and what they do, for the INSTRUCTIONS section
+const INSTRUCTIONS: &[(&str, &str)] = &[
+    (
+        "!Command",
+        "Type a command and run it, recording its output until the prompt is shown again. \
+            A map with the \"command\", a string, list of lines, or control code like \"^C\", \
+            and optionally \"hidden\", \"type_speed\", \"confirm\", \"highlight_stderr\", \
+            \"output_filter\", and \"allow_dangerous\". \
+            \"highlight_stderr\" pipes the command's stderr through the shell to style it, \
+            so it is no longer a terminal and may be reordered relative to stdout.",
+    ),
+    (
+        "!Interactive",
+        "Type a command and send it \"keys\", for programs which wait for input like editors. \
+            Keys are chars, control codes like \"^D\", or durations to wait for.",
+    ),
+    (
+        "!Capture",
+        "Run a hidden command and store its trimmed output in the variable \"var\", \
+            which later instructions reference as \"${var}\".",
+    ),
+    (
+        "!If",
+        "Run the \"then\" instructions if each variable in \"var_matches\" matches its regex, \
+            otherwise the \"else\" instructions.",
+    ),
+    ("!Wait", "Wait for a duration."),
+    (
+        "!Settle",
+        "Record output until there has been none for a duration, i.e. from background jobs.",
+    ),
+    ("!Marker", "Add a marker, or chapter, with a label."),
+    (
+        "!Title",
+        "Set the terminal's title, which renderers with window chrome can show as a caption.",
+    ),
+    (
+        "!Resize",
+        "Resize the terminal to the \"width\" and \"height\", i.e. to emulate zooming into a pane.",
+    ),
+    ("!Clear", "Clear the terminal."),
+    (
+        "!Include",
+        "Run the instructions of another script, relative to the including script's directory.",
+    ),
+];
+
+/// Writes the man page for the `command` to `writer`.
+pub fn write(command: clap::Command, mut writer: impl Write) -> io::Result<()> {
+    let has_subcommands = command.has_subcommands();
+    let has_version = command.get_version().is_some();
+    let has_author = command.get_author().is_some();
+    let man = Man::new(command);
+    man.render_title(&mut writer)?;
+    man.render_name_section(&mut writer)?;
+    man.render_synopsis_section(&mut writer)?;
+    man.render_description_section(&mut writer)?;
+    man.render_options_section(&mut writer)?;
+    if has_subcommands {
+        man.render_subcommands_section(&mut writer)?;
+    }
+    format_sections().to_writer(&mut writer)?;
+    if has_version {
+        man.render_version_section(&mut writer)?;
+    }
+    if has_author {
+        man.render_authors_section(&mut writer)?;
+    }
+    Ok(())
+}
+
+/// The INPUT FILE, INSTRUCTIONS, and DURATIONS sections.
+fn format_sections() -> Roff {
+    let mut roff = Roff::new();
+    let paragraph = |roff: &mut Roff, inlines: Vec<Inline>| {
+        roff.control("PP", []).text(inlines);
+    };
+
+    roff.control("SH", ["INPUT FILE"]);
+    paragraph(
+        &mut roff,
+        vec![
+            roman(
+                "The input file is YAML, or TOML, JSON, or Markdown if its extension is \
+                .toml, .json, or .md. It is a map with optional ",
+            ),
+            bold("settings"),
+            roman(" and a list of "),
+            bold("instructions"),
+            roman("."),
+        ],
+    );
+    paragraph(
+        &mut roff,
+        vec![
+            roman(
+                "Each setting is named after its option, without the leading dashes and with \
+                underscores, i.e. ",
+            ),
+            bold("type_speed"),
+            roman(" for "),
+            bold("--type-speed"),
+            roman(". Options given on the command line override the input file's settings."),
+        ],
+    );
+    paragraph(
+        &mut roff,
+        vec![roman(
+            "In Markdown files, code blocks tagged \"autocast\" contain YAML settings or \
+                instructions, and code blocks tagged \"console\" contain commands, \
+                the lines starting with \"$ \".",
+        )],
+    );
+
+    roff.control("SH", ["INSTRUCTIONS"]);
+    paragraph(
+        &mut roff,
+        vec![roman(
+            "Instructions are run in order. In YAML, each is tagged with its kind:",
+        )],
+    );
+    for (tag, description) in INSTRUCTIONS {
+        roff.control("TP", [])
+            .text([bold(*tag)])
+            .text([roman(*description)]);
+    }
+
+    roff.control("SH", ["DURATIONS"]);
+    paragraph(
+        &mut roff,
+        vec![
+            roman("Durations are an integer followed by a unit, "),
+            italic("s"),
+            roman(", "),
+            italic("ms"),
+            roman(", or "),
+            italic("us"),
+            roman(", without whitespace, i.e. \"1s\", \"150ms\", or \"900us\"."),
+        ],
+    );
+    roff
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    #[test]
+    fn write() {
+        let mut page = Vec::new();
+        super::write(crate::Cli::command(), &mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.contains("\n.TH autocast 1 "));
+        for section in [
+            "NAME",
+            "OPTIONS",
+            "SUBCOMMANDS",
+            "\"INPUT FILE\"",
+            "INSTRUCTIONS",
+            "DURATIONS",
+        ] {
+            assert!(
+                page.contains(&format!("\n.SH {section}\n")),
+                "no {section} section"
+            );
+        }
+        assert!(page.contains("\\-\\-type\\-speed"));
+    }
+}
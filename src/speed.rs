@@ -0,0 +1,97 @@
+//! Changing the speed of an existing asciicast, i.e. to tighten a demo without rerunning it.
+
+use std::time::Duration;
+
+use crate::asciicast::{self, EVENTS_HASH_ENV};
+
+/// Parses how much faster to play an asciicast, i.e. "2x", "1.5", or "0.5x" to slow it down.
+pub fn parse_factor(s: &str) -> Result<f64, String> {
+    let factor: f64 = s
+        .strip_suffix(['x', 'X'])
+        .unwrap_or(s)
+        .trim()
+        .parse()
+        .map_err(|_| format!("`{s}` is not a speed, i.e. \"2x\""))?;
+    if factor.is_finite() && factor > 0.0 {
+        Ok(factor)
+    } else {
+        Err(format!("speed must be a positive number, got {factor}"))
+    }
+}
+
+/// Divides the time between the `cast`'s events by the `factor`.
+///
+/// If `idle_threshold` is given, only the pauses longer than it are changed, so typing and
+/// output keep their pace, and pauses are not shortened to less than the threshold.
+pub fn change(cast: &mut asciicast::File, factor: f64, idle_threshold: Option<Duration>) {
+    let mut previous = Duration::ZERO;
+    let mut time = Duration::ZERO;
+    for event in &mut cast.events {
+        let gap = event.time.saturating_sub(previous);
+        previous = event.time;
+        time += match idle_threshold {
+            None => gap.div_f64(factor),
+            Some(threshold) if gap > threshold => gap.div_f64(factor).max(threshold),
+            Some(_) => gap,
+        };
+        event.time = time;
+    }
+
+    if cast.header.duration.is_some() {
+        cast.header.duration = cast.events.last().map(|event| event.time);
+    }
+    // Like when appending, the events hash no longer matches.
+    cast.header.env.remove(EVENTS_HASH_ENV);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_factor() {
+        assert_eq!(super::parse_factor("2x"), Ok(2.0));
+        assert_eq!(super::parse_factor("0.5"), Ok(0.5));
+        assert!(super::parse_factor("0x").is_err());
+        assert!(super::parse_factor("fast").is_err());
+    }
+
+    #[test]
+    fn change() {
+        let cast = || asciicast::File {
+            version: asciicast::Version::V2,
+            header: asciicast::Header {
+                width: 80,
+                height: 24,
+                timestamp: None,
+                duration: Some(Duration::from_millis(3400)),
+                idle_time_limit: None,
+                command: None,
+                title: None,
+                env: [(String::from(EVENTS_HASH_ENV), String::from("hash"))].into(),
+            },
+            events: [0, 100, 200, 3200, 3400]
+                .into_iter()
+                .map(|millis| {
+                    asciicast::Event::output(Duration::from_millis(millis), String::new())
+                })
+                .collect(),
+        };
+        let times = |cast: &asciicast::File| {
+            cast.events
+                .iter()
+                .map(|event| event.time.as_millis())
+                .collect::<Vec<_>>()
+        };
+
+        let mut changed = cast();
+        super::change(&mut changed, 2.0, None);
+        assert_eq!(times(&changed), [0, 50, 100, 1600, 1700]);
+        assert_eq!(changed.header.duration, Some(Duration::from_millis(1700)));
+        assert!(changed.header.env.is_empty());
+
+        let mut changed = cast();
+        super::change(&mut changed, 4.0, Some(Duration::from_secs(1)));
+        assert_eq!(times(&changed), [0, 100, 200, 1200, 1400]);
+    }
+}
@@ -0,0 +1,297 @@
+//! Rendering an asciicast as an animated SVG, which can be embedded in a README.
+//!
+//! Each distinct screen is rendered once, side by side, and a CSS animation moves
+//! them through the view at the time of the event which produced them.
+
+pub mod term;
+
+use std::{
+    fmt::{self, Write as _},
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::asciicast::{self, EventType};
+
+use self::term::{Cell, Color, Lines, Screen, Style};
+
+const FONT_SIZE: usize = 15;
+/// Width of a cell, most monospace fonts are 0.6 of the font size wide
+const CELL_WIDTH: usize = 9;
+const CELL_HEIGHT: usize = 18;
+const PADDING: usize = 10;
+const FOREGROUND: &str = "#cccccc";
+const BACKGROUND: &str = "#121314";
+/// Time the last screen is shown before the animation starts again
+const LOOP_DELAY: Duration = Duration::from_secs(3);
+
+/// Colors of the first 16 indexed colors
+const PALETTE: [&str; 16] = [
+    "#000000", "#dd3c69", "#4ebf22", "#ddaf3c", "#26b0d7", "#b954e1", "#54e1b9", "#d9d9d9",
+    "#4d4d4d", "#dd3c69", "#4ebf22", "#ddaf3c", "#26b0d7", "#b954e1", "#54e1b9", "#ffffff",
+];
+
+/// A screen and when it is first shown
+#[derive(Debug)]
+struct Frame {
+    time: Duration,
+    lines: Lines,
+}
+
+/// Writes the asciicast's output as an animated SVG to `writer`.
+pub fn write(cast: &asciicast::File, mut writer: impl Write) -> io::Result<()> {
+    let frames = frames(cast);
+    let mut svg = String::new();
+    render(&mut svg, cast.header.width, cast.header.height, &frames)
+        .expect("writing to a string can't fail");
+    writer.write_all(svg.as_bytes())?;
+    writer.flush()
+}
+
+/// Writes each output event to a [`Screen`], creating a frame when it changes.
+///
+/// Events at the same time are combined into one frame.
+fn frames(cast: &asciicast::File) -> Vec<Frame> {
+    let mut screen = Screen::new(cast.header.width, cast.header.height);
+    let mut frames = vec![Frame {
+        time: Duration::ZERO,
+        lines: screen.lines().clone(),
+    }];
+    let mut output = cast
+        .events
+        .iter()
+        .filter(|event| event.event_type == EventType::Output)
+        .peekable();
+    while let Some(event) = output.next() {
+        screen.write(&event.data);
+        if output.peek().is_some_and(|next| next.time == event.time) {
+            continue;
+        }
+        let last = frames.last_mut().expect("there is always a first frame");
+        if last.lines == *screen.lines() {
+            continue;
+        }
+        if last.time == event.time {
+            last.lines.clone_from(screen.lines());
+        } else {
+            frames.push(Frame {
+                time: event.time,
+                lines: screen.lines().clone(),
+            });
+        }
+    }
+    frames
+}
+
+fn render(svg: &mut String, width: u16, height: u16, frames: &[Frame]) -> fmt::Result {
+    let screen_width = usize::from(width) * CELL_WIDTH;
+    let screen_height = usize::from(height) * CELL_HEIGHT;
+    let total_width = screen_width + 2 * PADDING;
+    let total_height = screen_height + 2 * PADDING;
+    let duration = frames.last().map_or(Duration::ZERO, |frame| frame.time) + LOOP_DELAY;
+
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{total_width}" height="{total_height}" viewBox="0 0 {total_width} {total_height}" font-family="Menlo, Monaco, Consolas, 'Courier New', monospace" font-size="{FONT_SIZE}">"#
+    )?;
+    writeln!(svg, "<style>")?;
+    writeln!(
+        svg,
+        "#frames {{ animation: frames {:.3}s steps(1, end) infinite; }}",
+        duration.as_secs_f64()
+    )?;
+    writeln!(svg, "@keyframes frames {{")?;
+    for (index, frame) in frames.iter().enumerate() {
+        let percent = frame.time.as_secs_f64() / duration.as_secs_f64() * 100.0;
+        writeln!(
+            svg,
+            "  {percent:.3}% {{ transform: translateX(-{}px); }}",
+            index * screen_width
+        )?;
+    }
+    writeln!(svg, "}}")?;
+    writeln!(svg, "text {{ white-space: pre; }}")?;
+    writeln!(svg, "</style>")?;
+    writeln!(
+        svg,
+        r#"<rect width="{total_width}" height="{total_height}" rx="5" fill="{BACKGROUND}"/>"#
+    )?;
+    writeln!(
+        svg,
+        r#"<svg x="{PADDING}" y="{PADDING}" width="{screen_width}" height="{screen_height}">"#
+    )?;
+    writeln!(svg, r#"<g id="frames">"#)?;
+    for (index, frame) in frames.iter().enumerate() {
+        writeln!(
+            svg,
+            r#"<g transform="translate({} 0)">"#,
+            index * screen_width
+        )?;
+        for (row, line) in frame.lines.iter().enumerate() {
+            render_line(svg, row, line)?;
+        }
+        writeln!(svg, "</g>")?;
+    }
+    writeln!(svg, "</g>\n</svg>\n</svg>")
+}
+
+/// Renders the backgrounds and text of a line, in runs of cells with the same style.
+fn render_line(svg: &mut String, row: usize, line: &[Cell]) -> fmt::Result {
+    let y = row * CELL_HEIGHT;
+    let runs = runs(line);
+
+    for &(start, end, style) in &runs {
+        if let Some(background) = style_colors(style).1 {
+            writeln!(
+                svg,
+                r#"<rect x="{}" y="{y}" width="{}" height="{CELL_HEIGHT}" fill="{background}"/>"#,
+                start * CELL_WIDTH,
+                (end - start) * CELL_WIDTH,
+            )?;
+        }
+    }
+
+    let mut text = String::new();
+    for &(start, end, style) in &runs {
+        let chars: String = line[start..end].iter().map(|cell| cell.char).collect();
+        if chars.trim().is_empty() {
+            continue;
+        }
+        write!(text, r#"<tspan x="{}""#, start * CELL_WIDTH)?;
+        let (foreground, _) = style_colors(style);
+        if let Some(foreground) = foreground {
+            write!(text, r#" fill="{foreground}""#)?;
+        }
+        if style.bold {
+            text.push_str(r#" font-weight="bold""#);
+        }
+        if style.faint {
+            text.push_str(r#" opacity="0.5""#);
+        }
+        if style.italic {
+            text.push_str(r#" font-style="italic""#);
+        }
+        if style.underline {
+            text.push_str(r#" text-decoration="underline""#);
+        }
+        write!(text, ">{}</tspan>", escape(chars.trim_end()))?;
+    }
+    if !text.is_empty() {
+        // The baseline is near the bottom of the cell.
+        writeln!(
+            svg,
+            r#"<text y="{}" fill="{FOREGROUND}">{text}</text>"#,
+            y + CELL_HEIGHT * 4 / 5
+        )?;
+    }
+    Ok(())
+}
+
+/// Splits the line into runs of cells with the same style, as (start, end, style).
+fn runs(line: &[Cell]) -> Vec<(usize, usize, Style)> {
+    let mut runs: Vec<(usize, usize, Style)> = Vec::new();
+    for (col, cell) in line.iter().enumerate() {
+        match runs.last_mut() {
+            Some((_, end, style)) if *style == cell.style => *end = col + 1,
+            _ => runs.push((col, col + 1, cell.style)),
+        }
+    }
+    runs
+}
+
+/// Foreground and background colors of the style, if they are not the defaults.
+fn style_colors(style: Style) -> (Option<String>, Option<String>) {
+    let foreground = color(style.foreground);
+    let background = color(style.background);
+    if style.inverse {
+        (
+            Some(background.unwrap_or_else(|| String::from(BACKGROUND))),
+            Some(foreground.unwrap_or_else(|| String::from(FOREGROUND))),
+        )
+    } else {
+        (foreground, background)
+    }
+}
+
+fn color(color: Color) -> Option<String> {
+    match color {
+        Color::Default => None,
+        Color::Indexed(index @ 0..=15) => Some(String::from(PALETTE[usize::from(index)])),
+        // 6x6x6 color cube
+        Color::Indexed(index @ 16..=231) => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            Some(rgb(
+                level(index / 36),
+                level(index / 6 % 6),
+                level(index % 6),
+            ))
+        }
+        // Grayscale ramp
+        Color::Indexed(index) => {
+            let gray = 8 + (index - 232) * 10;
+            Some(rgb(gray, gray, gray))
+        }
+        Color::Rgb(red, green, blue) => Some(rgb(red, green, blue)),
+    }
+}
+
+fn rgb(red: u8, green: u8, blue: u8) -> String {
+    format!("#{red:02x}{green:02x}{blue:02x}")
+}
+
+/// Escapes the `text` for XML or HTML content.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use crate::asciicast::{Event, Header, Version};
+
+    use super::*;
+
+    #[test]
+    fn write() -> io::Result<()> {
+        let output = |millis, data: &str| Event::output(Duration::from_millis(millis), data.into());
+        let cast = asciicast::File {
+            version: Version::V2,
+            header: Header {
+                width: 10,
+                height: 2,
+                timestamp: None,
+                duration: None,
+                idle_time_limit: None,
+                command: None,
+                title: None,
+                env: HashMap::new(),
+            },
+            events: vec![
+                output(0, "$ "),
+                output(1000, "l"),
+                output(1000, "s"),
+                output(2000, "\r\n\x1b[31ma<b\x1b[0m\r\n"),
+                output(3000, ""),
+                Event::exit(Duration::from_secs(3), 0),
+            ],
+        };
+
+        let frames = frames(&cast);
+        assert_eq!(
+            frames.iter().map(|frame| frame.time).collect::<Vec<_>>(),
+            [0, 1000, 2000].map(Duration::from_millis)
+        );
+
+        let mut svg = Vec::new();
+        super::write(&cast, &mut svg)?;
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.contains("animation: frames 5.000s"));
+        assert!(svg.contains("  20.000% { transform: translateX(-90px); }"));
+        assert!(svg.contains(r##"<tspan x="0" fill="#dd3c69">a&lt;b</tspan>"##));
+        Ok(())
+    }
+}
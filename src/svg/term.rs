@@ -0,0 +1,411 @@
+//! A minimal terminal emulator, keeping track of what is on the screen
+//! as the output of an asciicast is written to it.
+
+use std::mem;
+
+/// Color of text or its background
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// The terminal's default foreground or background color
+    #[default]
+    Default,
+    /// One of the 256 indexed colors
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub foreground: Color,
+    pub background: Color,
+    pub bold: bool,
+    pub faint: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub char: char,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            char: ' ',
+            style: Style::default(),
+        }
+    }
+}
+
+/// Rows of cells on the screen
+pub type Lines = Vec<Vec<Cell>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    /// Parameters and intermediate bytes of a control sequence
+    Csi(String),
+    /// Operating system command, ended by BEL or ST
+    Osc {
+        escape: bool,
+    },
+    /// Designating a character set, the next char is ignored
+    Charset,
+}
+
+#[derive(Debug, Clone)]
+pub struct Screen {
+    width: usize,
+    height: usize,
+    lines: Lines,
+    /// Lines of the main screen while the alternate screen is shown
+    main_lines: Option<Lines>,
+    row: usize,
+    /// Can be equal to `width` after writing to the last column,
+    /// the cursor wraps when the next char is written
+    col: usize,
+    saved_cursor: (usize, usize),
+    style: Style,
+    state: State,
+}
+
+impl Screen {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self::with_size(usize::from(width).max(1), usize::from(height).max(1))
+    }
+
+    fn with_size(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            lines: vec![vec![Cell::default(); width]; height],
+            main_lines: None,
+            row: 0,
+            col: 0,
+            saved_cursor: (0, 0),
+            style: Style::default(),
+            state: State::Ground,
+        }
+    }
+
+    pub fn lines(&self) -> &Lines {
+        &self.lines
+    }
+
+    /// Writes terminal output to the screen, interpreting control characters
+    /// and escape sequences.
+    pub fn write(&mut self, data: &str) {
+        for char in data.chars() {
+            self.write_char(char);
+        }
+    }
+
+    fn write_char(&mut self, char: char) {
+        match mem::replace(&mut self.state, State::Ground) {
+            State::Ground => self.ground(char),
+            State::Escape => self.escape(char),
+            State::Csi(mut params) => {
+                if ('\x40'..='\x7e').contains(&char) {
+                    self.csi(&params, char);
+                } else {
+                    params.push(char);
+                    self.state = State::Csi(params);
+                }
+            }
+            State::Osc { escape } => {
+                if char != '\x07' && !(escape && char == '\\') {
+                    self.state = State::Osc {
+                        escape: char == '\x1b',
+                    };
+                }
+            }
+            State::Charset => {}
+        }
+    }
+
+    fn ground(&mut self, char: char) {
+        match char {
+            '\x1b' => self.state = State::Escape,
+            '\r' => self.col = 0,
+            '\n' | '\x0b' | '\x0c' => self.line_feed(),
+            '\x08' => self.col = self.col.min(self.width - 1).saturating_sub(1),
+            '\t' => self.col = ((self.col / 8 + 1) * 8).min(self.width - 1),
+            char if char.is_control() => {}
+            char => {
+                if self.col >= self.width {
+                    self.col = 0;
+                    self.line_feed();
+                }
+                self.lines[self.row][self.col] = Cell {
+                    char,
+                    style: self.style,
+                };
+                self.col += 1;
+            }
+        }
+    }
+
+    fn escape(&mut self, char: char) {
+        match char {
+            '[' => self.state = State::Csi(String::new()),
+            ']' => self.state = State::Osc { escape: false },
+            '(' | ')' | '*' | '+' => self.state = State::Charset,
+            '7' => self.saved_cursor = (self.row, self.col),
+            '8' => (self.row, self.col) = self.saved_cursor,
+            'D' => self.line_feed(),
+            'E' => {
+                self.col = 0;
+                self.line_feed();
+            }
+            'M' => {
+                if self.row == 0 {
+                    self.scroll_down(0, 1);
+                } else {
+                    self.row -= 1;
+                }
+            }
+            'c' => *self = Self::with_size(self.width, self.height),
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, params: &str, action: char) {
+        if let Some(params) = params.strip_prefix('?') {
+            match action {
+                'h' | 'l' => self.private_mode(params, action == 'h'),
+                _ => {}
+            }
+            return;
+        }
+        let params: Vec<usize> = params
+            .split(';')
+            .map(|param| param.parse().unwrap_or(0))
+            .collect();
+        let param = |index: usize| params.get(index).copied().unwrap_or(0);
+        // Movement defaults to 1 when the parameter is missing or 0.
+        let count = param(0).max(1);
+        match action {
+            'A' => self.row = self.row.saturating_sub(count),
+            'B' | 'e' => self.row = (self.row + count).min(self.height - 1),
+            'C' | 'a' => self.col = (self.col + count).min(self.width - 1),
+            'D' => self.col = self.col.min(self.width - 1).saturating_sub(count),
+            'E' => {
+                self.row = (self.row + count).min(self.height - 1);
+                self.col = 0;
+            }
+            'F' => {
+                self.row = self.row.saturating_sub(count);
+                self.col = 0;
+            }
+            'G' | '`' => self.col = (count - 1).min(self.width - 1),
+            'd' => self.row = (count - 1).min(self.height - 1),
+            'H' | 'f' => {
+                self.row = (param(0).max(1) - 1).min(self.height - 1);
+                self.col = (param(1).max(1) - 1).min(self.width - 1);
+            }
+            'J' => self.erase_display(param(0)),
+            'K' => self.erase_line(param(0)),
+            'L' => self.scroll_down(self.row, count),
+            'M' => self.scroll_up(self.row, count),
+            'S' => self.scroll_up(0, count),
+            'T' => self.scroll_down(0, count),
+            '@' => {
+                let col = self.col.min(self.width - 1);
+                let line = &mut self.lines[self.row];
+                for _ in 0..count.min(self.width - col) {
+                    line.insert(col, Cell::default());
+                    line.pop();
+                }
+            }
+            'P' => {
+                let col = self.col.min(self.width - 1);
+                let line = &mut self.lines[self.row];
+                for _ in 0..count.min(self.width - col) {
+                    line.remove(col);
+                    line.push(Cell::default());
+                }
+            }
+            'X' => {
+                let col = self.col.min(self.width - 1);
+                let end = (col + count).min(self.width);
+                self.lines[self.row][col..end].fill(Cell::default());
+            }
+            'm' => self.sgr(&params),
+            's' => self.saved_cursor = (self.row, self.col),
+            'u' => (self.row, self.col) = self.saved_cursor,
+            _ => {}
+        }
+    }
+
+    fn private_mode(&mut self, params: &str, set: bool) {
+        for mode in params.split(';') {
+            if matches!(mode, "1049" | "1047" | "47") {
+                if set && self.main_lines.is_none() {
+                    let blank = vec![vec![Cell::default(); self.width]; self.height];
+                    self.main_lines = Some(mem::replace(&mut self.lines, blank));
+                } else if let (false, Some(lines)) = (set, self.main_lines.take()) {
+                    self.lines = lines;
+                }
+            }
+        }
+    }
+
+    /// Select graphic rendition, sets the style of the following text.
+    // The color parameters are matched to ranges which fit in a `u8`.
+    #[allow(clippy::cast_possible_truncation)]
+    fn sgr(&mut self, params: &[usize]) {
+        let mut params = params.iter().copied();
+        while let Some(param) = params.next() {
+            let style = &mut self.style;
+            match param {
+                0 => *style = Style::default(),
+                1 => style.bold = true,
+                2 => style.faint = true,
+                3 => style.italic = true,
+                4 => style.underline = true,
+                7 => style.inverse = true,
+                22 => {
+                    style.bold = false;
+                    style.faint = false;
+                }
+                23 => style.italic = false,
+                24 => style.underline = false,
+                27 => style.inverse = false,
+                30..=37 => style.foreground = Color::Indexed((param - 30) as u8),
+                38 => style.foreground = extended_color(&mut params),
+                39 => style.foreground = Color::Default,
+                40..=47 => style.background = Color::Indexed((param - 40) as u8),
+                48 => style.background = extended_color(&mut params),
+                49 => style.background = Color::Default,
+                90..=97 => style.foreground = Color::Indexed((param - 90 + 8) as u8),
+                100..=107 => style.background = Color::Indexed((param - 100 + 8) as u8),
+                _ => {}
+            }
+        }
+    }
+
+    fn line_feed(&mut self) {
+        if self.row + 1 < self.height {
+            self.row += 1;
+        } else {
+            self.scroll_up(0, 1);
+        }
+    }
+
+    /// Moves the lines from `top` down up by `count`, adding blank lines at the bottom.
+    fn scroll_up(&mut self, top: usize, count: usize) {
+        for _ in 0..count.min(self.height - top) {
+            self.lines.remove(top);
+            self.lines.push(vec![Cell::default(); self.width]);
+        }
+    }
+
+    /// Moves the lines from `top` down down by `count`, adding blank lines at `top`.
+    fn scroll_down(&mut self, top: usize, count: usize) {
+        for _ in 0..count.min(self.height - top) {
+            self.lines.pop();
+            self.lines.insert(top, vec![Cell::default(); self.width]);
+        }
+    }
+
+    fn erase_display(&mut self, mode: usize) {
+        match mode {
+            0 => {
+                self.erase_line(0);
+                for line in &mut self.lines[self.row + 1..] {
+                    line.fill(Cell::default());
+                }
+            }
+            1 => {
+                self.erase_line(1);
+                for line in &mut self.lines[..self.row] {
+                    line.fill(Cell::default());
+                }
+            }
+            2 | 3 => {
+                for line in &mut self.lines {
+                    line.fill(Cell::default());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn erase_line(&mut self, mode: usize) {
+        let col = self.col.min(self.width - 1);
+        let line = &mut self.lines[self.row];
+        match mode {
+            0 => line[col..].fill(Cell::default()),
+            1 => line[..=col].fill(Cell::default()),
+            2 => line.fill(Cell::default()),
+            _ => {}
+        }
+    }
+}
+
+/// Parses the rest of a 256 color (`5;INDEX`) or RGB (`2;R;G;B`) SGR parameter.
+fn extended_color(params: &mut impl Iterator<Item = usize>) -> Color {
+    let mut next = || params.next().and_then(|param| u8::try_from(param).ok());
+    match next() {
+        Some(5) => next().map_or(Color::Default, Color::Indexed),
+        Some(2) => match (next(), next(), next()) {
+            (Some(red), Some(green), Some(blue)) => Color::Rgb(red, green, blue),
+            _ => Color::Default,
+        },
+        _ => Color::Default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(screen: &Screen) -> Vec<String> {
+        screen
+            .lines()
+            .iter()
+            .map(|line| {
+                let line: String = line.iter().map(|cell| cell.char).collect();
+                String::from(line.trim_end())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn write() {
+        let mut screen = Screen::new(5, 3);
+        screen.write("hello world\r\n");
+        assert_eq!(text(&screen), [" worl", "d", ""]);
+
+        screen.write("\x1b[2J\x1b[H\x1b[31mab\x1b[0mc\x1b[1;5Hd\rx\x1b[K");
+        assert_eq!(text(&screen), ["x", "", ""]);
+        screen.write("\x1b[2;2Hyz\x1b[D\x1b[P");
+        assert_eq!(text(&screen), ["x", " y", ""]);
+
+        screen.write("\x1b]0;title\x07\x1b[?1049h\x1b[32mvim");
+        assert_eq!(text(&screen), ["", "  vim", ""]);
+        assert_eq!(screen.lines()[1][2].style.foreground, Color::Indexed(2));
+        screen.write("\x1b[?1049l");
+        assert_eq!(text(&screen), ["x", " y", ""]);
+    }
+
+    #[test]
+    fn sgr() {
+        let mut screen = Screen::new(5, 1);
+        screen.write("\x1b[1;38;5;208;48;2;1;2;3mx");
+        assert_eq!(
+            screen.lines()[0][0].style,
+            Style {
+                foreground: Color::Indexed(208),
+                background: Color::Rgb(1, 2, 3),
+                bold: true,
+                ..Style::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Reading ttyrec recordings, as created by ttyrec, termrec, and shelr,
+//! and played by ttyplay or converted by ttygif.
+//!
+//! A ttyrec file is a sequence of frames, each a header of three little-endian `u32`s,
+//! the seconds and microseconds of the time it was recorded at and the length of its data,
+//! followed by that many bytes of output.
+
+use std::time::Duration;
+
+use color_eyre::eyre;
+
+use crate::{asciicast::Event, typescript::take_valid_utf8};
+
+/// Length of a frame's header
+const HEADER_LEN: usize = 12;
+
+/// Reads the frames of a `ttyrec` recording as output events,
+/// timed from the first frame.
+pub fn read(ttyrec: &[u8]) -> color_eyre::Result<Vec<Event>> {
+    let mut events = Vec::new();
+    let mut rest = ttyrec;
+    let mut start = None;
+    let mut time = Duration::ZERO;
+    // Bytes at the end of a frame which are the start of a split multi-byte char.
+    let mut partial = Vec::new();
+    for frame in 1.. {
+        if rest.is_empty() {
+            break;
+        }
+        eyre::ensure!(
+            rest.len() >= HEADER_LEN,
+            "frame {frame} has an incomplete header"
+        );
+        let (header, data) = rest.split_at(HEADER_LEN);
+        let [secs, micros, length] = [0, 4, 8].map(|offset| {
+            u32::from_le_bytes(
+                header[offset..offset + 4]
+                    .try_into()
+                    .expect("header field is 4 bytes"),
+            )
+        });
+        let length = usize::try_from(length)?;
+        eyre::ensure!(
+            length <= data.len(),
+            "frame {frame} is longer than the rest of the file"
+        );
+        let (data, next) = data.split_at(length);
+        rest = next;
+
+        let recorded = Duration::from_secs(secs.into()) + Duration::from_micros(micros.into());
+        time = recorded.saturating_sub(*start.get_or_insert(recorded));
+        partial.extend_from_slice(data);
+        let data = take_valid_utf8(&mut partial);
+        if !data.is_empty() {
+            events.push(Event::output(time, data));
+        }
+    }
+    if !partial.is_empty() {
+        events.push(Event::output(
+            time,
+            String::from_utf8_lossy(&partial).into_owned(),
+        ));
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(secs: u32, micros: u32, data: &[u8]) -> Vec<u8> {
+        let length = u32::try_from(data.len()).unwrap();
+        [secs, micros, length]
+            .into_iter()
+            .flat_map(u32::to_le_bytes)
+            .chain(data.iter().copied())
+            .collect()
+    }
+
+    #[test]
+    fn read() -> color_eyre::Result<()> {
+        let ttyrec = [
+            frame(1_700_000_000, 500_000, b"$ "),
+            frame(1_700_000_001, 0, &"é".as_bytes()[..1]),
+            frame(1_700_000_002, 250_000, &"é\r\n".as_bytes()[1..]),
+        ]
+        .concat();
+        assert_eq!(
+            super::read(&ttyrec)?,
+            [
+                Event::output(Duration::ZERO, String::from("$ ")),
+                // "é" is split across the second and third frame.
+                Event::output(Duration::from_millis(1750), String::from("é\r\n")),
+            ]
+        );
+
+        assert!(super::read(&ttyrec[..ttyrec.len() - 1]).is_err());
+        assert!(super::read(&ttyrec[..5]).is_err());
+        assert_eq!(super::read(&[])?, []);
+        Ok(())
+    }
+}
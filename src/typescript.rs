@@ -0,0 +1,256 @@
+//! Writing an asciicast's output as a typescript and timing file, like `script` creates,
+//! so it can be replayed with `scriptreplay`, and reading them back as events.
+
+use std::{
+    io::{self, Write},
+    str,
+    time::Duration,
+};
+
+use color_eyre::eyre::{self, Context};
+
+use crate::asciicast::{self, Event, EventType};
+
+/// Writes the output events of the `cast` to the `typescript`, and the delay before and
+/// size of each one to the `timing` file.
+///
+/// The typescript starts with a header line, which `scriptreplay` skips.
+pub fn write(
+    cast: &asciicast::File,
+    mut typescript: impl Write,
+    mut timing: Option<impl Write>,
+) -> io::Result<()> {
+    let asciicast::Header { width, height, .. } = cast.header;
+    writeln!(
+        typescript,
+        "Script started [COLUMNS=\"{width}\" LINES=\"{height}\"]"
+    )?;
+    let mut last_time = None;
+    for event in &cast.events {
+        if event.event_type != EventType::Output || event.data.is_empty() {
+            continue;
+        }
+        typescript.write_all(event.data.as_bytes())?;
+        if let Some(timing) = &mut timing {
+            let delay = event.time.saturating_sub(last_time.unwrap_or_default());
+            writeln!(timing, "{:.6} {}", delay.as_secs_f64(), event.data.len())?;
+        }
+        last_time = Some(event.time);
+    }
+    typescript.flush()?;
+    timing.map_or(Ok(()), |mut timing| timing.flush())
+}
+
+/// Output events and terminal size read from a typescript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub events: Vec<Event>,
+    /// Columns and lines, if they were recorded in the header or timing file
+    pub size: (Option<u16>, Option<u16>),
+}
+
+/// Reads a `typescript` and its `timing` file, as created by `script --timing`.
+///
+/// Both the classic timing format, "DELAY SIZE" lines, and the advanced format,
+/// where each line starts with an entry type, are supported.
+pub fn read(typescript: &[u8], timing: &str) -> color_eyre::Result<Recording> {
+    let mut size = (None, None);
+    // The header line isn't part of the timing file.
+    let mut output = match typescript.strip_prefix(b"Script started") {
+        Some(header) => {
+            let end = header.iter().position(|&byte| byte == b'\n');
+            let line = String::from_utf8_lossy(&header[..end.unwrap_or(header.len())]);
+            size = (header_value(&line, "COLUMNS"), header_value(&line, "LINES"));
+            end.map_or(&[][..], |end| &header[end + 1..])
+        }
+        None => typescript,
+    };
+
+    let mut events = Vec::new();
+    let mut time = Duration::ZERO;
+    // Bytes at the end of a chunk which are the start of a split multi-byte char.
+    let mut partial = Vec::new();
+    for (num, line) in timing.lines().enumerate() {
+        let entry = parse_timing(line)
+            .ok_or_else(|| eyre::eyre!("invalid timing entry {line:?}"))
+            .wrap_err_with(|| format!("could not parse timing file line {}", num + 1))?;
+        match entry {
+            Entry::Output { delay, length } => {
+                time += delay;
+                eyre::ensure!(
+                    length <= output.len(),
+                    "timing file line {} is past the end of the typescript",
+                    num + 1
+                );
+                let (chunk, rest) = output.split_at(length);
+                output = rest;
+                partial.extend_from_slice(chunk);
+                let data = take_valid_utf8(&mut partial);
+                if !data.is_empty() {
+                    events.push(Event::output(time, data));
+                }
+            }
+            Entry::Other { delay } => time += delay,
+            Entry::Header { name, value } => match name {
+                "COLUMNS" => size.0 = value.parse().ok().or(size.0),
+                "LINES" => size.1 = value.parse().ok().or(size.1),
+                _ => {}
+            },
+        }
+    }
+    if !partial.is_empty() {
+        events.push(Event::output(
+            time,
+            String::from_utf8_lossy(&partial).into_owned(),
+        ));
+    }
+    Ok(Recording { events, size })
+}
+
+/// Value of a `NAME="VALUE"` pair in a typescript's header line.
+fn header_value(line: &str, name: &str) -> Option<u16> {
+    let start = line.find(&format!("{name}=\""))? + name.len() + 2;
+    let end = line[start..].find('"')? + start;
+    line[start..end].parse().ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry<'a> {
+    /// Output which is the next `length` bytes of the typescript
+    Output {
+        delay: Duration,
+        length: usize,
+    },
+    /// Input or a signal, which are not in the typescript
+    Other {
+        delay: Duration,
+    },
+    Header {
+        name: &'a str,
+        value: &'a str,
+    },
+}
+
+fn parse_timing(line: &str) -> Option<Entry<'_>> {
+    let mut fields = line.split_whitespace();
+    let first = fields.next()?;
+    let delay = |delay: &str| {
+        delay
+            .parse()
+            .ok()
+            .and_then(|delay| Duration::try_from_secs_f64(delay).ok())
+    };
+    match first {
+        "O" => Some(Entry::Output {
+            delay: delay(fields.next()?)?,
+            length: fields.next()?.parse().ok()?,
+        }),
+        "I" | "S" => Some(Entry::Other {
+            delay: delay(fields.next()?)?,
+        }),
+        "H" => {
+            fields.next()?;
+            Some(Entry::Header {
+                name: fields.next()?,
+                value: fields.next().unwrap_or_default(),
+            })
+        }
+        delay_field => Some(Entry::Output {
+            delay: delay(delay_field)?,
+            length: fields.next()?.parse().ok()?,
+        }),
+    }
+}
+
+/// Takes the longest prefix of `bytes` which is valid UTF-8, leaving a char which is split
+/// at the end. Invalid bytes are replaced.
+pub fn take_valid_utf8(bytes: &mut Vec<u8>) -> String {
+    let valid = match str::from_utf8(bytes) {
+        Ok(_) => bytes.len(),
+        // Incomplete char at the end.
+        Err(error) if error.error_len().is_none() => error.valid_up_to(),
+        Err(_) => {
+            let data = String::from_utf8_lossy(bytes).into_owned();
+            bytes.clear();
+            return data;
+        }
+    };
+    let data = String::from_utf8_lossy(&bytes[..valid]).into_owned();
+    bytes.drain(..valid);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::asciicast::{Header, Version};
+
+    use super::*;
+
+    #[test]
+    fn write() {
+        let cast = asciicast::File {
+            version: Version::V2,
+            header: Header {
+                width: 80,
+                height: 24,
+                timestamp: None,
+                duration: None,
+                idle_time_limit: None,
+                command: None,
+                title: None,
+                env: HashMap::new(),
+            },
+            events: vec![
+                Event::output(Duration::ZERO, String::from("$ ")),
+                Event::marker(Duration::from_millis(100), String::from("marker")),
+                Event::output(Duration::from_millis(1500), String::from("é\r\n")),
+            ],
+        };
+        let mut typescript = Vec::new();
+        let mut timing = Vec::new();
+        super::write(&cast, &mut typescript, Some(&mut timing)).unwrap();
+        assert_eq!(
+            String::from_utf8(typescript).unwrap(),
+            "Script started [COLUMNS=\"80\" LINES=\"24\"]\n$ é\r\n"
+        );
+        assert_eq!(
+            String::from_utf8(timing).unwrap(),
+            "0.000000 2\n1.500000 4\n"
+        );
+    }
+
+    #[test]
+    fn read() -> color_eyre::Result<()> {
+        let typescript =
+            "Script started on 2024-01-01 [COLUMNS=\"100\" LINES=\"30\"]\n$ é\r\n".as_bytes();
+        let recording = super::read(typescript, "0.5 2\n1.0 1\n0.25 3\n")?;
+        assert_eq!(recording.size, (Some(100), Some(30)));
+        assert_eq!(
+            recording.events,
+            [
+                Event::output(Duration::from_millis(500), String::from("$ ")),
+                // "é" is split across the second and third chunk.
+                Event::output(Duration::from_millis(1750), String::from("é\r\n")),
+            ]
+        );
+
+        let recording = super::read(
+            b"ab",
+            "H 0.0 COLUMNS 60\nI 1.0 1\nO 0.5 1\nS 0.5 SIGWINCH\nO 1.0 1\n",
+        )?;
+        assert_eq!(recording.size, (Some(60), None));
+        assert_eq!(
+            recording.events,
+            [
+                Event::output(Duration::from_millis(1500), String::from("a")),
+                Event::output(Duration::from_secs(3), String::from("b")),
+            ]
+        );
+
+        assert!(super::read(b"a", "0.5 2\n").is_err());
+        assert!(super::read(b"a", "half 1\n").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+//! Uploading asciicast files to asciinema.org, or a self-hosted asciinema server.
+//!
+//! Uploads are authenticated with the asciinema install ID, the same one used by the
+//! asciinema CLI, so recordings show up in the account it is linked to.
+
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use color_eyre::{
+    eyre::{self, Context},
+    Section,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// Server used when one isn't given and `ASCIINEMA_API_URL` isn't set
+const DEFAULT_SERVER: &str = "https://asciinema.org";
+
+/// Response to a successful upload.
+#[derive(Deserialize, Debug)]
+struct Uploaded {
+    url: String,
+    /// Shown to the user, i.e. asking them to link their install ID to an account
+    message: Option<String>,
+}
+
+/// Uploads the asciicast file at `path`, returning the URL of the recording.
+///
+/// `server` defaults to `ASCIINEMA_API_URL`, or asciinema.org if it isn't set.
+/// If there isn't an install ID yet, one is created like the asciinema CLI does.
+pub fn upload(path: &Path, server: Option<&str>) -> color_eyre::Result<String> {
+    let server = server
+        .map(String::from)
+        .or_else(|| env::var("ASCIINEMA_API_URL").ok())
+        .unwrap_or_else(|| String::from(DEFAULT_SERVER));
+    let server = server.trim_end_matches('/');
+    let install_id = install_id()?;
+    let contents = fs::read(path).wrap_err("could not read asciicast file")?;
+    let file_name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+
+    let boundary = format!("autocast-{:016x}", fastrand::u64(..));
+    let username = env::var("USER").unwrap_or_default();
+    let credentials = STANDARD.encode(format!("{username}:{install_id}"));
+    let response = ureq::post(&format!("{server}/api/asciicasts"))
+        .set("Authorization", &format!("Basic {credentials}"))
+        .set("Accept", "application/json")
+        .set(
+            "User-Agent",
+            concat!("autocast/", env!("CARGO_PKG_VERSION")),
+        )
+        .set(
+            "Content-Type",
+            &format!("multipart/form-data; boundary={boundary}"),
+        )
+        .send_bytes(&multipart(&boundary, &file_name, &contents));
+
+    let response = match response {
+        Ok(response) => response,
+        Err(ureq::Error::Status(401, _)) => {
+            return Err(eyre::eyre!("the install ID was not accepted by {server}")).suggestion(
+                format!("link it to your account by opening {server}/connect/{install_id}"),
+            );
+        }
+        Err(ureq::Error::Status(413, _)) => {
+            return Err(eyre::eyre!("the asciicast file is too large for {server}"))
+                .suggestion("compress it with `--compress`");
+        }
+        Err(error) => return Err(error).wrap_err_with(|| format!("could not upload to {server}")),
+    };
+    let uploaded: Uploaded = serde_json::from_reader(response.into_reader())
+        .wrap_err("could not parse the server's response")?;
+    if let Some(message) = uploaded.message {
+        eprintln!("{}", message.trim_end());
+    }
+    Ok(uploaded.url)
+}
+
+/// Reads the asciinema install ID, creating it if it doesn't exist.
+fn install_id() -> color_eyre::Result<String> {
+    let path = config_dir()
+        .ok_or(eyre::eyre!("could not find asciinema's config directory"))?
+        .join("install-id");
+    match fs::read_to_string(&path) {
+        Ok(install_id) => Ok(install_id.trim().to_owned()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            let install_id = new_install_id();
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            write_install_id(&path, &install_id)
+                .wrap_err_with(|| format!("could not write `{}`", path.display()))?;
+            Ok(install_id)
+        }
+        Err(error) => Err(error).wrap_err_with(|| format!("could not read `{}`", path.display())),
+    }
+}
+
+/// asciinema's config directory, found the same way as the asciinema CLI.
+fn config_dir() -> Option<PathBuf> {
+    env::var_os("ASCIINEMA_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("XDG_CONFIG_HOME").map(|config| PathBuf::from(config).join("asciinema"))
+        })
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config").join("asciinema")))
+}
+
+/// A random version 4 UUID, from the operating system's secure random number generator.
+fn new_install_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// Writes the install ID so only the user can read it, as it authenticates their uploads.
+fn write_install_id(path: &Path, install_id: &str) -> io::Result<()> {
+    let mut options = fs::File::options();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(install_id.as_bytes())
+}
+
+/// A multipart form body with the asciicast file as the "asciicast" field.
+fn multipart(boundary: &str, file_name: &str, contents: &[u8]) -> Vec<u8> {
+    let mut body = format!(
+        "--{boundary}\r\n\
+        Content-Disposition: form-data; name=\"asciicast\"; filename=\"{}\"\r\n\
+        Content-Type: application/octet-stream\r\n\r\n",
+        file_name.replace('"', "")
+    )
+    .into_bytes();
+    body.extend_from_slice(contents);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn multipart() {
+        let body = super::multipart("b", "demo.cast", b"{}");
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "--b\r\nContent-Disposition: form-data; name=\"asciicast\"; filename=\"demo.cast\"\r\n\
+                Content-Type: application/octet-stream\r\n\r\n{}\r\n--b--\r\n"
+        );
+    }
+
+    #[test]
+    fn new_install_id() {
+        let install_id = super::new_install_id();
+        let parts: Vec<usize> = install_id.split('-').map(str::len).collect();
+        assert_eq!(parts, [8, 4, 4, 4, 12]);
+        assert_eq!(install_id.as_bytes()[14], b'4');
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_install_id() -> std::io::Result<()> {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("install-id");
+        super::write_install_id(&path, "id")?;
+        assert_eq!(fs::read_to_string(&path)?, "id");
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        Ok(())
+    }
+}
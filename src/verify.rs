@@ -0,0 +1,276 @@
+//! Checking an asciicast against a spec of durations and output,
+//! so regenerated asciicasts can be checked in CI.
+
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::Path,
+    sync::OnceLock,
+    time::Duration,
+};
+
+use color_eyre::eyre::Context;
+use regex::Regex;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    asciicast::{self, Event, EventType},
+    duration,
+};
+
+/// What the whole asciicast, and each of its chapters, is expected to contain.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    #[serde(default, with = "duration::option")]
+    min_duration: Option<Duration>,
+    #[serde(default, with = "duration::option")]
+    max_duration: Option<Duration>,
+    /// Text which must be in the output
+    #[serde(default)]
+    output: Vec<String>,
+    #[serde(default)]
+    chapters: Vec<Chapter>,
+}
+
+/// Part of the asciicast from a marker until the next marker, or the end.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+struct Chapter {
+    /// Label of the marker the chapter starts at
+    marker: String,
+    #[serde(default, with = "duration::option")]
+    min_duration: Option<Duration>,
+    #[serde(default, with = "duration::option")]
+    max_duration: Option<Duration>,
+    /// Text which must be in the chapter's output
+    #[serde(default)]
+    output: Vec<String>,
+}
+
+impl Spec {
+    /// Reads the spec from a YAML file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be read or is not a valid spec.
+    pub fn read(path: &Path) -> color_eyre::Result<Self> {
+        let spec = fs::read_to_string(path)
+            .wrap_err_with(|| format!("could not read spec `{}`", path.display()))?;
+        serde_yaml::from_str(&spec)
+            .wrap_err_with(|| format!("could not parse spec `{}`", path.display()))
+    }
+}
+
+/// An expectation of the spec which the asciicast doesn't meet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Marker of the chapter, or [`None`] for the whole asciicast
+    pub chapter: Option<String>,
+    pub message: String,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.chapter {
+            Some(marker) => write!(f, "chapter {marker:?}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Every expectation of the spec which the asciicast doesn't meet.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub struct Mismatches(pub Vec<Mismatch>);
+
+impl Display for Mismatches {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "asciicast does not match the spec, {} mismatch(es):",
+            self.0.len()
+        )?;
+        for mismatch in &self.0 {
+            write!(f, "\n  {mismatch}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the asciicast against the spec.
+///
+/// # Errors
+///
+/// Returns every expectation of the spec which the asciicast doesn't meet.
+pub fn assert_cast_matches(cast: &asciicast::File, spec: &Spec) -> Result<(), Mismatches> {
+    let events = &cast.events;
+    let time = |index: usize| {
+        events
+            .get(index)
+            .or_else(|| events.last())
+            .map_or(Duration::ZERO, |event| event.time)
+    };
+    let mut mismatches = Vec::new();
+
+    check(
+        None,
+        time(events.len()),
+        &output(events),
+        (spec.min_duration, spec.max_duration),
+        &spec.output,
+        &mut mismatches,
+    );
+
+    for chapter in &spec.chapters {
+        let Some(start) = events.iter().position(|event| {
+            event.event_type == EventType::Marker && event.data == chapter.marker
+        }) else {
+            mismatches.push(Mismatch {
+                chapter: Some(chapter.marker.clone()),
+                message: String::from("no marker with this label"),
+            });
+            continue;
+        };
+        let end = events[start + 1..]
+            .iter()
+            .position(|event| event.event_type == EventType::Marker)
+            .map_or(events.len(), |end| start + 1 + end);
+        check(
+            Some(&chapter.marker),
+            time(end).saturating_sub(time(start)),
+            &output(&events[start..end]),
+            (chapter.min_duration, chapter.max_duration),
+            &chapter.output,
+            &mut mismatches,
+        );
+    }
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(Mismatches(mismatches))
+    }
+}
+
+fn check(
+    chapter: Option<&String>,
+    duration: Duration,
+    output: &str,
+    (min_duration, max_duration): (Option<Duration>, Option<Duration>),
+    expected_output: &[String],
+    mismatches: &mut Vec<Mismatch>,
+) {
+    let mut push = |message| {
+        mismatches.push(Mismatch {
+            chapter: chapter.cloned(),
+            message,
+        });
+    };
+    if let Some(min) = min_duration.filter(|min| duration < *min) {
+        push(format!(
+            "took {duration:.2?}, shorter than the minimum of {min:?}"
+        ));
+    }
+    if let Some(max) = max_duration.filter(|max| duration > *max) {
+        push(format!(
+            "took {duration:.2?}, longer than the maximum of {max:?}"
+        ));
+    }
+    for expected in expected_output {
+        if !output.contains(expected.as_str()) {
+            push(format!("output does not contain {expected:?}"));
+        }
+    }
+}
+
+/// The output events' data, without escape sequences or carriage returns.
+fn output(events: &[Event]) -> String {
+    static ESCAPE: OnceLock<Regex> = OnceLock::new();
+    let escape = ESCAPE.get_or_init(|| {
+        // CSI and OSC sequences, and two character escapes.
+        Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(\x07|\x1b\\)|\x1b[@-_]")
+            .expect("escape sequence regex is valid")
+    });
+    let output: String = events
+        .iter()
+        .filter(|event| event.event_type == EventType::Output)
+        .map(|event| event.data.as_str())
+        .collect();
+    escape.replace_all(&output, "").replace('\r', "")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::asciicast::{Header, Version};
+
+    use super::*;
+
+    #[test]
+    fn assert_cast_matches() {
+        let output = |secs, data: &str| Event::output(Duration::from_secs(secs), data.into());
+        let marker = |secs, label: &str| Event::marker(Duration::from_secs(secs), label.into());
+        let cast = asciicast::File {
+            version: Version::V2,
+            header: Header {
+                width: 80,
+                height: 24,
+                timestamp: None,
+                duration: None,
+                idle_time_limit: None,
+                command: None,
+                title: None,
+                env: HashMap::new(),
+            },
+            events: vec![
+                marker(0, "install"),
+                output(1, "\x1b[32minstalled\x1b[0m\r\n"),
+                marker(3, "run"),
+                output(4, "hello\r\n"),
+                output(10, "$ "),
+            ],
+        };
+
+        let spec: Spec = serde_yaml::from_str(
+            "
+            max_duration: 15s
+            output: [installed, hello]
+            chapters:
+              - marker: install
+                max_duration: 3s
+                output: [installed]
+              - marker: run
+                min_duration: 7s
+            ",
+        )
+        .unwrap();
+        assert_eq!(super::assert_cast_matches(&cast, &spec), Ok(()));
+
+        let spec: Spec = serde_yaml::from_str(
+            "
+            min_duration: 15s
+            chapters:
+              - marker: install
+                max_duration: 2s
+                output: [hello]
+              - marker: missing
+            ",
+        )
+        .unwrap();
+        let Err(Mismatches(mismatches)) = super::assert_cast_matches(&cast, &spec) else {
+            panic!("cast should not match spec");
+        };
+        let messages: Vec<String> = mismatches.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "took 10.00s, shorter than the minimum of 15s",
+                "chapter \"install\": took 3.00s, longer than the maximum of 2s",
+                "chapter \"install\": output does not contain \"hello\"",
+                "chapter \"missing\": no marker with this label",
+            ]
+        );
+    }
+}